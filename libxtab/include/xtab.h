@@ -0,0 +1,48 @@
+/*
+ * The C API of the xtab crosstab engine (libxtab).
+ *
+ * The configuration of a crosstab is the text of a JSON object with the settings of an xtab job file: the names of
+ * the command-line arguments, such as {"row": "site", "col": "date", "value": "result", "aggregate": "mean"}, with
+ * the delimiter, output_delimiter, and output_format of the input and output text.
+ */
+
+#ifndef XTAB_H
+#define XTAB_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The status of a call that succeeded. Any other status is the exit code of the command line for the failure. */
+#define XTAB_OK 0
+#define XTAB_FAILURE 1
+#define XTAB_INVALID_ARGUMENT 2
+#define XTAB_MISSING_COLUMNS 3
+#define XTAB_IO_ERROR 4
+#define XTAB_DUPLICATES 5
+
+/* Bytes allocated by the library, which must be released with xtab_buffer_free. Messages are UTF-8 text without a
+ * terminating NUL. */
+typedef struct XtabBuffer {
+    uint8_t *data;
+    size_t len;
+} XtabBuffer;
+
+/* Crosstab input_len bytes of delimited text, as described by the NUL-terminated JSON configuration, into output.
+ * On failure, the error message is written to error instead. Either buffer may be NULL if it is not wanted. */
+int xtab_crosstab(const uint8_t *input, size_t input_len, const char *config_json, XtabBuffer *output, XtabBuffer *error);
+
+/* Release the bytes of a buffer filled by the library. A buffer without bytes is ignored. */
+void xtab_buffer_free(XtabBuffer buffer);
+
+/* The version of the library. */
+const char *xtab_version(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* XTAB_H */
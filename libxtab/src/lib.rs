@@ -0,0 +1,127 @@
+//! A C API of the xtab crosstab engine, so that programs in other languages (such as R through `.Call`, or C#
+//! through P/Invoke) can crosstab data in memory with the same logic as the command line, without running it.
+//!
+//! ```c
+//! #include "xtab.h"
+//!
+//! XtabBuffer output = {0}, error = {0};
+//! const char *config = "{\"row\": \"site\", \"col\": \"date\", \"value\": \"result\", \"aggregate\": \"mean\"}";
+//! int status = xtab_crosstab(csv, csv_len, config, &output, &error);
+//! if (status != XTAB_OK) {
+//!     fprintf(stderr, "%.*s\n", (int)error.len, error.data);
+//! }
+//! xtab_buffer_free(output);
+//! xtab_buffer_free(error);
+//! ```
+//!
+//! The configuration is the text of a JSON object with the settings of a job file, and the `delimiter`,
+//! `output_delimiter`, and `output_format` of the input and output (see [`crosstab_json`]). The status of a call is
+//! 0, or the exit code that the command line would exit with (see [`xtab_engine::error`]).
+
+use std::ffi::{c_char, c_int, CStr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use xtab_engine::error::{exit_code, EXIT_FAILURE};
+use xtab_engine::params::crosstab_json;
+use xtab_engine::XtabError;
+
+/// The status of a call that succeeded.
+pub const XTAB_OK: c_int = 0;
+
+/// Bytes that were allocated by the library, which the caller must release with [`xtab_buffer_free`]. The bytes of
+/// a message are UTF-8 text without a terminating NUL.
+#[repr(C)]
+pub struct XtabBuffer {
+    /// The bytes, or null if there are none.
+    pub data: *mut u8,
+    /// The number of bytes.
+    pub len: usize,
+}
+
+impl XtabBuffer {
+    // Hand the bytes over to the caller
+    fn new(bytes: Vec<u8>) -> XtabBuffer {
+        let bytes = Box::into_raw(bytes.into_boxed_slice());
+        XtabBuffer { data: bytes as *mut u8, len: bytes.len() }
+    }
+}
+
+// Crosstab the input as described by the configuration
+fn crosstab(input: *const u8, input_len: usize, config_json: *const c_char) -> anyhow::Result<Vec<u8>> {
+    if (input.is_null() && input_len > 0) || config_json.is_null() {
+        return Err(XtabError::Argument("The input and the configuration must not be null".to_string()).into());
+    }
+    // SAFETY: the caller passes input_len readable bytes at input, and a NUL-terminated configuration
+    let input: &[u8] = if input_len == 0 { &[] } else { unsafe { std::slice::from_raw_parts(input, input_len) } };
+    let config_json = unsafe { CStr::from_ptr(config_json) }.to_str()?;
+    crosstab_json(input, config_json)
+}
+
+/// Crosstab delimited text, as described by the configuration (the NUL-terminated text of a JSON object), into
+/// `output`. On failure, the message that the command line would print is written to `error` instead, and the
+/// status is the exit code of the command line. Either buffer may be null if it is not wanted.
+///
+/// # Safety
+///
+/// `input` must point to `input_len` readable bytes, `config_json` to a NUL-terminated string, and `output` and
+/// `error` to writable buffers (or be null).
+#[no_mangle]
+pub unsafe extern "C" fn xtab_crosstab(
+    input: *const u8,
+    input_len: usize,
+    config_json: *const c_char,
+    output: *mut XtabBuffer,
+    error: *mut XtabBuffer,
+) -> c_int {
+    let result = catch_unwind(AssertUnwindSafe(|| crosstab(input, input_len, config_json)));
+    let (status, bytes, buffer) = match result {
+        Ok(Ok(bytes)) => (XTAB_OK, bytes, output),
+        Ok(Err(e)) => (exit_code(&e), format!("{:#}", e).into_bytes(), error),
+        Err(_) => (EXIT_FAILURE, b"The crosstab failed unexpectedly".to_vec(), error),
+    };
+    if !buffer.is_null() {
+        *buffer = XtabBuffer::new(bytes);
+    }
+    status
+}
+
+/// Release the bytes of a buffer that was filled by the library. A buffer without bytes is ignored.
+///
+/// # Safety
+///
+/// The buffer must have been filled by the library and not released before.
+#[no_mangle]
+pub unsafe extern "C" fn xtab_buffer_free(buffer: XtabBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(buffer.data, buffer.len)));
+    }
+}
+
+/// The version of the library, as a NUL-terminated string.
+#[no_mangle]
+pub extern "C" fn xtab_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that the output or the error message is handed over with the status of the command line
+    #[test]
+    fn test_xtab_crosstab() {
+        let csv = b"site,date,result\nA01,d1,1.5\nB02,d1,3\n";
+        let mut output = XtabBuffer { data: std::ptr::null_mut(), len: 0 };
+        let mut error = XtabBuffer { data: std::ptr::null_mut(), len: 0 };
+        unsafe {
+            let config = c"{\"row\": \"site\", \"col\": \"date\", \"value\": \"result\"}";
+            assert_eq!(xtab_crosstab(csv.as_ptr(), csv.len(), config.as_ptr(), &mut output, &mut error), XTAB_OK);
+            assert_eq!(std::slice::from_raw_parts(output.data, output.len), b"site,d1_result\nA01,1.5\nB02,3.0\n");
+            xtab_buffer_free(output);
+
+            let config = c"{\"row\": \"site\", \"col\": \"day\", \"value\": \"result\"}";
+            assert_eq!(xtab_crosstab(csv.as_ptr(), csv.len(), config.as_ptr(), std::ptr::null_mut(), &mut error), 3);
+            assert!(String::from_utf8_lossy(std::slice::from_raw_parts(error.data, error.len)).contains("day"));
+            xtab_buffer_free(error);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Python bindings of the xtab crosstab engine, built with maturin into the `pyxtab` module, so that notebooks can
+//! build crosstabs with the same logic as the command line and get the same bytes out.
+//!
+//! ```python
+//! import pyxtab
+//!
+//! csv = pyxtab.crosstab("results.csv", row="site", col="date", value="result", aggregate="mean")
+//! xlsx = pyxtab.crosstab(df, row=["site"], col=["date"], value=["result"], bin="depth=10", output_format="xlsx")
+//! ```
+//!
+//! The parameters are the settings of a job file (see [`Settings`]): the names of the command-line arguments, with
+//! underscores for dashes, and lists (or comma-separated strings) for the arguments that take comma-separated lists,
+//! with the defaults of the command line.
+
+use polars::prelude::DataFrame;
+use pyo3::exceptions::{PyKeyError, PyOSError, PyTypeError, PyUserWarning, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyBool, PyBytes, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+use serde_json::Value;
+use std::ffi::CString;
+use std::io::Cursor;
+use xtab::io::{read_csv_from, read_input, read_ipc_from, InputFormat};
+use xtab::output::writer_for;
+use xtab::params::{Settings, Transforms};
+use xtab::{delimiter_for_path, ReadConfig, XtabError};
+
+// The Python exception of a failure, by its kind: a missing column is a KeyError, an unreadable input or output an
+// OSError, and anything else, such as an invalid argument, a ValueError
+fn py_error(error: anyhow::Error) -> PyErr {
+    let message = format!("{:#}", error);
+    match error.downcast_ref::<XtabError>() {
+        Some(XtabError::MissingColumns(_)) => PyKeyError::new_err(message),
+        Some(XtabError::Io(_)) => PyOSError::new_err(message),
+        _ if error.chain().any(|cause| cause.is::<std::io::Error>()) => PyOSError::new_err(message),
+        _ => PyValueError::new_err(message),
+    }
+}
+
+// The JSON value of a parameter: None, a bool, a number, a string, a path, or a list or tuple of them
+fn json_value(value: &Bound<'_, PyAny>) -> PyResult<Value> {
+    if value.is_none() {
+        Ok(Value::Null)
+    } else if let Ok(flag) = value.downcast::<PyBool>() {
+        Ok(Value::Bool(flag.is_true()))
+    } else if value.is_instance_of::<PyInt>() {
+        Ok(Value::from(value.extract::<i64>()?))
+    } else if let Ok(number) = value.downcast::<PyFloat>() {
+        serde_json::Number::from_f64(number.value()).map(Value::Number).ok_or_else(|| PyValueError::new_err("A parameter must be a finite number"))
+    } else if value.is_instance_of::<PyString>() {
+        Ok(Value::String(value.extract()?))
+    } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        value.try_iter()?.map(|item| json_value(&item?)).collect::<PyResult<Vec<Value>>>().map(Value::Array)
+    } else if let Ok(path) = value.extract::<std::path::PathBuf>() {
+        Ok(Value::String(path.display().to_string()))
+    } else {
+        Err(PyTypeError::new_err(format!("A parameter cannot be a {}", value.get_type().name()?)))
+    }
+}
+
+// Read the input data: a path (a string or path-like object) of a file in any input format, the bytes of delimited
+// text, or a polars or pandas DataFrame, which is passed as Arrow IPC so that its column types are kept
+fn read_data(data: &Bound<'_, PyAny>, settings: &Settings, transforms: &Transforms) -> PyResult<DataFrame> {
+    let read_config = settings.io.read_config(transforms).map_err(py_error)?;
+    if let Ok(bytes) = data.downcast::<PyBytes>() {
+        return read_csv_from(Cursor::new(bytes.as_bytes()), &read_config).map_err(|e| py_error(e.into()));
+    }
+    if let Some(ipc) = ipc_bytes(data)? {
+        return read_ipc_from(Cursor::new(ipc)).map_err(|e| py_error(e.into()));
+    }
+    let path: std::path::PathBuf = data.extract()?;
+    let read_config = match settings.io.delimiter {
+        Some(_) => read_config,
+        None => ReadConfig { delimiter: delimiter_for_path(&path), ..read_config },
+    };
+    read_input(InputFormat::from_path(&path), &path, &read_config).map_err(py_error)
+}
+
+// The Arrow IPC file of a polars or pandas DataFrame, or None if the data are not a DataFrame. Strings are written
+// as the large strings of older polars versions, which this version of polars reads, and pandas, which writes IPC
+// with pyarrow, drops the index, as it is not a column of the data.
+fn ipc_bytes(data: &Bound<'_, PyAny>) -> PyResult<Option<Vec<u8>>> {
+    let py = data.py();
+    let buffer = py.import("io")?.getattr("BytesIO")?.call0()?;
+    if data.hasattr("write_ipc")? {
+        let kwargs = PyDict::new(py);
+        if let Ok(level) = py.import("polars").and_then(|polars| polars.getattr("CompatLevel")) {
+            kwargs.set_item("compat_level", level.call_method0("oldest")?)?;
+        }
+        data.call_method("write_ipc", (&buffer,), Some(&kwargs))?;
+    } else if data.hasattr("to_feather")? {
+        let kwargs = [("compression", "uncompressed")].into_py_dict(py)?;
+        let frame = data.call_method("reset_index", (), Some(&[("drop", true)].into_py_dict(py)?))?;
+        frame.call_method("to_feather", (&buffer,), Some(&kwargs))?;
+    } else {
+        return Ok(None);
+    }
+    Ok(Some(buffer.call_method0("getvalue")?.extract()?))
+}
+
+/// Crosstab the data and return the output, as the bytes that the command line writes to the output file.
+///
+/// The data are the path of an input file, the bytes of delimited text, or a polars or pandas DataFrame, whose column
+/// types are kept (a pandas DataFrame needs pyarrow). The other parameters are those of the command-line arguments of the same names, with their defaults, and the delimiter,
+/// output_format, output_delimiter, on_duplicate, and strict settings of a job file. Multiple values for a cell that
+/// are not aggregated are reported with a UserWarning, as the command line warns about them.
+#[pyfunction]
+#[pyo3(signature = (data, row=None, col=None, value=None, **params))]
+fn crosstab<'py>(
+    py: Python<'py>,
+    data: &Bound<'py, PyAny>,
+    row: Option<&Bound<'py, PyAny>>,
+    col: Option<&Bound<'py, PyAny>>,
+    value: Option<&Bound<'py, PyAny>>,
+    params: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    // Resolve the parameters as the command line resolves the arguments of the same names
+    let mut table = serde_json::Map::new();
+    for (name, param) in [("row", row), ("col", col), ("value", value)] {
+        if let Some(param) = param {
+            table.insert(name.to_string(), json_value(param)?);
+        }
+    }
+    for (name, param) in params.iter().flat_map(|params| params.iter()) {
+        table.insert(name.extract()?, json_value(&param)?);
+    }
+    let settings = Settings::from_table(table).map_err(py_error)?;
+    let transforms = settings.data.transforms().map_err(py_error)?;
+    let output_format = settings.io.output_format().map_err(py_error)?;
+    let write_config = settings.io.write_config().map_err(py_error)?;
+
+    // Read the data and write the crosstab to memory, without holding the interpreter while the crosstab is built
+    let df = read_data(data, &settings, &transforms)?;
+    let (output, warning) = py
+        .allow_threads(|| -> anyhow::Result<(Vec<u8>, Option<String>)> {
+            let (crosstab, warning) = settings.crosstab(df, &transforms)?;
+            let mut output: Vec<u8> = Vec::new();
+            writer_for(output_format, &mut output, &write_config)?.write(&crosstab)?;
+            Ok((output, warning))
+        })
+        .map_err(py_error)?;
+    if let Some(warning) = warning {
+        let message = CString::new(warning).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        PyErr::warn(py, py.get_type::<PyUserWarning>().as_any(), &message, 1)?;
+    }
+    Ok(PyBytes::new(py, &output))
+}
+
+/// The xtab crosstab engine.
+#[pymodule]
+fn pyxtab(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(crosstab, m)?)?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
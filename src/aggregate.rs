@@ -0,0 +1,684 @@
+// Functions used to combine multiple input values that map to a single output cell.
+
+use crate::format::ValueFormat;
+use crate::plugin;
+use std::str::FromStr;
+
+/// The method used to combine the values of a cell when more than one input row maps to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregation {
+    Sum,
+    Mean,
+    Min,
+    Max,
+    Count,
+    First,
+    Last,
+    Concat,
+    /// The sample standard deviation, which is missing for fewer than two values.
+    Sd,
+    /// The smallest value, counting non-detects (such as `<0.5`) at their detection limits: a non-detect whose limit
+    /// is no larger than every detected value makes the result that non-detect.
+    MinNd,
+    /// The largest detected value, or if every value is a non-detect (such as `<0.5`), the non-detect with the
+    /// largest detection limit.
+    MaxNd,
+    /// The number of distinct values.
+    NUnique,
+    /// The most frequent value, or of the values that are equally frequent, the first in input order.
+    Mode,
+    /// Whether any value is true, for boolean values such as `true` and `false`.
+    Any,
+    /// Whether every value is true, for boolean values such as `true` and `false`.
+    All,
+    /// An aggregation registered with [`register_aggregator`](crate::plugin::register_aggregator), by its position in
+    /// the registry.
+    Custom(usize),
+}
+
+impl Aggregation {
+    /// The built-in aggregation of the name, if there is one.
+    pub fn builtin(name: &str) -> Option<Aggregation> {
+        match name.trim().to_lowercase().as_str() {
+            "sum" => Some(Aggregation::Sum),
+            "mean" => Some(Aggregation::Mean),
+            "min" => Some(Aggregation::Min),
+            "max" => Some(Aggregation::Max),
+            "count" => Some(Aggregation::Count),
+            "first" => Some(Aggregation::First),
+            "last" => Some(Aggregation::Last),
+            "concat" => Some(Aggregation::Concat),
+            "sd" => Some(Aggregation::Sd),
+            "min_nd" => Some(Aggregation::MinNd),
+            "max_nd" => Some(Aggregation::MaxNd),
+            "nunique" => Some(Aggregation::NUnique),
+            "mode" => Some(Aggregation::Mode),
+            "any" => Some(Aggregation::Any),
+            "all" => Some(Aggregation::All),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Aggregation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(aggregation) = Aggregation::builtin(s).or_else(|| plugin::registered(s)) {
+            return Ok(aggregation);
+        }
+        let names: Vec<String> = ["sum, mean, min, max, count, first, last, concat, sd, min_nd, max_nd, nunique, mode, any, all".to_string()]
+            .into_iter()
+            .chain(plugin::registered_names())
+            .collect();
+        Err(format!("Unknown aggregation '{}'. Must be one of {}", s, names.join(", ")))
+    }
+}
+
+// The separator placed between values combined with the concat aggregation.
+pub const CONCAT_SEP: &str = "; ";
+
+/// The prefix of a non-detect, a value below the detection limit that follows it, as in `<0.5`.
+pub const NONDETECT_PREFIX: &str = "<";
+
+/// The detection limit of a non-detect such as `<0.5`, or `None` if the value is not a non-detect.
+pub fn nondetect_limit(value: &str) -> Option<&str> {
+    value.trim().strip_prefix(NONDETECT_PREFIX).map(|limit| limit.trim())
+}
+
+/// A value written as a non-detect at its detection limit, as in `<0.5`. A value that is already a non-detect is kept.
+pub fn mark_nondetect(value: String) -> String {
+    match nondetect_limit(&value) {
+        Some(_) => value,
+        None => format!("{}{}", NONDETECT_PREFIX, value.trim()),
+    }
+}
+
+/// The number that stands for a non-detect in the arithmetic of the `sum`, `mean`, `min`, and `max` aggregations.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NdSubstitute {
+    /// Zero.
+    Zero,
+    /// Half of the detection limit.
+    Half,
+    /// The detection limit itself.
+    Limit,
+}
+
+impl FromStr for NdSubstitute {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "zero" => Ok(NdSubstitute::Zero),
+            "half" => Ok(NdSubstitute::Half),
+            "dl" => Ok(NdSubstitute::Limit),
+            _ => Err(format!("Unknown non-detect substitution '{}'. Must be one of zero, half, dl", s)),
+        }
+    }
+}
+
+impl NdSubstitute {
+    /// The number that stands for a value if it is a non-detect with a numeric detection limit, or `None` otherwise.
+    pub fn substitute(&self, value: &str) -> Option<String> {
+        let limit: f64 = nondetect_limit(value)?.parse().ok()?;
+        let number = match self {
+            NdSubstitute::Zero => 0.0,
+            NdSubstitute::Half => limit / 2.0,
+            NdSubstitute::Limit => limit,
+        };
+        Some(round_significant(number).to_string())
+    }
+}
+
+/// A value column, with the aggregation and output name it was given in its `--value` specification, if any.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueSpec {
+    /// The name (or pattern) of the input column.
+    pub column: String,
+    /// The aggregation of the column, which overrides any `--aggregate` specification.
+    pub aggregation: Option<Aggregation>,
+    /// The name of the value in the generated column names, instead of the column name.
+    pub label: Option<String>,
+}
+
+/// Parse a `--value` specification: a column name, optionally followed by `:aggregation` and then `:label`, such
+/// as `result:mean:avg_result` or `result:count`. A name that contains colons without ending with an aggregation
+/// (such as the regular expression `re:^result`) is kept whole.
+pub fn parse_value_spec(spec: &str) -> ValueSpec {
+    if let Some((rest, last)) = spec.rsplit_once(':') {
+        if let Some((column, aggregation)) = rest.rsplit_once(':') {
+            if let (false, Ok(aggregation)) = (column.is_empty(), aggregation.parse()) {
+                let label = Some(last.to_string()).filter(|l| !l.is_empty());
+                return ValueSpec { column: column.to_string(), aggregation: Some(aggregation), label };
+            }
+        }
+        if let (false, Ok(aggregation)) = (rest.is_empty(), last.parse()) {
+            return ValueSpec { column: rest.to_string(), aggregation: Some(aggregation), label: None };
+        }
+    }
+    ValueSpec { column: spec.to_string(), aggregation: None, label: None }
+}
+
+/// Expand each value specification into one value for each of the statistics, such as `mean`, `sd`, and `n`, so that
+/// the statistics are written in adjacent columns. Each is an aggregation, or `n` for the count of the values, and
+/// is labeled by its name, which is prefixed by the column name when there is more than one value column. Each
+/// expanded value is returned with the position of the specification it came from. Without statistics, the
+/// specifications are kept.
+pub fn expand_stats(specs: &[ValueSpec], stats: &[String]) -> Result<Vec<(usize, ValueSpec)>, String> {
+    if stats.is_empty() {
+        return Ok(specs.iter().cloned().enumerate().collect());
+    }
+    let mut expanded: Vec<(usize, ValueSpec)> = Vec::new();
+    for (i, spec) in specs.iter().enumerate() {
+        if spec.aggregation.is_some() || spec.label.is_some() {
+            return Err(format!("The value '{}' cannot have an aggregation or a label when its statistics are written", spec.column));
+        }
+        for stat in stats.iter().map(|s| s.trim()) {
+            let aggregation = match stat {
+                "n" => Aggregation::Count,
+                _ => stat.parse()?,
+            };
+            let label = match specs.len() {
+                1 => stat.to_string(),
+                _ => format!("{}_{}", spec.column, stat),
+            };
+            expanded.push((i, ValueSpec { column: spec.column.clone(), aggregation: Some(aggregation), label: Some(label) }));
+        }
+    }
+    Ok(expanded)
+}
+
+/// Determine the aggregation of each value column from the `--aggregate` specifications.
+/// A bare aggregation name (e.g. `sum`) applies to every value column, and a `column=aggregation`
+/// pair (e.g. `result=mean`) applies to a single value column. Value columns without a specification use `first`.
+pub fn parse_aggregations(specs: &[String], cell_values: &[String]) -> Result<Vec<Aggregation>, String> {
+    let mut aggregations = vec![Aggregation::First; cell_values.len()];
+    // Apply the bare aggregation names first so that column-specific pairs override them regardless of order
+    for spec in specs.iter().filter(|s| !s.contains('=')) {
+        let aggregation = spec.parse()?;
+        aggregations.iter_mut().for_each(|a| *a = aggregation);
+    }
+    for spec in specs.iter().filter(|s| s.contains('=')) {
+        let (column, aggregation) = spec.split_once('=').unwrap();
+        match cell_values.iter().position(|v| v == column.trim()) {
+            Some(i) => aggregations[i] = aggregation.parse()?,
+            None => return Err(format!("The aggregated column '{}' is not one of the value columns", column)),
+        }
+    }
+    Ok(aggregations)
+}
+
+// Parse every non-missing value as a number, failing on the first value that is not numeric.
+fn numbers(aggregation: Aggregation, values: &[Option<String>]) -> Result<Vec<f64>, String> {
+    values
+        .iter()
+        .flatten()
+        .map(|v| {
+            v.trim().parse::<f64>().map_err(|_| match nondetect_limit(v) {
+                Some(_) => format!("The non-detect '{}' cannot be aggregated with {:?} without a substitution for non-detects", v, aggregation),
+                None => format!("The value '{}' cannot be aggregated with {:?} because it is not a number", v, aggregation),
+            })
+        })
+        .collect()
+}
+
+// The boolean values of a cell, without its missing values. Besides `true` and `false`, in any case, `1` and `0` are
+// booleans, as are `yes` and `no`.
+fn booleans(aggregation: Aggregation, values: &[Option<String>]) -> Result<Vec<bool>, String> {
+    values
+        .iter()
+        .flatten()
+        .map(|v| match v.trim().to_lowercase().as_str() {
+            "true" | "1" | "yes" => Ok(true),
+            "false" | "0" | "no" => Ok(false),
+            _ => Err(format!("The value '{}' cannot be aggregated with {:?} because it is not a boolean", v, aggregation)),
+        })
+        .collect()
+}
+
+// The distinct values of a cell, without its missing values, in the order of their first appearance, with the number
+// of times each appears
+fn frequencies(values: &[Option<String>]) -> Vec<(&str, usize)> {
+    let mut frequencies: Vec<(&str, usize)> = Vec::new();
+    for value in values.iter().flatten() {
+        match frequencies.iter_mut().find(|(v, _)| *v == value.as_str()) {
+            Some((_, n)) => *n += 1,
+            None => frequencies.push((value, 1)),
+        }
+    }
+    frequencies
+}
+
+// Round a computed number to 15 significant digits, the precision of a double, so that the rounding error of the
+// arithmetic is not written, as in 0.30000000000000004 for the sum of 0.1 and 0.2
+pub(crate) fn round_significant(value: f64) -> f64 {
+    format!("{:.14e}", value).parse().unwrap_or(value)
+}
+
+// The smallest or largest value of a cell whose values may be non-detects, written as a number or as a non-detect at
+// its detection limit
+fn censored_extreme(aggregation: Aggregation, values: &[Option<String>]) -> Result<Option<String>, String> {
+    let (mut detected, mut limits): (Vec<f64>, Vec<f64>) = (Vec::new(), Vec::new());
+    for v in values.iter().flatten() {
+        let (text, numbers) = match nondetect_limit(v) {
+            Some(limit) => (limit, &mut limits),
+            None => (v.trim(), &mut detected),
+        };
+        numbers.push(text.parse::<f64>().map_err(|_| {
+            format!("The value '{}' cannot be aggregated with {:?} because it is not a number or a non-detect", v, aggregation)
+        })?);
+    }
+    let smallest = |numbers: &[f64]| numbers.iter().cloned().reduce(f64::min);
+    let largest = |numbers: &[f64]| numbers.iter().cloned().reduce(f64::max);
+    let nondetect = |limit: f64| format!("{}{}", NONDETECT_PREFIX, round_significant(limit));
+    Ok(match aggregation {
+        Aggregation::MinNd => match (smallest(&detected), smallest(&limits)) {
+            (Some(x), Some(limit)) if x < limit => Some(round_significant(x).to_string()),
+            (_, Some(limit)) => Some(nondetect(limit)),
+            (x, None) => x.map(|x| round_significant(x).to_string()),
+        },
+        _ => match (largest(&detected), largest(&limits)) {
+            (Some(x), _) => Some(round_significant(x).to_string()),
+            (None, limit) => limit.map(nondetect),
+        },
+    })
+}
+
+/// Combine the values of one cell, in input order, using the given aggregation.
+/// Missing values are ignored by every aggregation except `first` and `last`. Only `min_nd` and `max_nd` understand
+/// non-detects, such as `<0.5`.
+pub fn aggregate(aggregation: Aggregation, values: &[Option<String>]) -> Result<Option<String>, String> {
+    let result = match aggregation {
+        Aggregation::Custom(i) => match plugin::aggregator(i) {
+            Some((name, aggregator)) => aggregator.aggregate(values).map_err(|e| format!("The aggregation '{}' failed: {}", name, e))?,
+            None => return Err(format!("There is no registered aggregation {}", i)),
+        },
+        Aggregation::First => values.first().cloned().flatten(),
+        Aggregation::Last => values.last().cloned().flatten(),
+        Aggregation::Count => Some(values.iter().flatten().count().to_string()),
+        Aggregation::Concat => concat(values, CONCAT_SEP, false, false),
+        Aggregation::MinNd | Aggregation::MaxNd => censored_extreme(aggregation, values)?,
+        Aggregation::NUnique => Some(frequencies(values).len().to_string()),
+        Aggregation::Mode => frequencies(values)
+            .into_iter()
+            .fold(None, |mode: Option<(&str, usize)>, (v, n)| match mode {
+                Some((_, m)) if m >= n => mode,
+                _ => Some((v, n)),
+            })
+            .map(|(v, _)| v.to_string()),
+        Aggregation::Any | Aggregation::All => {
+            let booleans = booleans(aggregation, values)?;
+            match aggregation {
+                _ if booleans.is_empty() => None,
+                Aggregation::Any => Some(booleans.iter().any(|&b| b).to_string()),
+                _ => Some(booleans.iter().all(|&b| b).to_string()),
+            }
+        }
+        Aggregation::Sum | Aggregation::Mean | Aggregation::Min | Aggregation::Max | Aggregation::Sd => {
+            let numbers = numbers(aggregation, values)?;
+            let mean = || numbers.iter().sum::<f64>() / numbers.len() as f64;
+            if numbers.is_empty() || (aggregation == Aggregation::Sd && numbers.len() < 2) {
+                None
+            } else {
+                let value = match aggregation {
+                    Aggregation::Sum => numbers.iter().sum(),
+                    Aggregation::Mean => mean(),
+                    Aggregation::Min => numbers.iter().cloned().fold(f64::INFINITY, f64::min),
+                    Aggregation::Sd => {
+                        let mean = mean();
+                        (numbers.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (numbers.len() - 1) as f64).sqrt()
+                    }
+                    _ => numbers.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                };
+                Some(round_significant(value).to_string())
+            }
+        }
+    };
+    Ok(result)
+}
+
+/// Join the values of one cell with the separator, in input order or sorted, without the missing values. If `unique`,
+/// only the first of the values that are the same is kept.
+pub fn concat(values: &[Option<String>], sep: &str, unique: bool, sort: bool) -> Option<String> {
+    let mut present: Vec<&str> = values.iter().flatten().map(|v| v.as_str()).collect();
+    if sort {
+        present.sort();
+    }
+    if unique {
+        let mut distinct: Vec<&str> = Vec::new();
+        for value in present {
+            if !distinct.contains(&value) {
+                distinct.push(value);
+            }
+        }
+        present = distinct;
+    }
+    (!present.is_empty()).then(|| present.join(sep))
+}
+
+/// Combine the values of one cell like [`aggregate`], weighting each value by the weight of its input row, in the
+/// same order: `sum` is the sum of the weighted values, `mean` is their sum divided by the sum of their weights, and
+/// `count` is the sum of the weights of the values. The values with a missing weight are ignored by these
+/// aggregations, and the others are not weighted.
+pub fn weighted_aggregate(aggregation: Aggregation, values: &[Option<String>], weights: &[Option<String>]) -> Result<Option<String>, String> {
+    if !matches!(aggregation, Aggregation::Sum | Aggregation::Mean | Aggregation::Count) {
+        return aggregate(aggregation, values);
+    }
+    let mut pairs: Vec<(Option<f64>, f64)> = Vec::new();
+    for (value, weight) in values.iter().zip(weights) {
+        let (Some(value), Some(weight)) = (value, weight) else { continue };
+        let weight: f64 = weight.trim().parse().map_err(|_| format!("The weight '{}' is not a number", weight))?;
+        let number = match aggregation {
+            Aggregation::Count => None,
+            _ => Some(numbers(aggregation, std::slice::from_ref(&Some(value.clone())))?[0]),
+        };
+        pairs.push((number, weight));
+    }
+    if pairs.is_empty() {
+        return Ok(None);
+    }
+    let total_weight: f64 = pairs.iter().map(|(_, w)| w).sum();
+    let weighted_sum: f64 = pairs.iter().map(|(x, w)| x.unwrap_or_default() * w).sum();
+    let value = match aggregation {
+        Aggregation::Count => total_weight,
+        Aggregation::Sum => weighted_sum,
+        _ if total_weight == 0.0 => return Ok(None),
+        _ => weighted_sum / total_weight,
+    };
+    Ok(Some(round_significant(value).to_string()))
+}
+
+/// The default template of the statistics of a cell.
+pub const DEFAULT_STATS_TEMPLATE: &str = "{mean} ({sd}), n={n}";
+
+/// The placeholders of a statistics template, each replaced by the statistic of the same name of the values of a
+/// cell: the mean, the sample standard deviation, the number of values, the smallest and largest values, and the sum.
+pub const STATS_PLACEHOLDERS: [&str; 6] = ["mean", "sd", "n", "min", "max", "sum"];
+
+// The aggregation that computes a statistic of a statistics template
+fn stat_aggregation(name: &str) -> Option<Aggregation> {
+    match name {
+        "mean" => Some(Aggregation::Mean),
+        "sd" => Some(Aggregation::Sd),
+        "n" => Some(Aggregation::Count),
+        "min" => Some(Aggregation::Min),
+        "max" => Some(Aggregation::Max),
+        "sum" => Some(Aggregation::Sum),
+        _ => None,
+    }
+}
+
+/// Check that every `{name}` placeholder of a statistics template, such as `{mean} ({sd}), n={n}`, is one of the
+/// [`STATS_PLACEHOLDERS`].
+pub fn check_stats_template(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some((_, after)) = rest.split_once('{') {
+        let Some((name, after)) = after.split_once('}') else { break };
+        if stat_aggregation(name).is_none() {
+            return Err(format!(
+                "Unknown statistic '{{{}}}' in the statistics template. Must be one of {}",
+                name,
+                STATS_PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(", ")
+            ));
+        }
+        rest = after;
+    }
+    Ok(())
+}
+
+/// The statistics of the values of one cell written in a statistics template, with the statistics other than `n`
+/// written in the given format. The standard deviation of a single value is written as NA, and a cell without
+/// values is missing.
+pub fn stats_cell(template: &str, values: &[Option<String>], format: Option<&ValueFormat>) -> Result<Option<String>, String> {
+    if values.iter().flatten().next().is_none() {
+        return Ok(None);
+    }
+    let mut cell = template.to_string();
+    for name in STATS_PLACEHOLDERS {
+        let placeholder = format!("{{{}}}", name);
+        if !cell.contains(&placeholder) {
+            continue;
+        }
+        let aggregation = stat_aggregation(name).unwrap();
+        // The values must be numbers even when only their count is written
+        numbers(aggregation, values)?;
+        let value = match (aggregate(aggregation, values)?, format) {
+            (None, _) => "NA".to_string(),
+            (Some(v), Some(format)) if name != "n" => format.format(&v),
+            (Some(v), _) => v,
+        };
+        cell = cell.replace(&placeholder, &value);
+    }
+    Ok(Some(cell))
+}
+
+/// Check that each placeholder of a cell template is `{value}` or the name of an aggregation, such as `{count}`.
+pub fn check_cell_template(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some((_, after)) = rest.split_once('{') {
+        let Some((name, after)) = after.split_once('}') else { break };
+        if name != "value" && name.parse::<Aggregation>().is_err() {
+            return Err(format!("Unknown placeholder '{{{}}}' in the cell template. Must be {{value}} or an aggregation, such as {{count}}", name));
+        }
+        rest = after;
+    }
+    Ok(())
+}
+
+/// The values of one cell written in a cell template, such as `{value} ({count})`: `{value}` is replaced by the
+/// aggregation of the values, and each other placeholder by the aggregation that it names, as combined by `combine`.
+/// The numbers other than counts are written in the given format, a missing aggregation is written as NA, and a cell
+/// without values is missing.
+pub fn template_cell(
+    template: &str,
+    aggregation: Aggregation,
+    values: &[Option<String>],
+    format: Option<&ValueFormat>,
+    combine: impl Fn(Aggregation) -> Result<Option<String>, String>,
+) -> Result<Option<String>, String> {
+    if values.iter().flatten().next().is_none() {
+        return Ok(None);
+    }
+    let mut cell = String::new();
+    let mut rest = template;
+    while let Some((before, after)) = rest.split_once('{') {
+        let Some((name, after)) = after.split_once('}') else { break };
+        let placeholder = match name {
+            "value" => aggregation,
+            _ => name.parse().map_err(|_| format!("Unknown placeholder '{{{}}}' in the cell template", name))?,
+        };
+        let value = match (combine(placeholder)?, format) {
+            (None, _) => "NA".to_string(),
+            (Some(v), Some(format)) if !matches!(placeholder, Aggregation::Count | Aggregation::NUnique) => format.format(&v),
+            (Some(v), _) => v,
+        };
+        cell.push_str(before);
+        cell.push_str(&value);
+        rest = after;
+    }
+    cell.push_str(rest);
+    Ok(Some(cell))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that value specifications are split into columns, aggregations, and labels
+    #[test]
+    fn test_parse_value_spec() {
+        let spec = |column: &str, aggregation: Option<Aggregation>, label: Option<&str>| ValueSpec {
+            column: column.to_string(),
+            aggregation,
+            label: label.map(|l| l.to_string()),
+        };
+        assert_eq!(parse_value_spec("result"), spec("result", None, None));
+        assert_eq!(parse_value_spec("result:mean:avg_result"), spec("result", Some(Aggregation::Mean), Some("avg_result")));
+        assert_eq!(parse_value_spec("result:count"), spec("result", Some(Aggregation::Count), None));
+        assert_eq!(parse_value_spec("re:^result"), spec("re:^result", None, None));
+        assert_eq!(parse_value_spec("re:^conc_:max:peak"), spec("re:^conc_", Some(Aggregation::Max), Some("peak")));
+        assert_eq!(parse_value_spec("time:12:00"), spec("time:12:00", None, None));
+    }
+
+    // Test that each value column is expanded into one labeled value for each statistic
+    #[test]
+    fn test_expand_stats() {
+        let specs = vec![parse_value_spec("result")];
+        let stats: Vec<String> = ["mean", "sd", "n"].iter().map(|s| s.to_string()).collect();
+        let expanded = expand_stats(&specs, &stats).unwrap();
+        assert_eq!(expanded[1], (0, ValueSpec { column: "result".to_string(), aggregation: Some(Aggregation::Sd), label: Some("sd".to_string()) }));
+        assert_eq!(expanded[2].1.aggregation, Some(Aggregation::Count));
+        let specs = vec![parse_value_spec("result"), parse_value_spec("depth")];
+        assert_eq!(expand_stats(&specs, &stats).unwrap()[3].1.label, Some("depth_mean".to_string()));
+        assert_eq!(expand_stats(&specs, &[]).unwrap()[1], (1, specs[1].clone()));
+        assert!(expand_stats(&[parse_value_spec("result:max")], &stats).is_err());
+        assert!(expand_stats(&specs, &["median".to_string()]).is_err());
+    }
+
+    fn values(values: &[Option<&str>]) -> Vec<Option<String>> {
+        values.iter().map(|v| v.map(|s| s.to_string())).collect()
+    }
+
+    // Test every aggregation against the same set of cell values
+    #[test]
+    fn test_aggregate() {
+        let cell = values(&[Some("1.5"), None, Some("4"), Some("2.5")]);
+        let result = |a| aggregate(a, &cell).unwrap();
+        assert_eq!(result(Aggregation::Sum), Some("8".to_string()));
+        assert_eq!(result(Aggregation::Mean), Some("2.66666666666667".to_string()));
+        assert_eq!(result(Aggregation::Min), Some("1.5".to_string()));
+        assert_eq!(result(Aggregation::Max), Some("4".to_string()));
+        assert_eq!(result(Aggregation::Count), Some("3".to_string()));
+        assert_eq!(result(Aggregation::First), Some("1.5".to_string()));
+        assert_eq!(result(Aggregation::Last), Some("2.5".to_string()));
+        assert_eq!(result(Aggregation::Concat), Some("1.5; 4; 2.5".to_string()));
+        assert_eq!(result(Aggregation::Sd), Some("1.25830573921179".to_string()));
+        assert_eq!(aggregate(Aggregation::Sd, &values(&[Some("1.5")])).unwrap(), None);
+    }
+
+    // Test that the values are joined with the separator, without repeats and sorted if asked
+    #[test]
+    fn test_concat() {
+        let cell = values(&[Some("turbid"), None, Some("algae"), Some("turbid")]);
+        assert_eq!(concat(&cell, " | ", false, false), Some("turbid | algae | turbid".to_string()));
+        assert_eq!(concat(&cell, "; ", true, false), Some("turbid; algae".to_string()));
+        assert_eq!(concat(&cell, "; ", true, true), Some("algae; turbid".to_string()));
+        assert_eq!(concat(&values(&[None]), "; ", false, false), None);
+    }
+
+    // Test the distinct count and the most frequent value, and that the boolean aggregations reject other values
+    #[test]
+    fn test_aggregate_categorical() {
+        let cell = values(&[Some("J"), None, Some("U"), Some("U"), Some("J")]);
+        assert_eq!(aggregate(Aggregation::NUnique, &cell).unwrap(), Some("2".to_string()));
+        assert_eq!(aggregate(Aggregation::Mode, &cell).unwrap(), Some("J".to_string()));
+        assert_eq!(aggregate(Aggregation::Mode, &values(&[None])).unwrap(), None);
+        assert_eq!(aggregate(Aggregation::NUnique, &values(&[None])).unwrap(), Some("0".to_string()));
+
+        let flags = values(&[Some("true"), None, Some("False")]);
+        assert_eq!(aggregate(Aggregation::Any, &flags).unwrap(), Some("true".to_string()));
+        assert_eq!(aggregate(Aggregation::All, &flags).unwrap(), Some("false".to_string()));
+        assert_eq!(aggregate(Aggregation::All, &values(&[Some("1"), Some("yes")])).unwrap(), Some("true".to_string()));
+        assert_eq!(aggregate(Aggregation::Any, &values(&[None])).unwrap(), None);
+        assert!(aggregate(Aggregation::Any, &cell).is_err());
+    }
+
+    // Test that the sum, mean, and count are weighted, and that the values without weights are ignored
+    #[test]
+    fn test_weighted_aggregate() {
+        let cell = values(&[Some("1.5"), None, Some("4"), Some("2.5")]);
+        let weights = values(&[Some("2"), Some("1"), Some("0.5"), None]);
+        let result = |a| weighted_aggregate(a, &cell, &weights).unwrap();
+        assert_eq!(result(Aggregation::Sum), Some("5".to_string()));
+        assert_eq!(result(Aggregation::Mean), Some("2".to_string()));
+        assert_eq!(result(Aggregation::Count), Some("2.5".to_string()));
+        assert_eq!(result(Aggregation::Max), Some("4".to_string()));
+        assert!(weighted_aggregate(Aggregation::Sum, &cell, &values(&[Some("heavy"), None, None, None])).is_err());
+    }
+
+    // Test that the statistics of a cell are written in the template, in the value format
+    #[test]
+    fn test_stats_cell() {
+        let cell = values(&[Some("1.5"), None, Some("4"), Some("2.5")]);
+        let format: ValueFormat = "%.2f".parse().unwrap();
+        assert_eq!(stats_cell(DEFAULT_STATS_TEMPLATE, &cell, Some(&format)).unwrap(), Some("2.67 (1.26), n=3".to_string()));
+        assert_eq!(stats_cell("{min}–{max}", &cell, None).unwrap(), Some("1.5–4".to_string()));
+        assert_eq!(stats_cell(DEFAULT_STATS_TEMPLATE, &values(&[Some("2")]), None).unwrap(), Some("2 (NA), n=1".to_string()));
+        assert_eq!(stats_cell(DEFAULT_STATS_TEMPLATE, &values(&[None]), None).unwrap(), None);
+        assert!(stats_cell("{n}", &values(&[Some("ND")]), None).is_err());
+        assert!(check_stats_template("{mean} ± {sd}").is_ok());
+        assert!(check_stats_template("{median}").is_err());
+    }
+
+    // Test that the cell template writes the aggregation of the cell and the other aggregations that it names
+    #[test]
+    fn test_template_cell() {
+        let cell = values(&[Some("1.5"), None, Some("4"), Some("2.5")]);
+        let combine = |a| aggregate(a, &cell);
+        let format: ValueFormat = "%.1f".parse().unwrap();
+        let result = template_cell("{value} ({count})", Aggregation::Mean, &cell, Some(&format), combine).unwrap();
+        assert_eq!(result, Some("2.7 (3)".to_string()));
+        let result = template_cell("{min}-{max}, sd {sd}", Aggregation::First, &cell, None, combine).unwrap();
+        assert_eq!(result, Some("1.5-4, sd 1.25830573921179".to_string()));
+        let single = values(&[Some("2")]);
+        let result = template_cell("{value} ± {sd}", Aggregation::Mean, &single, None, |a| aggregate(a, &single)).unwrap();
+        assert_eq!(result, Some("2 ± NA".to_string()));
+        assert_eq!(template_cell("{value}", Aggregation::Mean, &values(&[None]), None, combine).unwrap(), None);
+        assert!(check_cell_template("{value} (n={count})").is_ok());
+        assert!(check_cell_template("{median}").is_err());
+    }
+
+    // Test that the rounding error of the arithmetic is not written
+    #[test]
+    fn test_aggregate_rounding() {
+        let cell = values(&[Some("0.1"), Some("0.2")]);
+        assert_eq!(aggregate(Aggregation::Sum, &cell).unwrap(), Some("0.3".to_string()));
+    }
+
+    // Test that numeric aggregations reject values that are not numbers
+    #[test]
+    fn test_aggregate_not_numeric() {
+        let cell = values(&[Some("1.5"), Some("ND")]);
+        assert!(aggregate(Aggregation::Sum, &cell).is_err());
+        assert_eq!(aggregate(Aggregation::Concat, &cell).unwrap(), Some("1.5; ND".to_string()));
+    }
+
+    // Test that the non-detect aggregations compare detection limits with detected values, and that non-detects are
+    // substituted by numbers
+    #[test]
+    fn test_aggregate_nondetects() {
+        let cell = values(&[Some("<0.5"), Some("1.5"), Some("< 2"), None]);
+        assert_eq!(aggregate(Aggregation::MaxNd, &cell).unwrap(), Some("1.5".to_string()));
+        assert_eq!(aggregate(Aggregation::MinNd, &cell).unwrap(), Some("<0.5".to_string()));
+        let nondetects = values(&[Some("<0.5"), Some("<2")]);
+        assert_eq!(aggregate(Aggregation::MaxNd, &nondetects).unwrap(), Some("<2".to_string()));
+        let detected = values(&[Some("0.2"), Some("<0.5")]);
+        assert_eq!(aggregate(Aggregation::MinNd, &detected).unwrap(), Some("0.2".to_string()));
+        let error = aggregate(Aggregation::Sum, &cell).unwrap_err();
+        assert!(error.starts_with("The non-detect '<0.5'"), "{}", error);
+
+        assert_eq!(mark_nondetect("0.5".to_string()), "<0.5");
+        assert_eq!(mark_nondetect("<0.5".to_string()), "<0.5");
+        assert_eq!(NdSubstitute::Half.substitute("<0.5"), Some("0.25".to_string()));
+        assert_eq!(NdSubstitute::Zero.substitute("<0.5"), Some("0".to_string()));
+        assert_eq!(NdSubstitute::Limit.substitute("< 0.5"), Some("0.5".to_string()));
+        assert_eq!(NdSubstitute::Half.substitute("1.5"), None);
+    }
+
+    // Test that column-specific aggregations override a bare aggregation name
+    #[test]
+    fn test_parse_aggregations() {
+        let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        assert_eq!(
+            parse_aggregations(&strings(&["result=mean", "sum"]), &strings(&["result", "count"])).unwrap(),
+            vec![Aggregation::Mean, Aggregation::Sum]
+        );
+        assert_eq!(
+            parse_aggregations(&[], &strings(&["result"])).unwrap(),
+            vec![Aggregation::First]
+        );
+        assert!(parse_aggregations(&strings(&["median"]), &strings(&["result"])).is_err());
+        assert!(parse_aggregations(&strings(&["other=sum"]), &strings(&["result"])).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Binning: replacing the values of continuous columns, such as depths or dates, with the bins that they fall in,
+//! so that they make a manageable set of row or column headers.
+
+use polars::prelude::*;
+use std::str::FromStr;
+
+/// The bins of a column's values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bin {
+    /// Numbers in bins of this width, starting at zero, each written as its lower bound: with a width of 10, the
+    /// numbers from 10 up to (but not including) 20 become 10.
+    Width(f64),
+    /// Dates in calendar months, written as e.g. `2024-03`.
+    Month,
+    /// Dates in calendar quarters, written as e.g. `2024-Q1`.
+    Quarter,
+    /// Dates in calendar years, written as e.g. `2024`.
+    Year,
+}
+
+impl FromStr for Bin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "month" => Ok(Bin::Month),
+            "quarter" => Ok(Bin::Quarter),
+            "year" => Ok(Bin::Year),
+            other => match other.parse::<f64>() {
+                Ok(width) if width > 0.0 && width.is_finite() => Ok(Bin::Width(width)),
+                _ => Err(format!("Unknown bin '{}'. Must be a positive bin width or one of month, quarter, year", s)),
+            },
+        }
+    }
+}
+
+/// Parse the `--bin` specifications, each a `column=bin` pair such as `depth=10` or `date=month`.
+pub fn parse_bins(specs: &[String]) -> Result<Vec<(String, Bin)>, String> {
+    specs
+        .iter()
+        .map(|spec| match spec.split_once('=') {
+            Some((column, bin)) if !column.trim().is_empty() => Ok((column.trim().to_string(), bin.parse()?)),
+            _ => Err(format!("The bin '{}' must be a column=bin pair, e.g. depth=10 or date=month", spec)),
+        })
+        .collect()
+}
+
+/// Plan the replacement of the values of each of the columns with their bins. Integer columns binned by a whole
+/// width stay integers. Text columns binned by dates are first converted to dates, and a value that cannot be
+/// converted is an error.
+pub fn bin_lazy(lf: LazyFrame, bins: &[(String, Bin)]) -> PolarsResult<LazyFrame> {
+    if bins.is_empty() {
+        return Ok(lf);
+    }
+    let schema = lf.schema()?;
+    let exprs = bins
+        .iter()
+        .map(|(name, bin)| {
+            let dtype = schema.try_get(name)?;
+            let expr = match bin {
+                Bin::Width(width) => {
+                    let lower = (col(name).cast(DataType::Float64) / lit(*width)).floor() * lit(*width);
+                    match dtype.is_integer() && width.fract() == 0.0 {
+                        true => lower.cast(dtype.clone()),
+                        false => lower,
+                    }
+                }
+                _ => {
+                    let dates = match dtype {
+                        DataType::String => col(name).strict_cast(DataType::Date),
+                        _ => col(name),
+                    };
+                    match bin {
+                        Bin::Month => dates.dt().strftime("%Y-%m"),
+                        Bin::Quarter => concat_str(
+                            [dates.clone().dt().year().cast(DataType::String), lit("-Q"), dates.dt().quarter().cast(DataType::String)],
+                            "",
+                            false,
+                        ),
+                        _ => dates.dt().year().cast(DataType::String),
+                    }
+                }
+            };
+            Ok(expr.alias(name))
+        })
+        .collect::<PolarsResult<Vec<Expr>>>()?;
+    Ok(lf.with_columns(exprs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that the bin specifications are parsed
+    #[test]
+    fn test_parse_bins() {
+        let specs = vec!["depth=2.5".to_string(), "date = Quarter".to_string()];
+        assert_eq!(parse_bins(&specs).unwrap(), vec![("depth".to_string(), Bin::Width(2.5)), ("date".to_string(), Bin::Quarter)]);
+        assert!(parse_bins(&["depth=0".to_string()]).is_err());
+        assert!(parse_bins(&["date=week".to_string()]).is_err());
+        assert!(parse_bins(&["10".to_string()]).is_err());
+    }
+
+    // Test that numbers are binned by their lower bounds and dates by their calendar periods
+    #[test]
+    fn test_bin_lazy() {
+        let df = df!(
+            "depth" => &[0, 9, 10, 25],
+            "ph" => &[6.9, 7.2, 7.4, 8.1],
+            "date" => &["2023-12-31", "2024-01-01", "2024-03-31", "2024-04-01"],
+        )
+        .unwrap();
+        let bins = parse_bins(&["depth=10".to_string(), "ph=0.5".to_string(), "date=quarter".to_string()]).unwrap();
+        let binned = bin_lazy(df.clone().lazy(), &bins).unwrap().collect().unwrap();
+        let expected = df!(
+            "depth" => &[0, 0, 10, 20],
+            "ph" => &[6.5, 7.0, 7.0, 8.0],
+            "date" => &["2023-Q4", "2024-Q1", "2024-Q1", "2024-Q2"],
+        )
+        .unwrap();
+        assert_eq!(binned, expected);
+
+        let months = bin_lazy(df.clone().lazy(), &[("date".to_string(), Bin::Month)]).unwrap().collect().unwrap();
+        assert_eq!(months.column("date").unwrap(), &Series::new("date", &["2023-12", "2024-01", "2024-03", "2024-04"]));
+        assert!(bin_lazy(df.lazy(), &[("site".to_string(), Bin::Year)]).is_err());
+    }
+}
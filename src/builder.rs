@@ -0,0 +1,182 @@
+//! A typed builder of crosstabs, for embedding xtab in other programs: it takes a polars `DataFrame` and returns the
+//! crosstab as a `DataFrame`, together with a report of the warnings that the command line would print.
+
+use crate::aggregate::Aggregation;
+use crate::{Crosstab, HeaderNames, XtabConfig};
+use anyhow::Result;
+use polars::prelude::DataFrame;
+
+/// What happened while a crosstab was built by a [`CrosstabBuilder`]: the sizes of the input and output, and the
+/// warnings about the input data, such as the cells with more than one value and no aggregation to combine them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CrosstabReport {
+    /// The number of input rows.
+    pub input_rows: usize,
+    /// The number of output rows.
+    pub output_rows: usize,
+    /// The number of output columns, including the row key columns.
+    pub output_columns: usize,
+    /// The number of input rows left out because they have a missing row or column key value.
+    pub dropped_rows: usize,
+    /// The warnings, one for each problem, in the words of the command line.
+    pub warnings: Vec<String>,
+}
+
+/// Builds crosstabs of DataFrames from a typed configuration.
+///
+/// The row keys, column keys, and value columns are required; without an aggregation, the first value of each cell
+/// is used and the cells with more than one value are reported as warnings. Every other option of an
+/// [`XtabConfig`] can be given by starting from one with [`CrosstabBuilder::from`].
+///
+/// ```no_run
+/// use xtab::aggregate::Aggregation;
+/// use xtab::{read_csv, CrosstabBuilder, ReadConfig};
+///
+/// let df = read_csv("results.csv", &ReadConfig::default())?;
+/// let (crosstab, report) = CrosstabBuilder::new()
+///     .row_keys(&["site"])
+///     .col_keys(&["date"])
+///     .values(&["result"])
+///     .aggregation(Aggregation::Mean)
+///     .fill("0")
+///     .build(&df)?;
+/// for warning in &report.warnings {
+///     eprintln!("{}", warning);
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrosstabBuilder {
+    config: XtabConfig,
+    aggregation: Option<Aggregation>,
+}
+
+impl Default for CrosstabBuilder {
+    fn default() -> Self {
+        CrosstabBuilder::from(XtabConfig::new::<&str>(&[], &[], &[]))
+    }
+}
+
+impl From<XtabConfig> for CrosstabBuilder {
+    /// A builder that starts from the configuration, keeping its aggregations unless one is chosen.
+    fn from(config: XtabConfig) -> Self {
+        CrosstabBuilder { config, aggregation: None }
+    }
+}
+
+impl CrosstabBuilder {
+    /// A builder without any columns, which keeps the first value of each cell and writes one row of column names.
+    pub fn new() -> CrosstabBuilder {
+        CrosstabBuilder::default()
+    }
+
+    /// The columns whose unique values make the output rows.
+    pub fn row_keys<S: AsRef<str>>(mut self, columns: &[S]) -> CrosstabBuilder {
+        self.config.row_headers = columns.iter().map(|c| c.as_ref().to_string()).collect();
+        self
+    }
+
+    /// The columns whose unique values make the groups of generated columns.
+    pub fn col_keys<S: AsRef<str>>(mut self, columns: &[S]) -> CrosstabBuilder {
+        self.config.col_headers = columns.iter().map(|c| c.as_ref().to_string()).collect();
+        self
+    }
+
+    /// The columns whose values fill the cells, or none to count the input rows of each cell.
+    pub fn values<S: AsRef<str>>(mut self, columns: &[S]) -> CrosstabBuilder {
+        self.config.cell_values = columns.iter().map(|c| c.as_ref().to_string()).collect();
+        self
+    }
+
+    /// The aggregation that combines the values of every cell with more than one input row.
+    pub fn aggregation(mut self, aggregation: Aggregation) -> CrosstabBuilder {
+        self.aggregation = Some(aggregation);
+        self
+    }
+
+    /// How the names of the generated columns are built from the column key values and the value column names.
+    pub fn header_format(mut self, header_names: HeaderNames) -> CrosstabBuilder {
+        self.config.header_names = header_names;
+        self
+    }
+
+    /// The value of the cells without input data, which are otherwise null.
+    pub fn fill<S: Into<String>>(mut self, fill: S) -> CrosstabBuilder {
+        self.config.fill = Some(fill.into());
+        self
+    }
+
+    /// The configuration of the crosstabs that the builder builds.
+    pub fn config(&self) -> XtabConfig {
+        let mut config = self.config.clone();
+        match self.aggregation {
+            Some(aggregation) => config.aggregations = vec![aggregation; config.cell_values.len()],
+            None if config.aggregations.len() != config.cell_values.len() => {
+                config.aggregations = vec![Aggregation::First; config.cell_values.len()]
+            }
+            None => {}
+        }
+        config
+    }
+
+    /// Build the crosstab of the DataFrame, as a DataFrame with one row of column names (see
+    /// [`Crosstab::to_dataframe`]), with the report of the sizes and warnings.
+    pub fn build(&self, df: &DataFrame) -> Result<(DataFrame, CrosstabReport)> {
+        let config = self.config();
+        let crosstab = Crosstab::new(df, &config)?;
+        let output = crosstab.to_dataframe()?;
+        let mut warnings: Vec<String> = Vec::new();
+        // The values of a cell are combined when an aggregation was chosen, rather than the default of the first value
+        let aggregated = self.aggregation.is_some()
+            || config.cell_values.is_empty()
+            || config.stats_template.is_some()
+            || config.aggregations.iter().any(|a| *a != Aggregation::First);
+        if !crosstab.duplicates().is_empty() && !aggregated {
+            warnings.push(format!(
+                "Multiple values were found for {} row/column combination(s); only the first value of each was used",
+                crosstab.duplicates().len()
+            ));
+        }
+        if crosstab.dropped_rows() > 0 {
+            warnings.push(format!("{} input row(s) with a missing row or column key value were left out", crosstab.dropped_rows()));
+        }
+        let report = CrosstabReport {
+            input_rows: df.height(),
+            output_rows: output.height(),
+            output_columns: output.width(),
+            dropped_rows: crosstab.dropped_rows(),
+            warnings,
+        };
+        Ok((output, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NullKeys;
+    use polars::prelude::*;
+
+    // Test that the builder pivots the DataFrame and reports the duplicates that it did not aggregate
+    #[test]
+    fn test_build() {
+        let df = df!(
+            "site" => &[Some("A01"), Some("A01"), Some("B02"), None],
+            "date" => &["d1", "d1", "d2", "d1"],
+            "result" => &[1.0, 3.0, 4.0, 5.0],
+        )
+        .unwrap();
+        let builder = CrosstabBuilder::new().row_keys(&["site"]).col_keys(&["date"]).values(&["result"]);
+        let (output, report) = builder.clone().fill("0").build(&df).unwrap();
+        assert_eq!(output.get_column_names(), vec!["site", "d1_result", "d2_result"]);
+        assert_eq!(report.output_rows, 3);
+        assert_eq!(report.warnings.len(), 1);
+
+        let config = XtabConfig { null_keys: NullKeys::Drop, ..builder.config() };
+        let header_names = HeaderNames { separator: ".".to_string(), template: None };
+        let (output, report) = CrosstabBuilder::from(config).aggregation(Aggregation::Sum).header_format(header_names).build(&df).unwrap();
+        assert_eq!(output.column("d1.result").unwrap().f64().unwrap().get(0), Some(4.0));
+        assert_eq!(report.dropped_rows, 1);
+        assert_eq!(report.warnings, vec!["1 input row(s) with a missing row or column key value were left out"]);
+    }
+}
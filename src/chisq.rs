@@ -0,0 +1,141 @@
+//! The chi-square test of the independence of the rows and columns of a contingency table, such as the counts of a
+//! survey crosstab, with Cramér's V as the strength of their association.
+
+use serde_json::{json, Value};
+
+/// The result of a chi-square test of independence.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChiSquare {
+    /// The chi-square statistic.
+    pub statistic: f64,
+    /// The degrees of freedom, (rows - 1) × (columns - 1).
+    pub df: usize,
+    /// The probability of a statistic at least this large if the rows and columns are independent.
+    pub p_value: f64,
+    /// Cramér's V, between 0 for no association and 1 for complete association.
+    pub cramers_v: f64,
+}
+
+impl ChiSquare {
+    /// The result as a JSON object.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "chi_square": self.statistic,
+            "df": self.df,
+            "p_value": self.p_value,
+            "cramers_v": self.cramers_v,
+        })
+    }
+}
+
+/// Test the independence of the rows and columns of a table of counts. The rows and columns whose counts are all 0
+/// are left out, and a table with fewer than two of the others, either way, cannot be tested.
+pub fn chi_square(table: &[Vec<f64>]) -> Option<ChiSquare> {
+    let n_cols = table.iter().map(|row| row.len()).max().unwrap_or(0);
+    let count = |r: usize, c: usize| table[r].get(c).copied().unwrap_or_default();
+    let row_sums: Vec<f64> = (0..table.len()).map(|r| (0..n_cols).map(|c| count(r, c)).sum()).collect();
+    let col_sums: Vec<f64> = (0..n_cols).map(|c| (0..table.len()).map(|r| count(r, c)).sum()).collect();
+    let rows: Vec<usize> = (0..table.len()).filter(|&r| row_sums[r] > 0.0).collect();
+    let cols: Vec<usize> = (0..n_cols).filter(|&c| col_sums[c] > 0.0).collect();
+    if rows.len() < 2 || cols.len() < 2 {
+        return None;
+    }
+    let total: f64 = row_sums.iter().sum();
+    let mut statistic = 0.0;
+    for &r in &rows {
+        for &c in &cols {
+            let expected = row_sums[r] * col_sums[c] / total;
+            statistic += (count(r, c) - expected).powi(2) / expected;
+        }
+    }
+    let df = (rows.len() - 1) * (cols.len() - 1);
+    Some(ChiSquare {
+        statistic,
+        df,
+        p_value: upper_gamma(df as f64 / 2.0, statistic / 2.0),
+        cramers_v: (statistic / (total * (rows.len().min(cols.len()) - 1) as f64)).sqrt(),
+    })
+}
+
+// The natural logarithm of the gamma function of a positive number, by the Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum: f64 = COEFFICIENTS[0] + COEFFICIENTS[1..].iter().enumerate().map(|(i, c)| c / (x + i as f64 + 1.0)).sum::<f64>();
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+// The regularized upper incomplete gamma function Q(a, x), the probability that a chi-square variable with 2a degrees
+// of freedom exceeds 2x: by its series for small x, and by its continued fraction otherwise
+fn upper_gamma(a: f64, x: f64) -> f64 {
+    const EPSILON: f64 = 1e-15;
+    const TINY: f64 = 1e-300;
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let scale = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        return (1.0 - sum * scale).max(0.0);
+    }
+    let mut b = x + 1.0 - a;
+    let (mut c, mut d) = (1.0 / TINY, 1.0 / b);
+    let mut fraction = d;
+    for i in 1..1000 {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        d = if d.abs() < TINY { TINY } else { d };
+        c = b + an / c;
+        c = if c.abs() < TINY { TINY } else { c };
+        d = 1.0 / d;
+        fraction *= d * c;
+        if (d * c - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    scale * fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test the statistic, p-value, and Cramér's V of tables with known results
+    #[test]
+    fn test_chi_square() {
+        let result = chi_square(&[vec![10.0, 20.0], vec![30.0, 40.0]]).unwrap();
+        assert!((result.statistic - 0.793650793650794).abs() < 1e-12);
+        assert_eq!(result.df, 1);
+        assert!((result.p_value - 0.372998483613487).abs() < 1e-9);
+        assert!((result.cramers_v - 0.0890870806374748).abs() < 1e-12);
+
+        // With two degrees of freedom, the p-value is exp(-statistic / 2)
+        let result = chi_square(&[vec![20.0, 5.0, 0.0], vec![5.0, 20.0, 0.0], vec![10.0, 10.0, 0.0]]).unwrap();
+        assert_eq!(result.df, 2);
+        assert!((result.p_value - (-result.statistic / 2.0).exp()).abs() < 1e-12);
+        assert!((upper_gamma(10.0, 30.0) - 7.121750862815577e-6).abs() < 1e-16);
+        assert!((upper_gamma(10.0, 3.0) - 0.9988975118698844).abs() < 1e-12);
+
+        assert_eq!(chi_square(&[vec![1.0, 2.0]]), None);
+    }
+}
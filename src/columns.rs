@@ -0,0 +1,126 @@
+//! The matching of requested column names with the names of the input columns, which tolerates differences of
+//! capitalization and surrounding whitespace, and suggests the nearest name when there is no match. Many columns
+//! can be requested at once with a glob pattern such as `conc_*`, or a regular expression such as `re:^result_\d+$`.
+
+use regex::Regex;
+
+/// The prefix of a requested column name that is a regular expression.
+pub const REGEX_PREFIX: &str = "re:";
+
+/// Whether a requested column name is a pattern that may match many input columns: a regular expression with the
+/// [`REGEX_PREFIX`], or a glob pattern with a `*` or `?` wildcard.
+pub fn is_pattern(name: &str) -> bool {
+    name.starts_with(REGEX_PREFIX) || name.contains(['*', '?'])
+}
+
+/// The input columns, in input order, whose whole names match a pattern. In a glob pattern, `*` matches any
+/// characters and `?` matches one character.
+pub fn match_pattern<'a>(col_names: &[&'a str], pattern: &str) -> Result<Vec<&'a str>, String> {
+    let expression = match pattern.strip_prefix(REGEX_PREFIX) {
+        Some(expression) => expression.to_string(),
+        None => {
+            let escape = |part: &str| part.split('?').map(regex::escape).collect::<Vec<String>>().join(".");
+            format!("^{}$", pattern.split('*').map(escape).collect::<Vec<String>>().join(".*"))
+        }
+    };
+    let regex = Regex::new(&expression).map_err(|e| format!("The column pattern '{}' is invalid: {}", pattern, e))?;
+    Ok(col_names.iter().filter(|c| regex.is_match(c)).copied().collect())
+}
+
+/// The input column with the requested name: the column with exactly that name, or else the only column whose name
+/// is the same apart from capitalization and surrounding whitespace, or `None` if there is no such column.
+pub fn find_column<'a>(col_names: &[&'a str], name: &str) -> Option<&'a str> {
+    if let Some(exact) = col_names.iter().find(|c| **c == name) {
+        return Some(exact);
+    }
+    let folded = fold(name);
+    let mut loose = col_names.iter().filter(|c| fold(c) == folded);
+    match (loose.next(), loose.next()) {
+        (Some(column), None) => Some(column),
+        _ => None,
+    }
+}
+
+/// The input column whose name is nearest to the requested name by edit distance, if it is near enough to be a
+/// likely misspelling: within a third of the length of the name, and at least two edits.
+pub fn suggest_column<'a>(col_names: &[&'a str], name: &str) -> Option<&'a str> {
+    let folded = fold(name);
+    let limit = (folded.chars().count() / 3).max(2);
+    col_names
+        .iter()
+        .map(|c| (edit_distance(&fold(c), &folded), *c))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, c)| c)
+}
+
+/// Describe a requested column of the given kind (e.g. "row header") that is not in the input, with the nearest
+/// input column name as a suggestion if there is one.
+pub fn describe_missing(col_names: &[&str], kind: &str, name: &str) -> String {
+    match suggest_column(col_names, name) {
+        Some(suggestion) => format!("The {} column '{}' is not in the input file; did you mean `{}`?", kind, name, suggestion),
+        None => format!("The {} column '{}' is not in the input file", kind, name),
+    }
+}
+
+// A column name without surrounding whitespace, in lowercase
+fn fold(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+// The number of single-character insertions, deletions, and substitutions that change one string into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that names are matched apart from capitalization and whitespace, unless the match is ambiguous
+    #[test]
+    fn test_find_column() {
+        let col_names = ["Sample_Date", "site", "Site ", "result"];
+        assert_eq!(find_column(&col_names, "Sample_Date"), Some("Sample_Date"));
+        assert_eq!(find_column(&col_names, " sample_date"), Some("Sample_Date"));
+        assert_eq!(find_column(&col_names, "site"), Some("site"));
+        assert_eq!(find_column(&col_names, "SITE"), None);
+        assert_eq!(find_column(&col_names, "date"), None);
+    }
+
+    // Test that glob patterns match whole names and that regular expressions match anywhere unless anchored
+    #[test]
+    fn test_match_pattern() {
+        let col_names = ["site", "conc_Pb", "conc_Zn", "conc.x", "result_1", "result_12", "result_x"];
+        assert!(is_pattern("conc_*") && is_pattern("re:^conc") && !is_pattern("conc_Pb"));
+        assert_eq!(match_pattern(&col_names, "conc_*").unwrap(), vec!["conc_Pb", "conc_Zn"]);
+        assert_eq!(match_pattern(&col_names, "conc?x").unwrap(), vec!["conc.x"]);
+        assert_eq!(match_pattern(&col_names, r"re:^result_\d+$").unwrap(), vec!["result_1", "result_12"]);
+        assert_eq!(match_pattern(&col_names, "re:_").unwrap().len(), 5);
+        assert!(match_pattern(&col_names, "re:(").is_err());
+    }
+
+    // Test that misspelled names get the nearest column name as a suggestion
+    #[test]
+    fn test_suggest_column() {
+        let col_names = ["Sample_Date", "site", "result"];
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(suggest_column(&col_names, "SampleDate"), Some("Sample_Date"));
+        assert_eq!(suggest_column(&col_names, "reslt"), Some("result"));
+        assert_eq!(suggest_column(&col_names, "analyte"), None);
+        assert_eq!(
+            describe_missing(&col_names, "column header", "sample-dt"),
+            "The column header column 'sample-dt' is not in the input file; did you mean `Sample_Date`?"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Transparent gzip and zstd compression of input and output files.
+
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The compression of an input or output file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    /// Not compressed.
+    Uncompressed,
+    /// Compressed with gzip.
+    Gzip,
+    /// Compressed with Zstandard.
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "none" => Ok(Compression::Uncompressed),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            _ => Err(format!("Unknown compression '{}'. Must be one of none, gzip, zstd", s)),
+        }
+    }
+}
+
+impl Compression {
+    /// The compression implied by a file's extension: gzip for `.gz` files, Zstandard for `.zst` files,
+    /// and uncompressed otherwise.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Compression {
+        let extension = path.as_ref().extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+        match extension.as_deref() {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::Uncompressed,
+        }
+    }
+}
+
+/// The path without its compression extension, so that the extension before it (as in `results.csv.gz`)
+/// determines the format of the file.
+pub fn uncompressed_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+    match Compression::from_path(path) {
+        Compression::Uncompressed => path.to_path_buf(),
+        _ => path.with_extension(""),
+    }
+}
+
+// The error of zstd compression when xtab is built without the native feature, as for WebAssembly
+#[cfg(not(feature = "native"))]
+fn without_zstd() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "xtab was built without support for zstd compression")
+}
+
+/// Wrap a reader so that it reads the decompressed data.
+pub fn decoder<'a, R: Read + 'a>(compression: Compression, reader: R) -> io::Result<Box<dyn Read + 'a>> {
+    Ok(match compression {
+        Compression::Uncompressed => Box::new(reader),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        #[cfg(feature = "native")]
+        Compression::Zstd => Box::new(zstd::Decoder::new(reader)?),
+        #[cfg(not(feature = "native"))]
+        Compression::Zstd => return Err(without_zstd()),
+    })
+}
+
+/// A writer that compresses the data written to it. [`Encoder::finish`] must be called once all of the data
+/// has been written, to write the end of the compressed stream.
+#[cfg_attr(not(feature = "native"), allow(clippy::large_enum_variant))]
+pub enum Encoder<W: Write> {
+    Uncompressed(W),
+    Gzip(GzEncoder<W>),
+    #[cfg(feature = "native")]
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(compression: Compression, writer: W) -> io::Result<Encoder<W>> {
+        Ok(match compression {
+            Compression::Uncompressed => Encoder::Uncompressed(writer),
+            Compression::Gzip => Encoder::Gzip(GzEncoder::new(writer, flate2::Compression::default())),
+            #[cfg(feature = "native")]
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(writer, 0)?),
+            #[cfg(not(feature = "native"))]
+            Compression::Zstd => return Err(without_zstd()),
+        })
+    }
+
+    /// Finish the compressed stream and return the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        let mut writer = match self {
+            Encoder::Uncompressed(writer) => writer,
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            #[cfg(feature = "native")]
+            Encoder::Zstd(encoder) => encoder.finish()?,
+        };
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Uncompressed(writer) => writer.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "native")]
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Uncompressed(writer) => writer.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "native")]
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that the compression extension is detected and removed
+    #[test]
+    fn test_uncompressed_path() {
+        assert_eq!(Compression::from_path("results.csv.GZ"), Compression::Gzip);
+        assert_eq!(Compression::from_path("results.ndjson.zst"), Compression::Zstd);
+        assert_eq!(uncompressed_path("results.tsv.gz"), PathBuf::from("results.tsv"));
+        assert_eq!(uncompressed_path("results.csv"), PathBuf::from("results.csv"));
+    }
+
+    // Test that data compressed by an encoder are recovered by the decoder
+    #[test]
+    fn test_round_trip() {
+        for compression in [Compression::Uncompressed, Compression::Gzip, Compression::Zstd] {
+            let mut encoder = Encoder::new(compression, Vec::new()).unwrap();
+            encoder.write_all(b"site,result\nA01,1.5\n").unwrap();
+            let compressed = encoder.finish().unwrap();
+            let mut data = String::new();
+            decoder(compression, compressed.as_slice()).unwrap().read_to_string(&mut data).unwrap();
+            assert_eq!(data, "site,result\nA01,1.5\n");
+        }
+    }
+}
@@ -0,0 +1,2381 @@
+// The crosstab configuration and the pivot of normalized data into a crosstab.
+
+use crate::aggregate::{
+    aggregate, check_cell_template, check_stats_template, concat, mark_nondetect, round_significant, stats_cell, template_cell,
+    weighted_aggregate, Aggregation, NdSubstitute, CONCAT_SEP,
+};
+use crate::columns::{describe_missing, find_column, is_pattern, match_pattern};
+use crate::format::ValueFormat;
+use crate::header::{header_rows, HeaderNames};
+use crate::pair::{companion, with_suffix, PairConflict, PairStyle};
+use crate::output::{CrosstabWriter, CsvWriter};
+use crate::dates::format_dates;
+use crate::sort::{sort_keys_ranked, SortKey, SortOrder};
+use crate::{Key, WriteConfig, XtabError};
+use anyhow::{anyhow, bail, Result};
+use log::info;
+use polars::prelude::*;
+use rayon::prelude::*;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::str::FromStr;
+
+/// The total that each cell value is expressed as a percentage of.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Percent {
+    /// The sum of the values of the cell's output row.
+    Row,
+    /// The sum of the values of the cell's generated column.
+    Col,
+    /// The sum of all of the values of the crosstab.
+    Total,
+}
+
+impl FromStr for Percent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "row" => Ok(Percent::Row),
+            "col" | "column" => Ok(Percent::Col),
+            "total" => Ok(Percent::Total),
+            _ => Err(format!("Unknown percentage '{}'. Must be one of row, col, total", s)),
+        }
+    }
+}
+
+/// The cells among which each cell value is ranked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rank {
+    /// The cells of the cell's output row.
+    Row,
+    /// The cells of the cell's generated column.
+    Col,
+}
+
+impl FromStr for Rank {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "row" => Ok(Rank::Row),
+            "col" | "column" => Ok(Rank::Col),
+            _ => Err(format!("Unknown ranking '{}'. Must be one of row, col", s)),
+        }
+    }
+}
+
+/// The change from the previous generated column that each cell value is replaced by.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Delta {
+    /// The difference from the value of the previous column.
+    Abs,
+    /// The difference as a percentage of the value of the previous column.
+    Pct,
+}
+
+impl FromStr for Delta {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "abs" => Ok(Delta::Abs),
+            "pct" => Ok(Delta::Pct),
+            _ => Err(format!("Unknown change '{}'. Must be one of abs, pct", s)),
+        }
+    }
+}
+
+/// What to do when more than one input value maps to a cell that is not aggregated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OnDuplicate {
+    /// Use the first value, silently.
+    First,
+    /// Use the first value, and warn about every cell with multiple values.
+    Warn,
+    /// Fail without writing the crosstab.
+    Error,
+}
+
+impl FromStr for OnDuplicate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "first" => Ok(OnDuplicate::First),
+            "warn" => Ok(OnDuplicate::Warn),
+            "error" => Ok(OnDuplicate::Error),
+            _ => Err(format!("Unknown duplicate handling '{}'. Must be one of first, warn, error", s)),
+        }
+    }
+}
+
+/// What to do with input rows that have a missing value in a row header or column header column.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NullKeys {
+    /// Leave the rows out of the crosstab.
+    Drop,
+    /// Keep the rows, with the null label in place of each missing value.
+    Keep,
+    /// Fail to build the crosstab.
+    Error,
+}
+
+impl FromStr for NullKeys {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "drop" => Ok(NullKeys::Drop),
+            "keep" => Ok(NullKeys::Keep),
+            "error" => Ok(NullKeys::Error),
+            _ => Err(format!("Unknown null key handling '{}'. Must be one of drop, keep, error", s)),
+        }
+    }
+}
+
+/// The description of a crosstab: which columns to use and how to build the output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct XtabConfig {
+    /// The columns whose unique values appear at the beginning of every output row.
+    pub row_headers: Vec<String>,
+    /// The columns whose unique values are spread into the generated output columns. With several columns, each
+    /// group of generated columns is a combination of their values, nested in the order of the columns, and only
+    /// the combinations in the input are generated unless `col_product` is set.
+    pub col_headers: Vec<String>,
+    /// The columns whose values fill the cells of the crosstab. If there are none, each cell is instead the
+    /// number of input rows with its row and column keys, named [`COUNT_LABEL`].
+    pub cell_values: Vec<String>,
+    /// The format of the column headers, between 1 and 4.
+    pub format: u8,
+    /// The aggregation of each value column, in the same order as `cell_values`.
+    pub aggregations: Vec<Aggregation>,
+    /// Whether to append a group of totals columns, with the aggregation of all of the values of each output row.
+    pub row_totals: bool,
+    /// Whether to append a totals row, with the aggregation of all of the values of each generated column.
+    pub col_totals: bool,
+    /// Whether to add a subtotal row after each group of output rows that share their leading row key values,
+    /// at every level of the row headers above the last.
+    pub subtotals: bool,
+    /// The total to express each aggregated value as a percentage of, or `None` to keep the values themselves.
+    pub percent: Option<Percent>,
+    /// The value of the cells of row/column key combinations without input data, or `None` to leave them empty.
+    pub fill: Option<String>,
+    /// The order of the output rows, by row header column in order of precedence. Row header columns without a
+    /// sort key are sorted in ascending order after the others. With subtotals, the row header columns keep
+    /// their own precedence and only the order of the values of each is changed.
+    pub row_sort: Vec<SortKey>,
+    /// The order of the values of every column header column in the generated columns.
+    pub col_sort: SortOrder,
+    /// Column header values that come first, in this order, before the other values of their column.
+    pub col_order: Vec<String>,
+    /// How the names of the generated columns are built.
+    pub header_names: HeaderNames,
+    /// What to do with input rows that have a missing row header or column header value.
+    pub null_keys: NullKeys,
+    /// The value that stands for a missing row header or column header value when the rows are kept.
+    pub null_label: String,
+    /// The format of the numeric values of each value column, in the same order as `cell_values`, or `None` to
+    /// write them as they are. Columns beyond the end of the list are not formatted.
+    pub value_formats: Vec<Option<ValueFormat>>,
+    /// The strftime format (e.g. `%Y-%m`) in which the values of date and datetime row header and column header
+    /// columns are written, or `None` to write them as they are. The formatted values are still sorted chronologically.
+    pub date_format: Option<String>,
+    /// Whether the row header, column header, value, and sorted columns are given by their positions in the input
+    /// (counting from 1) instead of their names, for inputs with repeated or unwieldy column names.
+    pub by_index: bool,
+    /// The name of each value column in the generated column names, in the same order as `cell_values`, or `None`
+    /// to use the column name. Columns beyond the end of the list use their names.
+    pub value_labels: Vec<Option<String>>,
+    /// Columns that are written once for each output row, after the row header columns, without being part of the
+    /// row keys, such as the coordinates of a site. Each must have a single value for each row key.
+    pub carry: Vec<String>,
+    /// Whether the generated column groups are every combination of the values of the column header columns, nested
+    /// in the order of the columns, rather than only the combinations that appear in the input.
+    pub col_product: bool,
+    /// The largest number of generated columns allowed, or `None` for no limit. A crosstab that would generate more
+    /// columns is an error before any cells are built, rather than exhausting memory.
+    pub max_columns: Option<usize>,
+    /// The number of column keys to keep, the ones with the most input rows, or `None` to keep them all. The input
+    /// rows with the other column keys are aggregated together in a single group of columns named `other_label`,
+    /// which comes last.
+    pub top_cols: Option<usize>,
+    /// The value of every column header column in the column key of the input rows that are not in the top columns.
+    pub other_label: String,
+    /// Whether to leave out the groups of generated columns of the column keys without any values, such as the
+    /// combinations of column header values without input data when `col_product` is set.
+    pub drop_empty_cols: bool,
+    /// Whether to leave out the output rows without any values.
+    pub drop_empty_rows: bool,
+    /// Whether the crosstab is written transposed, with the row keys across the top and the generated columns down
+    /// the side: each output row is then one generated column, and each column after the first few is one row key.
+    pub transpose: bool,
+    /// The value columns that carry a companion column into the output, such as the qualifier column of a result
+    /// column, as (value column, companion column) pairs. Each cell of the value gets the companion value of its
+    /// input rows.
+    pub pairs: Vec<(String, String)>,
+    /// How the companion values are written: in generated columns of their own, or after the values in their cells.
+    pub pair_style: PairStyle,
+    /// What to do with the different companion values of the input rows of a cell whose values are combined.
+    pub pair_conflict: PairConflict,
+    /// The qualifier column that flags the values of the input rows that are non-detects, or `None` if only values
+    /// written as non-detects (such as `<0.5`, a value below the detection limit of 0.5) are non-detects.
+    pub nd_column: Option<String>,
+    /// The qualifiers (such as `U`) of the `nd_column` that flag a non-detect, whose value is its detection limit.
+    /// They are matched regardless of capitalization.
+    pub nd_flags: Vec<String>,
+    /// The number that stands for each non-detect in the values combined with the `sum`, `mean`, `min`, and `max`
+    /// aggregations, or `None` to leave non-detects as they are, which these aggregations reject.
+    pub nd_substitute: Option<NdSubstitute>,
+    /// A template of statistics, such as `{mean} ({sd}), n={n}`, in which each cell of every value column writes the
+    /// statistics of its values instead of their aggregation, or `None` to aggregate them. The statistics are written
+    /// in the format of the value column.
+    pub stats_template: Option<String>,
+    /// A template, such as `{value} ({count})`, in which each cell of every value column writes its aggregated value
+    /// (`{value}`) together with other aggregations of the same values (such as `{count}` or `{max}`), or `None` to
+    /// write the aggregated value alone. The numbers other than counts are written in the format of the value column.
+    pub cell_template: Option<String>,
+    /// The cells among which each aggregated value is replaced by its rank, separately for each value column, or
+    /// `None` to keep the values themselves. Equal values share the smallest of their ranks.
+    pub rank: Option<Rank>,
+    /// Whether the largest value has rank 1, rather than the smallest.
+    pub rank_descending: bool,
+    /// Whether each aggregated value is replaced by the running total of the values of its output row, in the order
+    /// of the generated columns, such as the year-to-date total of a monthly crosstab.
+    pub cumulative: bool,
+    /// The change from the previous generated column of its row that each aggregated value is replaced by, or `None`
+    /// to keep the values themselves. The first generated column, and the cells whose previous cell has no value,
+    /// are empty.
+    pub delta: Option<Delta>,
+    /// The column of the weight of each input row, such as a sample volume or a survey weight, by which the `sum`,
+    /// `mean`, and `count` aggregations weight the values, or `None` to weight every row alike. The count of a
+    /// crosstab without value columns is then the sum of the weights of the input rows of each cell.
+    pub weight: Option<String>,
+    /// The separator between the values of a cell that are combined with the `concat` aggregation.
+    pub concat_sep: String,
+    /// Whether the `concat` aggregation keeps only the first of the values of a cell that are the same.
+    pub concat_unique: bool,
+    /// Whether the `concat` aggregation sorts the values of a cell, instead of keeping them in input order.
+    pub concat_sort: bool,
+}
+
+impl XtabConfig {
+    /// Create a configuration with header format 1 that keeps the first value of each cell, and labels missing
+    /// row header and column header values with empty strings.
+    pub fn new<S: AsRef<str>>(row_headers: &[S], col_headers: &[S], cell_values: &[S]) -> XtabConfig {
+        let strings = |names: &[S]| names.iter().map(|s| s.as_ref().to_string()).collect::<Vec<String>>();
+        XtabConfig {
+            row_headers: strings(row_headers),
+            col_headers: strings(col_headers),
+            cell_values: strings(cell_values),
+            format: 1,
+            aggregations: vec![Aggregation::First; cell_values.len()],
+            row_totals: false,
+            col_totals: false,
+            subtotals: false,
+            percent: None,
+            fill: None,
+            row_sort: Vec::new(),
+            col_sort: SortOrder::Asc,
+            col_order: Vec::new(),
+            header_names: HeaderNames::default(),
+            null_keys: NullKeys::Keep,
+            null_label: String::new(),
+            value_formats: Vec::new(),
+            date_format: None,
+            by_index: false,
+            value_labels: Vec::new(),
+            carry: Vec::new(),
+            col_product: false,
+            max_columns: None,
+            top_cols: None,
+            other_label: "Other".to_string(),
+            drop_empty_cols: false,
+            drop_empty_rows: false,
+            transpose: false,
+            pairs: Vec::new(),
+            pair_style: PairStyle::Adjacent,
+            pair_conflict: PairConflict::Join,
+            nd_column: None,
+            nd_flags: vec!["U".to_string()],
+            nd_substitute: None,
+            stats_template: None,
+            cell_template: None,
+            rank: None,
+            rank_descending: false,
+            cumulative: false,
+            delta: None,
+            weight: None,
+            concat_sep: CONCAT_SEP.to_string(),
+            concat_unique: false,
+            concat_sort: false,
+        }
+    }
+
+    /// The columns written at the start of every output row: the row header columns, then the carried columns.
+    pub fn output_row_headers(&self) -> Vec<String> {
+        self.row_headers.iter().chain(&self.carry).cloned().collect()
+    }
+
+    /// The name of each value column in the generated column names: its label, or else its column name.
+    pub fn value_names(&self) -> Vec<String> {
+        self.cell_values
+            .iter()
+            .enumerate()
+            .map(|(i, name)| self.value_labels.get(i).cloned().flatten().unwrap_or_else(|| name.clone()))
+            .collect()
+    }
+
+    /// The input columns that the crosstab uses: the row headers, column headers, value columns, companion columns,
+    /// non-detect qualifier column, weight column, and carried columns, without repeats.
+    pub fn columns(&self) -> Vec<String> {
+        let mut columns: Vec<String> = Vec::new();
+        let companions = self.pairs.iter().map(|(_, companion)| companion).chain(&self.nd_column).chain(&self.weight);
+        for name in self.row_headers.iter().chain(&self.col_headers).chain(&self.cell_values).chain(companions).chain(&self.carry) {
+            if !columns.contains(name) {
+                columns.push(name.clone());
+            }
+        }
+        columns
+    }
+}
+
+/// The key value that labels the totals row and the totals columns.
+pub const TOTAL_LABEL: &str = "Total";
+
+/// The name of the value of a crosstab without value columns, which counts the input rows of each cell.
+pub const COUNT_LABEL: &str = "count";
+
+/// The name of the column of the input row numbers in the [`duplicates_table`](Crosstab::duplicates_table).
+pub const INPUT_ROW_LABEL: &str = "input_row";
+
+/// The suffix of the row key value that labels a subtotal row, as in `A01 — subtotal`.
+pub const SUBTOTAL_SUFFIX: &str = " — subtotal";
+
+/// The name of the column of the number of input rows of each cell of the input aggregated by [`aggregate_cells`].
+pub const CELL_ROWS_LABEL: &str = "__xtab_cell_rows";
+
+// The key of the subtotal row of the rows that share the first `level` values of the given row key
+fn subtotal_key(key: &Key, level: usize) -> Key {
+    let mut subtotal = key[..level].to_vec();
+    subtotal[level - 1].push_str(SUBTOTAL_SUFFIX);
+    subtotal.resize(key.len(), String::new());
+    subtotal
+}
+
+/// A copy of the configuration with the row header, column header, value, companion, carried, and sorted columns that are
+/// given by their positions (counting from 1) replaced by the names of the columns at those positions.
+pub fn resolve_indices(col_names: &[&str], config: &XtabConfig) -> Result<XtabConfig> {
+    let mut missing: Vec<String> = Vec::new();
+    let mut resolve = |kind: &str, index: &String| -> Result<String> {
+        if is_pattern(index) {
+            return Ok(index.clone());
+        }
+        let position: usize = index
+            .trim()
+            .parse()
+            .map_err(|_| XtabError::Argument(format!("The {} column index '{}' must be a positive integer", kind, index)))?;
+        match position.checked_sub(1).and_then(|i| col_names.get(i)) {
+            Some(name) => Ok(name.to_string()),
+            None => {
+                missing.push(format!("The {} column {} is not in the input file, which has {} columns", kind, position, col_names.len()));
+                Ok(index.clone())
+            }
+        }
+    };
+    let mut names = |kind: &str, indices: &[String]| indices.iter().map(|i| resolve(kind, i)).collect::<Result<Vec<String>>>();
+    let row_headers = names("row header", &config.row_headers)?;
+    let col_headers = names("column header", &config.col_headers)?;
+    let cell_values = names("value", &config.cell_values)?;
+    let carry = names("carried", &config.carry)?;
+    let paired = names("value", &config.pairs.iter().map(|(value, _)| value.clone()).collect::<Vec<String>>())?;
+    let companions = names("companion", &config.pairs.iter().map(|(_, companion)| companion.clone()).collect::<Vec<String>>())?;
+    let pairs = paired.into_iter().zip(companions).collect();
+    let nd_column = names("non-detect qualifier", &config.nd_column.iter().cloned().collect::<Vec<String>>())?.pop();
+    let weight = names("weight", &config.weight.iter().cloned().collect::<Vec<String>>())?.pop();
+    let sorted = names("sorted", &config.row_sort.iter().map(|k| k.column.clone()).collect::<Vec<String>>())?;
+    let row_sort = config.row_sort.iter().zip(sorted).map(|(k, column)| SortKey { column, order: k.order }).collect();
+    if !missing.is_empty() {
+        return Err(XtabError::MissingColumns(missing).into());
+    }
+    Ok(XtabConfig { row_headers, col_headers, cell_values, carry, row_sort, pairs, nd_column, weight, by_index: false, ..config.clone() })
+}
+
+/// A copy of the configuration with each value column pattern (such as `conc_*` or `re:^result_\d+$`) replaced by
+/// the input columns it matches, in input order, other than the row header and column header columns. Each of the
+/// matched columns has the aggregation and value format of the pattern. A pattern cannot have a label, because its
+/// columns would share it.
+pub fn expand_patterns(col_names: &[&str], config: &XtabConfig) -> Result<XtabConfig> {
+    let mut cell_values: Vec<String> = Vec::new();
+    let mut aggregations: Vec<Aggregation> = Vec::new();
+    let mut value_formats: Vec<Option<ValueFormat>> = Vec::new();
+    let mut value_labels: Vec<Option<String>> = Vec::new();
+    let mut missing: Vec<String> = Vec::new();
+    for (i, name) in config.cell_values.iter().enumerate() {
+        let label = config.value_labels.get(i).cloned().flatten();
+        if label.is_some() && is_pattern(name) {
+            return Err(XtabError::Argument(format!("The value column pattern '{}' cannot have a label", name)).into());
+        }
+        let matched: Vec<String> = match is_pattern(name) {
+            true => match_pattern(col_names, name)
+                .map_err(XtabError::Argument)?
+                .into_iter()
+                .filter(|c| !config.row_headers.iter().chain(&config.col_headers).any(|h| h == c))
+                .map(|c| c.to_string())
+                .collect(),
+            false => vec![name.clone()],
+        };
+        if matched.is_empty() {
+            missing.push(format!("The value column pattern '{}' does not match any input columns", name));
+        }
+        for column in matched {
+            cell_values.push(column);
+            aggregations.push(config.aggregations[i]);
+            value_formats.push(config.value_formats.get(i).copied().flatten());
+            value_labels.push(label.clone());
+        }
+    }
+    if !missing.is_empty() {
+        return Err(XtabError::MissingColumns(missing).into());
+    }
+    Ok(XtabConfig { cell_values, aggregations, value_formats, value_labels, ..config.clone() })
+}
+
+/// A copy of the configuration with the row header, column header, value, companion, carried, and sorted columns that match an input
+/// column only apart from capitalization and surrounding whitespace replaced by the names of the input columns.
+pub fn resolve_names(col_names: &[&str], config: &XtabConfig) -> XtabConfig {
+    let resolve = |name: &String| match find_column(col_names, name) {
+        Some(column) if column != name => {
+            info!("The column '{}' was matched with the input column '{}'", name, column);
+            column.to_string()
+        }
+        _ => name.clone(),
+    };
+    XtabConfig {
+        row_headers: config.row_headers.iter().map(resolve).collect(),
+        col_headers: config.col_headers.iter().map(resolve).collect(),
+        cell_values: config.cell_values.iter().map(resolve).collect(),
+        carry: config.carry.iter().map(resolve).collect(),
+        pairs: config.pairs.iter().map(|(value, companion)| (resolve(value), resolve(companion))).collect(),
+        nd_column: config.nd_column.as_ref().map(resolve),
+        weight: config.weight.as_ref().map(resolve),
+        row_sort: config.row_sort.iter().map(|k| SortKey { column: resolve(&k.column), order: k.order }).collect(),
+        ..config.clone()
+    }
+}
+
+/// Describe every requested column that is not one of the given column names, in argument order, with the nearest
+/// column name as a suggestion.
+pub fn missing_columns(col_names: &[&str], config: &XtabConfig) -> Vec<String> {
+    let mut errors: Vec<String> = Vec::new();
+    let companions: Vec<String> = config.pairs.iter().map(|(_, companion)| companion.clone()).collect();
+    let nd_column: Vec<String> = config.nd_column.iter().cloned().collect();
+    let weight: Vec<String> = config.weight.iter().cloned().collect();
+    let requested = [
+        ("row header", &config.row_headers),
+        ("column header", &config.col_headers),
+        ("value", &config.cell_values),
+        ("companion", &companions),
+        ("non-detect qualifier", &nd_column),
+        ("weight", &weight),
+        ("carried", &config.carry),
+    ];
+    for (kind, names) in requested {
+        for name in names.iter().filter(|name| !col_names.contains(&name.as_str())) {
+            errors.push(describe_missing(col_names, kind, name));
+        }
+    }
+    errors
+}
+
+/// A lazy query of the cells of the crosstab described by the configuration: the input rows grouped by their row
+/// and column keys, and by the other key columns (such as the column that splits them into groups), with the
+/// aggregation of the values of each group and its number of input rows, named [`CELL_ROWS_LABEL`]. Together with
+/// the configuration that is returned with it, which aggregates each single value of a cell into itself, the query
+/// builds the same crosstab as the input rows, without holding them in memory when it is collected with the polars
+/// streaming engine.
+///
+/// Only crosstabs whose cells can be built from the aggregations of their input rows alone have such a query:
+/// the values must be numbers aggregated with `sum`, `mean`, `min`, or `max`, or any values counted with `count`
+/// (or there are none, and the input rows are counted), without non-detects, companion, carried, or weight columns,
+/// statistics or cell templates, top columns, columns given by position, or keys in order of appearance, with the
+/// missing keys kept, and with means without totals or percentages. Other crosstabs, and queries without all of the
+/// columns, have `None`.
+pub fn aggregate_cells(lf: &LazyFrame, config: &XtabConfig, keys: &[String]) -> Result<Option<(LazyFrame, XtabConfig)>> {
+    let schema = lf.schema()?;
+    let col_names: Vec<&str> = schema.iter_names().map(|name| name.as_str()).collect();
+    if config.by_index {
+        return Ok(None);
+    }
+    let Ok(config) = expand_patterns(&col_names, &resolve_names(&col_names, config)) else { return Ok(None) };
+    let keys: Vec<String> = config.row_headers.iter().chain(&config.col_headers).chain(keys).fold(Vec::new(), |mut keys, key| {
+        if !keys.contains(key) {
+            keys.push(key.clone());
+        }
+        keys
+    });
+    let appearance = config.col_sort == SortOrder::Appearance || config.row_sort.iter().any(|k| k.order == SortOrder::Appearance);
+    let means = config.aggregations.contains(&Aggregation::Mean);
+    if !missing_columns(&col_names, &config).is_empty()
+        || keys.iter().any(|key| !col_names.contains(&key.as_str()) || config.cell_values.contains(key))
+        || !config.pairs.is_empty()
+        || !config.carry.is_empty()
+        || config.nd_column.is_some()
+        || config.nd_substitute.is_some()
+        || config.weight.is_some()
+        || config.stats_template.is_some()
+        || config.cell_template.is_some()
+        || config.top_cols.is_some()
+        || config.null_keys != NullKeys::Keep
+        || appearance
+        || (means && (config.row_totals || config.col_totals || config.subtotals || config.percent.is_some()))
+    {
+        return Ok(None);
+    }
+
+    // Each value is aggregated in the query, and the counts are then summed, as the single count of their cell. A
+    // value column that is aggregated more than once has a cell column for each aggregation after the first, which
+    // is labeled with its name
+    let mut aggregations: Vec<Expr> = Vec::new();
+    let mut cell_values: Vec<String> = Vec::new();
+    let mut value_labels: Vec<Option<String>> = Vec::new();
+    for (i, (name, aggregation)) in config.cell_values.iter().zip(&config.aggregations).enumerate() {
+        let number = col(name).cast(DataType::Float64);
+        let numeric = schema.get(name).is_some_and(|dtype| dtype.is_numeric());
+        // The values are counted by summing their presence, so that the missing values are left out by either engine
+        let count = col(name).is_not_null().sum();
+        let expr = match aggregation {
+            Aggregation::Count => count,
+            // The sum of a cell without values is missing, as in the crosstab, rather than 0
+            Aggregation::Sum if numeric => when(count.gt(lit(0))).then(number.sum()).otherwise(lit(NULL)),
+            Aggregation::Mean if numeric => number.mean(),
+            Aggregation::Min if numeric => number.min(),
+            Aggregation::Max if numeric => number.max(),
+            _ => return Ok(None),
+        };
+        let (cell_name, label) = match config.cell_values[..i].contains(name) {
+            true => (format!("__xtab_value_{}", i), Some(config.value_names()[i].clone())),
+            false => (name.clone(), config.value_labels.get(i).cloned().flatten()),
+        };
+        aggregations.push(expr.alias(&cell_name));
+        cell_values.push(cell_name);
+        value_labels.push(label);
+    }
+    aggregations.push(len().alias(CELL_ROWS_LABEL));
+    let cells = lf.clone().group_by(keys.iter().map(|key| col(key)).collect::<Vec<Expr>>()).agg(aggregations);
+
+    // A crosstab without value columns counts its input rows, which are the weights of the cells
+    let config = match config.cell_values.is_empty() {
+        true => XtabConfig { weight: Some(CELL_ROWS_LABEL.to_string()), ..config },
+        false => XtabConfig {
+            aggregations: config.aggregations.iter().map(|&a| if a == Aggregation::Count { Aggregation::Sum } else { a }).collect(),
+            cell_values,
+            value_labels,
+            ..config
+        },
+    };
+    Ok(Some((cells, config)))
+}
+
+/// The number of input rows of the data of a crosstab: the sum of the [`CELL_ROWS_LABEL`] column of the cells of an
+/// input aggregated by [`aggregate_cells`], or else the number of its rows.
+pub fn input_rows(df: &DataFrame) -> usize {
+    match df.column(CELL_ROWS_LABEL).ok().and_then(|rows| rows.cast(&DataType::UInt64).ok()) {
+        Some(rows) => rows.u64().map(|rows| rows.sum().unwrap_or(0) as usize).unwrap_or(0),
+        None => df.height(),
+    }
+}
+
+/// The number of input rows of each cell of the crosstab that has more than one: from the [`CELL_ROWS_LABEL`] column
+/// of the cells of an input aggregated by [`aggregate_cells`], in which every cell is a single row, or else the
+/// [`duplicate_counts`](Crosstab::duplicate_counts) of the crosstab.
+pub fn duplicate_counts(df: &DataFrame, crosstab: &Crosstab) -> Vec<usize> {
+    match df.column(CELL_ROWS_LABEL).ok().and_then(|rows| rows.cast(&DataType::UInt64).ok()) {
+        Some(rows) => rows.u64().map(|rows| rows.into_iter().flatten().filter(|&n| n > 1).map(|n| n as usize).collect()).unwrap_or_default(),
+        None => crosstab.duplicate_counts(),
+    }
+}
+
+// The name of the column of the position of the column key of each input row among the column keys of a lazy pivot
+const LAZY_COL_LABEL: &str = "__xtab_col_key";
+
+// The first option of the configuration that a lazy pivot cannot express, if there is one
+fn lazy_unsupported(config: &XtabConfig, schema: &Schema) -> Option<String> {
+    let temporal = |name: &String| schema.get(name).is_some_and(|dtype| dtype.is_temporal());
+    let options = [
+        (config.row_totals || config.col_totals, "totals"),
+        (config.subtotals, "subtotals"),
+        (config.percent.is_some(), "percentages"),
+        (config.rank.is_some(), "ranks"),
+        (config.cumulative, "cumulative values"),
+        (config.delta.is_some(), "changes"),
+        (config.stats_template.is_some() || config.cell_template.is_some(), "a statistics or cell template"),
+        (config.value_formats.iter().any(Option::is_some), "value formats"),
+        (!config.pairs.is_empty(), "companion columns"),
+        (config.nd_column.is_some() || config.nd_substitute.is_some(), "non-detects"),
+        (config.weight.is_some(), "weights"),
+        (!config.carry.is_empty(), "carried columns"),
+        (config.top_cols.is_some(), "top columns"),
+        (config.drop_empty_cols || config.drop_empty_rows, "dropped empty rows or columns"),
+        (config.transpose, "transposition"),
+        (config.null_keys == NullKeys::Error, "missing keys as errors"),
+        (config.row_sort.iter().any(|k| !matches!(k.order, SortOrder::Asc | SortOrder::Desc)), "rows in natural order or order of appearance"),
+        (config.date_format.is_some() && config.row_headers.iter().any(temporal), "formatted dates as row keys"),
+    ];
+    if let Some((_, option)) = options.iter().find(|(used, _)| *used) {
+        return Some(option.to_string());
+    }
+    let aggregated = |a: &Aggregation| matches!(a, Aggregation::First | Aggregation::Last | Aggregation::Sum | Aggregation::Mean | Aggregation::Min | Aggregation::Max | Aggregation::Count);
+    config
+        .cell_values
+        .iter()
+        .zip(&config.aggregations)
+        .find(|(_, a)| !aggregated(a))
+        .map(|(name, a)| format!("the {:?} aggregation of '{}'", a, name).to_lowercase())
+}
+
+// The lazy aggregation of the values of a value column of one cell, of the given type. Sums, means, extremes, and
+// counts are numbers, and first and last values are numbers if the column is numeric, and strings otherwise
+fn lazy_aggregation(values: Expr, aggregation: Aggregation, dtype: Option<&DataType>) -> Expr {
+    let numbers = values.clone().strict_cast(DataType::Float64);
+    let typed = |value: Expr| match dtype.is_some_and(|dtype| dtype.is_numeric()) {
+        true => value.cast(DataType::Float64),
+        false => value.cast(DataType::String),
+    };
+    match aggregation {
+        // The sum of a cell without values is missing, as in the crosstab, rather than 0
+        Aggregation::Sum => when(values.clone().is_not_null().any(false)).then(numbers.sum()).otherwise(lit(NULL)),
+        Aggregation::Mean => numbers.mean(),
+        Aggregation::Min => numbers.min(),
+        Aggregation::Max => numbers.max(),
+        Aggregation::Count => values.is_not_null().sum().cast(DataType::Float64),
+        Aggregation::Last => typed(values.last()),
+        _ => typed(values.first()),
+    }
+}
+
+// The values of the carried columns of each output row, from the input rows of its row key. Missing values are
+// ignored, and different values for the same row key are an error. Rows without any input rows (the subtotal rows)
+// have empty values.
+fn carried_values(
+    df: &DataFrame,
+    config: &XtabConfig,
+    rows: &[usize],
+    row_keys: &[Key],
+    row_index: &HashMap<&Key, usize>,
+    n_rows: usize,
+) -> Result<Vec<Vec<String>>> {
+    let mut carried: Vec<Vec<Option<String>>> = vec![vec![None; config.carry.len()]; n_rows];
+    for (c, name) in config.carry.iter().enumerate() {
+        let values = column_strings(df, name)?;
+        for &i in rows {
+            let (Some(value), r) = (&values[i], row_index[&row_keys[i]]) else { continue };
+            match &carried[r][c] {
+                None => carried[r][c] = Some(value.clone()),
+                Some(first) if first != value => bail!(
+                    "The carried column '{}' has more than one value for the row key {:?}: '{}' and '{}'",
+                    name,
+                    row_keys[i],
+                    first,
+                    value
+                ),
+                Some(_) => {}
+            }
+        }
+    }
+    Ok(carried.into_iter().map(|row| row.into_iter().map(Option::unwrap_or_default).collect()).collect())
+}
+
+// Cast a column to strings so that keys and values of any type can be compared and written.
+fn column_strings(df: &DataFrame, name: &str) -> PolarsResult<Vec<Option<String>>> {
+    let series = df.column(name)?.cast(&DataType::String)?;
+    Ok(series
+        .str()?
+        .into_iter()
+        .map(|v| v.map(|s| s.to_string()))
+        .collect())
+}
+
+// The chronological rank of each written value of each header column that is a formatted date column
+type Ranks = Vec<Option<HashMap<String, usize>>>;
+
+// Build the composite key of each input row from the given columns. Missing values become the null label.
+// With a date format, the values of date columns are written in it and ranked in chronological order.
+fn key_strings(df: &DataFrame, names: &[String], null_label: &str, date_format: Option<&str>) -> Result<(Vec<Key>, Ranks)> {
+    let (columns, ranks): (Vec<Vec<Option<String>>>, Ranks) = names
+        .par_iter()
+        .map(|name| match date_format.map(|format| format_dates(df.column(name)?, format)).transpose()?.flatten() {
+            Some((values, order)) => Ok((values, Some(order))),
+            None => Ok((column_strings(df, name)?, None)),
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+    let keys = (0..df.height())
+        .into_par_iter()
+        .map(|i| {
+            columns
+                .iter()
+                .map(|c| c[i].clone().unwrap_or_else(|| null_label.to_string()))
+                .collect()
+        })
+        .collect();
+    Ok((keys, ranks))
+}
+
+// The positions of the input rows with a missing value in any of the given columns
+fn null_rows(df: &DataFrame, names: &[String]) -> PolarsResult<Vec<usize>> {
+    let mut nulls = BooleanChunked::full("", false, df.height());
+    for name in names {
+        nulls = &nulls | &df.column(name)?.is_null();
+    }
+    Ok(nulls.into_no_null_iter().enumerate().filter(|(_, null)| *null).map(|(i, _)| i).collect())
+}
+
+// The distinct keys of the given input rows, in ascending order
+fn unique_keys(keys: &[Key], rows: &[usize]) -> Vec<Key> {
+    let mut unique: Vec<&Key> = rows.iter().map(|&i| &keys[i]).collect();
+    unique.par_sort_unstable();
+    unique.dedup();
+    unique.into_iter().cloned().collect()
+}
+
+// Replace every key of the input rows that is not one of the `n` keys with the most rows with the other key. Keys
+// with the same number of rows are kept in ascending order.
+fn keep_top_keys(keys: &mut [Key], rows: &[usize], n: usize, other_key: &Key) {
+    let mut counts: HashMap<&Key, usize> = HashMap::new();
+    for &i in rows {
+        *counts.entry(&keys[i]).or_default() += 1;
+    }
+    let mut ranked: Vec<(&Key, usize)> = counts.into_iter().collect();
+    ranked.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    let kept: HashSet<Key> = ranked.into_iter().take(n).map(|(k, _)| k.clone()).collect();
+    for key in keys.iter_mut().filter(|k| !kept.contains(*k)) {
+        key.clone_from(other_key);
+    }
+}
+
+// Leave out the output rows (if `rows`) and the column keys (if `cols`) without any non-empty values, and renumber
+// the cells of the others. The totals row and column, which follow the last output row and column key, are kept.
+fn drop_empty(
+    cells: HashMap<(usize, usize), Vec<Option<String>>>,
+    row_keys: &mut Vec<Key>,
+    carried: &mut Vec<Vec<String>>,
+    col_keys: &mut Vec<Key>,
+    rows: bool,
+    cols: bool,
+) -> HashMap<(usize, usize), Vec<Option<String>>> {
+    let mut filled_rows = vec![!rows; row_keys.len() + 1];
+    let mut filled_cols = vec![!cols; col_keys.len() + 1];
+    for (&(r, c), values) in &cells {
+        if values.iter().any(|v| v.as_deref().is_some_and(|v| !v.is_empty())) {
+            filled_rows[r] = true;
+            filled_cols[c] = true;
+        }
+    }
+    filled_rows[row_keys.len()] = true;
+    filled_cols[col_keys.len()] = true;
+    // The new position of each kept row or column
+    let renumber = |filled: &[bool]| -> Vec<Option<usize>> {
+        let mut next = 0;
+        filled
+            .iter()
+            .map(|&f| {
+                next += usize::from(f);
+                f.then_some(next - 1)
+            })
+            .collect()
+    };
+    let (row_map, col_map) = (renumber(&filled_rows), renumber(&filled_cols));
+    let mut filled = filled_rows.iter();
+    row_keys.retain(|_| *filled.next().unwrap());
+    let mut filled = filled_rows.iter();
+    carried.retain(|_| *filled.next().unwrap());
+    let mut filled = filled_cols.iter();
+    col_keys.retain(|_| *filled.next().unwrap());
+    cells
+        .into_iter()
+        .filter_map(|((r, c), values)| Some(((row_map[r]?, col_map[c]?), values)))
+        .collect()
+}
+
+// The items of the value columns, each followed by the items of the companion columns paired with it, in the order
+// of the pairs
+fn interleave<T>(items: Vec<T>, paired: &[(usize, String)], companion: impl Fn(usize) -> T) -> Vec<T> {
+    let mut interleaved: Vec<T> = Vec::with_capacity(items.len() + paired.len());
+    for (v, item) in items.into_iter().enumerate() {
+        interleaved.push(item);
+        interleaved.extend(paired.iter().enumerate().filter(|(_, (p, _))| *p == v).map(|(j, _)| companion(j)));
+    }
+    interleaved
+}
+
+// Write the companion values of each cell, in the order of the pairs, after the values they are paired with: as
+// suffixes of the values, or as values of their own
+fn pair_cells(
+    cells: &mut HashMap<(usize, usize), Vec<Option<String>>>,
+    companions: &HashMap<(usize, usize), Vec<Option<String>>>,
+    paired: &[(usize, String)],
+    style: PairStyle,
+) {
+    // The cells that were only filled after the companion values were set aside have none
+    let missing = vec![None; paired.len()];
+    cells.par_iter_mut().for_each(|(key, cell)| {
+        let companions = companions.get(key).unwrap_or(&missing);
+        match style {
+            PairStyle::Suffix => {
+                for (&(v, _), companion) in paired.iter().zip(companions) {
+                    cell[v] = with_suffix(cell[v].take(), companion.as_deref());
+                }
+            }
+            PairStyle::Adjacent => *cell = interleave(std::mem::take(cell), paired, |j| companions[j].clone()),
+        }
+    });
+}
+
+// A copy of the configuration in which each companion column written in generated columns of its own is a value
+// column, named by its column, after the value column it is paired with
+fn paired_config(config: &XtabConfig, paired: &[(usize, String)]) -> XtabConfig {
+    let n_values = config.cell_values.len();
+    let value_formats = (0..n_values).map(|v| config.value_formats.get(v).copied().flatten()).collect();
+    let value_labels = (0..n_values).map(|v| config.value_labels.get(v).cloned().flatten()).collect();
+    XtabConfig {
+        cell_values: interleave(config.cell_values.clone(), paired, |j| paired[j].1.clone()),
+        aggregations: interleave(config.aggregations.clone(), paired, |_| Aggregation::First),
+        value_formats: interleave(value_formats, paired, |_| None),
+        value_labels: interleave(value_labels, paired, |_| None),
+        ..config.clone()
+    }
+}
+
+// The named columns of a DataFrame. The columns after the row headers whose values are all numbers become Float64
+// columns, and all other columns are strings.
+fn typed_series(names: &[String], columns: Vec<Vec<Option<String>>>, n_row_headers: usize) -> Vec<Series> {
+    names
+        .iter()
+        .zip(columns)
+        .enumerate()
+        .map(|(j, (name, values))| {
+            let numbers: Option<Vec<Option<f64>>> = values
+                .iter()
+                .map(|v| match v {
+                    Some(v) => v.trim().parse::<f64>().ok().map(Some),
+                    None => Some(None),
+                })
+                .collect();
+            match numbers {
+                Some(numbers) if j >= n_row_headers => Series::new(name, numbers),
+                _ => Series::new(name, values),
+            }
+        })
+        .collect()
+}
+
+// The sorted distinct values of each position of the keys
+fn level_values(keys: &[Key]) -> Vec<Vec<&String>> {
+    let n = keys.first().map_or(0, |k| k.len());
+    (0..n)
+        .map(|i| {
+            let mut values: Vec<&String> = keys.iter().map(|k| &k[i]).collect();
+            values.sort_unstable();
+            values.dedup();
+            values
+        })
+        .collect()
+}
+
+// Every combination of the values of each position of the sorted unique keys, in sorted order, so that the last
+// position varies fastest
+fn key_product(keys: &[Key]) -> Vec<Key> {
+    if keys.is_empty() {
+        return Vec::new();
+    }
+    let mut product: Vec<Key> = vec![Vec::new()];
+    for values in level_values(keys) {
+        product = product
+            .into_iter()
+            .flat_map(|prefix| values.iter().map(move |v| prefix.iter().chain([*v]).cloned().collect()))
+            .collect();
+    }
+    product
+}
+
+// Replace each aggregated value with its percentage of the sum of the values of its row, its column, or the whole
+// crosstab, separately for each value column. Only the cells of the detail rows and of the columns before the totals
+// columns count toward these sums, so that totals and subtotals are not counted twice.
+fn percentages(
+    cells: &mut HashMap<(usize, usize), Vec<Option<String>>>,
+    percent: Percent,
+    detail_rows: &HashSet<usize>,
+    n_cols: usize,
+) -> Result<()> {
+    let group = |(r, c): (usize, usize)| match percent {
+        Percent::Row => r,
+        Percent::Col => c,
+        Percent::Total => 0,
+    };
+    let number = |v: &str| {
+        v.trim()
+            .parse::<f64>()
+            .map_err(|_| anyhow!("The value '{}' cannot be expressed as a percentage because it is not a number", v))
+    };
+    let mut sums: HashMap<(usize, usize), f64> = HashMap::new();
+    for (&(r, c), cell) in cells.iter() {
+        if (percent == Percent::Col || c < n_cols) && (percent == Percent::Row || detail_rows.contains(&r)) {
+            for (v, value) in cell.iter().enumerate() {
+                if let Some(value) = value {
+                    *sums.entry((group((r, c)), v)).or_default() += number(value)?;
+                }
+            }
+        }
+    }
+    for (&key, cell) in cells.iter_mut() {
+        for (v, value) in cell.iter_mut().enumerate() {
+            let sum = sums.get(&(group(key), v)).copied().unwrap_or_default();
+            *value = match value {
+                Some(x) if sum != 0.0 => Some(round_significant(number(x)? / sum * 100.0).to_string()),
+                _ => None,
+            };
+        }
+    }
+    Ok(())
+}
+
+// Replace each aggregated value with the running total of the values of its row, in the order of the generated columns
+// before the totals columns, separately for each value column. The cells without a value after the first value of
+// their row get the running total.
+fn cumulative_sums(cells: &mut HashMap<(usize, usize), Vec<Option<String>>>, n_rows: usize, n_cols: usize, n_values: usize) -> Result<()> {
+    let number = |v: &str| {
+        v.trim()
+            .parse::<f64>()
+            .map_err(|_| anyhow!("The value '{}' cannot be added to a running total because it is not a number", v))
+    };
+    for r in 0..n_rows {
+        let mut totals: Vec<Option<f64>> = vec![None; n_values];
+        for c in 0..n_cols {
+            if let Some(cell) = cells.get(&(r, c)) {
+                for (total, value) in totals.iter_mut().zip(cell) {
+                    if let Some(value) = value {
+                        *total = Some(total.unwrap_or_default() + number(value)?);
+                    }
+                }
+            }
+            if totals.iter().any(|t| t.is_some()) {
+                let cell = cells.entry((r, c)).or_insert_with(|| vec![None; n_values]);
+                for (value, total) in cell.iter_mut().zip(&totals) {
+                    *value = total.map(|t| round_significant(t).to_string());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Replace each aggregated value with its change from the value of the previous generated column of its row, before the
+// totals columns, separately for each value column. A percentage change from 0 is missing.
+fn changes(cells: &mut HashMap<(usize, usize), Vec<Option<String>>>, delta: Delta, n_rows: usize, n_cols: usize) -> Result<()> {
+    let number = |v: &Option<String>| {
+        v.as_deref()
+            .map(|v| v.trim().parse::<f64>().map_err(|_| anyhow!("The value '{}' cannot be compared because it is not a number", v)))
+            .transpose()
+    };
+    for r in 0..n_rows {
+        let mut previous: Vec<Option<f64>> = Vec::new();
+        for c in 0..n_cols {
+            let Some(cell) = cells.get_mut(&(r, c)) else {
+                previous.clear();
+                continue;
+            };
+            let values = cell.iter().map(number).collect::<Result<Vec<Option<f64>>>>()?;
+            for (v, value) in cell.iter_mut().enumerate() {
+                let change = match (values[v], previous.get(v).copied().flatten()) {
+                    (Some(x), Some(p)) if delta == Delta::Abs => Some(x - p),
+                    (Some(x), Some(p)) if p != 0.0 => Some((x - p) / p * 100.0),
+                    _ => None,
+                };
+                *value = change.map(|change| round_significant(change).to_string());
+            }
+            previous = values;
+        }
+    }
+    Ok(())
+}
+
+// Replace each aggregated value with its rank among the values of its row or its column, separately for each value
+// column, counting from 1 for the smallest (or the largest, if descending). Equal values share the smallest of their
+// ranks, as in 1, 2, 2, 4.
+fn ranks(cells: &mut HashMap<(usize, usize), Vec<Option<String>>>, rank: Rank, descending: bool) -> Result<()> {
+    let group = |(r, c): (usize, usize)| match rank {
+        Rank::Row => r,
+        Rank::Col => c,
+    };
+    let number = |v: &str| {
+        v.trim()
+            .parse::<f64>()
+            .map_err(|_| anyhow!("The value '{}' cannot be ranked because it is not a number", v))
+    };
+    let mut groups: HashMap<(usize, usize), Vec<f64>> = HashMap::new();
+    for (&key, cell) in cells.iter() {
+        for (v, value) in cell.iter().enumerate() {
+            if let Some(value) = value {
+                groups.entry((group(key), v)).or_default().push(number(value)?);
+            }
+        }
+    }
+    for numbers in groups.values_mut() {
+        numbers.sort_unstable_by(|a, b| if descending { b.total_cmp(a) } else { a.total_cmp(b) });
+    }
+    for (&key, cell) in cells.iter_mut() {
+        for (v, value) in cell.iter_mut().enumerate() {
+            if let Some(x) = value {
+                let x = number(x)?;
+                let before = groups[&(group(key), v)].partition_point(|&y| if descending { y > x } else { y < x });
+                *value = Some((before + 1).to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A crosstab of normalized data, with one output row for each unique row key and one group of
+/// generated columns for each unique column key. Row and column keys are sorted in the configured orders.
+///
+/// With row totals, the last column key is the totals key, whose values are all [`TOTAL_LABEL`].
+/// With column totals, the last row key is the totals key, whose first value is [`TOTAL_LABEL`] and whose other
+/// values are empty. With subtotals, each subtotal row follows its group of rows, and its key is the shared leading
+/// values of the group, the last of them followed by [`SUBTOTAL_SUFFIX`].
+#[derive(Clone, Debug)]
+pub struct Crosstab {
+    config: XtabConfig,
+    row_keys: Vec<Key>,
+    col_keys: Vec<Key>,
+    // The aggregated values of each cell that has input data, indexed by row key and column key position
+    cells: HashMap<(usize, usize), Vec<Option<String>>>,
+    // The values of the carried columns of each output row, which are empty for the subtotal and totals rows
+    carried: Vec<Vec<String>>,
+    duplicates: Vec<(Key, Key)>,
+    // The positions of the input rows of each of the duplicates, in input order
+    duplicate_rows: Vec<Vec<usize>>,
+    // The number of input rows left out because of a missing row or column header value
+    dropped_rows: usize,
+}
+
+impl Crosstab {
+    /// Pivot the DataFrame into a crosstab as described by the configuration.
+    pub fn new(df: &DataFrame, config: &XtabConfig) -> Result<Crosstab> {
+        if !(1..=4).contains(&config.format) {
+            bail!("The format must be an integer between 1 and 4");
+        }
+        if config.aggregations.len() != config.cell_values.len() {
+            bail!("There must be one aggregation for each value column");
+        }
+        if let Some(template) = &config.stats_template {
+            check_stats_template(template).map_err(XtabError::Argument)?;
+        }
+        if let Some(template) = &config.cell_template {
+            check_cell_template(template).map_err(XtabError::Argument)?;
+        }
+        // Resolve the columns given by their positions and the columns whose names differ in capitalization or
+        // whitespace, then expand the value column patterns
+        let col_names = df.get_column_names();
+        let indexed: XtabConfig;
+        let config = match config.by_index {
+            true => {
+                indexed = resolve_indices(&col_names, config)?;
+                &indexed
+            }
+            false => config,
+        };
+        let config = &expand_patterns(&col_names, &resolve_names(&col_names, config))?;
+        let errors = missing_columns(&col_names, config);
+        if !errors.is_empty() {
+            return Err(XtabError::MissingColumns(errors).into());
+        }
+        if let Some(key) = config.row_sort.iter().find(|k| !config.row_headers.contains(&k.column)) {
+            bail!("The sorted column '{}' is not one of the row header columns", key.column);
+        }
+        let value_names = config.value_names();
+        if let Some(name) = value_names.iter().enumerate().find(|(i, n)| value_names[..*i].contains(n)).map(|(_, n)| n) {
+            return Err(XtabError::Argument(format!(
+                "The value '{}' is named more than once; give each a label, e.g. {}:mean:mean_{}",
+                name, name, name
+            ))
+            .into());
+        }
+        // Find the value column of each pair. In their own generated columns, the companion values are named by their
+        // columns, which must not repeat the names of the values.
+        let mut paired: Vec<(usize, String)> = Vec::new();
+        for (value, companion) in &config.pairs {
+            let Some(v) = config.cell_values.iter().position(|name| name == value) else {
+                return Err(XtabError::Argument(format!("The paired column '{}' is not one of the value columns", value)).into());
+            };
+            if config.pair_style == PairStyle::Adjacent && (value_names.contains(companion) || paired.iter().any(|(_, c)| c == companion)) {
+                return Err(XtabError::Argument(format!(
+                    "The companion column '{}' is named more than once among the values; pair it with a single value \
+                     column, or write it as a suffix",
+                    companion
+                ))
+                .into());
+            }
+            paired.push((v, companion.clone()));
+        }
+        // Without value columns, the crosstab is a contingency table of the number of input rows in each cell
+        let counting = config.cell_values.is_empty();
+        let config = &XtabConfig {
+            cell_values: if counting { vec![COUNT_LABEL.to_string()] } else { config.cell_values.clone() },
+            aggregations: if counting { vec![Aggregation::Count] } else { config.aggregations.clone() },
+            ..config.clone()
+        };
+
+        // Find the input rows with a missing row or column header value, which are labeled, left out, or an error
+        let headers: Vec<String> = config.row_headers.iter().chain(&config.col_headers).cloned().collect();
+        let nulls = null_rows(df, &headers)?;
+        if config.null_keys == NullKeys::Error && !nulls.is_empty() {
+            bail!(
+                "{} input row(s) have a missing row or column header value, the first of them row {}",
+                nulls.len(),
+                nulls[0] + 1
+            );
+        }
+        let rows: Vec<usize> = match config.null_keys {
+            NullKeys::Drop => {
+                let nulls: HashSet<usize> = nulls.into_iter().collect();
+                (0..df.height()).filter(|i| !nulls.contains(i)).collect()
+            }
+            _ => (0..df.height()).collect(),
+        };
+        let dropped_rows = df.height() - rows.len();
+
+        // Extract the row keys, column keys, and cell values of every input row as strings
+        let (row_keys, row_ranks) = key_strings(df, &config.row_headers, &config.null_label, config.date_format.as_deref())?;
+        let (mut col_keys, col_ranks) = key_strings(df, &config.col_headers, &config.null_label, config.date_format.as_deref())?;
+        let mut values = if counting {
+            vec![vec![Some("1".to_string()); df.height()]]
+        } else {
+            config
+                .cell_values
+                .par_iter()
+                .map(|name| column_strings(df, name))
+                .collect::<PolarsResult<Vec<_>>>()?
+        };
+        // Write the values of the input rows that the qualifier column flags as non-detects, as in <0.5, and substitute
+        // for the non-detects of the values that are combined arithmetically
+        if let Some(name) = config.nd_column.as_ref().filter(|_| !counting) {
+            let qualifiers = column_strings(df, name)?;
+            let flagged = |q: &Option<String>| q.as_deref().is_some_and(|q| config.nd_flags.iter().any(|f| f.trim().eq_ignore_ascii_case(q.trim())));
+            for column in values.iter_mut() {
+                for (value, _) in column.iter_mut().zip(&qualifiers).filter(|(_, q)| flagged(q)) {
+                    *value = value.take().map(mark_nondetect);
+                }
+            }
+        }
+        if let Some(substitute) = config.nd_substitute.filter(|_| !counting) {
+            let arithmetic = |a: &Aggregation| matches!(a, Aggregation::Sum | Aggregation::Mean | Aggregation::Min | Aggregation::Max);
+            for (column, _) in values.iter_mut().zip(&config.aggregations).filter(|(_, a)| arithmetic(a)) {
+                for value in column.iter_mut().flatten() {
+                    if let Some(number) = substitute.substitute(value) {
+                        *value = number;
+                    }
+                }
+            }
+        }
+        // The companion columns and then the weight column follow the value columns, so that each cell also collects
+        // the companion values and the weights
+        let n_values = values.len();
+        for (_, name) in &paired {
+            values.push(column_strings(df, name)?);
+        }
+        if let Some(name) = &config.weight {
+            values.push(column_strings(df, name)?);
+        }
+        let weights = config.weight.as_ref().map(|_| n_values + paired.len());
+
+        let other_key: Key = vec![config.other_label.clone(); config.col_headers.len()];
+        if let Some(n) = config.top_cols {
+            keep_top_keys(&mut col_keys, &rows, n, &other_key);
+        }
+
+        // Collect the unique row and column keys. These determine the output rows and the generated output columns.
+        let mut unique_rows: Vec<Key> = unique_keys(&row_keys, &rows);
+        if !config.row_sort.is_empty() || row_ranks.iter().any(|r| r.is_some()) {
+            // With subtotals, the row header columns keep their precedence so that the rows of each group stay together
+            let row_sort: Vec<SortKey> = if config.subtotals {
+                config
+                    .row_headers
+                    .iter()
+                    .filter_map(|h| config.row_sort.iter().find(|k| &k.column == h).cloned())
+                    .collect()
+            } else {
+                config.row_sort.clone()
+            };
+            sort_keys_ranked(&mut unique_rows, &config.row_headers, &row_sort, &row_keys, &[], &row_ranks).map_err(|e| anyhow!(e))?;
+        }
+        let mut unique_cols: Vec<Key> = unique_keys(&col_keys, &rows);
+        let n_groups = match config.col_product {
+            true => level_values(&unique_cols).iter().fold(1_usize, |n, values| n.saturating_mul(values.len())),
+            false => unique_cols.len(),
+        };
+        let n_generated = n_groups.saturating_mul(config.cell_values.len());
+        if let Some(max_columns) = config.max_columns.filter(|max| n_generated > *max) {
+            bail!(
+                "The column header columns would generate {} output columns ({} distinct combinations of their values \
+                 for each of {} value columns), more than the maximum of {}",
+                n_generated,
+                n_groups,
+                config.cell_values.len(),
+                max_columns
+            );
+        }
+        if config.col_product {
+            unique_cols = key_product(&unique_cols);
+        }
+        if config.col_sort != SortOrder::Asc || !config.col_order.is_empty() || col_ranks.iter().any(|r| r.is_some()) {
+            let col_sort: Vec<SortKey> = config
+                .col_headers
+                .iter()
+                .map(|h| SortKey { column: h.clone(), order: config.col_sort })
+                .collect();
+            sort_keys_ranked(&mut unique_cols, &config.col_headers, &col_sort, &col_keys, &config.col_order, &col_ranks)
+                .map_err(|e| anyhow!(e))?;
+        }
+        if let Some(i) = config.top_cols.and_then(|_| unique_cols.iter().position(|k| k == &other_key)) {
+            let other = unique_cols.remove(i);
+            unique_cols.push(other);
+        }
+        let col_index: HashMap<&Key, usize> = unique_cols.iter().enumerate().map(|(i, k)| (k, i)).collect();
+
+        // Order the output rows. With subtotals, a subtotal row follows the last row of each group of rows that share
+        // their first `level` key values, with the innermost level first.
+        let n_levels = if config.subtotals { config.row_headers.len() } else { 1 };
+        let mut output_rows: Vec<Key> = Vec::new();
+        let mut row_index: HashMap<&Key, usize> = HashMap::new();
+        let mut subtotal_index: HashMap<(usize, &[String]), usize> = HashMap::new();
+        for (i, key) in unique_rows.iter().enumerate() {
+            row_index.insert(key, output_rows.len());
+            output_rows.push(key.clone());
+            let next = unique_rows.get(i + 1);
+            for level in (1..n_levels).rev() {
+                if next.is_none_or(|next| next[..level] != key[..level]) {
+                    subtotal_index.insert((level, &key[..level]), output_rows.len());
+                    output_rows.push(subtotal_key(key, level));
+                }
+            }
+        }
+
+        // The values of the carried columns of each output row, which must not differ between its input rows
+        let carried = carried_values(df, config, &rows, &row_keys, &row_index, output_rows.len())?;
+
+        // Map each row/column key combination to all of the values of its cell, in input order,
+        // and record every combination that has more than one input row so that it can be reported.
+        let mut cells: HashMap<(usize, usize), Vec<Vec<Option<String>>>> = HashMap::new();
+        let mut duplicates: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+        // The first input row of each cell, which is only reported if the cell has another one
+        let mut first_rows: HashMap<(usize, usize), usize> = HashMap::new();
+        // The totals row and the totals columns are the positions after the last unique key
+        let total_row = output_rows.len();
+        let total_col = unique_cols.len();
+        for &i in &rows {
+            let key = (row_index[&row_keys[i]], col_index[&col_keys[i]]);
+            // Each input value also belongs to the subtotal rows of its groups and to the totals row, and to the totals column of each of those rows
+            let mut total_rows: Vec<usize> = (1..n_levels).map(|level| subtotal_index[&(level, &row_keys[i][..level])]).collect();
+            if config.col_totals {
+                total_rows.push(total_row);
+            }
+            let mut totals: Vec<(usize, usize)> = total_rows.iter().map(|&r| (r, key.1)).collect();
+            if config.row_totals {
+                totals.push((key.0, total_col));
+                totals.extend(total_rows.iter().map(|&r| (r, total_col)));
+            }
+            for total in totals {
+                let cell = cells.entry(total).or_insert_with(|| vec![Vec::new(); values.len()]);
+                for (cell, v) in cell.iter_mut().zip(&values) {
+                    cell.push(v[i].clone());
+                }
+            }
+            match cells.entry(key) {
+                Entry::Occupied(mut entry) => {
+                    duplicates.entry(key).or_insert_with(|| vec![first_rows[&key]]).push(i);
+                    for (cell, v) in entry.get_mut().iter_mut().zip(&values) {
+                        cell.push(v[i].clone());
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    first_rows.insert(key, i);
+                    entry.insert(values.iter().map(|v| vec![v[i].clone()]).collect());
+                }
+            }
+        }
+
+        // Combine the values of each cell using the aggregation of its value column. The cells are independent, so they are
+        // aggregated in parallel.
+        let mut aggregated: HashMap<(usize, usize), Vec<Option<String>>> = cells
+            .into_par_iter()
+            .map(|(key, cell)| {
+                let companions = paired
+                    .iter()
+                    .enumerate()
+                    .map(|(j, &(v, _))| companion(config.aggregations[v], &cell[v], &cell[n_values + j], config.pair_conflict));
+                let combined = cell
+                    .iter()
+                    .zip(&config.aggregations)
+                    .enumerate()
+                    .map(|(v, (vals, agg))| {
+                        let format = config.value_formats.get(v).and_then(|f| f.as_ref());
+                        let combine = |aggregation| match weights {
+                            _ if aggregation == Aggregation::Concat => {
+                                Ok(concat(vals, &config.concat_sep, config.concat_unique, config.concat_sort))
+                            }
+                            Some(w) => weighted_aggregate(aggregation, vals, &cell[w]),
+                            None => aggregate(aggregation, vals),
+                        };
+                        match (&config.stats_template, &config.cell_template) {
+                            (Some(template), _) => stats_cell(template, vals, format),
+                            (None, Some(template)) => template_cell(template, *agg, vals, format, combine),
+                            (None, None) => combine(*agg),
+                        }
+                    })
+                    .chain(companions)
+                    .collect::<Result<Vec<_>, String>>()?;
+                Ok((key, combined))
+            })
+            .collect::<Result<_, String>>()
+            .map_err(|e| anyhow!(e))?;
+        // Set aside the companion values of each cell, so that only the values are expressed as percentages and formatted
+        let companions: HashMap<(usize, usize), Vec<Option<String>>> = match paired.is_empty() {
+            true => HashMap::new(),
+            false => aggregated.iter_mut().map(|(key, cell)| (*key, cell.split_off(n_values))).collect(),
+        };
+        if let Some(percent) = config.percent {
+            percentages(&mut aggregated, percent, &row_index.values().copied().collect(), total_col)?;
+        }
+        if config.cumulative {
+            let n_rows = output_rows.len() + usize::from(config.col_totals);
+            cumulative_sums(&mut aggregated, n_rows, unique_cols.len(), config.cell_values.len())?;
+        }
+        if let Some(delta) = config.delta {
+            let n_rows = output_rows.len() + usize::from(config.col_totals);
+            changes(&mut aggregated, delta, n_rows, unique_cols.len())?;
+        }
+        if let Some(rank) = config.rank {
+            ranks(&mut aggregated, rank, config.rank_descending)?;
+        }
+        // Format the numeric values of each value column that has a format, unless its statistics or its template were
+        // formatted or its values were ranked
+        if config.stats_template.is_none() && config.cell_template.is_none() && config.rank.is_none() && config.value_formats.iter().any(|f| f.is_some()) {
+            aggregated.par_iter_mut().for_each(|(_, cell)| {
+                for (value, format) in cell.iter_mut().zip(&config.value_formats) {
+                    if let (Some(v), Some(format)) = (value.as_mut(), format) {
+                        *v = format.format(v);
+                    }
+                }
+            });
+        }
+
+        if !paired.is_empty() {
+            pair_cells(&mut aggregated, &companions, &paired, config.pair_style);
+        }
+
+        let duplicate_rows = duplicates.values().cloned().collect();
+        let duplicates = duplicates
+            .into_keys()
+            .map(|(r, c)| (output_rows[r].clone(), unique_cols[c].clone()))
+            .collect();
+        let mut unique_rows = output_rows;
+        let mut carried = carried;
+        if config.drop_empty_rows || config.drop_empty_cols {
+            let (rows, cols) = (config.drop_empty_rows, config.drop_empty_cols);
+            aggregated = drop_empty(aggregated, &mut unique_rows, &mut carried, &mut unique_cols, rows, cols);
+        }
+        if config.row_totals {
+            unique_cols.push(vec![TOTAL_LABEL.to_string(); config.col_headers.len()]);
+        }
+        if config.col_totals {
+            let mut total_key = vec![String::new(); config.row_headers.len()];
+            total_key[0] = TOTAL_LABEL.to_string();
+            unique_rows.push(total_key);
+            carried.push(vec![String::new(); config.carry.len()]);
+        }
+        // The companion values that are written in generated columns of their own become value columns
+        let config = match config.pair_style {
+            PairStyle::Adjacent if !paired.is_empty() => paired_config(config, &paired),
+            _ => config.clone(),
+        };
+        Ok(Crosstab {
+            config,
+            row_keys: unique_rows,
+            carried,
+            col_keys: unique_cols,
+            cells: aggregated,
+            duplicates,
+            duplicate_rows,
+            dropped_rows,
+        })
+    }
+
+    /// The configuration the crosstab was built with.
+    pub fn config(&self) -> &XtabConfig {
+        &self.config
+    }
+
+    /// The unique row keys, one for each output row.
+    pub fn row_keys(&self) -> &[Key] {
+        &self.row_keys
+    }
+
+    /// The values of the carried columns of each output row, in the same order as the row keys. The values of the
+    /// subtotal and totals rows are empty.
+    pub fn carried(&self) -> &[Vec<String>] {
+        &self.carried
+    }
+
+    /// The unique column keys, one for each group of generated output columns.
+    pub fn col_keys(&self) -> &[Key] {
+        &self.col_keys
+    }
+
+    /// The row/column key combinations that had more than one input row.
+    pub fn duplicates(&self) -> &[(Key, Key)] {
+        &self.duplicates
+    }
+
+    /// The number of input rows of each of the [`duplicates`](Crosstab::duplicates), in the same order.
+    pub fn duplicate_counts(&self) -> Vec<usize> {
+        self.duplicate_rows.iter().map(|rows| rows.len()).collect()
+    }
+
+    /// The positions, in the input DataFrame, of the input rows of each of the [`duplicates`](Crosstab::duplicates),
+    /// in the same order.
+    pub fn duplicate_rows(&self) -> &[Vec<usize>] {
+        &self.duplicate_rows
+    }
+
+    /// The number of input rows left out of the crosstab because they have a missing row or column header value and
+    /// the missing keys are dropped.
+    pub fn dropped_rows(&self) -> usize {
+        self.dropped_rows
+    }
+
+    /// The numbers of the first value of the cells of the detail rows and of the generated columns before the totals
+    /// columns, such as the counts of a contingency table, without the totals and subtotals. Cells without input data
+    /// are 0, and values that are not numbers are an error.
+    pub fn value_table(&self) -> Result<Vec<Vec<f64>>> {
+        let n_rows = self.row_keys.len() - usize::from(self.config.col_totals);
+        let n_cols = self.col_keys.len() - usize::from(self.config.row_totals);
+        let subtotal = |key: &Key| self.config.subtotals && key.iter().any(|v| v.ends_with(SUBTOTAL_SUFFIX));
+        (0..n_rows)
+            .filter(|&r| !subtotal(&self.row_keys[r]))
+            .map(|r| {
+                (0..n_cols)
+                    .map(|c| match self.cell(r, c).and_then(|cell| cell[0].as_deref()) {
+                        Some(value) => value.trim().parse::<f64>().map_err(|_| anyhow!("The value '{}' is not a number", value)),
+                        None => Ok(0.0),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The input rows of every one of the [`duplicates`](Crosstab::duplicates), grouped by cell in the same order:
+    /// the row number in the input DataFrame (counting from 1), named [`INPUT_ROW_LABEL`], followed by the row header,
+    /// column header, and value columns of the input. The DataFrame must be the one the crosstab was built from.
+    pub fn duplicates_table(&self, df: &DataFrame) -> Result<DataFrame> {
+        let config = &self.config;
+        let mut names: Vec<&str> = Vec::new();
+        for name in config.row_headers.iter().chain(&config.col_headers).chain(&config.cell_values) {
+            // A crosstab without value columns counts the input rows, which have no value column to report
+            if df.get_column_names().contains(&name.as_str()) && !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+        let rows: Vec<IdxSize> = self.duplicate_rows.iter().flatten().map(|&i| i as IdxSize).collect();
+        let mut table = df.select(names)?.take(&IdxCa::from_vec("", rows.clone()))?;
+        let numbers: Vec<u64> = rows.iter().map(|&i| i as u64 + 1).collect();
+        table.insert_column(0, Series::new(INPUT_ROW_LABEL, numbers))?;
+        Ok(table)
+    }
+
+    /// The aggregated values of the cell at the given row and column key positions,
+    /// or `None` if there was no input data for that combination.
+    pub fn cell(&self, row: usize, col: usize) -> Option<&[Option<String>]> {
+        self.cells.get(&(row, col)).map(|c| c.as_slice())
+    }
+
+    /// The header row(s) of the crosstab, in the configured header format. When the crosstab is transposed, there is
+    /// a header row for each row header (and carried) column, with the row keys.
+    pub fn header_rows(&self) -> Vec<Vec<String>> {
+        let header_rows = self.layout_header_rows();
+        if !self.config.transpose {
+            return header_rows;
+        }
+        (0..self.config.output_row_headers().len().min(self.transposed_width(&header_rows)))
+            .map(|i| self.transposed_row(&header_rows, i))
+            .collect()
+    }
+
+    /// The data rows of the crosstab, with the cells for every column key in the same order as the header.
+    /// Cells without input data contain the fill value, or are empty if there is none. When the crosstab is
+    /// transposed, there is a data row for each generated column, starting with its header values.
+    pub fn data_rows(&self) -> Vec<Vec<String>> {
+        self.iter_data_rows().collect()
+    }
+
+    /// The data rows of the crosstab, as [`data_rows`](Crosstab::data_rows), each laid out from the cells only when it
+    /// is iterated, so that a writer does not hold every row as text at once. The cells themselves are all in memory.
+    pub fn iter_data_rows(&self) -> Box<dyn Iterator<Item = Vec<String>> + '_> {
+        if !self.config.transpose {
+            return Box::new((0..self.row_keys.len()).map(|r| self.layout_data_row(r)));
+        }
+        let header_rows = self.layout_header_rows();
+        let start = self.config.output_row_headers().len();
+        let width = self.transposed_width(&header_rows);
+        Box::new((start.min(width)..width).map(move |c| self.transposed_row(&header_rows, c)))
+    }
+
+    /// The number of data rows of the crosstab, without laying them out.
+    pub fn n_data_rows(&self) -> usize {
+        match self.config.transpose {
+            true => self.transposed_width(&self.layout_header_rows()).saturating_sub(self.config.output_row_headers().len()),
+            false => self.row_keys.len(),
+        }
+    }
+
+    /// The number of columns of the crosstab, those of its last header row, without laying out its rows.
+    pub fn n_output_columns(&self) -> usize {
+        let header_rows = self.layout_header_rows();
+        match self.config.transpose {
+            true if self.transposed_width(&header_rows) > 0 => header_rows.len() + self.row_keys.len(),
+            true => 0,
+            false => header_rows.last().map_or(0, |r| r.len()),
+        }
+    }
+
+    /// The number of cells at the start of each data row that label it rather than contain values: the row header
+    /// and carried columns, or the header rows of the generated columns when the crosstab is transposed.
+    pub fn n_row_headers(&self) -> usize {
+        match self.config.transpose {
+            true => self.layout_header_rows().len(),
+            false => self.config.output_row_headers().len(),
+        }
+    }
+
+    // The number of rows of the transposed crosstab, which are the columns of the crosstab as it is laid out before
+    // any transposition: the widest of its header rows and data rows
+    fn transposed_width(&self, header_rows: &[Vec<String>]) -> usize {
+        let data_width = match self.row_keys.is_empty() {
+            true => 0,
+            false => self.config.output_row_headers().len() + self.col_keys.len() * self.config.cell_values.len(),
+        };
+        header_rows.iter().map(|r| r.len()).chain([data_width]).max().unwrap_or(0)
+    }
+
+    // The row of the transposed crosstab that is the given column of the crosstab as it is laid out before any
+    // transposition, with the cells of the header rows followed by those of each row key
+    fn transposed_row(&self, header_rows: &[Vec<String>], c: usize) -> Vec<String> {
+        let mut record: Vec<String> = Vec::with_capacity(header_rows.len() + self.row_keys.len());
+        record.extend(header_rows.iter().map(|r| r.get(c).cloned().unwrap_or_default()));
+        record.extend((0..self.row_keys.len()).map(|r| self.layout_cell(r, c)));
+        record
+    }
+
+    // The header rows of the crosstab as it is laid out before any transposition
+    fn layout_header_rows(&self) -> Vec<Vec<String>> {
+        header_rows(
+            self.config.format,
+            &self.config.output_row_headers(),
+            &self.config.col_headers,
+            &self.col_keys,
+            &self.config.value_names(),
+            &self.config.header_names,
+        )
+    }
+
+    // The data row of a row key position, before any transposition
+    fn layout_data_row(&self, r: usize) -> Vec<String> {
+        let n_values = self.config.cell_values.len();
+        let fill = self.config.fill.as_deref().unwrap_or_default();
+        let mut record: Vec<String> = Vec::with_capacity(self.config.output_row_headers().len() + self.col_keys.len() * n_values);
+        record.extend(self.row_keys[r].iter().chain(&self.carried[r]).cloned());
+        for c in 0..self.col_keys.len() {
+            match self.cell(r, c) {
+                Some(cell) => record.extend(cell.iter().map(|v| v.clone().unwrap_or_default())),
+                None => record.extend(std::iter::repeat_n(fill.to_string(), n_values)),
+            }
+        }
+        record
+    }
+
+    // The cell of a data row at the given column position, before any transposition
+    fn layout_cell(&self, r: usize, c: usize) -> String {
+        let labels = self.row_keys[r].len() + self.carried[r].len();
+        if c < labels {
+            return self.row_keys[r].iter().chain(&self.carried[r]).nth(c).cloned().unwrap_or_default();
+        }
+        let n_values = self.config.cell_values.len().max(1);
+        match self.cell(r, (c - labels) / n_values) {
+            Some(cell) => cell.get((c - labels) % n_values).cloned().flatten().unwrap_or_default(),
+            None if (c - labels) / n_values < self.col_keys.len() => self.config.fill.clone().unwrap_or_default(),
+            None => String::new(),
+        }
+    }
+
+    /// The crosstab as a DataFrame, with the format 1 (single row) column names.
+    /// Generated columns whose values are all numbers become Float64 columns, all other columns are strings,
+    /// and cells without input data contain the fill value, or are null if there is none. When the crosstab is
+    /// transposed, the names of the columns of the row keys are their values joined by the separator, and empty
+    /// cells are null.
+    pub fn to_dataframe(&self) -> Result<DataFrame> {
+        if self.config.transpose {
+            let header_rows = self.header_rows();
+            let data_rows = self.data_rows();
+            let names: Vec<String> = (0..header_rows.first().map_or(0, |r| r.len()))
+                .map(|c| {
+                    let cells: Vec<&str> = header_rows.iter().map(|r| r[c].as_str()).filter(|v| !v.is_empty()).collect();
+                    cells.join(&self.config.header_names.separator)
+                })
+                .collect();
+            let columns: Vec<Vec<Option<String>>> = (0..names.len())
+                .map(|c| data_rows.iter().map(|r| Some(r[c].clone()).filter(|v| !v.is_empty())).collect())
+                .collect();
+            return Ok(DataFrame::new(typed_series(&names, columns, self.n_row_headers()))?);
+        }
+        let names = header_rows(
+            1,
+            &self.config.output_row_headers(),
+            &self.config.col_headers,
+            &self.col_keys,
+            &self.config.value_names(),
+            &self.config.header_names,
+        )
+        .remove(0);
+        let n_row_headers = self.config.output_row_headers().len();
+        let n_values = self.config.cell_values.len();
+
+        let mut columns: Vec<Vec<Option<String>>> = vec![Vec::with_capacity(self.row_keys.len()); names.len()];
+        for (r, row_key) in self.row_keys.iter().enumerate() {
+            for (k, value) in row_key.iter().chain(&self.carried[r]).enumerate() {
+                columns[k].push(Some(value.clone()));
+            }
+            for c in 0..self.col_keys.len() {
+                for v in 0..n_values {
+                    let value = match self.cell(r, c) {
+                        Some(cell) => cell[v].clone(),
+                        None => self.config.fill.clone(),
+                    };
+                    columns[n_row_headers + c * n_values + v].push(value);
+                }
+            }
+        }
+
+        Ok(DataFrame::new(typed_series(&names, columns, n_row_headers))?)
+    }
+
+    /// Pivot a lazy query into a lazy query of the crosstab as described by the configuration, with the columns of
+    /// [`to_dataframe`](Crosstab::to_dataframe), so that the pivot can be composed with other lazy steps, such as
+    /// filters and joins before it and after it, and polars can optimize the whole query.
+    ///
+    /// Because the generated columns depend on the values of the data, the distinct column keys of the query are
+    /// collected when the pivot is built. The returned query then groups the rows of the given query by their row keys
+    /// and aggregates the values of each column key, without collecting them. The value columns aggregated with `sum`,
+    /// `mean`, `min`, `max`, or `count` are Float64 columns, and those aggregated with `first` or `last` keep numbers
+    /// as Float64 and everything else as strings.
+    ///
+    /// Only the crosstabs that the query can express can be pivoted lazily: crosstabs without totals, subtotals,
+    /// percentages, ranks, cumulative values, changes, statistics or cell templates, value formats, companion,
+    /// non-detect, weight, or carried columns, top columns, dropped empty rows or columns, or transposition, whose
+    /// values are aggregated with `first`, `last`, `sum`, `mean`, `min`, `max`, or `count`, whose missing keys are kept
+    /// or dropped, and whose rows are sorted in ascending or descending order, without formatted dates as row keys.
+    /// The other crosstabs are an error; build them with [`Crosstab::new`] instead.
+    pub fn lazy(lf: LazyFrame, config: &XtabConfig) -> Result<LazyFrame> {
+        let schema = lf.schema()?;
+        let col_names: Vec<&str> = schema.iter_names().map(|name| name.as_str()).collect();
+        let config = match config.by_index {
+            true => resolve_indices(&col_names, config)?,
+            false => config.clone(),
+        };
+        let config = expand_patterns(&col_names, &resolve_names(&col_names, &config))?;
+        let errors = missing_columns(&col_names, &config);
+        if !errors.is_empty() {
+            return Err(XtabError::MissingColumns(errors).into());
+        }
+        if config.aggregations.len() != config.cell_values.len() {
+            bail!("There must be one aggregation for each value column");
+        }
+        if let Some(key) = config.row_sort.iter().find(|k| !config.row_headers.contains(&k.column)) {
+            bail!("The sorted column '{}' is not one of the row header columns", key.column);
+        }
+        if let Some(unsupported) = lazy_unsupported(&config, &schema) {
+            let message = format!("The lazy pivot cannot build a crosstab with {}; build it with Crosstab::new instead", unsupported);
+            return Err(XtabError::Argument(message).into());
+        }
+        let headers: Vec<String> = config.row_headers.iter().chain(&config.col_headers).cloned().collect();
+        let lf = match config.null_keys {
+            NullKeys::Drop => lf.drop_nulls(Some(headers.iter().map(|name| col(name)).collect())),
+            _ => lf,
+        };
+
+        // Collect the distinct column keys, in order of appearance, and order them as the crosstab does, by building
+        // the crosstab of the keys alone. Each distinct key is then numbered by its generated columns
+        let col_exprs: Vec<Expr> = config.col_headers.iter().map(|name| col(name)).collect();
+        let mut distinct = lf.clone().select(&col_exprs).unique_stable(None, UniqueKeepStrategy::First).collect()?;
+        let mut keys_config = XtabConfig::new(&[], &config.col_headers, &[]);
+        keys_config.col_sort = config.col_sort;
+        keys_config.col_order = config.col_order.clone();
+        keys_config.col_product = config.col_product;
+        keys_config.null_keys = config.null_keys;
+        keys_config.null_label = config.null_label.clone();
+        keys_config.date_format = config.date_format.clone();
+        let col_keys: Vec<Key> = Crosstab::new(&distinct, &keys_config)?.col_keys().to_vec();
+        let n_values = config.cell_values.len().max(1);
+        if let Some(max_columns) = config.max_columns.filter(|max| col_keys.len().saturating_mul(n_values) > *max) {
+            bail!(
+                "The column header columns would generate {} output columns ({} distinct combinations of their values \
+                 for each of {} value columns), more than the maximum of {}",
+                col_keys.len() * n_values,
+                col_keys.len(),
+                n_values,
+                max_columns
+            );
+        }
+        let col_index: HashMap<&Key, u32> = col_keys.iter().enumerate().map(|(i, key)| (key, i as u32)).collect();
+        let (distinct_keys, _) = key_strings(&distinct, &config.col_headers, &config.null_label, config.date_format.as_deref())?;
+        let positions: Vec<Option<u32>> = distinct_keys.iter().map(|key| col_index.get(key).copied()).collect();
+        distinct.with_column(Series::new(LAZY_COL_LABEL, positions))?;
+
+        // Aggregate the values of each column key of each row key, as the named generated columns
+        let value_names = match config.cell_values.is_empty() {
+            true => vec![COUNT_LABEL.to_string()],
+            false => config.value_names(),
+        };
+        let names = header_rows(1, &config.row_headers, &config.col_headers, &col_keys, &value_names, &config.header_names).remove(0);
+        // A fill value that is not a number makes the cells strings
+        let fill: Option<(Expr, bool)> = config.fill.as_ref().map(|fill| match fill.trim().parse::<f64>() {
+            Ok(number) => (lit(number), true),
+            Err(_) => (lit(fill.clone()), false),
+        });
+        let mut cells: Vec<Expr> = Vec::new();
+        for c in 0..col_keys.len() {
+            let in_key = col(LAZY_COL_LABEL).eq(lit(c as u32));
+            for v in 0..n_values {
+                let value = match config.cell_values.get(v) {
+                    Some(name) => lazy_aggregation(col(name).filter(in_key.clone()), config.aggregations[v], schema.get(name)),
+                    None => in_key.clone().sum().cast(DataType::Float64),
+                };
+                let cell = match &fill {
+                    Some((fill, true)) => when(in_key.clone().any(false)).then(value).otherwise(fill.clone()),
+                    Some((fill, false)) => when(in_key.clone().any(false)).then(value.cast(DataType::String)).otherwise(fill.clone()),
+                    None => when(in_key.clone().any(false)).then(value).otherwise(lit(NULL)),
+                };
+                cells.push(cell.alias(&names[config.row_headers.len() + c * n_values + v]));
+            }
+        }
+
+        // Group the rows by their row keys, as strings with the null label for missing values, in the order of the
+        // sorted row header columns and then the others
+        let row_exprs: Vec<Expr> = config
+            .row_headers
+            .iter()
+            .map(|name| col(name).cast(DataType::String).fill_null(lit(config.null_label.clone())).alias(name))
+            .collect();
+        let mut order: Vec<(Expr, bool)> = config.row_sort.iter().map(|k| (col(&k.column), k.order == SortOrder::Desc)).collect();
+        for name in config.row_headers.iter().filter(|name| !config.row_sort.iter().any(|k| &&k.column == name)) {
+            order.push((col(name), false));
+        }
+        let (order, descending): (Vec<Expr>, Vec<bool>) = order.into_iter().unzip();
+        let join_args = JoinArgs { join_nulls: true, ..JoinArgs::new(JoinType::Inner) };
+        Ok(lf
+            .join(distinct.lazy(), &col_exprs, &col_exprs, join_args)
+            .group_by(row_exprs)
+            .agg(cells)
+            .sort_by_exprs(order, descending, false, false))
+    }
+
+    /// Write the header row(s) and data rows of the crosstab as CSV, or as delimited text with another delimiter.
+    pub fn write_csv<W: Write>(&self, writer: W, write_config: &WriteConfig) -> Result<()> {
+        CsvWriter::new(writer, write_config).write(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized() -> DataFrame {
+        df!(
+            "site" => &["B02", "A01", "A01", "B02", "A01"],
+            "date" => &["2024-01-02", "2024-01-01", "2024-01-02", "2024-01-01", "2024-01-01"],
+            "result" => &[3.1, 1.5, 2.5, 4.0, 9.9],
+        )
+        .unwrap()
+    }
+
+    // Test that a lazy query is pivoted into a lazy query that can be composed with further steps
+    #[test]
+    fn test_lazy() {
+        let lf = normalized().lazy().filter(col("result").lt(lit(9.0)));
+        let config = XtabConfig::new(&["SITE"], &["date"], &["result"]);
+        let crosstab = Crosstab::lazy(lf, &config).unwrap().filter(col("site").eq(lit("B02"))).collect().unwrap();
+        assert_eq!(crosstab.get_column_names(), vec!["site", "2024-01-01_result", "2024-01-02_result"]);
+        assert_eq!(crosstab.height(), 1);
+        assert_eq!(crosstab.column("2024-01-01_result").unwrap().f64().unwrap().get(0), Some(4.0));
+        assert!(Crosstab::lazy(normalized().lazy(), &XtabConfig::new(&["site"], &["day"], &["result"])).is_err());
+    }
+
+    // Test that a lazy pivot aggregates the cells like the crosstab, and that it refuses what it cannot express
+    #[test]
+    fn test_lazy_aggregation() {
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result", "result"]);
+        config.aggregations = vec![Aggregation::Sum, Aggregation::Count];
+        config.value_labels = vec![None, Some("n".to_string())];
+        config.col_sort = SortOrder::Desc;
+        config.fill = Some("0".to_string());
+        let lf = normalized().lazy().filter(col("site").neq(lit("B02")).or(col("date").neq(lit("2024-01-02"))));
+        let expected = Crosstab::new(&lf.clone().collect().unwrap(), &config).unwrap().to_dataframe().unwrap();
+        assert_eq!(Crosstab::lazy(lf, &config).unwrap().collect().unwrap(), expected);
+        config.row_totals = true;
+        assert!(Crosstab::lazy(normalized().lazy(), &config).is_err_and(|e| e.to_string().contains("totals")));
+    }
+
+    // Test that the cells aggregated in a lazy query build the same crosstab as the input rows, and that crosstabs
+    // that need the input rows have no such query
+    #[test]
+    fn test_aggregate_cells() {
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result", "result"]);
+        config.aggregations = vec![Aggregation::Sum, Aggregation::Count];
+        config.value_labels = vec![None, Some("n".to_string())];
+        config.col_totals = true;
+        let (cells, cells_config) = aggregate_cells(&normalized().lazy(), &config, &[]).unwrap().unwrap();
+        let cells = cells.collect().unwrap();
+        assert_eq!(cells.height(), 4);
+        assert_eq!(input_rows(&cells), 5);
+        let expected = Crosstab::new(&normalized(), &config).unwrap().data_rows();
+        assert_eq!(Crosstab::new(&cells, &cells_config).unwrap().data_rows(), expected);
+        let config = XtabConfig::new(&["site"], &["date"], &[]);
+        let (cells, cells_config) = aggregate_cells(&normalized().lazy(), &config, &[]).unwrap().unwrap();
+        assert_eq!(Crosstab::new(&cells.collect().unwrap(), &cells_config).unwrap().data_rows(), Crosstab::new(&normalized(), &config).unwrap().data_rows());
+        assert!(aggregate_cells(&normalized().lazy(), &XtabConfig::new(&["site"], &["date"], &["result"]), &[]).unwrap().is_none());
+    }
+
+    // Test that the DataFrame of a crosstab has typed value columns and nulls for cells without input data
+    #[test]
+    fn test_to_dataframe() {
+        let df = df!(
+            "site" => &["A01", "B02"],
+            "date" => &["2024-01-01", "2024-01-02"],
+            "result" => &[1.5, 3.1],
+        )
+        .unwrap();
+        let crosstab = Crosstab::new(&df, &XtabConfig::new(&["site"], &["date"], &["result"])).unwrap();
+        let output = crosstab.to_dataframe().unwrap();
+        assert_eq!(output.get_column_names(), &["site", "2024-01-01_result", "2024-01-02_result"]);
+        assert_eq!(output.column("2024-01-01_result").unwrap().dtype(), &DataType::Float64);
+        assert_eq!(output.column("2024-01-02_result").unwrap().null_count(), 1);
+    }
+
+    // Test that cells without input data contain the fill value
+    #[test]
+    fn test_fill() {
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result"]);
+        config.fill = Some("0".to_string());
+        let df = df!("site" => &["A01", "B02"], "date" => &["2024-01-01", "2024-01-02"], "result" => &[1.5, 3.1]).unwrap();
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.data_rows()[0], vec!["A01", "1.5", "0"]);
+        let output = crosstab.to_dataframe().unwrap();
+        assert_eq!(output.column("2024-01-02_result").unwrap().null_count(), 0);
+        assert_eq!(output.column("2024-01-02_result").unwrap().dtype(), &DataType::Float64);
+    }
+
+    // Test that columns given by their positions are resolved to the names of the input columns
+    #[test]
+    fn test_resolve_indices() {
+        let col_names = ["site", "date", "result"];
+        let mut config = XtabConfig::new(&["1"], &["2"], &["3"]);
+        config.by_index = true;
+        config.row_sort = vec![SortKey { column: "1".to_string(), order: SortOrder::Desc }];
+        let resolved = resolve_indices(&col_names, &config).unwrap();
+        assert_eq!(resolved.row_headers, vec!["site".to_string()]);
+        assert_eq!(resolved.col_headers, vec!["date".to_string()]);
+        assert_eq!(resolved.cell_values, vec!["result".to_string()]);
+        assert_eq!(resolved.row_sort[0].column, "site");
+        assert!(!resolved.by_index);
+
+        let error = resolve_indices(&col_names, &XtabConfig::new(&["1"], &["0"], &["4"])).unwrap_err();
+        assert_eq!(crate::error::exit_code(&error), 3);
+        assert!(error.to_string().contains("The value column 4 is not in the input file, which has 3 columns"));
+        assert_eq!(crate::error::exit_code(&resolve_indices(&col_names, &XtabConfig::new(&["site"], &["2"], &["3"])).unwrap_err()), 2);
+    }
+
+    // Test that carried columns are written once per output row, and that different values for a row key are an error
+    #[test]
+    fn test_carry() {
+        let df = df!(
+            "site" => &["A01", "A01", "B02"],
+            "date" => &["2024-01-01", "2024-01-02", "2024-01-01"],
+            "result" => &[1.5, 2.5, 4.0],
+            "lat" => &[Some("45.1"), None, Some("46.2")],
+        )
+        .unwrap();
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result"]);
+        config.carry = vec!["lat".to_string()];
+        config.col_totals = true;
+        config.aggregations = vec![Aggregation::Sum];
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.header_rows(), vec![vec!["site", "lat", "2024-01-01_result", "2024-01-02_result"]]);
+        assert_eq!(crosstab.data_rows()[0], vec!["A01", "45.1", "1.5", "2.5"]);
+        assert_eq!(crosstab.data_rows()[2], vec!["Total", "", "5.5", "2.5"]);
+        assert_eq!(crosstab.to_dataframe().unwrap().get_column_names(), &["site", "lat", "2024-01-01_result", "2024-01-02_result"]);
+
+        let df = df!("site" => &["A01", "A01"], "date" => &["d1", "d2"], "result" => &[1, 2], "lat" => &["45.1", "45.2"]).unwrap();
+        let error = Crosstab::new(&df, &config).unwrap_err().to_string();
+        assert_eq!(error, "The carried column 'lat' has more than one value for the row key [\"A01\"]: '45.1' and '45.2'");
+    }
+
+    // Test that the generated columns are every combination of the column header values with the product option
+    #[test]
+    fn test_col_product() {
+        let df = df!(
+            "site" => &["A01", "A01", "B02"],
+            "year" => &["2023", "2024", "2024"],
+            "analyte" => &["Pb", "Zn", "Pb"],
+            "result" => &[1.5, 2.5, 4.0],
+        )
+        .unwrap();
+        let mut config = XtabConfig::new(&["site"], &["year", "analyte"], &["result"]);
+        assert_eq!(Crosstab::new(&df, &config).unwrap().col_keys().len(), 3);
+        config.col_product = true;
+        config.format = 3;
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        let key = |year: &str, analyte: &str| vec![year.to_string(), analyte.to_string()];
+        assert_eq!(crosstab.col_keys(), &[key("2023", "Pb"), key("2023", "Zn"), key("2024", "Pb"), key("2024", "Zn")]);
+        assert_eq!(crosstab.header_rows()[0], vec!["", "2023", "2023", "2024", "2024"]);
+        assert_eq!(crosstab.header_rows()[1], vec!["", "Pb", "Zn", "Pb", "Zn"]);
+        assert_eq!(crosstab.data_rows()[1], vec!["B02", "", "", "4.0", ""]);
+        assert!(key_product(&[]).is_empty());
+
+        config.max_columns = Some(3);
+        let error = Crosstab::new(&df, &config).unwrap_err().to_string();
+        assert!(error.starts_with("The column header columns would generate 4 output columns"), "{}", error);
+    }
+
+    // Test that the rows and column keys without values are left out, and that the remaining cells stay in place
+    #[test]
+    fn test_drop_empty() {
+        let df = df!(
+            "site" => &["A01", "A01", "B02", "C03"],
+            "year" => &["2023", "2024", "2024", "2023"],
+            "analyte" => &["Pb", "Zn", "Pb", "Zn"],
+            "result" => &[Some(1.5), Some(2.5), Some(4.0), None],
+        )
+        .unwrap();
+        let mut config = XtabConfig::new(&["site"], &["year", "analyte"], &["result"]);
+        config.col_product = true;
+        config.row_totals = true;
+        config.aggregations = vec![Aggregation::Sum];
+        assert_eq!(Crosstab::new(&df, &config).unwrap().col_keys().len(), 5);
+        config.drop_empty_cols = true;
+        config.drop_empty_rows = true;
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.header_rows()[0], vec!["site", "2023_Pb_result", "2024_Pb_result", "2024_Zn_result", "Total_Total_result"]);
+        assert_eq!(crosstab.data_rows(), vec![vec!["A01", "1.5", "", "2.5", "4"], vec!["B02", "", "4", "", "4"]]);
+    }
+
+    // Test that the transposed crosstab has the row keys across the top and the generated columns down the side
+    #[test]
+    fn test_transpose() {
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result"]);
+        config.aggregations = vec![Aggregation::Max];
+        config.transpose = true;
+        let crosstab = Crosstab::new(&normalized(), &config).unwrap();
+        assert_eq!(crosstab.header_rows(), vec![vec!["site", "A01", "B02"]]);
+        assert_eq!(crosstab.data_rows(), vec![vec!["2024-01-01_result", "9.9", "4"], vec!["2024-01-02_result", "2.5", "3.1"]]);
+        assert_eq!(crosstab.n_row_headers(), 1);
+        assert_eq!(crosstab.n_data_rows(), 2);
+        assert_eq!(crosstab.n_output_columns(), 3);
+        let df = crosstab.to_dataframe().unwrap();
+        assert_eq!(df.get_column_names(), vec!["site", "A01", "B02"]);
+        assert_eq!(df.column("B02").unwrap().dtype(), &DataType::Float64);
+
+        config.format = 2;
+        let crosstab = Crosstab::new(&normalized(), &config).unwrap();
+        assert_eq!(crosstab.n_row_headers(), 2);
+        assert_eq!(crosstab.data_rows()[0], vec!["2024-01-01", "result", "9.9", "4"]);
+    }
+
+    // Test that the companion values are written after their values, in their own columns or as suffixes
+    #[test]
+    fn test_pairs() {
+        let df = df!(
+            "site" => &["A01", "A01", "B02"],
+            "date" => &["2024-01-01", "2024-01-01", "2024-01-01"],
+            "result" => &[0.5, 2.5, 1.5],
+            "qual" => &[Some("U"), Some("J"), None],
+        )
+        .unwrap();
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result"]);
+        config.aggregations = vec![Aggregation::Max];
+        config.pairs = vec![("result".to_string(), "qual".to_string())];
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.header_rows(), vec![vec!["site", "2024-01-01_result", "2024-01-01_qual"]]);
+        assert_eq!(crosstab.data_rows(), vec![vec!["A01", "2.5", "J"], vec!["B02", "1.5", ""]]);
+
+        config.pair_style = PairStyle::Suffix;
+        config.aggregations = vec![Aggregation::Min];
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.data_rows(), vec![vec!["A01", "0.5 U"], vec!["B02", "1.5"]]);
+
+        config.aggregations = vec![Aggregation::Sum];
+        config.pair_conflict = PairConflict::Error;
+        assert!(Crosstab::new(&df, &config).is_err());
+        config.pairs = vec![("unit".to_string(), "qual".to_string())];
+        assert!(Crosstab::new(&df, &config).is_err());
+    }
+
+    // Test that the values flagged by the qualifier column are non-detects, which are substituted in sums
+    #[test]
+    fn test_nondetects() {
+        let df = df!(
+            "site" => &["A01", "A01", "B02", "B02"],
+            "date" => &["2024-01-01"; 4],
+            "result" => &["0.5", "2", "<1", "0.4"],
+            "qual" => &[Some("u"), None, None, Some("U")],
+        )
+        .unwrap();
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result"]);
+        config.aggregations = vec![Aggregation::MaxNd];
+        config.nd_column = Some("qual".to_string());
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.data_rows(), vec![vec!["A01", "2"], vec!["B02", "<1"]]);
+
+        config.aggregations = vec![Aggregation::Sum];
+        assert!(Crosstab::new(&df, &config).is_err());
+        config.nd_substitute = Some(NdSubstitute::Half);
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.data_rows(), vec![vec!["A01", "2.25"], vec!["B02", "0.7"]]);
+    }
+
+    // Test that each cell writes the statistics of its values in the template, including the totals
+    #[test]
+    fn test_stats_template() {
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result"]);
+        config.stats_template = Some("{mean} ± {sd} ({n})".to_string());
+        config.row_totals = true;
+        let crosstab = Crosstab::new(&normalized(), &config).unwrap();
+        assert_eq!(crosstab.data_rows()[0], vec!["A01", "5.7 ± 5.939696961967 (2)", "2.5 ± NA (1)", "4.63333333333333 ± 4.58839114868527 (3)"]);
+
+        config.stats_template = Some("{median}".to_string());
+        assert!(Crosstab::new(&normalized(), &config).is_err());
+    }
+
+    // Test that each cell writes its aggregated value and its count in the template, in the value format
+    #[test]
+    fn test_cell_template() {
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result"]);
+        config.aggregations = vec![Aggregation::Mean];
+        config.cell_template = Some("{value} ({count})".to_string());
+        config.value_formats = vec![Some("%.1f".parse().unwrap())];
+        let crosstab = Crosstab::new(&normalized(), &config).unwrap();
+        assert_eq!(crosstab.data_rows()[0], vec!["A01", "5.7 (2)", "2.5 (1)"]);
+
+        config.cell_template = Some("{value} ({median})".to_string());
+        assert!(Crosstab::new(&normalized(), &config).is_err());
+    }
+
+    // Test that the table of values leaves out the totals and subtotals
+    #[test]
+    fn test_value_table() {
+        let mut config = XtabConfig::new(&["site", "date"], &["unit"], &[]);
+        config.subtotals = true;
+        config.row_totals = true;
+        config.col_totals = true;
+        let df = df!("site" => &["A01", "A01", "B02"], "date" => &["d1", "d2", "d1"], "unit" => &["mg/L", "ug/L", "mg/L"]).unwrap();
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.value_table().unwrap(), vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 0.0]]);
+    }
+
+    // Test that the values are ranked within their rows or columns, with ties sharing a rank
+    #[test]
+    fn test_rank() {
+        let df = df!(
+            "site" => &["A01", "A01", "A01", "B02", "B02"],
+            "date" => &["d1", "d2", "d3", "d1", "d2"],
+            "result" => &[2.5, 1.5, 2.5, 0.5, 9.9],
+        )
+        .unwrap();
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result"]);
+        config.rank = Some(Rank::Row);
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.data_rows(), vec![vec!["A01", "2", "1", "2"], vec!["B02", "1", "2", ""]]);
+        config.rank = Some(Rank::Col);
+        config.rank_descending = true;
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.data_rows(), vec![vec!["A01", "1", "2", "1"], vec!["B02", "2", "1", ""]]);
+    }
+
+    // Test that the values accumulate along each row, also through the cells without input data
+    #[test]
+    fn test_cumulative() {
+        let df = df!(
+            "site" => &["A01", "A01", "B02", "B02"],
+            "month" => &["01", "03", "02", "03"],
+            "result" => &[1.5, 2.5, 0.5, 9.9],
+        )
+        .unwrap();
+        let mut config = XtabConfig::new(&["site"], &["month"], &["result"]);
+        config.cumulative = true;
+        config.row_totals = true;
+        config.aggregations = vec![Aggregation::Sum];
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.data_rows(), vec![vec!["A01", "1.5", "1.5", "4", "4"], vec!["B02", "", "0.5", "10.4", "10.4"]]);
+    }
+
+    // Test that each value is replaced by its change from the previous column
+    #[test]
+    fn test_delta() {
+        let df = df!(
+            "site" => &["A01", "A01", "A01", "B02", "B02"],
+            "month" => &["01", "02", "03", "01", "03"],
+            "result" => &[2.0, 2.5, 1.5, 0.0, 9.9],
+        )
+        .unwrap();
+        let mut config = XtabConfig::new(&["site"], &["month"], &["result"]);
+        config.delta = Some(Delta::Abs);
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.data_rows(), vec![vec!["A01", "", "0.5", "-1"], vec!["B02", "", "", ""]]);
+        config.delta = Some(Delta::Pct);
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.data_rows()[0], vec!["A01", "", "25", "-40"]);
+    }
+
+    // Test that the means and the counts are weighted by the weight column
+    #[test]
+    fn test_weight() {
+        let df = df!(
+            "site" => &["A01", "A01", "B02"],
+            "date" => &["d1", "d1", "d1"],
+            "result" => &[1.0, 4.0, 2.5],
+            "volume" => &[2.0, 1.0, 0.5],
+        )
+        .unwrap();
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result"]);
+        config.aggregations = vec![Aggregation::Mean];
+        config.weight = Some("volume".to_string());
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.data_rows(), vec![vec!["A01", "2"], vec!["B02", "2.5"]]);
+        let mut config = XtabConfig::new(&["site"], &["date"], &[]);
+        config.weight = Some("volume".to_string());
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.data_rows(), vec![vec!["A01", "3"], vec!["B02", "0.5"]]);
+    }
+
+    // Test that only the most frequent column keys are kept, and that the rest are aggregated in the last column
+    #[test]
+    fn test_top_cols() {
+        let df = df!(
+            "site" => &["A01", "A01", "A01", "A01", "B02", "B02"],
+            "analyte" => &["Zn", "As", "Pb", "Zn", "Pb", "Cu"],
+        )
+        .unwrap();
+        let mut config = XtabConfig::new(&["site"], &["analyte"], &[]);
+        config.top_cols = Some(2);
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.header_rows()[0], vec!["site", "Pb_count", "Zn_count", "Other_count"]);
+        assert_eq!(crosstab.data_rows()[0], vec!["A01", "1", "2", "1"]);
+        assert_eq!(crosstab.data_rows()[1], vec!["B02", "1", "", "1"]);
+
+        config.top_cols = Some(4);
+        config.other_label = "Rest".to_string();
+        assert_eq!(Crosstab::new(&df, &config).unwrap().col_keys().len(), 4);
+    }
+
+    // Test that every missing column is reported, not just the first
+    #[test]
+    fn test_missing_columns() {
+        let col_names = ["site", "date", "result"];
+        assert!(missing_columns(&col_names, &XtabConfig::new(&["site"], &["date"], &["result"])).is_empty());
+        assert_eq!(
+            missing_columns(&col_names, &XtabConfig::new(&["site", "Site"], &["month"], &["result", "unit"])),
+            vec![
+                "The row header column 'Site' is not in the input file; did you mean `site`?".to_string(),
+                "The column header column 'month' is not in the input file".to_string(),
+                "The value column 'unit' is not in the input file".to_string(),
+            ]
+        );
+    }
+
+    // Test the keys, cells, and duplicates of a crosstab built directly from a DataFrame
+    #[test]
+    fn test_crosstab() {
+        let crosstab = Crosstab::new(&normalized(), &XtabConfig::new(&["site"], &["date"], &["result"])).unwrap();
+        assert_eq!(crosstab.row_keys(), &[vec!["A01".to_string()], vec!["B02".to_string()]]);
+        assert_eq!(crosstab.col_keys().len(), 2);
+        assert_eq!(crosstab.cell(0, 0), Some(&[Some("1.5".to_string())][..]));
+        assert_eq!(
+            crosstab.duplicates(),
+            &[(vec!["A01".to_string()], vec!["2024-01-01".to_string()])]
+        );
+        assert_eq!(crosstab.duplicate_rows(), &[vec![1, 4]]);
+        assert_eq!(crosstab.n_data_rows(), 2);
+        assert_eq!(
+            crosstab.data_rows(),
+            vec![
+                vec!["A01".to_string(), "1.5".to_string(), "2.5".to_string()],
+                vec!["B02".to_string(), "4.0".to_string(), "3.1".to_string()],
+            ]
+        );
+    }
+
+    // Test that the table of duplicates lists every input row of each cell with multiple values
+    #[test]
+    fn test_duplicates_table() {
+        let df = normalized();
+        let crosstab = Crosstab::new(&df, &XtabConfig::new(&["site"], &["date"], &["result"])).unwrap();
+        let expected = df!(
+            INPUT_ROW_LABEL => &[2u64, 5],
+            "site" => &["A01", "A01"],
+            "date" => &["2024-01-01", "2024-01-01"],
+            "result" => &[1.5, 9.9],
+        )
+        .unwrap();
+        assert_eq!(crosstab.duplicates_table(&df).unwrap(), expected);
+    }
+
+    // Test that rows with missing header values are labeled, dropped, or an error
+    #[test]
+    fn test_null_keys() {
+        let df = df!(
+            "site" => &[Some("A01"), None, Some("B02")],
+            "date" => &[Some("2024-01-01"), Some("2024-01-01"), None],
+            "result" => &[1.5, 2.5, 3.5],
+        )
+        .unwrap();
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result"]);
+        config.null_label = "<NULL>".to_string();
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.header_rows()[0], vec!["site", "2024-01-01_result", "<NULL>_result"]);
+        assert_eq!(crosstab.data_rows()[0], vec!["<NULL>", "2.5", ""]);
+
+        config.null_keys = NullKeys::Drop;
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.data_rows(), vec![vec!["A01".to_string(), "1.5".to_string()]]);
+        assert_eq!(crosstab.dropped_rows(), 2);
+
+        config.null_keys = NullKeys::Error;
+        assert!(Crosstab::new(&df, &config).is_err());
+    }
+
+    // Test that date column headers are written in the date format, combining the dates that are written alike,
+    // and sorted chronologically
+    #[test]
+    fn test_date_format() {
+        let df = df!(
+            "site" => &["A01", "A01", "A01"],
+            "date" => &["2024-03-05", "2023-12-01", "2024-03-01"],
+            "result" => &[1, 2, 3],
+        )
+        .unwrap();
+        let df = crate::dates::parse_dates(df.lazy(), &[("date".to_string(), "%Y-%m-%d".to_string())]).collect().unwrap();
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result"]);
+        config.aggregations = vec![Aggregation::Sum];
+        config.date_format = Some("%b %Y".to_string());
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.header_rows()[0], vec!["site", "Dec 2023_result", "Mar 2024_result"]);
+        assert_eq!(crosstab.data_rows()[0], vec!["A01", "2", "4"]);
+    }
+
+    // Test that the totals row and columns aggregate all of the input values of each row and column
+    #[test]
+    fn test_totals() {
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result"]);
+        config.aggregations = vec![Aggregation::Sum];
+        config.row_totals = true;
+        config.col_totals = true;
+        let crosstab = Crosstab::new(&normalized(), &config).unwrap();
+        assert_eq!(crosstab.header_rows()[0].last().unwrap(), "Total_result");
+        let rows = crosstab.data_rows();
+        assert_eq!(rows[0], vec!["A01", "11.4", "2.5", "13.9"]);
+        assert_eq!(rows[2], vec!["Total", "15.4", "5.6", "21"]);
+    }
+
+    // Test that a subtotal row follows each group of rows at every level above the last row header
+    #[test]
+    fn test_subtotals() {
+        let df = df!(
+            "site" => &["A01", "A01", "A01", "B02"],
+            "station" => &["S1", "S1", "S2", "S1"],
+            "depth" => &["0", "5", "0", "0"],
+            "date" => &["2024-01-01"; 4],
+            "result" => &[1.0, 2.0, 4.0, 8.0],
+        )
+        .unwrap();
+        let mut config = XtabConfig::new(&["site", "station", "depth"], &["date"], &["result"]);
+        config.aggregations = vec![Aggregation::Sum];
+        config.subtotals = true;
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        let rows: Vec<Vec<String>> = crosstab.data_rows();
+        let labels: Vec<&[String]> = rows.iter().map(|r| &r[..3]).collect();
+        assert_eq!(
+            labels,
+            vec![
+                &["A01", "S1", "0"][..],
+                &["A01", "S1", "5"],
+                &["A01", "S1 — subtotal", ""],
+                &["A01", "S2", "0"],
+                &["A01", "S2 — subtotal", ""],
+                &["A01 — subtotal", "", ""],
+                &["B02", "S1", "0"],
+                &["B02", "S1 — subtotal", ""],
+                &["B02 — subtotal", "", ""],
+            ]
+        );
+        assert_eq!(rows[2][3], "3");
+        assert_eq!(rows[5][3], "7");
+    }
+
+    // Test that a crosstab without value columns counts the input rows of each cell
+    #[test]
+    fn test_count() {
+        let mut config = XtabConfig::new(&["site"], &["date"], &[]);
+        config.col_totals = true;
+        let crosstab = Crosstab::new(&normalized(), &config).unwrap();
+        assert_eq!(crosstab.header_rows()[0], vec!["site", "2024-01-01_count", "2024-01-02_count"]);
+        assert_eq!(crosstab.data_rows()[0], vec!["A01", "2", "1"]);
+        assert_eq!(crosstab.data_rows()[2], vec!["Total", "3", "2"]);
+        assert!(crosstab.duplicates().len() == 1);
+        assert_eq!(crosstab.duplicate_counts(), vec![2]);
+    }
+
+    // Test that the generated columns follow an explicit order of column header values
+    #[test]
+    fn test_col_order() {
+        let df = df!("site" => &["A01", "A01", "A01"], "month" => &["Feb", "Jan", "Dec"], "result" => &[1, 2, 3]).unwrap();
+        let mut config = XtabConfig::new(&["site"], &["month"], &["result"]);
+        config.col_order = vec!["Jan".to_string(), "Feb".to_string(), "Dec".to_string()];
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.header_rows()[0], vec!["site", "Jan_result", "Feb_result", "Dec_result"]);
+        config.col_order = Vec::new();
+        config.col_sort = SortOrder::Desc;
+        let crosstab = Crosstab::new(&df, &config).unwrap();
+        assert_eq!(crosstab.header_rows()[0], vec!["site", "Jan_result", "Feb_result", "Dec_result"]);
+    }
+
+    // Test that values are expressed as percentages of their row, column, and grand totals
+    #[test]
+    fn test_percent() {
+        let df = df!(
+            "site" => &["A01", "A01", "B02", "B02"],
+            "date" => &["2024-01-01", "2024-01-02", "2024-01-01", "2024-01-02"],
+            "result" => &[1.0, 3.0, 2.0, 2.0],
+        )
+        .unwrap();
+        let mut config = XtabConfig::new(&["site"], &["date"], &["result"]);
+        config.row_totals = true;
+        config.aggregations = vec![Aggregation::Sum];
+        let mut rows = |percent| {
+            config.percent = Some(percent);
+            Crosstab::new(&df, &config).unwrap().data_rows()
+        };
+        assert_eq!(rows(Percent::Row)[0], vec!["A01", "25", "75", "100"]);
+        assert_eq!(rows(Percent::Col)[0], vec!["A01", "33.3333333333333", "60", "50"]);
+        assert_eq!(rows(Percent::Total)[1], vec!["B02", "25", "25", "50"]);
+    }
+}
@@ -0,0 +1,98 @@
+//! Dates: parsing text columns as dates, and formatting date header values so that they render consistently and
+//! sort chronologically.
+
+use anyhow::{anyhow, Result};
+use polars::prelude::*;
+use std::collections::HashMap;
+
+// The strftime fields of the time of day. A format without any of them is parsed as a date without a time
+const TIME_FIELDS: [&str; 11] = ["%H", "%I", "%M", "%S", "%T", "%R", "%p", "%f", "%.f", "%s", "%c"];
+
+/// Parse the `--parse-dates` specifications, each a `column=format` pair such as `date=%m/%d/%y`, where the format
+/// uses the strftime fields (e.g. `%Y-%m-%d`).
+pub fn parse_date_specs(specs: &[String]) -> Result<Vec<(String, String)>, String> {
+    specs
+        .iter()
+        .map(|spec| match spec.split_once('=') {
+            Some((column, format)) if !column.trim().is_empty() && !format.is_empty() => Ok((column.trim().to_string(), format.to_string())),
+            _ => Err(format!("The date specification '{}' must be a column=format pair, e.g. date=%Y-%m-%d", spec)),
+        })
+        .collect()
+}
+
+/// Plan the parsing of each of the columns as dates in its format. A format with a time of day gives a datetime
+/// column. Values that are missing stay missing, and any other value that does not match the format is an error.
+pub fn parse_dates(lf: LazyFrame, columns: &[(String, String)]) -> LazyFrame {
+    if columns.is_empty() {
+        return lf;
+    }
+    let exprs: Vec<Expr> = columns
+        .iter()
+        .map(|(name, format)| {
+            let options = StrptimeOptions { format: Some(format.clone()), strict: true, exact: true, cache: true };
+            let text = col(name).cast(DataType::String).str();
+            match TIME_FIELDS.iter().any(|field| format.contains(field)) {
+                true => text.to_datetime(None, None, options, lit("raise")),
+                false => text.to_date(options),
+            }
+        })
+        .collect();
+    lf.with_columns(exprs)
+}
+
+// The written values of a date column, and the chronological rank of each distinct written value
+pub(crate) type DateStrings = (Vec<Option<String>>, HashMap<String, usize>);
+
+/// The values of a date or datetime column written in the format, with the position in chronological order of each
+/// distinct written value (the order of its earliest date), or `None` if the column is not a date column.
+pub(crate) fn format_dates(series: &Series, format: &str) -> Result<Option<DateStrings>> {
+    // Dates are formatted as datetimes at midnight, whose formatting checks the format instead of panicking
+    let datetimes = match series.dtype() {
+        DataType::Date => series.cast(&DataType::Datetime(TimeUnit::Milliseconds, None))?,
+        DataType::Datetime(_, _) => series.clone(),
+        _ => return Ok(None),
+    };
+    let text = datetimes.datetime()?.to_string(format).map_err(|_| anyhow!("The date format '{}' is invalid", format))?;
+    let values: Vec<Option<String>> = text.into_iter().map(|v| v.map(|s| s.to_string())).collect();
+    let instants = series.to_physical_repr().cast(&DataType::Int64)?;
+    let mut earliest: HashMap<&str, i64> = HashMap::new();
+    for (value, instant) in values.iter().zip(instants.i64()?) {
+        if let (Some(value), Some(instant)) = (value, instant) {
+            let e = earliest.entry(value.as_str()).or_insert(instant);
+            *e = (*e).min(instant);
+        }
+    }
+    let mut chronological: Vec<(&str, i64)> = earliest.into_iter().collect();
+    chronological.sort_by_key(|&(value, instant)| (instant, value));
+    let order = chronological.into_iter().enumerate().map(|(i, (value, _))| (value.to_string(), i)).collect();
+    Ok(Some((values, order)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that dates and datetimes are parsed in their formats
+    #[test]
+    fn test_parse_dates() {
+        let df = df!("date" => &["3/1/24", "12/15/23"], "time" => &["3/1/24 0:00", "12/15/23 13:30"]).unwrap();
+        let specs = parse_date_specs(&["date=%m/%d/%y".to_string(), "time=%m/%d/%y %H:%M".to_string()]).unwrap();
+        let parsed = parse_dates(df.lazy(), &specs).collect().unwrap();
+        assert_eq!(parsed.column("date").unwrap().dtype(), &DataType::Date);
+        assert!(matches!(parsed.column("time").unwrap().dtype(), DataType::Datetime(_, _)));
+        assert_eq!(parsed.column("date").unwrap().date().unwrap().to_string("%Y-%m-%d").get(0), Some("2024-03-01"));
+        assert!(parse_date_specs(&["date".to_string()]).is_err());
+    }
+
+    // Test that formatted dates are ordered chronologically rather than alphabetically
+    #[test]
+    fn test_format_dates() {
+        let df = df!("date" => &["2024-03-05", "2023-12-01", "2024-03-01"]).unwrap();
+        let specs = vec![("date".to_string(), "%Y-%m-%d".to_string())];
+        let parsed = parse_dates(df.lazy(), &specs).collect().unwrap();
+        let (values, order) = format_dates(parsed.column("date").unwrap(), "%b %Y").unwrap().unwrap();
+        assert_eq!(values, vec![Some("Mar 2024".to_string()), Some("Dec 2023".to_string()), Some("Mar 2024".to_string())]);
+        assert_eq!(order["Dec 2023"], 0);
+        assert_eq!(order["Mar 2024"], 1);
+    }
+}
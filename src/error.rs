@@ -0,0 +1,81 @@
+//! The kinds of failure that the program reports with distinct exit codes, so that shell scripts can branch on them.
+//!
+//! | Exit code | Failure |
+//! |-----------|---------|
+//! | 1 | Any other error, such as a value that cannot be aggregated |
+//! | 2 | Invalid arguments |
+//! | 3 | A requested column is not in the input |
+//! | 4 | The input or output cannot be read or written |
+//! | 5 | Multiple values for an output cell, when they are errors (as with `--strict`) |
+
+use polars::prelude::PolarsError;
+use thiserror::Error;
+
+/// The exit code of any error that is not one of the [`XtabError`] kinds.
+pub const EXIT_FAILURE: i32 = 1;
+
+/// A failure with its own exit code. Library functions return these inside an [`anyhow::Error`], so the
+/// kind of failure can be recovered with [`exit_code`] after any context has been added.
+#[derive(Debug, Error)]
+pub enum XtabError {
+    /// An argument, or a combination of arguments, is invalid.
+    #[error("{0}")]
+    Argument(String),
+    /// Requested columns are not in the input, with one description for each of them.
+    #[error("{}", .0.join("\n"))]
+    MissingColumns(Vec<String>),
+    /// The input or output cannot be read or written.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// There are multiple values for the given number of output cells, and they are not allowed.
+    #[error("Multiple values were found for {0} row/column combination(s)")]
+    Duplicates(usize),
+}
+
+impl XtabError {
+    /// The exit code of the program when it fails with this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            XtabError::Argument(_) => 2,
+            XtabError::MissingColumns(_) => 3,
+            XtabError::Io(_) => 4,
+            XtabError::Duplicates(_) => 5,
+        }
+    }
+}
+
+/// The exit code of the program when it fails with the error: that of the first [`XtabError`] in its chain of
+/// causes, 4 for any other I/O error, and 1 otherwise.
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    // An XtabError given as the context of another error is not one of the causes of the chain
+    if let Some(e) = error.downcast_ref::<XtabError>() {
+        return e.exit_code();
+    }
+    for cause in error.chain() {
+        if let Some(e) = cause.downcast_ref::<XtabError>() {
+            return e.exit_code();
+        }
+        if cause.is::<std::io::Error>() || matches!(cause.downcast_ref::<PolarsError>(), Some(PolarsError::Io(_))) {
+            return 4;
+        }
+    }
+    EXIT_FAILURE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    // Test that the exit code is found through added context, and that other errors exit with 1
+    #[test]
+    fn test_exit_code() {
+        let missing: anyhow::Error = XtabError::MissingColumns(vec!["a".to_string(), "b".to_string()]).into();
+        assert_eq!(missing.to_string(), "a\nb");
+        assert_eq!(exit_code(&missing), 3);
+        let io: anyhow::Result<()> = Err(std::io::Error::from(std::io::ErrorKind::NotFound)).context("Reading the input");
+        assert_eq!(exit_code(&io.unwrap_err()), 4);
+        assert_eq!(exit_code(&anyhow::anyhow!("The value cannot be aggregated")), 1);
+        assert_eq!(exit_code(&XtabError::Duplicates(2).into()), 5);
+    }
+}
@@ -0,0 +1,272 @@
+//! Filter expressions, such as `depth > 10 && site != 'QC'`, that select the input rows to crosstab.
+//!
+//! An expression compares columns, numbers, strings (in single or double quotes), `true`, `false`, and `null`
+//! with `==` (or `=`), `!=`, `<`, `<=`, `>`, and `>=`, and combines comparisons with `&&` (or `and`), `||` (or `or`),
+//! `!` (or `not`), and parentheses. Column names that are not simple identifiers are written in backquotes, as in
+//! `` `sample depth` > 10 ``. Comparing a column with `null` tests whether its value is missing.
+
+use crate::columns::describe_missing;
+use crate::XtabError;
+use anyhow::{anyhow, Result};
+use polars::prelude::*;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Column(String),
+    Number(String),
+    Text(String),
+    Keyword(String),
+    Operator(&'static str),
+    LeftParen,
+    RightParen,
+}
+
+// The operators, longest first so that `<=` is not read as `<` followed by `=`
+const OPERATORS: [&str; 10] = ["&&", "||", "==", "!=", "<=", ">=", "<", ">", "=", "!"];
+
+// Split an expression into tokens.
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..].iter().take(2).collect();
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' || c == ')' {
+            tokens.push(if c == '(' { Token::LeftParen } else { Token::RightParen });
+            i += 1;
+        } else if c == '\'' || c == '"' || c == '`' {
+            let end = chars[i + 1..]
+                .iter()
+                .position(|&ch| ch == c)
+                .ok_or_else(|| format!("The quote {} at position {} is not closed", c, i + 1))?;
+            let text: String = chars[i + 1..i + 1 + end].iter().collect();
+            tokens.push(if c == '`' { Token::Column(text) } else { Token::Text(text) });
+            i += end + 2;
+        } else if c.is_ascii_digit() || (c == '-' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit() || *d == '.')) || c == '.' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token::Number(chars[start..i].iter().collect()));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            match word.to_lowercase().as_str() {
+                "and" | "or" | "not" | "true" | "false" | "null" => tokens.push(Token::Keyword(word.to_lowercase())),
+                _ => tokens.push(Token::Column(word)),
+            }
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Operator(op));
+            i += op.len();
+        } else {
+            return Err(format!("Unexpected character '{}' at position {}", c, i + 1));
+        }
+    }
+    Ok(tokens)
+}
+
+// One side of a comparison: an expression, or `null`
+enum Operand {
+    Value(Expr),
+    Null,
+}
+
+// A recursive descent parser over the tokens of an expression
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    // Consume the next token if it is one of the given operators or keywords
+    fn accept(&mut self, operators: &[&str], keyword: &str) -> bool {
+        let found = match self.peek() {
+            Some(Token::Operator(op)) => operators.contains(op),
+            Some(Token::Keyword(k)) => k == keyword,
+            _ => false,
+        };
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.accept(&["||"], "or") {
+            expr = expr.or(self.and()?);
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.accept(&["&&"], "and") {
+            expr = expr.and(self.not()?);
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.accept(&["!"], "not") {
+            return Ok(self.not()?.not());
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.operand()?;
+        let op = match self.peek() {
+            Some(Token::Operator(op)) if ["==", "=", "!=", "<", "<=", ">", ">="].contains(op) => *op,
+            _ => {
+                return match left {
+                    Operand::Value(expr) => Ok(expr),
+                    Operand::Null => Err("null can only be compared with == or !=".to_string()),
+                };
+            }
+        };
+        self.position += 1;
+        let right = self.operand()?;
+        match (left, right, op) {
+            (Operand::Value(e), Operand::Null, "==" | "=") | (Operand::Null, Operand::Value(e), "==" | "=") => Ok(e.is_null()),
+            (Operand::Value(e), Operand::Null, "!=") | (Operand::Null, Operand::Value(e), "!=") => Ok(e.is_not_null()),
+            (Operand::Value(l), Operand::Value(r), _) => Ok(match op {
+                "==" | "=" => l.eq(r),
+                "!=" => l.neq(r),
+                "<" => l.lt(r),
+                "<=" => l.lt_eq(r),
+                ">" => l.gt(r),
+                _ => l.gt_eq(r),
+            }),
+            _ => Err("null can only be compared with == or !=".to_string()),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let token = self.peek().cloned().ok_or("The expression ends where a value was expected")?;
+        self.position += 1;
+        match token {
+            Token::LeftParen => {
+                let expr = self.or()?;
+                match self.peek() {
+                    Some(Token::RightParen) => {
+                        self.position += 1;
+                        Ok(Operand::Value(expr))
+                    }
+                    _ => Err("A parenthesis is not closed".to_string()),
+                }
+            }
+            Token::Column(name) => Ok(Operand::Value(col(&name))),
+            Token::Text(text) => Ok(Operand::Value(lit(text))),
+            Token::Number(n) => match (n.parse::<i64>(), n.parse::<f64>()) {
+                (Ok(i), _) => Ok(Operand::Value(lit(i))),
+                (_, Ok(f)) => Ok(Operand::Value(lit(f))),
+                _ => Err(format!("'{}' is not a number", n)),
+            },
+            Token::Keyword(k) if k == "true" || k == "false" => Ok(Operand::Value(lit(k == "true"))),
+            Token::Keyword(k) if k == "null" => Ok(Operand::Null),
+            other => Err(format!("Unexpected {} where a value was expected", describe(&other))),
+        }
+    }
+}
+
+// Describe a token for an error message
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Column(s) | Token::Number(s) | Token::Text(s) | Token::Keyword(s) => format!("'{}'", s),
+        Token::Operator(s) => format!("'{}'", s),
+        Token::LeftParen => "'('".to_string(),
+        Token::RightParen => "')'".to_string(),
+    }
+}
+
+/// Parse a filter expression into a polars expression.
+pub fn parse_filter(expression: &str) -> Result<Expr, String> {
+    let mut parser = Parser { tokens: tokenize(expression)?, position: 0 };
+    let expr = parser.or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(format!("Unexpected {} after the end of the expression", describe(token))),
+    }
+}
+
+/// Keep only the rows of the DataFrame for which the filter expression is true.
+pub fn filter_rows(df: DataFrame, expression: &str) -> Result<DataFrame> {
+    Ok(filter_lazy(df.lazy(), expression)?.collect()?)
+}
+
+/// Plan the selection of the rows of a LazyFrame for which the filter expression is true, without running it. Columns
+/// of the expression that are not in the LazyFrame are reported with the nearest column names as suggestions.
+pub fn filter_lazy(lf: LazyFrame, expression: &str) -> Result<LazyFrame> {
+    let expr = parse_filter(expression).map_err(|e| anyhow!("Invalid filter expression '{}': {}", expression, e))?;
+    let schema = lf.schema()?;
+    let names: Vec<&str> = schema.iter_names().map(|name| name.as_str()).collect();
+    let mut missing: Vec<String> = Vec::new();
+    for token in tokenize(expression).unwrap_or_default() {
+        if let Token::Column(name) = token {
+            let description = describe_missing(&names, "filter", &name);
+            if !schema.contains(&name) && !missing.contains(&description) {
+                missing.push(description);
+            }
+        }
+    }
+    if !missing.is_empty() {
+        return Err(XtabError::MissingColumns(missing).into());
+    }
+    Ok(lf.filter(expr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sites(expression: &str) -> Vec<String> {
+        let df = df!(
+            "site" => &["A01", "QC", "B02", "C03"],
+            "depth" => &[Some(5), Some(20), Some(15), None],
+            "sample type" => &["grab", "grab", "composite", "grab"],
+        )
+        .unwrap();
+        let filtered = filter_rows(df, expression).unwrap();
+        filtered.column("site").unwrap().str().unwrap().into_no_null_iter().map(|s| s.to_string()).collect()
+    }
+
+    // Test comparisons, logical operators, and null tests
+    #[test]
+    fn test_filter_rows() {
+        assert_eq!(sites("depth > 10 && site != 'QC'"), vec!["B02"]);
+        assert_eq!(sites("depth <= 5 or depth == null"), vec!["A01", "C03"]);
+        assert_eq!(sites("!(`sample type` = \"grab\")"), vec!["B02"]);
+        assert_eq!(sites("depth >= 15.5 || site == 'C03'"), vec!["QC", "C03"]);
+    }
+
+    // Test that columns that are not in the input are reported with suggestions
+    #[test]
+    fn test_filter_missing_columns() {
+        let df = df!("result" => &[1, 2], "site" => &["A01", "B02"]).unwrap();
+        let err = filter_rows(df, "reslt > 1 && site != 'QC' && reslt < 5").unwrap_err();
+        assert_eq!(crate::error::exit_code(&err), 3);
+        assert_eq!(err.to_string(), "The filter column 'reslt' is not in the input file; did you mean `result`?");
+    }
+
+    // Test that malformed expressions are reported
+    #[test]
+    fn test_parse_filter_errors() {
+        assert!(parse_filter("depth >").is_err());
+        assert!(parse_filter("(depth > 1").is_err());
+        assert!(parse_filter("site == 'QC").is_err());
+        assert!(parse_filter("depth > 1 depth").is_err());
+        assert!(parse_filter("depth < null").is_err());
+    }
+}
@@ -0,0 +1,153 @@
+//! The formatting of numeric cell values, such as `%.3f` for three decimal places or `%,.2f` to also group
+//! thousands with commas.
+
+use crate::aggregate::{nondetect_limit, NONDETECT_PREFIX};
+use std::str::FromStr;
+
+/// How the numeric values of a value column are written. Values that are not numbers are written as they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ValueFormat {
+    /// The number of decimal places, or `None` for as many as the value needs.
+    pub decimals: Option<usize>,
+    /// The separator of the groups of thousands of the integer part, in fixed notation.
+    pub thousands: Option<char>,
+    /// Whether every value is written in scientific notation, such as `1.235e3`.
+    pub scientific: bool,
+    /// The absolute value at or above which (and below whose reciprocal, other than zero) values are written in
+    /// scientific notation, when they are not all.
+    pub scientific_threshold: Option<f64>,
+}
+
+impl FromStr for ValueFormat {
+    type Err = String;
+
+    /// Parse a format of the form `%[,][.N]f` for fixed notation, optionally with thousands separated by commas, or
+    /// `%[.N]e` for scientific notation, with `N` decimal places.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("Unknown value format '{}'. Must be like %.2f, %,.2f, or %.3e", s);
+        let spec = s.trim().strip_prefix('%').ok_or_else(error)?;
+        let (spec, scientific) = match (spec.strip_suffix('f'), spec.strip_suffix('e')) {
+            (Some(spec), _) => (spec, false),
+            (_, Some(spec)) => (spec, true),
+            _ => return Err(error()),
+        };
+        let (spec, thousands) = match spec.strip_prefix(',') {
+            Some(spec) if !scientific => (spec, Some(',')),
+            Some(_) => return Err(error()),
+            None => (spec, None),
+        };
+        let decimals = match spec {
+            "" => None,
+            _ => Some(spec.strip_prefix('.').and_then(|n| n.parse().ok()).ok_or_else(error)?),
+        };
+        Ok(ValueFormat { decimals, thousands, scientific, scientific_threshold: None })
+    }
+}
+
+impl ValueFormat {
+    /// Format a value. Values that are not numbers are returned unchanged, except that the detection limit of a
+    /// non-detect such as `<0.5` is formatted.
+    pub fn format(&self, value: &str) -> String {
+        if let Some(limit) = nondetect_limit(value).filter(|limit| limit.parse::<f64>().is_ok()) {
+            return format!("{}{}", NONDETECT_PREFIX, self.format(limit));
+        }
+        let number: f64 = match value.trim().parse() {
+            Ok(x) => x,
+            Err(_) => return value.to_string(),
+        };
+        let magnitude = number.abs();
+        let scientific = self.scientific
+            || self.scientific_threshold.is_some_and(|t| magnitude != 0.0 && (magnitude >= t || magnitude < 1.0 / t));
+        if scientific {
+            return match self.decimals {
+                Some(n) => format!("{:.*e}", n, number),
+                None => format!("{:e}", number),
+            };
+        }
+        let fixed = match self.decimals {
+            Some(n) => format!("{:.*}", n, number),
+            None => number.to_string(),
+        };
+        match self.thousands {
+            Some(separator) => group_thousands(&fixed, separator),
+            None => fixed,
+        }
+    }
+}
+
+// Insert the separator between the groups of three digits of the integer part of a number in fixed notation
+fn group_thousands(fixed: &str, separator: char) -> String {
+    let (sign, unsigned) = match fixed.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", fixed),
+    };
+    let (integer, fraction) = match unsigned.find('.') {
+        Some(i) => unsigned.split_at(i),
+        None => (unsigned, ""),
+    };
+    let mut grouped = String::new();
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    format!("{}{}{}", sign, grouped, fraction)
+}
+
+/// Determine the format of each value column from the `--value-format` specifications, in the same way as the
+/// aggregations: a bare format (e.g. `%.2f`) applies to every value column, and a `column=format` pair (e.g.
+/// `result=%.3f`) applies to a single value column. Value columns without a specification are not formatted.
+pub fn parse_value_formats(specs: &[String], cell_values: &[String]) -> Result<Vec<Option<ValueFormat>>, String> {
+    let mut formats: Vec<Option<ValueFormat>> = vec![None; cell_values.len()];
+    for spec in specs.iter().filter(|s| !s.contains('=')) {
+        let format: ValueFormat = spec.parse()?;
+        formats.iter_mut().for_each(|f| *f = Some(format));
+    }
+    for spec in specs.iter().filter(|s| s.contains('=')) {
+        let (column, format) = spec.split_once('=').unwrap();
+        match cell_values.iter().position(|v| v == column.trim()) {
+            Some(i) => formats[i] = Some(format.parse()?),
+            None => return Err(format!("The formatted column '{}' is not one of the value columns", column)),
+        }
+    }
+    Ok(formats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test fixed and scientific notation, thousands separators, and the scientific notation threshold
+    #[test]
+    fn test_format() {
+        let format = |spec: &str, value: &str| spec.parse::<ValueFormat>().unwrap().format(value);
+        assert_eq!(format("%.3f", "2.5"), "2.500");
+        assert_eq!(format("%,.2f", "-1234567.891"), "-1,234,567.89");
+        assert_eq!(format("%,f", "999"), "999");
+        assert_eq!(format("%.2e", "1234.5"), "1.23e3");
+        assert_eq!(format("%.2f", "ND"), "ND");
+        assert_eq!(format("%.2f", "<0.5"), "<0.50");
+
+        let threshold = ValueFormat { scientific_threshold: Some(1e6), ..Default::default() };
+        assert_eq!(threshold.format("2500000"), "2.5e6");
+        assert_eq!(threshold.format("0.0000001"), "1e-7");
+        assert_eq!(threshold.format("42"), "42");
+        assert_eq!(threshold.format("0"), "0");
+
+        assert!("%.2d".parse::<ValueFormat>().is_err());
+        assert!("%,.2e".parse::<ValueFormat>().is_err());
+        assert!(".2f".parse::<ValueFormat>().is_err());
+    }
+
+    // Test that column-specific formats override a bare format
+    #[test]
+    fn test_parse_value_formats() {
+        let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        let formats = parse_value_formats(&strings(&["result=%.3f", "%.1f"]), &strings(&["result", "depth"])).unwrap();
+        assert_eq!(formats[0].unwrap().decimals, Some(3));
+        assert_eq!(formats[1].unwrap().decimals, Some(1));
+        assert_eq!(parse_value_formats(&[], &strings(&["result"])).unwrap(), vec![None]);
+        assert!(parse_value_formats(&strings(&["other=%.1f"]), &strings(&["result"])).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+// Construction of the header row(s) of a crosstab.
+
+use crate::Key;
+
+/// How the names of the generated columns are built from the column header values and the value column names.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderNames {
+    /// The separator placed between the column header values, and before the value column name in format 1.
+    pub separator: String,
+    /// A template for the format 1 column names, such as `{value}.{var}`, in which `{value}` is replaced by the
+    /// column header values joined by the separator, `{var}` by the value column name, and `{name}` by the value
+    /// of the column header column `name`. If this is `None`, the names are `{value}` and `{var}` joined by the separator.
+    pub template: Option<String>,
+}
+
+impl Default for HeaderNames {
+    fn default() -> Self {
+        HeaderNames { separator: "_".to_string(), template: None }
+    }
+}
+
+impl HeaderNames {
+    // The format 1 name of the generated column for a column key and value column
+    fn name(&self, col_headers: &[String], col_key: &Key, cell_value: &str) -> String {
+        let value = col_key.join(&self.separator);
+        match &self.template {
+            None => format!("{}{}{}", value, self.separator, cell_value),
+            Some(template) => {
+                let mut name = template.replace("{value}", &value).replace("{var}", cell_value);
+                for (col_header, v) in col_headers.iter().zip(col_key) {
+                    name = name.replace(&format!("{{{}}}", col_header), v);
+                }
+                name
+            }
+        }
+    }
+}
+
+/// Build the header row(s) of the crosstab for the given header format.
+///
+/// Every header row has one cell for each row header, followed by one cell for each combination of
+/// column key and value column. The row header names always appear on the last header row; the cells
+/// above them are left empty.
+pub fn header_rows(
+    format: u8,
+    row_headers: &[String],
+    col_headers: &[String],
+    col_keys: &[Key],
+    cell_values: &[String],
+    names: &HeaderNames,
+) -> Vec<Vec<String>> {
+    let mut header_rows: Vec<Vec<String>> = Vec::new();
+    match format {
+        1 => {
+            // One row of column headers, with the column header values and the value column names joined by the separator
+            let mut header_row: Vec<String> = row_headers.to_vec();
+            for col_key in col_keys {
+                for cell_value in cell_values {
+                    header_row.push(names.name(col_headers, col_key, cell_value));
+                }
+            }
+            header_rows.push(header_row);
+        }
+        2 => {
+            // The first row contains the column header values, repeated once for each value column
+            let mut col_row: Vec<String> = vec![String::new(); row_headers.len()];
+            for col_key in col_keys {
+                for _ in cell_values {
+                    col_row.push(col_key.join(&names.separator));
+                }
+            }
+            header_rows.push(col_row);
+        }
+        _ => {
+            // One row for each column header variable. Format 4 labels each value with its column name.
+            for (i, col_header) in col_headers.iter().enumerate() {
+                let mut col_row: Vec<String> = vec![String::new(); row_headers.len()];
+                for col_key in col_keys {
+                    for _ in cell_values {
+                        if format == 4 {
+                            col_row.push(format!("{}:{}", col_header, col_key[i]));
+                        } else {
+                            col_row.push(col_key[i].clone());
+                        }
+                    }
+                }
+                header_rows.push(col_row);
+            }
+        }
+    }
+    // Formats 2, 3, and 4 end with a row of the row header names followed by the value column names
+    if format != 1 {
+        let mut value_row: Vec<String> = row_headers.to_vec();
+        for _ in col_keys {
+            value_row.extend(cell_values.iter().cloned());
+        }
+        header_rows.push(value_row);
+    }
+    header_rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Convert a slice of string slices into a header row for comparison
+    fn row(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|s| s.to_string()).collect()
+    }
+
+    // Test the header rows produced by each of the four header formats
+    #[test]
+    fn test_header_rows() {
+        let row_headers = row(&["site"]);
+        let col_headers = row(&["year", "month"]);
+        let col_keys: Vec<Key> = vec![row(&["2024", "01"]), row(&["2024", "02"])];
+        let cell_values = row(&["result", "unit"]);
+
+        assert_eq!(
+            header_rows(1, &row_headers, &col_headers, &col_keys, &cell_values, &HeaderNames::default()),
+            vec![row(&["site", "2024_01_result", "2024_01_unit", "2024_02_result", "2024_02_unit"])]
+        );
+        assert_eq!(
+            header_rows(2, &row_headers, &col_headers, &col_keys, &cell_values, &HeaderNames::default()),
+            vec![
+                row(&["", "2024_01", "2024_01", "2024_02", "2024_02"]),
+                row(&["site", "result", "unit", "result", "unit"]),
+            ]
+        );
+        assert_eq!(
+            header_rows(3, &row_headers, &col_headers, &col_keys, &cell_values, &HeaderNames::default()),
+            vec![
+                row(&["", "2024", "2024", "2024", "2024"]),
+                row(&["", "01", "01", "02", "02"]),
+                row(&["site", "result", "unit", "result", "unit"]),
+            ]
+        );
+        assert_eq!(
+            header_rows(4, &row_headers, &col_headers, &col_keys, &cell_values, &HeaderNames::default()),
+            vec![
+                row(&["", "year:2024", "year:2024", "year:2024", "year:2024"]),
+                row(&["", "month:01", "month:01", "month:02", "month:02"]),
+                row(&["site", "result", "unit", "result", "unit"]),
+            ]
+        );
+    }
+
+    // Test the format 1 names built with a separator and with a template
+    #[test]
+    fn test_header_names() {
+        let col_headers = row(&["year", "month"]);
+        let col_keys: Vec<Key> = vec![row(&["2024", "01"])];
+        let names = |separator: &str, template: Option<&str>| {
+            let names = HeaderNames { separator: separator.to_string(), template: template.map(|t| t.to_string()) };
+            header_rows(1, &row(&["site"]), &col_headers, &col_keys, &row(&["result"]), &names).remove(0)
+        };
+        assert_eq!(names(".", None), row(&["site", "2024.01.result"]));
+        assert_eq!(names("-", Some("{var} ({value})")), row(&["site", "result (2024-01)"]));
+        assert_eq!(names("_", Some("{var}_{month}/{year}")), row(&["site", "result_01/2024"]));
+    }
+}
@@ -0,0 +1,220 @@
+//! Conditional highlighting of the cells of spreadsheet and HTML output, such as the exceedances of a threshold,
+//! by rules that compare the value of each cell with a number and style the cells that match.
+
+use crate::{Crosstab, XtabError};
+use anyhow::Result;
+use std::str::FromStr;
+
+/// The comparison of a highlighting rule, between the value of a cell and the threshold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+impl Comparison {
+    // The operators of the comparisons, with each two-character operator before its one-character prefix
+    const OPERATORS: [(&'static str, Comparison); 7] = [
+        (">=", Comparison::Ge),
+        ("<=", Comparison::Le),
+        ("!=", Comparison::Ne),
+        ("==", Comparison::Eq),
+        (">", Comparison::Gt),
+        ("<", Comparison::Lt),
+        ("=", Comparison::Eq),
+    ];
+
+    /// Whether the value compares with the threshold.
+    pub fn matches(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Gt => value > threshold,
+            Comparison::Ge => value >= threshold,
+            Comparison::Lt => value < threshold,
+            Comparison::Le => value <= threshold,
+            Comparison::Eq => value == threshold,
+            Comparison::Ne => value != threshold,
+        }
+    }
+}
+
+/// The style of a highlighted cell, with its colors as RGB numbers such as `0xFF0000`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HighlightStyle {
+    /// The fill (background) color of the cell.
+    pub fill: Option<u32>,
+    /// The color of the text of the cell.
+    pub font: Option<u32>,
+    /// Whether the text of the cell is bold.
+    pub bold: bool,
+}
+
+impl HighlightStyle {
+    /// The style as the declarations of a CSS `style` attribute.
+    pub fn css(&self) -> String {
+        let mut declarations: Vec<String> = Vec::new();
+        if let Some(fill) = self.fill {
+            declarations.push(format!("background-color: #{:06X}", fill));
+        }
+        if let Some(font) = self.font {
+            declarations.push(format!("color: #{:06X}", font));
+        }
+        if self.bold {
+            declarations.push("font-weight: bold".to_string());
+        }
+        declarations.join("; ")
+    }
+}
+
+// The named colors, with the RGB numbers of the CSS colors of the same names
+const COLORS: [(&str, u32); 8] = [
+    ("red", 0xFF0000),
+    ("orange", 0xFFA500),
+    ("yellow", 0xFFFF00),
+    ("green", 0x008000),
+    ("blue", 0x0000FF),
+    ("gray", 0x808080),
+    ("black", 0x000000),
+    ("white", 0xFFFFFF),
+];
+
+// Parse a color: one of the named colors, or an RGB hex triplet such as #FFC7CE
+fn parse_color(s: &str) -> Result<u32, String> {
+    let s = s.trim().to_lowercase();
+    if let Some((_, rgb)) = COLORS.iter().find(|(name, _)| *name == s) {
+        return Ok(*rgb);
+    }
+    match s.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 => u32::from_str_radix(hex, 16).map_err(|_| format!("Unknown color '{}'", s)),
+        _ => Err(format!(
+            "Unknown color '{}'. Must be one of {}, or a hex color such as #FFC7CE",
+            s,
+            COLORS.map(|(name, _)| name).join(", ")
+        )),
+    }
+}
+
+impl FromStr for HighlightStyle {
+    type Err = String;
+
+    /// Parse a style of `+`-separated parts: a color (such as `red` or `#FFC7CE`) for the fill, `font-` and a color
+    /// for the text, and `bold`, as in `red+bold` or `font-gray`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut style = HighlightStyle::default();
+        for part in s.split('+').map(|p| p.trim()) {
+            match part.strip_prefix("font-") {
+                _ if part.eq_ignore_ascii_case("bold") => style.bold = true,
+                Some(color) => style.font = Some(parse_color(color)?),
+                None => style.fill = Some(parse_color(part)?),
+            }
+        }
+        Ok(style)
+    }
+}
+
+/// A rule that highlights the cells of a value column whose numbers compare with a threshold, as in `result>10:red`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HighlightRule {
+    /// The name of the value column of the cells, as in the generated column names, or `None` for every value column.
+    pub column: Option<String>,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    pub style: HighlightStyle,
+}
+
+impl FromStr for HighlightRule {
+    type Err = String;
+
+    /// Parse a rule of a comparison (`>`, `>=`, `<`, `<=`, `=`, or `!=`) of a value column with a number, followed by
+    /// a colon and a style, as in `result>10:red+bold`. Without a column, as in `>10:red`, the rule applies to every
+    /// value column.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("The highlighting rule '{}' must be a comparison and a style, e.g. result>10:red", s);
+        let (condition, style) = s.rsplit_once(':').ok_or_else(invalid)?;
+        let start = condition.find(['<', '>', '=', '!']).ok_or_else(invalid)?;
+        let (column, rest) = condition.split_at(start);
+        let (operator, comparison) = Comparison::OPERATORS.iter().find(|(op, _)| rest.starts_with(op)).ok_or_else(invalid)?;
+        let threshold = rest[operator.len()..].trim().parse::<f64>().map_err(|_| invalid())?;
+        Ok(HighlightRule {
+            column: Some(column.trim().to_string()).filter(|c| !c.is_empty()),
+            comparison: *comparison,
+            threshold,
+            style: style.parse()?,
+        })
+    }
+}
+
+/// The style of each cell of the data rows of the crosstab, from the first of the rules that matches it, or `None`
+/// for the cells that no rule matches. Only the cells of the generated columns whose values are numbers can match.
+/// A rule for a column that is not a value column is an error.
+pub fn cell_styles(crosstab: &Crosstab, rules: &[HighlightRule]) -> Result<Vec<Vec<Option<HighlightStyle>>>> {
+    if rules.is_empty() {
+        return Ok(Vec::new());
+    }
+    let config = crosstab.config();
+    let value_names = config.value_names();
+    if let Some(column) = rules.iter().filter_map(|rule| rule.column.as_ref()).find(|c| !value_names.contains(c)) {
+        return Err(XtabError::Argument(format!("The highlighted column '{}' is not a value column", column)).into());
+    }
+    // The value column of a cell follows from its generated column: its position in the data row, or when the
+    // crosstab is transposed, its data row
+    let n_row_headers = crosstab.n_row_headers();
+    let value_name = |r: usize, c: usize| match config.transpose {
+        true => &value_names[r % value_names.len()],
+        false => &value_names[(c - n_row_headers) % value_names.len()],
+    };
+    let styles = crosstab
+        .data_rows()
+        .iter()
+        .enumerate()
+        .map(|(r, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(c, cell)| {
+                    let number = cell.trim().parse::<f64>().ok().filter(|_| c >= n_row_headers)?;
+                    rules
+                        .iter()
+                        .find(|rule| {
+                            rule.column.as_ref().is_none_or(|column| column == value_name(r, c))
+                                && rule.comparison.matches(number, rule.threshold)
+                        })
+                        .map(|rule| rule.style)
+                })
+                .collect()
+        })
+        .collect();
+    Ok(styles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::XtabConfig;
+    use polars::prelude::*;
+
+    // Test that the rules are parsed, and that each cell gets the style of the first rule that matches it
+    #[test]
+    fn test_cell_styles() {
+        let rule: HighlightRule = "result >= 2.5 : red+bold".parse().unwrap();
+        assert_eq!(rule.column.as_deref(), Some("result"));
+        assert_eq!(rule.comparison, Comparison::Ge);
+        assert_eq!(rule.style, HighlightStyle { fill: Some(0xFF0000), font: None, bold: true });
+        assert_eq!(rule.style.css(), "background-color: #FF0000; font-weight: bold");
+        let any: HighlightRule = "<1:#FFC7CE+font-gray".parse().unwrap();
+        assert_eq!(any.column, None);
+        assert_eq!(any.style, HighlightStyle { fill: Some(0xFFC7CE), font: Some(0x808080), bold: false });
+        assert!("result>high:red".parse::<HighlightRule>().is_err());
+        assert!("result>1:crimson".parse::<HighlightRule>().is_err());
+        assert!("result:red".parse::<HighlightRule>().is_err());
+
+        let df = df!("site" => &["A01", "B02"], "date" => &["d1", "d1"], "result" => &["0.5", "4"], "unit" => &["mg/L", "mg/L"]).unwrap();
+        let crosstab = Crosstab::new(&df, &XtabConfig::new(&["site"], &["date"], &["result", "unit"])).unwrap();
+        let styles = cell_styles(&crosstab, &[rule, any.clone()]).unwrap();
+        assert_eq!(styles[0], vec![None, Some(any.style), None]);
+        assert_eq!(styles[1], vec![None, Some(HighlightStyle { fill: Some(0xFF0000), font: None, bold: true }), None]);
+        assert!(cell_styles(&crosstab, &["depth>1:red".parse().unwrap()]).is_err());
+    }
+}
@@ -0,0 +1,53 @@
+//! A summary of the columns of an input table, to help choose the row header, column header, and value columns.
+
+use anyhow::Result;
+use polars::prelude::*;
+
+/// The summary of one input column.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnSummary {
+    /// The name of the column.
+    pub name: String,
+    /// The data type inferred when the input was read.
+    pub dtype: String,
+    /// The number of distinct values, not counting missing values.
+    pub distinct: usize,
+    /// The number of missing values.
+    pub nulls: usize,
+}
+
+/// Summarize every column of the DataFrame, in order.
+pub fn summarize(df: &DataFrame) -> Result<Vec<ColumnSummary>> {
+    df.get_columns()
+        .iter()
+        .map(|series| {
+            Ok(ColumnSummary {
+                name: series.name().to_string(),
+                dtype: series.dtype().to_string(),
+                distinct: series.drop_nulls().n_unique()?,
+                nulls: series.null_count(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that distinct values are counted without the missing values
+    #[test]
+    fn test_summarize() {
+        let df = df!(
+            "site" => &["A01", "A01", "B02"],
+            "result" => &[Some(1.5), None, None],
+        )
+        .unwrap();
+        let summary = summarize(&df).unwrap();
+        assert_eq!(
+            summary[0],
+            ColumnSummary { name: "site".to_string(), dtype: "str".to_string(), distinct: 2, nulls: 0 }
+        );
+        assert_eq!((summary[1].distinct, summary[1].nulls), (1, 2));
+    }
+}
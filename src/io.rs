@@ -0,0 +1,471 @@
+//! Readers that load normalized input data into a DataFrame from each of the supported input formats.
+
+mod excel;
+mod text;
+
+use crate::compression::{decoder, uncompressed_path, Compression};
+#[cfg(feature = "native")]
+use crate::source::sqlite::read_sqlite;
+use crate::source::table_query;
+use crate::XtabError;
+use anyhow::{bail, Result};
+use encoding_rs::Encoding;
+use polars::io::mmap::MmapBytesReader;
+use polars::prelude::*;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub use excel::{read_excel, read_excel_from};
+use text::{clean_csv, explain_csv_error, transcode};
+
+/// The file formats normalized data can be read from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputFormat {
+    /// Delimited text, such as CSV or TSV.
+    Csv,
+    /// An Excel or OpenDocument workbook.
+    Xlsx,
+    /// A Parquet file.
+    Parquet,
+    /// Newline-delimited JSON, with one object per line.
+    Ndjson,
+    /// A SQLite database, read with a query.
+    Sqlite,
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(InputFormat::Csv),
+            "xlsx" => Ok(InputFormat::Xlsx),
+            "parquet" => Ok(InputFormat::Parquet),
+            "ndjson" | "jsonl" => Ok(InputFormat::Ndjson),
+            "sqlite" => Ok(InputFormat::Sqlite),
+            _ => Err(format!("Unknown input format '{}'. Must be one of csv, xlsx, parquet, ndjson, sqlite", s)),
+        }
+    }
+}
+
+impl InputFormat {
+    /// The input format implied by a file's extension. Files with an unrecognized extension are read as delimited text.
+    /// A compression extension is ignored, so `.ndjson.gz` files are read as newline-delimited JSON.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> InputFormat {
+        let extension = uncompressed_path(path).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+        match extension.as_deref() {
+            Some("xlsx" | "xlsm" | "xlsb" | "xls" | "ods") => InputFormat::Xlsx,
+            Some("parquet") => InputFormat::Parquet,
+            Some("ndjson" | "jsonl") => InputFormat::Ndjson,
+            Some("sqlite" | "sqlite3" | "db") => InputFormat::Sqlite,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+/// How lines of delimited text whose number of fields differs from that of the header line are handled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BadLines {
+    /// Fail, with the line number of the first bad line.
+    Error,
+    /// Skip the bad lines.
+    Skip,
+    /// Skip the bad lines, with a warning for each of them.
+    Warn,
+}
+
+impl FromStr for BadLines {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "error" => Ok(BadLines::Error),
+            "skip" => Ok(BadLines::Skip),
+            "warn" => Ok(BadLines::Warn),
+            _ => Err(format!("Unknown bad line handling '{}'. Must be one of error, skip, warn", s)),
+        }
+    }
+}
+
+/// Parse a quote, escape, or comment character of delimited text, which must be a single ASCII character.
+pub fn parse_text_char(s: &str) -> Result<u8, String> {
+    match s.as_bytes() {
+        [c] if c.is_ascii() => Ok(*c),
+        _ => Err(format!("The quote, escape, and comment characters must be single ASCII characters: '{}'", s)),
+    }
+}
+
+/// Parse the name of the encoding of delimited text: utf-8, latin-1 (read as windows-1252, of which it is a
+/// subset for printable characters), windows-1252, utf-16 (little-endian unless the text starts with a byte order
+/// mark), or utf-16be. UTF-8 is given as `None`, because it is read without conversion.
+pub fn parse_encoding(s: &str) -> Result<Option<&'static Encoding>, String> {
+    match s.trim().to_lowercase().as_str() {
+        "utf-8" | "utf8" => Ok(None),
+        "latin-1" | "latin1" | "iso-8859-1" | "windows-1252" | "cp1252" => Ok(Some(encoding_rs::WINDOWS_1252)),
+        "utf-16" | "utf16" | "utf-16le" => Ok(Some(encoding_rs::UTF_16LE)),
+        "utf-16be" => Ok(Some(encoding_rs::UTF_16BE)),
+        _ => Err(format!("Unknown encoding '{}'. Must be one of utf-8, latin-1, windows-1252, utf-16, utf-16be", s)),
+    }
+}
+
+/// Options that control how the input is parsed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadConfig {
+    /// The character that separates the fields of each line of delimited text.
+    pub delimiter: u8,
+    /// The name of the worksheet to read from a workbook, or `None` for the first worksheet.
+    pub sheet: Option<String>,
+    /// The query used to read a database.
+    pub query: Option<String>,
+    /// The table to read from a database when there is no query.
+    pub table: Option<String>,
+    /// The compression of the input, or `None` to detect a compressed file from its extension.
+    pub compression: Option<Compression>,
+    /// The types of columns of delimited text, which override the types inferred from their values, such as text
+    /// for dates that are parsed afterwards in their own format, or for identifiers with leading zeros.
+    pub dtypes: Vec<(String, DataType)>,
+    /// The character that quotes fields of delimited text, or `None` if fields are not quoted.
+    pub quote: Option<u8>,
+    /// The character that escapes a quote character inside a quoted field, or `None` if quotes are escaped by
+    /// doubling them.
+    pub escape: Option<u8>,
+    /// The character at the start of the comment lines of delimited text, which are ignored.
+    pub comment: Option<u8>,
+    /// How lines of delimited text with too many or too few fields are handled.
+    pub bad_lines: BadLines,
+    /// The encoding of delimited text, which is converted to UTF-8 before it is parsed, or `None` for UTF-8.
+    pub encoding: Option<&'static Encoding>,
+    /// The number of lines of delimited text (or rows of a worksheet) before the line with the column names, such as
+    /// instrument metadata or titles, which are skipped.
+    pub skip_rows: usize,
+}
+
+impl Default for ReadConfig {
+    fn default() -> Self {
+        ReadConfig {
+            delimiter: b',',
+            sheet: None,
+            query: None,
+            table: None,
+            compression: None,
+            dtypes: Vec::new(),
+            quote: Some(b'"'),
+            escape: None,
+            comment: None,
+            bad_lines: BadLines::Error,
+            encoding: None,
+            skip_rows: 0,
+        }
+    }
+}
+
+impl ReadConfig {
+    // The schema that overrides the inferred types of delimited text, or `None` if there are no column types
+    fn dtype_schema(&self) -> Option<Schema> {
+        (!self.dtypes.is_empty()).then(|| self.dtypes.iter().map(|(name, dtype)| Field::new(name, dtype.clone())).collect())
+    }
+
+    // Whether delimited text must be read with the csv crate first, because polars reads neither escape
+    // characters nor bad lines
+    fn needs_cleaning(&self) -> bool {
+        self.escape.is_some() || self.bad_lines != BadLines::Error
+    }
+
+    // Whether delimited text must be read into memory before it is parsed, to be converted or cleaned
+    fn reads_in_memory(&self) -> bool {
+        self.needs_cleaning() || self.encoding.is_some()
+    }
+
+    // The comment prefix of delimited text, as polars takes it
+    fn comment_prefix(&self) -> Option<String> {
+        self.comment.map(|c| (c as char).to_string())
+    }
+
+    /// The query used to read a database: the query itself, or a query of every row of the table.
+    pub fn database_query(&self) -> Result<String> {
+        match (&self.query, &self.table) {
+            (Some(query), _) => Ok(query.clone()),
+            (None, Some(table)) => Ok(table_query(table)),
+            (None, None) => bail!("A query or a table name is required to read from a database"),
+        }
+    }
+}
+
+// Buffer the whole input. The polars readers need random access to the data.
+fn read_bytes<R: Read>(mut reader: R) -> std::io::Result<Cursor<Vec<u8>>> {
+    let mut bytes: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(Cursor::new(bytes))
+}
+
+// Read delimited text with the polars reader
+fn polars_csv<R: MmapBytesReader>(reader: R, read_config: &ReadConfig) -> PolarsResult<DataFrame> {
+    CsvReader::new(reader)
+            .has_header(true)
+            .with_skip_rows(read_config.skip_rows)
+            .with_separator(read_config.delimiter)
+            .with_quote_char(read_config.quote)
+            .with_comment_prefix(read_config.comment_prefix().as_deref())
+            .with_dtypes(read_config.dtype_schema().map(Arc::new))
+            .finish()
+}
+
+/// Read a delimited text file, whose first line contains the column names, into a DataFrame.
+/// If the file cannot be read, the first line with too many or too few fields is reported, if there is one.
+pub fn read_csv<P: AsRef<Path>>(file: P, read_config: &ReadConfig) -> PolarsResult<DataFrame> {
+    let file = file.as_ref();
+    if read_config.reads_in_memory() {
+        return read_csv_from(std::fs::File::open(file)?, read_config);
+    }
+    polars_csv(std::fs::File::open(file)?, read_config).map_err(|e| match std::fs::File::open(file) {
+        Ok(f) => explain_csv_error(e, f, read_config),
+        Err(_) => e,
+    })
+}
+
+/// Read delimited text data, whose first line contains the column names, from any reader (such as standard input) into a DataFrame.
+pub fn read_csv_from<R: Read>(reader: R, read_config: &ReadConfig) -> PolarsResult<DataFrame> {
+    let bytes = match read_config.encoding {
+        Some(encoding) => transcode(reader, encoding)?,
+        None => read_bytes(reader)?.into_inner(),
+    };
+    if read_config.needs_cleaning() {
+        let cleaned = clean_csv(bytes.as_slice(), read_config)?;
+        let cleaned_config = ReadConfig { quote: Some(b'"'), comment: None, skip_rows: 0, ..read_config.clone() };
+        return polars_csv(Cursor::new(cleaned), &cleaned_config);
+    }
+    polars_csv(Cursor::new(bytes.as_slice()), read_config).map_err(|e| explain_csv_error(e, bytes.as_slice(), read_config))
+}
+
+/// Read a Parquet file into a DataFrame.
+#[cfg(feature = "native")]
+pub fn read_parquet<P: AsRef<Path>>(file: P) -> PolarsResult<DataFrame> {
+    ParquetReader::new(std::fs::File::open(file)?).finish()
+}
+
+/// Read Parquet data from any reader (such as standard input) into a DataFrame.
+#[cfg(feature = "native")]
+pub fn read_parquet_from<R: Read>(reader: R) -> PolarsResult<DataFrame> {
+    ParquetReader::new(read_bytes(reader)?).finish()
+}
+
+/// Read an Arrow IPC file from any reader (such as the bytes of record batches received over a network) into a
+/// DataFrame.
+#[cfg(feature = "native")]
+pub fn read_ipc_from<R: Read>(reader: R) -> PolarsResult<DataFrame> {
+    IpcReader::new(read_bytes(reader)?).finish()
+}
+
+/// Read a newline-delimited JSON file, with one object per line, into a DataFrame.
+/// The columns are the union of the keys of the objects.
+pub fn read_ndjson<P: AsRef<Path>>(file: P) -> PolarsResult<DataFrame> {
+    JsonLineReader::from_path(file.as_ref())?.finish()
+}
+
+/// Read newline-delimited JSON data from any reader (such as standard input) into a DataFrame.
+pub fn read_ndjson_from<R: Read>(reader: R) -> PolarsResult<DataFrame> {
+    JsonLineReader::new(read_bytes(reader)?).finish()
+}
+
+/// Read a file in the given input format into a DataFrame. A compressed file is decompressed as it is read.
+pub fn read_input<P: AsRef<Path>>(format: InputFormat, file: P, read_config: &ReadConfig) -> Result<DataFrame> {
+    let compression = read_config.compression.unwrap_or_else(|| Compression::from_path(&file));
+    if compression != Compression::Uncompressed {
+        let reader = decoder(compression, std::fs::File::open(&file)?)?;
+        return read_input_from(format, reader, &ReadConfig { compression: Some(Compression::Uncompressed), ..read_config.clone() });
+    }
+    let df = match format {
+        InputFormat::Csv => read_csv(file, read_config)?,
+        InputFormat::Xlsx => read_excel(file, read_config.sheet.as_deref(), read_config.skip_rows)?,
+        #[cfg(feature = "native")]
+        InputFormat::Parquet => read_parquet(file)?,
+        InputFormat::Ndjson => read_ndjson(file)?,
+        #[cfg(feature = "native")]
+        InputFormat::Sqlite => read_sqlite(file, &read_config.database_query()?)?,
+        #[cfg(not(feature = "native"))]
+        InputFormat::Parquet | InputFormat::Sqlite => bail!("xtab was built without support for {:?} input", format),
+    };
+    Ok(df)
+}
+
+/// Scan a file in the given input format into a LazyFrame, so that the data are only read when the frame is
+/// collected, and only the columns and rows that are used. Delimited text, Parquet, and newline-delimited JSON
+/// files are scanned; compressed files, workbooks, databases, and delimited text that is not UTF-8 or that has
+/// escape characters or bad lines to skip are read into memory first.
+pub fn scan_input<P: AsRef<Path>>(format: InputFormat, file: P, read_config: &ReadConfig) -> Result<LazyFrame> {
+    let compression = read_config.compression.unwrap_or_else(|| Compression::from_path(&file));
+    if compression != Compression::Uncompressed {
+        return Ok(read_input(format, file, read_config)?.lazy());
+    }
+    let dtype_schema = read_config.dtype_schema();
+    let lf = match format {
+        InputFormat::Csv if read_config.reads_in_memory() => read_csv(file, read_config)?.lazy(),
+        InputFormat::Csv => LazyCsvReader::new(file.as_ref())
+            .has_header(true)
+            .with_skip_rows(read_config.skip_rows)
+            .with_separator(read_config.delimiter)
+            .with_quote_char(read_config.quote)
+            .with_comment_prefix(read_config.comment_prefix().as_deref())
+            .with_dtype_overwrite(dtype_schema.as_ref())
+            .finish()?,
+        #[cfg(feature = "native")]
+        InputFormat::Parquet => LazyFrame::scan_parquet(file.as_ref(), ScanArgsParquet::default())?,
+        #[cfg(not(feature = "native"))]
+        InputFormat::Parquet => read_input(format, file, read_config)?.lazy(),
+        InputFormat::Ndjson => LazyJsonLineReader::new(file.as_ref()).finish()?,
+        InputFormat::Xlsx | InputFormat::Sqlite => read_input(format, file, read_config)?.lazy(),
+    };
+    Ok(lf)
+}
+
+/// The input files named by a path: the files of a directory, or the files that match a glob pattern with `*`, `?`,
+/// or `[...]` wildcards (e.g. `data/2024-*.csv`), in the order of their names, or else the file itself. It is an
+/// error if there are no such files.
+pub fn input_paths<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>> {
+    let path = path.as_ref();
+    let not_found = |message: String| XtabError::Io(std::io::Error::new(std::io::ErrorKind::NotFound, message));
+    let mut paths: Vec<PathBuf> = if path.is_dir() {
+        std::fs::read_dir(path)?.map(|entry| Ok(entry?.path())).filter(|p| !matches!(p, Ok(p) if p.is_dir())).collect::<Result<_>>()?
+    } else if path.exists() {
+        return Ok(vec![path.to_path_buf()]);
+    } else {
+        let pattern = path.to_string_lossy();
+        if !pattern.contains(['*', '?', '[']) {
+            return Err(not_found(format!("The input file does not exist: {}", path.display())).into());
+        }
+        let matches = glob::glob(&pattern).map_err(|e| XtabError::Argument(format!("The input file pattern is invalid: {}", e)))?;
+        matches.filter(|p| !matches!(p, Ok(p) if p.is_dir())).collect::<Result<_, glob::GlobError>>()?
+    };
+    if paths.is_empty() {
+        return Err(not_found(format!("No input files match {}", path.display())).into());
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Stack input frames into one. Each frame is named, such as by the path of its input file, for the error that lists
+/// the differences between the frames: they must have the same columns, in any order, and each column must have the
+/// same type in all of them, except that numeric columns whose types differ (such as integers in one file and decimals
+/// in another) are converted to a type that holds all of their values.
+pub fn concat_inputs(mut frames: Vec<(String, LazyFrame)>) -> Result<LazyFrame> {
+    if frames.len() == 1 {
+        return Ok(frames.remove(0).1);
+    }
+    let schemas = frames.iter().map(|(_, lf)| lf.schema()).collect::<PolarsResult<Vec<_>>>()?;
+    let (first_name, first) = (&frames[0].0, &schemas[0]);
+    let quoted = |names: Vec<&str>| names.iter().map(|name| format!("'{}'", name)).collect::<Vec<_>>().join(", ");
+    let mut differences: Vec<String> = Vec::new();
+    for ((name, _), schema) in frames.iter().zip(&schemas).skip(1) {
+        let missing: Vec<&str> = first.iter_names().map(|column| column.as_str()).filter(|column| !schema.contains(column)).collect();
+        if !missing.is_empty() {
+            differences.push(format!("  {} lacks the column(s) {}", name, quoted(missing)));
+        }
+        let extra: Vec<&str> = schema.iter_names().map(|column| column.as_str()).filter(|column| !first.contains(column)).collect();
+        if !extra.is_empty() {
+            differences.push(format!("  {} has the extra column(s) {}", name, quoted(extra)));
+        }
+        for (column, dtype) in first.iter() {
+            match schema.get(column) {
+                Some(other) if other != dtype && !(other.is_numeric() && dtype.is_numeric()) => {
+                    differences.push(format!("  the column '{}' is {} in {} but {} in {}", column, dtype, first_name, other, name))
+                }
+                _ => {}
+            }
+        }
+    }
+    if !differences.is_empty() {
+        bail!("The input files cannot be stacked, because they differ from {}:\n{}", first_name, differences.join("\n"));
+    }
+
+    // Line up the columns of every frame with those of the first
+    let columns: Vec<Expr> = first.iter_names().map(|column| col(column)).collect();
+    let frames: Vec<LazyFrame> = frames.into_iter().map(|(_, lf)| lf.select(&columns)).collect();
+    Ok(concat(frames, UnionArgs { to_supertypes: true, ..Default::default() })?)
+}
+
+/// Read data in the given input format from any reader (such as standard input) into a DataFrame.
+/// The data are only decompressed if the compression is given in the read configuration.
+pub fn read_input_from<R: Read>(format: InputFormat, reader: R, read_config: &ReadConfig) -> Result<DataFrame> {
+    let reader = decoder(read_config.compression.unwrap_or(Compression::Uncompressed), reader)?;
+    let df = match format {
+        InputFormat::Csv => read_csv_from(reader, read_config)?,
+        InputFormat::Xlsx => read_excel_from(reader, read_config.sheet.as_deref(), read_config.skip_rows)?,
+        #[cfg(feature = "native")]
+        InputFormat::Parquet => read_parquet_from(reader)?,
+        #[cfg(not(feature = "native"))]
+        InputFormat::Parquet => bail!("xtab was built without support for {:?} input", format),
+        InputFormat::Ndjson => read_ndjson_from(reader)?,
+        InputFormat::Sqlite => bail!("SQLite databases cannot be read from standard input"),
+    };
+    Ok(df)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that the input format is detected from the extension
+    #[test]
+    fn test_input_format_from_path() {
+        assert_eq!(InputFormat::from_path("data.xlsx"), InputFormat::Xlsx);
+        assert_eq!(InputFormat::from_path("data.ODS"), InputFormat::Xlsx);
+        assert_eq!(InputFormat::from_path("data.parquet"), InputFormat::Parquet);
+        assert_eq!(InputFormat::from_path("data.jsonl"), InputFormat::Ndjson);
+        assert_eq!(InputFormat::from_path("data.sqlite"), InputFormat::Sqlite);
+        assert_eq!(InputFormat::from_path("data.ndjson.gz"), InputFormat::Ndjson);
+        assert_eq!(InputFormat::from_path("data.tsv"), InputFormat::Csv);
+        assert_eq!(InputFormat::from_path("-"), InputFormat::Csv);
+    }
+
+    // Test that a scan reads the same data as an eager read
+    #[test]
+    fn test_scan_input() {
+        let path = std::env::temp_dir().join(format!("xtab_{}_scan.csv", std::process::id()));
+        std::fs::write(&path, "site,result\nA01,1.5\nB02,3.1\n").unwrap();
+        let scanned = scan_input(InputFormat::Csv, &path, &ReadConfig::default()).unwrap().with_streaming(true).collect().unwrap();
+        assert_eq!(scanned, read_input(InputFormat::Csv, &path, &ReadConfig::default()).unwrap());
+    }
+
+    // Test that a directory or a glob pattern names the files in it or that match it, in name order
+    #[test]
+    fn test_input_paths() {
+        let dir = std::env::temp_dir().join(format!("xtab_{}_input_paths", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        for name in ["2024-02.csv", "2024-01.csv", "notes.txt"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        assert_eq!(input_paths(&dir).unwrap(), vec![dir.join("2024-01.csv"), dir.join("2024-02.csv"), dir.join("notes.txt")]);
+        assert_eq!(input_paths(dir.join("2024-*.csv")).unwrap(), vec![dir.join("2024-01.csv"), dir.join("2024-02.csv")]);
+        assert_eq!(input_paths(dir.join("notes.txt")).unwrap(), vec![dir.join("notes.txt")]);
+        assert!(input_paths(dir.join("2023-*.csv")).is_err());
+        assert!(input_paths(dir.join("2023-01.csv")).is_err());
+    }
+
+    // Test that frames are stacked with their columns lined up, and that their differences are listed
+    #[test]
+    fn test_concat_inputs() {
+        let a = df!("site" => &["A01"], "result" => &[1]).unwrap().lazy();
+        let b = df!("result" => &[2.5], "site" => &["B02"]).unwrap().lazy();
+        let stacked = concat_inputs(vec![("a.csv".to_string(), a.clone()), ("b.csv".to_string(), b)]).unwrap().collect().unwrap();
+        assert_eq!(stacked, df!("site" => &["A01", "B02"], "result" => &[1.0, 2.5]).unwrap());
+
+        let c = df!("site" => &[7], "unit" => &["mg/L"]).unwrap().lazy();
+        let error = concat_inputs(vec![("a.csv".to_string(), a), ("c.csv".to_string(), c)]).err().unwrap().to_string();
+        let expected = "\
+The input files cannot be stacked, because they differ from a.csv:
+  c.csv lacks the column(s) 'result'
+  c.csv has the extra column(s) 'unit'
+  the column 'site' is str in a.csv but i32 in c.csv";
+        assert_eq!(error, expected);
+    }
+
+    // Test that newline-delimited JSON objects are read as rows
+    #[test]
+    fn test_read_ndjson_from() {
+        let data = "{\"site\":\"A01\",\"result\":1.5}\n{\"site\":\"B02\",\"result\":3.1}\n";
+        let df = read_input_from(InputFormat::Ndjson, data.as_bytes(), &ReadConfig::default()).unwrap();
+        assert_eq!(df.shape(), (2, 2));
+        assert_eq!(df.column("result").unwrap().dtype(), &DataType::Float64);
+    }
+}
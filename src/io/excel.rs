@@ -0,0 +1,102 @@
+// Reading of Excel (and other spreadsheet) workbooks into a DataFrame.
+
+use anyhow::{anyhow, Result};
+use calamine::{open_workbook_auto, open_workbook_auto_from_rs, Data, DataType as _, Reader, Sheets};
+use polars::prelude::*;
+use std::io::{Cursor, Read, Seek};
+use std::path::Path;
+
+// The text of a cell, or `None` if it is empty. Dates are written as ISO 8601 dates, with the time only when it is not midnight.
+fn cell_string(cell: &Data) -> Option<String> {
+    match cell {
+        Data::Empty => None,
+        Data::DateTime(_) => cell.as_datetime().map(|dt| {
+            if dt.time() == Default::default() {
+                dt.format("%Y-%m-%d").to_string()
+            } else {
+                dt.format("%Y-%m-%d %H:%M:%S").to_string()
+            }
+        }),
+        _ => Some(cell.to_string()),
+    }
+}
+
+// Convert one worksheet column to a Series, using the narrowest type that holds every non-empty cell.
+fn cells_to_series(name: &str, cells: &[&Data]) -> Series {
+    let present = || cells.iter().filter(|c| !matches!(c, Data::Empty));
+    if present().all(|c| matches!(c, Data::Int(_))) {
+        let values: Vec<Option<i64>> = cells.iter().map(|c| match c { Data::Int(i) => Some(*i), _ => None }).collect();
+        Series::new(name, values)
+    } else if present().all(|c| matches!(c, Data::Int(_) | Data::Float(_))) {
+        let values: Vec<Option<f64>> = cells.iter().map(|c| match c {
+            Data::Int(i) => Some(*i as f64),
+            Data::Float(f) => Some(*f),
+            _ => None,
+        }).collect();
+        Series::new(name, values)
+    } else if present().all(|c| matches!(c, Data::Bool(_))) {
+        let values: Vec<Option<bool>> = cells.iter().map(|c| match c { Data::Bool(b) => Some(*b), _ => None }).collect();
+        Series::new(name, values)
+    } else {
+        let values: Vec<Option<String>> = cells.iter().map(|c| cell_string(c)).collect();
+        Series::new(name, values)
+    }
+}
+
+// Read a worksheet of an open workbook into a DataFrame, using the first row after the skipped rows as the column names.
+fn read_worksheet<RS: Read + Seek>(mut workbook: Sheets<RS>, sheet: Option<&str>, skip_rows: usize) -> Result<DataFrame> {
+    let range = match sheet {
+        Some(name) => workbook.worksheet_range(name)?,
+        None => workbook
+            .worksheet_range_at(0)
+            .ok_or_else(|| anyhow!("The workbook does not contain any worksheets"))??,
+    };
+
+    let mut rows = range.rows().skip(skip_rows);
+    let header = rows.next().ok_or_else(|| anyhow!("The worksheet is empty"))?;
+    let data: Vec<&[Data]> = rows.collect();
+    let columns = header
+        .iter()
+        .enumerate()
+        .map(|(j, name)| {
+            let cells: Vec<&Data> = data.iter().map(|row| row.get(j).unwrap_or(&Data::Empty)).collect();
+            cells_to_series(&name.to_string(), &cells)
+        })
+        .collect();
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Read a worksheet of an Excel (.xlsx, .xlsm, .xlsb, or .xls) or OpenDocument (.ods) workbook into a DataFrame.
+/// The first row of the worksheet after the skipped rows must contain the column names. If no sheet name is given, the
+/// first worksheet is read.
+pub fn read_excel<P: AsRef<Path>>(path: P, sheet: Option<&str>, skip_rows: usize) -> Result<DataFrame> {
+    read_worksheet(open_workbook_auto(path)?, sheet, skip_rows)
+}
+
+/// Read a worksheet of a workbook from any reader (such as standard input) into a DataFrame.
+pub fn read_excel_from<R: Read>(mut reader: R, sheet: Option<&str>, skip_rows: usize) -> Result<DataFrame> {
+    // Workbooks are zip (or compound document) files that must be read with random access, so the whole input is buffered first
+    let mut bytes: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    read_worksheet(open_workbook_auto_from_rs(Cursor::new(bytes))?, sheet, skip_rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that each worksheet column gets the narrowest type that holds its cells
+    #[test]
+    fn test_cells_to_series() {
+        let ints = [Data::Int(1), Data::Empty, Data::Int(3)];
+        let series = cells_to_series("count", &ints.iter().collect::<Vec<_>>());
+        assert_eq!(series.dtype(), &DataType::Int64);
+        assert_eq!(series.null_count(), 1);
+
+        let floats = [Data::Int(1), Data::Float(2.5)];
+        assert_eq!(cells_to_series("result", &floats.iter().collect::<Vec<_>>()).dtype(), &DataType::Float64);
+
+        let mixed = [Data::String("ND".to_string()), Data::Float(2.5)];
+        assert_eq!(cells_to_series("result", &mixed.iter().collect::<Vec<_>>()).dtype(), &DataType::String);
+    }
+}
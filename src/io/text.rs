@@ -0,0 +1,109 @@
+// Reading of delimited text that polars cannot read itself: text in encodings other than UTF-8, text with escape
+// characters, or lines whose number of fields differs from that of the header line.
+
+use super::{BadLines, ReadConfig};
+use encoding_rs::Encoding;
+use log::warn;
+use polars::prelude::*;
+use std::io::{BufRead, BufReader, Read};
+
+// Read the whole text in the encoding and convert it to UTF-8. A byte order mark overrides the encoding, and bytes
+// that are not valid in the encoding are replaced with the replacement character.
+pub(crate) fn transcode<R: Read>(mut reader: R, encoding: &'static Encoding) -> std::io::Result<Vec<u8>> {
+    let mut bytes: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let (text, used, had_errors) = encoding.decode(&bytes);
+    if had_errors {
+        warn!("The input contains bytes that are not valid {} text, which were replaced", used.name());
+    }
+    Ok(text.into_owned().into_bytes())
+}
+
+// Read delimited text with the csv crate, skipping the lines before the header line and skipping or rejecting the
+// bad lines, and write it back as standard delimited text (with double quotes and without comments) for the polars
+// reader
+pub(crate) fn clean_csv<R: Read>(reader: R, read_config: &ReadConfig) -> PolarsResult<Vec<u8>> {
+    let mut reader = BufReader::new(reader);
+    let mut skipped_line: Vec<u8> = Vec::new();
+    for _ in 0..read_config.skip_rows {
+        skipped_line.clear();
+        reader.read_until(b'\n', &mut skipped_line)?;
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(read_config.delimiter)
+        .quoting(read_config.quote.is_some())
+        .quote(read_config.quote.unwrap_or(b'"'))
+        .escape(read_config.escape)
+        .double_quote(read_config.escape.is_none())
+        .comment(read_config.comment)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(reader);
+    let mut writer = csv::WriterBuilder::new().delimiter(read_config.delimiter).from_writer(Vec::new());
+    let csv_error = |e: csv::Error| polars_err!(ComputeError: "{}", e);
+    let mut width: Option<usize> = None;
+    let mut skipped: usize = 0;
+    for record in reader.records() {
+        let record = record.map_err(csv_error)?;
+        match width {
+            None => width = Some(record.len()),
+            Some(n) if record.len() != n => {
+                let line = record.position().map(|p| p.line()).unwrap_or_default() + read_config.skip_rows as u64;
+                let problem = format!("Line {} has {} fields, but the header line has {}", line, record.len(), n);
+                match read_config.bad_lines {
+                    BadLines::Error => return Err(polars_err!(ComputeError: "{}", problem)),
+                    BadLines::Warn => warn!("{}; the line was skipped", problem),
+                    BadLines::Skip => {}
+                }
+                skipped += 1;
+                continue;
+            }
+            Some(_) => {}
+        }
+        writer.write_record(&record).map_err(csv_error)?;
+    }
+    if skipped > 0 && read_config.bad_lines == BadLines::Warn {
+        warn!("{} bad line(s) were skipped", skipped);
+    }
+    writer.into_inner().map_err(|e| polars_err!(ComputeError: "{}", e))
+}
+
+// Describe the first bad line of delimited text that polars failed to read, or return the polars error if the
+// csv crate finds no bad line
+pub(crate) fn explain_csv_error<R: Read>(error: PolarsError, reader: R, read_config: &ReadConfig) -> PolarsError {
+    let strict = ReadConfig { bad_lines: BadLines::Error, ..read_config.clone() };
+    match clean_csv(reader, &strict) {
+        Err(e) => e,
+        Ok(_) => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that windows-1252 and UTF-16 text are converted to UTF-8
+    #[test]
+    fn test_transcode() {
+        let windows_1252: &[u8] = b"site,analyte\nA01,\xb5g/L \x96 total\n";
+        let text = transcode(windows_1252, encoding_rs::WINDOWS_1252).unwrap();
+        assert_eq!(String::from_utf8(text).unwrap(), "site,analyte\nA01,µg/L – total\n");
+        let utf_16: Vec<u8> = [0xFF, 0xFE].into_iter().chain("né".encode_utf16().flat_map(|u| u.to_le_bytes())).collect();
+        assert_eq!(transcode(utf_16.as_slice(), encoding_rs::UTF_16LE).unwrap(), "né".as_bytes());
+    }
+
+    const RAGGED: &str = "# exported 2024-06-01\nsite,result\nA01,1.5\nB02,2.5,extra\n\"C,03\",\"3\\\"5\"\n";
+
+    // Test that bad lines are skipped or rejected, and that comments and escape characters are handled
+    #[test]
+    fn test_clean_csv() {
+        let mut read_config = ReadConfig { comment: Some(b'#'), escape: Some(b'\\'), ..ReadConfig::default() };
+        read_config.bad_lines = BadLines::Skip;
+        let cleaned = String::from_utf8(clean_csv(RAGGED.as_bytes(), &read_config).unwrap()).unwrap();
+        assert_eq!(cleaned, "site,result\nA01,1.5\n\"C,03\",\"3\"\"5\"\n");
+
+        read_config.bad_lines = BadLines::Error;
+        let error = clean_csv(RAGGED.as_bytes(), &read_config).unwrap_err().to_string();
+        assert!(error.contains("Line 4 has 3 fields, but the header line has 2"), "{}", error);
+    }
+}
@@ -0,0 +1,180 @@
+//! Job files that declare the arguments of a crosstab, so that a crosstab that is run repeatedly needs no long command line.
+//!
+//! A job file is a TOML (`.toml`) or YAML (`.yaml` or `.yml`) table whose keys are the long names of the command line
+//! arguments, written with underscores or hyphens, such as
+//!
+//! ```toml
+//! infile = "results.csv"
+//! outfile = "crosstab.xlsx"
+//! row = ["site", "sample_date"]
+//! col = "analyte"
+//! value = ["result", "unit"]
+//! aggregate = "first"
+//! row_totals = true
+//! ```
+//!
+//! A list is equivalent to a comma-separated argument, and `true` to a flag that is given.
+//!
+//! A batch job file also has a list of named jobs, each with its own settings, which are run in order against a single
+//! read of the input. The settings at the top level are shared by all of the jobs:
+//!
+//! ```toml
+//! infile = "results.csv"
+//! row = ["site"]
+//! col = "sample_date"
+//!
+//! [[jobs]]
+//! name = "results"
+//! value = ["result", "unit"]
+//! outfile = "results.xlsx"
+//!
+//! [[jobs]]
+//! name = "counts"
+//! count = true
+//! outfile = "counts.csv"
+//! ```
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+use std::path::Path;
+
+/// The value of one argument declared in a job file.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Setting {
+    /// A flag, which is given if it is true.
+    Flag(bool),
+    /// A single value.
+    Value(String),
+    /// A list of values.
+    List(Vec<String>),
+}
+
+/// One of the named jobs of a batch job file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Job {
+    /// The name of the job, or `job N` for the Nth job if it has none.
+    pub name: String,
+    /// The settings of the job, which are added to the shared settings.
+    pub settings: Vec<(String, Setting)>,
+}
+
+/// The contents of a job file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JobFile {
+    /// The settings at the top level, which are shared by all of the jobs.
+    pub settings: Vec<(String, Setting)>,
+    /// The named jobs of a batch job file, or none for a single job.
+    pub jobs: Vec<Job>,
+}
+
+// The key of the list of jobs of a batch job file
+const JOBS_KEY: &str = "jobs";
+
+// The text of a scalar value, or None if it is not a string, number, or boolean
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// The settings of a table of a job file, in order, with keys normalized to argument names. The list of jobs and the
+/// name of a job are not settings.
+pub fn table_settings(table: &serde_json::Map<String, Value>) -> Result<Vec<(String, Setting)>> {
+    let mut settings: Vec<(String, Setting)> = Vec::new();
+    for (key, value) in table.iter().filter(|(key, _)| key.as_str() != JOBS_KEY && key.as_str() != "name") {
+        let setting = match value {
+            Value::Bool(b) => Setting::Flag(*b),
+            Value::Array(values) => Setting::List(
+                values
+                    .iter()
+                    .map(|v| scalar(v).ok_or_else(|| anyhow!("The list '{}' must contain only strings and numbers", key)))
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            Value::Null => continue,
+            other => Setting::Value(scalar(other).ok_or_else(|| anyhow!("The setting '{}' must be a string, number, boolean, or list", key))?),
+        };
+        settings.push((key.replace('_', "-"), setting));
+    }
+    Ok(settings)
+}
+
+/// Read the shared settings and the jobs of a TOML or YAML job file, in the order they are declared. The keys are
+/// returned as argument names, with hyphens instead of underscores.
+pub fn read_job_file<P: AsRef<Path>>(path: P) -> Result<JobFile> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path).with_context(|| format!("The job file cannot be read: {}", path.display()))?;
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    let value: Value = match extension.as_deref() {
+        Some("toml") => serde_json::to_value(text.parse::<toml::Table>()?)?,
+        Some("yaml" | "yml") => serde_yaml::from_str(&text)?,
+        _ => bail!("The job file must be a .toml, .yaml, or .yml file: {}", path.display()),
+    };
+    let Value::Object(table) = &value else {
+        bail!("The job file must contain a table of settings: {}", path.display());
+    };
+    let jobs = match table.get(JOBS_KEY) {
+        None => Vec::new(),
+        Some(Value::Array(jobs)) => jobs
+            .iter()
+            .enumerate()
+            .map(|(i, job)| match job {
+                Value::Object(job) => Ok(Job {
+                    name: job.get("name").and_then(scalar).unwrap_or_else(|| format!("job {}", i + 1)),
+                    settings: table_settings(job)?,
+                }),
+                _ => bail!("Each of the jobs must be a table of settings"),
+            })
+            .collect::<Result<Vec<_>>>()?,
+        Some(_) => bail!("The jobs must be a list of tables of settings"),
+    };
+    Ok(JobFile { settings: table_settings(table)?, jobs })
+}
+
+/// The command line arguments equivalent to the settings, such as `--row=site,date` for `row = ["site", "date"]`.
+pub fn job_args(settings: &[(String, Setting)]) -> Vec<String> {
+    settings
+        .iter()
+        .filter_map(|(name, setting)| match setting {
+            Setting::Flag(true) => Some(format!("--{}", name)),
+            Setting::Flag(false) => None,
+            Setting::Value(value) => Some(format!("--{}={}", name, value)),
+            Setting::List(values) => Some(format!("--{}={}", name, values.join(","))),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that TOML and YAML job files declare the same arguments
+    #[test]
+    fn test_read_job_file() {
+        let dir = std::env::temp_dir();
+        let toml_path = dir.join(format!("xtab_{}_job.toml", std::process::id()));
+        std::fs::write(&toml_path, "row = [\"site\", \"date\"]\ncol = \"analyte\"\nformat = 2\nrow_totals = true\ncol_totals = false\n").unwrap();
+        let yaml_path = dir.join(format!("xtab_{}_job.yaml", std::process::id()));
+        std::fs::write(&yaml_path, "row: [site, date]\ncol: analyte\nformat: 2\nrow_totals: true\ncol_totals: false\n").unwrap();
+        let expected = vec!["--row=site,date", "--col=analyte", "--format=2", "--row-totals"];
+        assert_eq!(job_args(&read_job_file(&toml_path).unwrap().settings), expected);
+        assert_eq!(job_args(&read_job_file(&yaml_path).unwrap().settings), expected);
+        assert!(read_job_file(dir.join("job.ini")).is_err());
+    }
+
+    // Test that the jobs of a batch job file are read separately from the shared settings
+    #[test]
+    fn test_read_batch_job_file() {
+        let path = std::env::temp_dir().join(format!("xtab_{}_batch.yml", std::process::id()));
+        std::fs::write(&path, "row: site\njobs:\n  - name: results\n    value: [result, unit]\n  - count: true\n").unwrap();
+        let job_file = read_job_file(&path).unwrap();
+        assert_eq!(job_args(&job_file.settings), vec!["--row=site"]);
+        assert_eq!(job_file.jobs.len(), 2);
+        assert_eq!(job_file.jobs[0].name, "results");
+        assert_eq!(job_args(&job_file.jobs[0].settings), vec!["--value=result,unit"]);
+        assert_eq!(job_file.jobs[1].name, "job 2");
+        assert_eq!(job_args(&job_file.jobs[1].settings), vec!["--count"]);
+    }
+}
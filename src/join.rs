@@ -0,0 +1,82 @@
+//! Joining a lookup table to the input data, so that descriptive attributes of the rows, such as the name and region
+//! of each site, can be used as row headers or carried columns without a separate join step.
+
+use crate::XtabError;
+use anyhow::{bail, Result};
+use polars::prelude::*;
+
+/// Plan a left join of the lookup table to the input data on the key columns, which both must have, adding the
+/// given lookup columns (or every lookup column other than the keys, if none are given). Input rows whose keys are
+/// not in the lookup table get missing values. The key columns of the lookup table are converted to the types of
+/// those of the input data, and a lookup table with more than one row for a key is an error, because it would
+/// repeat input rows.
+pub fn join_lazy(lf: LazyFrame, lookup: DataFrame, on: &[String], columns: &[String]) -> Result<LazyFrame> {
+    let schema = lf.schema()?;
+    let lookup_names: Vec<&str> = lookup.get_column_names();
+    let columns: Vec<String> = match columns.is_empty() {
+        true => lookup_names.iter().filter(|name| !on.iter().any(|key| key == *name)).map(|name| name.to_string()).collect(),
+        false => columns.to_vec(),
+    };
+    let mut missing: Vec<String> = Vec::new();
+    for key in on {
+        if !schema.contains(key) {
+            missing.push(format!("The join column '{}' is not an input column", key));
+        }
+    }
+    for column in on.iter().chain(&columns) {
+        if !lookup_names.contains(&column.as_str()) {
+            missing.push(format!("The column '{}' is not in the lookup table", column));
+        }
+    }
+    if !missing.is_empty() {
+        return Err(XtabError::MissingColumns(missing).into());
+    }
+    if let Some(column) = columns.iter().find(|column| schema.contains(column)) {
+        return Err(XtabError::Argument(format!("The lookup column '{}' is already an input column", column)).into());
+    }
+
+    let keys: Vec<Expr> = on.iter().map(|key| col(key).cast(schema.get(key).unwrap().clone())).collect();
+    let lookup = lookup
+        .lazy()
+        .select(keys.iter().cloned().chain(columns.iter().map(|column| col(column))).collect::<Vec<Expr>>())
+        .collect()?;
+    let n_keys = lookup.select(on)?.unique(None, UniqueKeepStrategy::Any, None)?.height();
+    if n_keys != lookup.height() {
+        bail!("The lookup table has more than one row for {} of its keys", lookup.height() - n_keys);
+    }
+    let on: Vec<Expr> = on.iter().map(|key| col(key)).collect();
+    Ok(lf.join(lookup.lazy(), &on, &on, JoinArgs::new(JoinType::Left)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that the lookup columns are added to the matching input rows, and that a repeated key is an error
+    #[test]
+    fn test_join_lazy() {
+        let df = df!("site_id" => &[1, 2, 1, 3], "result" => &[1.5, 2.5, 3.5, 4.5]).unwrap();
+        let lookup = df!(
+            "site_id" => &["1", "2"],
+            "site_name" => &["Mill Creek", "Cedar Run"],
+            "region" => &["North", "South"],
+        )
+        .unwrap();
+        let on = vec!["site_id".to_string()];
+        let joined = join_lazy(df.clone().lazy(), lookup.clone(), &on, &["site_name".to_string()]).unwrap().collect().unwrap();
+        let expected = df!(
+            "site_id" => &[1, 2, 1, 3],
+            "result" => &[1.5, 2.5, 3.5, 4.5],
+            "site_name" => &[Some("Mill Creek"), Some("Cedar Run"), Some("Mill Creek"), None],
+        )
+        .unwrap();
+        assert_eq!(joined, expected);
+        let joined = join_lazy(df.clone().lazy(), lookup.clone(), &on, &[]).unwrap().collect().unwrap();
+        assert_eq!(joined.get_column_names(), vec!["site_id", "result", "site_name", "region"]);
+
+        let repeated = df!("site_id" => &[1, 1], "site_name" => &["Mill Creek", "Mill Run"]).unwrap();
+        assert!(join_lazy(df.clone().lazy(), repeated, &on, &[]).is_err());
+        let error = join_lazy(df.lazy(), lookup, &["site".to_string()], &[]).err().unwrap();
+        assert_eq!(crate::error::exit_code(&error), 3);
+    }
+}
@@ -0,0 +1,142 @@
+//! Read a table of data in normalized form and cross-tab it, allowing multiple data columns to be crosstabbed.
+//!
+//! The crosstab is built in three steps: read the normalized data into a polars `DataFrame` with one of
+//! the [`io`] readers, describe the crosstab with an [`XtabConfig`], and pivot the data with [`Crosstab::new`].
+//! The resulting [`Crosstab`] can then be written as a CSV file, or in another format with the [`output`] writers.
+//! To embed the crosstab in another program, a [`CrosstabBuilder`] pivots a `DataFrame` into a `DataFrame` in one
+//! step, with a [`CrosstabReport`] of the warnings about the input. The bindings in other languages describe a
+//! crosstab with the [`params`] of the command line.
+//!
+//! ```no_run
+//! use xtab::{read_csv, Crosstab, ReadConfig, WriteConfig, XtabConfig};
+//!
+//! let df = read_csv("results.csv", &ReadConfig::default())?;
+//! let config = XtabConfig::new(&["site"], &["date"], &["result"]);
+//! let crosstab = Crosstab::new(&df, &config)?;
+//! crosstab.write_csv(std::fs::File::create("crosstab.csv")?, &WriteConfig::default())?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod aggregate;
+pub mod bins;
+mod builder;
+pub mod chisq;
+pub mod columns;
+pub mod compression;
+mod crosstab;
+pub mod dates;
+pub mod error;
+pub mod filter;
+pub mod format;
+mod header;
+pub mod highlight;
+pub mod inspect;
+pub mod io;
+pub mod join;
+pub mod job;
+pub mod melt;
+pub mod merge;
+pub mod metadata;
+pub mod output;
+pub mod pair;
+pub mod params;
+pub mod plugin;
+pub mod recode;
+pub mod schema;
+#[cfg(feature = "native")]
+pub mod serve;
+pub mod sort;
+pub mod source;
+pub mod split;
+pub mod sql;
+
+use std::path::Path;
+
+pub use builder::{CrosstabBuilder, CrosstabReport};
+pub use crosstab::{aggregate_cells, duplicate_counts, expand_patterns, input_rows, missing_columns, resolve_indices, resolve_names, Crosstab, Delta, NullKeys, OnDuplicate, Percent, Rank, XtabConfig, CELL_ROWS_LABEL, COUNT_LABEL, INPUT_ROW_LABEL, SUBTOTAL_SUFFIX, TOTAL_LABEL};
+pub use error::XtabError;
+pub use header::{header_rows, HeaderNames};
+pub use io::{read_csv, ReadConfig};
+
+/// The composite key of an output row or generated output column.
+pub type Key = Vec<String>;
+
+/// Options that control how the crosstab is written as delimited text, or as another text format.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WriteConfig {
+    /// The character that separates the fields of each output line.
+    pub delimiter: u8,
+    /// The CSS class of an HTML table, so that a style sheet can pick it out, or `None` for no class.
+    pub html_class: Option<String>,
+    /// An HTML document to write an HTML table in, in place of its `{{table}}` placeholder, or `None` to write the
+    /// table in a standalone document.
+    pub html_template: Option<String>,
+    /// The largest width, in characters, of a table for reading in a terminal, or `None` for the width of the
+    /// terminal. The columns that do not fit are left out.
+    pub max_width: Option<usize>,
+    /// The rules that highlight the cells of spreadsheet and HTML output, in order: each cell gets the style of the
+    /// first rule that it matches.
+    pub highlights: Vec<highlight::HighlightRule>,
+}
+
+impl Default for WriteConfig {
+    fn default() -> Self {
+        WriteConfig { delimiter: b',', html_class: None, html_template: None, max_width: None, highlights: Vec::new() }
+    }
+}
+
+/// Parse a delimiter argument: a single character, or one of the names `tab`, `\t`, `comma`, `pipe`, `semicolon`, or `space`.
+pub fn parse_delimiter(s: &str) -> Result<u8, String> {
+    match s {
+        "tab" | "\\t" | "\t" => Ok(b'\t'),
+        "comma" => Ok(b','),
+        "pipe" => Ok(b'|'),
+        "semicolon" => Ok(b';'),
+        "space" => Ok(b' '),
+        _ if s.len() == 1 => Ok(s.as_bytes()[0]),
+        _ => Err(format!("The delimiter must be a single character: '{}'", s)),
+    }
+}
+
+/// The delimiter implied by a file's extension: a tab for `.tsv`, `.tab`, and `.txt` files, and a comma otherwise.
+/// A compression extension is ignored, so `.tsv.gz` files are also tab-delimited.
+pub fn delimiter_for_path<P: AsRef<Path>>(path: P) -> u8 {
+    let extension = compression::uncompressed_path(path).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    match extension.as_deref() {
+        Some("tsv") | Some("tab") | Some("txt") => b'\t',
+        _ => b',',
+    }
+}
+
+/// Limit the number of threads used to read and query the input (by polars) and to build the crosstab (by rayon).
+/// This must be called before any data are read, because the thread pools are created when they are first used.
+pub fn set_threads(threads: usize) -> anyhow::Result<()> {
+    std::env::set_var("POLARS_MAX_THREADS", threads.to_string());
+    rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test the delimiter names and single characters accepted by the delimiter arguments
+    #[test]
+    fn test_parse_delimiter() {
+        assert_eq!(parse_delimiter("tab"), Ok(b'\t'));
+        assert_eq!(parse_delimiter("\\t"), Ok(b'\t'));
+        assert_eq!(parse_delimiter("|"), Ok(b'|'));
+        assert_eq!(parse_delimiter(";"), Ok(b';'));
+        assert!(parse_delimiter("||").is_err());
+    }
+
+    // Test that the delimiter is detected from the file extension
+    #[test]
+    fn test_delimiter_for_path() {
+        assert_eq!(delimiter_for_path("data.tsv"), b'\t');
+        assert_eq!(delimiter_for_path("data.TXT"), b'\t');
+        assert_eq!(delimiter_for_path("data.csv"), b',');
+        assert_eq!(delimiter_for_path("data.tsv.gz"), b'\t');
+        assert_eq!(delimiter_for_path("-"), b',');
+    }
+}
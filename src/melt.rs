@@ -0,0 +1,189 @@
+//! The reverse of a crosstab: melting a wide table back into normalized (long) form.
+
+use anyhow::{anyhow, Result};
+use polars::prelude::*;
+use regex::Regex;
+
+use crate::columns::{describe_missing, find_column};
+use crate::{Key, XtabError};
+
+/// The name of the column holding the wide column names when there is no split pattern.
+pub const DEFAULT_KEY_NAME: &str = "variable";
+
+/// The name of the column holding the values when the split pattern has no `var` group.
+pub const DEFAULT_VALUE_NAME: &str = "value";
+
+/// The description of how to melt a wide table.
+#[derive(Clone, Debug)]
+pub struct MeltConfig {
+    /// The identifier columns, which are repeated on every output row. All other columns are melted.
+    pub id_columns: Vec<String>,
+    /// The name of the output column holding the wide column names, when there is no split pattern.
+    pub key_name: String,
+    /// The name of the output column holding the values, when there is no `var` group in the split pattern.
+    pub value_name: String,
+    /// A pattern that splits each wide column name into parts with named capture groups, such as
+    /// `^(?P<date>.+)_(?P<var>[^_]+)$` for the format 1 names of a crosstab by date. Each named group other
+    /// than `var` becomes an output column, and each `var` part becomes an output value column.
+    pub split: Option<Regex>,
+}
+
+impl MeltConfig {
+    /// Create a configuration with the default key and value column names and no split pattern.
+    pub fn new<S: AsRef<str>>(id_columns: &[S]) -> MeltConfig {
+        MeltConfig {
+            id_columns: id_columns.iter().map(|s| s.as_ref().to_string()).collect(),
+            key_name: DEFAULT_KEY_NAME.to_string(),
+            value_name: DEFAULT_VALUE_NAME.to_string(),
+            split: None,
+        }
+    }
+}
+
+// The position of a melted column among the melted columns, and the position of its value column
+type Position = (usize, usize);
+
+// The parts of a wide column name: the values of the key columns and the name of the value column
+fn split_name(config: &MeltConfig, key_names: &[String], name: &str) -> Result<(Vec<String>, String)> {
+    match &config.split {
+        None => Ok((vec![name.to_string()], config.value_name.clone())),
+        Some(pattern) => {
+            let captures = pattern
+                .captures(name)
+                .ok_or_else(|| anyhow!("The column '{}' does not match the split pattern '{}'", name, pattern))?;
+            let part = |group: &str| captures.name(group).map(|m| m.as_str().to_string()).unwrap_or_default();
+            let var = match captures.name("var") {
+                Some(m) => m.as_str().to_string(),
+                None => config.value_name.clone(),
+            };
+            Ok((key_names.iter().map(|k| part(k)).collect(), var))
+        }
+    }
+}
+
+/// Melt a wide table into normalized form, with one output row for each input row and distinct combination of key
+/// column values that has at least one value. Missing and empty values are dropped, so melting a crosstab
+/// reproduces its input data. All of the key and value columns are strings.
+pub fn melt(df: &DataFrame, config: &MeltConfig) -> Result<DataFrame> {
+    let names = df.get_column_names();
+    // Identifier columns match input columns whose names differ only in capitalization or whitespace
+    let id_columns: Vec<String> =
+        config.id_columns.iter().map(|id| find_column(&names, id).unwrap_or(id).to_string()).collect();
+    let missing: Vec<String> = id_columns
+        .iter()
+        .filter(|id| !names.contains(&id.as_str()))
+        .map(|id| describe_missing(&names, "identifier", id))
+        .collect();
+    if !missing.is_empty() {
+        return Err(XtabError::MissingColumns(missing).into());
+    }
+    let key_names: Vec<String> = match &config.split {
+        None => vec![config.key_name.clone()],
+        Some(pattern) => pattern.capture_names().flatten().filter(|n| *n != "var").map(|n| n.to_string()).collect(),
+    };
+
+    // Split the name of every melted column, and collect the value column names in order of first appearance
+    let mut melted: Vec<(&str, Vec<String>, usize)> = Vec::new();
+    let mut var_names: Vec<String> = Vec::new();
+    for name in names.iter().filter(|n| !id_columns.iter().any(|id| id == *n)) {
+        let (key, var) = split_name(config, &key_names, name)?;
+        let v = match var_names.iter().position(|n| n == &var) {
+            Some(v) => v,
+            None => {
+                var_names.push(var);
+                var_names.len() - 1
+            }
+        };
+        melted.push((name, key, v));
+    }
+    // The distinct keys, in order of first appearance, with the positions of their melted columns and value columns
+    let mut keys: Vec<(Key, Vec<Position>)> = Vec::new();
+    for (m, (_, key, v)) in melted.iter().enumerate() {
+        match keys.iter_mut().find(|(k, _)| k == key) {
+            Some((_, columns)) => columns.push((m, *v)),
+            None => keys.push((key.clone(), vec![(m, *v)])),
+        }
+    }
+
+    let strings = |name: &str| -> Result<Vec<Option<String>>> {
+        let series = df.column(name)?.cast(&DataType::String)?;
+        Ok(series.str()?.into_iter().map(|v| v.filter(|s| !s.is_empty()).map(|s| s.to_string())).collect())
+    };
+    let ids = id_columns.iter().map(|id| strings(id)).collect::<Result<Vec<_>>>()?;
+    let values = melted.iter().map(|(name, _, _)| strings(name)).collect::<Result<Vec<_>>>()?;
+
+    let mut id_out: Vec<Vec<Option<String>>> = vec![Vec::new(); ids.len()];
+    let mut key_out: Vec<Vec<String>> = vec![Vec::new(); key_names.len()];
+    let mut var_out: Vec<Vec<Option<String>>> = vec![Vec::new(); var_names.len()];
+    for i in 0..df.height() {
+        for (key, columns) in &keys {
+            let mut row: Vec<Option<String>> = vec![None; var_names.len()];
+            for &(m, v) in columns {
+                if let Some(value) = &values[m][i] {
+                    row[v] = Some(value.clone());
+                }
+            }
+            if row.iter().all(|v| v.is_none()) {
+                continue;
+            }
+            for (out, id) in id_out.iter_mut().zip(&ids) {
+                out.push(id[i].clone());
+            }
+            for (out, k) in key_out.iter_mut().zip(key) {
+                out.push(k.clone());
+            }
+            for (out, value) in var_out.iter_mut().zip(row) {
+                out.push(value);
+            }
+        }
+    }
+
+    let mut series: Vec<Series> = Vec::new();
+    series.extend(id_columns.iter().zip(id_out).map(|(name, values)| Series::new(name, values)));
+    series.extend(key_names.iter().zip(key_out).map(|(name, values)| Series::new(name, values)));
+    series.extend(var_names.iter().zip(var_out).map(|(name, values)| Series::new(name, values)));
+    Ok(DataFrame::new(series)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wide() -> DataFrame {
+        df!(
+            "site" => &["A01", "B02"],
+            "2024-01-01_result" => &[Some(1.5), None],
+            "2024-01-01_unit" => &[Some("mg/L"), None],
+            "2024-01-02_result" => &[Some(2.5), Some(3.1)],
+            "2024-01-02_unit" => &[Some("mg/L"), Some("mg/L")],
+        )
+        .unwrap()
+    }
+
+    // Test that every non-identifier column is melted into name and value columns
+    #[test]
+    fn test_melt() {
+        let output = melt(&wide(), &MeltConfig::new(&["site"])).unwrap();
+        assert_eq!(output.get_column_names(), &["site", "variable", "value"]);
+        assert_eq!(output.height(), 6);
+    }
+
+    // Test that a split pattern reverses a format 1 crosstab
+    #[test]
+    fn test_melt_split() {
+        let mut config = MeltConfig::new(&["site"]);
+        config.split = Some(Regex::new("^(?P<date>.+)_(?P<var>[^_]+)$").unwrap());
+        let output = melt(&wide(), &config).unwrap();
+        let expected = df!(
+            "site" => &["A01", "A01", "B02"],
+            "date" => &["2024-01-01", "2024-01-02", "2024-01-02"],
+            "result" => &["1.5", "2.5", "3.1"],
+            "unit" => &["mg/L", "mg/L", "mg/L"],
+        )
+        .unwrap();
+        assert_eq!(output, expected);
+
+        config.split = Some(Regex::new("^(?P<year>\\d+)_(?P<var>.+)$").unwrap());
+        assert!(melt(&wide(), &config).is_err());
+    }
+}
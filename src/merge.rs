@@ -0,0 +1,128 @@
+//! Merging a crosstab into an existing output file, so that a crosstab of new data (e.g. the latest month) can be
+//! added to the crosstab of the earlier data without rebuilding it.
+
+use crate::Crosstab;
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// A table of text cells with a single header row, such as a crosstab with format 1 column headers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextTable {
+    /// The column names.
+    pub header: Vec<String>,
+    /// The cells of each row, one for each column.
+    pub rows: Vec<Vec<String>>,
+}
+
+impl TextTable {
+    /// Read a table from delimited text whose first line has the column names. Short lines are padded with empty
+    /// cells.
+    pub fn read_csv<R: Read>(reader: R, delimiter: u8) -> Result<TextTable> {
+        let mut reader = csv::ReaderBuilder::new().delimiter(delimiter).flexible(true).from_reader(reader);
+        let header: Vec<String> = reader.headers()?.iter().map(|s| s.to_string()).collect();
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        for record in reader.records() {
+            let mut row: Vec<String> = record?.iter().map(|s| s.to_string()).collect();
+            row.resize(header.len().max(row.len()), String::new());
+            rows.push(row);
+        }
+        Ok(TextTable { header, rows })
+    }
+
+    /// The header row and data rows of a crosstab, which must have a single header row.
+    pub fn from_crosstab(crosstab: &Crosstab) -> Result<TextTable> {
+        let mut header_rows = crosstab.header_rows();
+        if header_rows.len() != 1 {
+            bail!("Only a crosstab with a single header row (header format 1) can be merged into a table");
+        }
+        Ok(TextTable { header: header_rows.remove(0), rows: crosstab.data_rows() })
+    }
+
+    /// Merge a newer table into this one, matching their rows by the values of the key columns. The columns of the
+    /// newer table that this one lacks are added after its columns, and the rows whose keys this one lacks are added
+    /// after its rows. Where the rows match, each non-empty cell of the newer table replaces the cell of this one.
+    pub fn merge(&self, newer: &TextTable, keys: &[String]) -> Result<TextTable> {
+        let position = |table: &TextTable, which: &str, key: &String| match table.header.iter().position(|h| h == key) {
+            Some(i) => Ok(i),
+            None => Err(anyhow!("The merge column '{}' is not in the {} table", key, which)),
+        };
+        let old_keys = keys.iter().map(|k| position(self, "existing", k)).collect::<Result<Vec<usize>>>()?;
+        let new_keys = keys.iter().map(|k| position(newer, "new", k)).collect::<Result<Vec<usize>>>()?;
+
+        // The position in the merged table of each column of the newer table
+        let mut header = self.header.clone();
+        let columns: Vec<usize> = newer
+            .header
+            .iter()
+            .map(|name| match header.iter().position(|h| h == name) {
+                Some(i) => i,
+                None => {
+                    header.push(name.clone());
+                    header.len() - 1
+                }
+            })
+            .collect();
+
+        let mut rows: Vec<Vec<String>> = self.rows.iter().map(|r| pad(r, header.len())).collect();
+        let mut index: HashMap<Vec<String>, usize> = HashMap::new();
+        for (i, row) in rows.iter().enumerate() {
+            index.entry(old_keys.iter().map(|&k| row[k].clone()).collect()).or_insert(i);
+        }
+        for new_row in &newer.rows {
+            let key: Vec<String> = new_keys.iter().map(|&k| new_row.get(k).cloned().unwrap_or_default()).collect();
+            let i = match index.get(&key) {
+                Some(&i) => i,
+                None => {
+                    rows.push(vec![String::new(); header.len()]);
+                    index.insert(key, rows.len() - 1);
+                    rows.len() - 1
+                }
+            };
+            for (cell, &c) in new_row.iter().zip(&columns) {
+                if !cell.is_empty() {
+                    rows[i][c] = cell.clone();
+                }
+            }
+        }
+        Ok(TextTable { header, rows })
+    }
+
+    /// Write the table as delimited text.
+    pub fn write_csv<W: Write>(&self, writer: W, delimiter: u8) -> Result<()> {
+        let mut writer = csv::WriterBuilder::new().delimiter(delimiter).from_writer(writer);
+        writer.write_record(&self.header)?;
+        for row in &self.rows {
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+// A row padded with empty cells to the given width
+fn pad(row: &[String], width: usize) -> Vec<String> {
+    let mut padded = row.to_vec();
+    padded.resize(width.max(row.len()), String::new());
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that new columns and rows are added and that matching cells are replaced by non-empty new values
+    #[test]
+    fn test_merge() {
+        let existing = TextTable::read_csv("site,2024-01_result\nA01,1.5\nB02,2.5\n".as_bytes(), b',').unwrap();
+        let newer = TextTable::read_csv("site,2024-01_result,2024-02_result\nB02,,3.5\nC03,4.5,5.5\n".as_bytes(), b',').unwrap();
+        let merged = existing.merge(&newer, &["site".to_string()]).unwrap();
+        let mut csv: Vec<u8> = Vec::new();
+        merged.write_csv(&mut csv, b',').unwrap();
+        let expected = "site,2024-01_result,2024-02_result\nA01,1.5,\nB02,2.5,3.5\nC03,4.5,5.5\n";
+        assert_eq!(String::from_utf8(csv).unwrap(), expected);
+
+        let error = existing.merge(&newer, &["station".to_string()]).unwrap_err().to_string();
+        assert_eq!(error, "The merge column 'station' is not in the existing table");
+    }
+}
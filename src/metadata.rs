@@ -0,0 +1,110 @@
+//! The metadata of a crosstab run, written to a JSON sidecar file so that pipelines can check and audit each run:
+//! the sizes of the input and output, the arguments of the run, and a hash of the output.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::Path;
+
+/// The version of xtab that made a run.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The metadata of a crosstab run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunMetadata {
+    /// The number of input rows, after any `--sql` query and `--filter` expression.
+    pub input_rows: usize,
+    /// The number of output data rows, not counting the header rows.
+    pub output_rows: usize,
+    /// The number of output columns, including the row header columns.
+    pub output_columns: usize,
+    /// The number of output cells to which more than one value mapped.
+    pub duplicate_cells: usize,
+    /// The arguments of the run, as given on the command line or declared by a job file.
+    pub arguments: Vec<String>,
+    /// The hexadecimal SHA-256 hash of the bytes written to the output, or `None` if it was written to a database
+    /// or to the files of several groups.
+    pub sha256: Option<String>,
+}
+
+impl RunMetadata {
+    /// The metadata as a JSON object.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "version": VERSION,
+            "input_rows": self.input_rows,
+            "output_rows": self.output_rows,
+            "output_columns": self.output_columns,
+            "duplicate_cells": self.duplicate_cells,
+            "arguments": self.arguments,
+            "sha256": self.sha256,
+        })
+    }
+
+    /// Write the metadata to a JSON file.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut file = std::fs::File::create(path).with_context(|| format!("The metadata file cannot be created: {}", path.display()))?;
+        serde_json::to_writer_pretty(&mut file, &self.to_json())?;
+        writeln!(file)?;
+        Ok(())
+    }
+}
+
+/// A writer that computes the SHA-256 hash of the bytes written through it.
+pub struct HashWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashWriter<W> {
+    pub fn new(inner: W) -> HashWriter<W> {
+        HashWriter { inner, hasher: Sha256::new() }
+    }
+
+    /// The hexadecimal hash of the bytes written so far.
+    pub fn hex_digest(self) -> String {
+        self.hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that the hash is of the bytes written, and that it is recorded in the metadata
+    #[test]
+    fn test_metadata() {
+        let mut writer = HashWriter::new(Vec::new());
+        writer.write_all(b"abc").unwrap();
+        let sha256 = writer.hex_digest();
+        assert_eq!(sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+        let metadata = RunMetadata {
+            input_rows: 4,
+            output_rows: 2,
+            output_columns: 3,
+            duplicate_cells: 0,
+            arguments: vec!["xtab".to_string(), "-r".to_string(), "site".to_string()],
+            sha256: Some(sha256),
+        };
+        let json = metadata.to_json();
+        assert_eq!(json["version"], VERSION);
+        assert_eq!(json["output_columns"], 3);
+        assert_eq!(json["arguments"][2], "site");
+        assert_eq!(json["sha256"].as_str().unwrap().len(), 64);
+    }
+}
@@ -0,0 +1,630 @@
+//! Writers that render a [`Crosstab`] in each of the supported output formats.
+
+use crate::compression::uncompressed_path;
+use crate::highlight::{cell_styles, HighlightStyle};
+use crate::source::{write_database, TableMode};
+use crate::{Crosstab, WriteConfig};
+use anyhow::Result;
+#[cfg(feature = "native")]
+use polars::prelude::{IpcWriter, SerWriter};
+use polars::prelude::{DataFrame, DataType};
+use rust_xlsxwriter::{Color, Format, FormatAlign, FormatBorder, Workbook, Worksheet};
+use serde_json::{Map, Value};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+mod markup;
+mod ods;
+mod pretty;
+
+pub use markup::TABLE_PLACEHOLDER;
+
+/// The file formats a crosstab can be written in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// Delimited text, such as CSV or TSV.
+    Csv,
+    /// An Excel workbook.
+    Xlsx,
+    /// An OpenDocument spreadsheet.
+    Ods,
+    /// A Parquet file.
+    Parquet,
+    /// An Arrow IPC (Feather version 2) file.
+    Arrow,
+    /// A JSON array of objects, one for each output row.
+    Json,
+    /// A GitHub-flavored Markdown table.
+    Markdown,
+    /// An HTML table.
+    Html,
+    /// A LaTeX tabular in the style of the booktabs package.
+    Latex,
+    /// A table with aligned columns and borders, for reading in a terminal.
+    Pretty,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "xlsx" => Ok(OutputFormat::Xlsx),
+            "ods" => Ok(OutputFormat::Ods),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "arrow" | "ipc" | "feather" => Ok(OutputFormat::Arrow),
+            "json" => Ok(OutputFormat::Json),
+            "md" | "markdown" => Ok(OutputFormat::Markdown),
+            "html" => Ok(OutputFormat::Html),
+            "latex" | "tex" => Ok(OutputFormat::Latex),
+            "pretty" => Ok(OutputFormat::Pretty),
+            _ => Err(format!("Unknown output format '{}'. Must be one of csv, xlsx, ods, parquet, arrow, json, md, html, latex, pretty", s)),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// The output format implied by a file's extension, or `None` if the extension is not a supported output format.
+    /// A compression extension is ignored, so `.csv.gz` files are written as CSV.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<OutputFormat> {
+        let extension = uncompressed_path(path).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+        match extension.as_deref() {
+            Some("csv" | "tsv" | "tab" | "txt") => Some(OutputFormat::Csv),
+            Some("xlsx") => Some(OutputFormat::Xlsx),
+            Some("ods") => Some(OutputFormat::Ods),
+            Some("parquet") => Some(OutputFormat::Parquet),
+            Some("arrow" | "ipc" | "feather") => Some(OutputFormat::Arrow),
+            Some("json") => Some(OutputFormat::Json),
+            Some("md" | "markdown") => Some(OutputFormat::Markdown),
+            Some("html" | "htm") => Some(OutputFormat::Html),
+            Some("tex") => Some(OutputFormat::Latex),
+            _ => None,
+        }
+    }
+}
+
+/// Something that renders a complete crosstab to an output.
+pub trait CrosstabWriter {
+    /// Write the header row(s) and data rows of the crosstab.
+    fn write(&mut self, crosstab: &Crosstab) -> Result<()>;
+}
+
+/// Writes the crosstab as delimited text.
+pub struct CsvWriter<W: Write> {
+    writer: W,
+    write_config: WriteConfig,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(writer: W, write_config: &WriteConfig) -> CsvWriter<W> {
+        CsvWriter { writer, write_config: write_config.clone() }
+    }
+}
+
+impl<W: Write> CrosstabWriter for CsvWriter<W> {
+    fn write(&mut self, crosstab: &Crosstab) -> Result<()> {
+        let mut writer = csv::WriterBuilder::new()
+            .flexible(true)
+            .delimiter(self.write_config.delimiter)
+            .from_writer(&mut self.writer);
+        for record in crosstab.header_rows() {
+            writer.write_record(&record)?;
+        }
+        // The cells are all in memory, but each data row is laid out as text only when it is written
+        for record in crosstab.iter_data_rows() {
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes the crosstab as an Excel workbook with a single worksheet, or several crosstabs each on its own worksheet.
+///
+/// The header rows are bold and frozen above the data. In the multi-row header formats, adjacent
+/// copies of the same column header value are merged into a single cell spanning its group of columns.
+/// Cells whose values are numbers are written as numbers, and the cells that match a highlighting rule of the write
+/// configuration are written in its style.
+pub struct ExcelWriter<W: Write> {
+    writer: W,
+    write_config: WriteConfig,
+}
+
+impl<W: Write> ExcelWriter<W> {
+    pub fn new(writer: W, write_config: &WriteConfig) -> ExcelWriter<W> {
+        ExcelWriter { writer, write_config: write_config.clone() }
+    }
+}
+
+// The format of the cells of a highlighting style
+fn highlight_format(style: &HighlightStyle) -> Format {
+    let mut format = Format::new();
+    if let Some(fill) = style.fill {
+        format = format.set_background_color(Color::RGB(fill));
+    }
+    if let Some(font) = style.font {
+        format = format.set_font_color(Color::RGB(font));
+    }
+    if style.bold {
+        format = format.set_bold();
+    }
+    format
+}
+
+/// The runs of generated columns in one of the upper header rows that should be merged into a single cell,
+/// as `(first, last)` column positions. A run only continues while the cells of every header row above it are
+/// also unchanged, so that merged cells never cross into a different column group.
+pub fn merged_runs(header_rows: &[Vec<String>], row: usize, first_col: usize) -> Vec<(usize, usize)> {
+    let width = header_rows[row].len();
+    let same_group = |a: usize, b: usize| (0..=row).all(|r| header_rows[r][a] == header_rows[r][b]);
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    let mut start = first_col;
+    for col in first_col..=width {
+        if col == width || !same_group(start, col) {
+            if col - 1 > start {
+                runs.push((start, col - 1));
+            }
+            start = col;
+        }
+    }
+    runs
+}
+
+// Write the crosstab on a worksheet
+fn write_worksheet(worksheet: &mut Worksheet, crosstab: &Crosstab, write_config: &WriteConfig) -> Result<()> {
+    let header_format = Format::new()
+        .set_bold()
+        .set_align(FormatAlign::Center)
+        .set_border_bottom(FormatBorder::Thin);
+
+    let header_rows = crosstab.header_rows();
+    let n_header_rows = header_rows.len();
+    let n_row_headers = crosstab.n_row_headers();
+
+    // Write every header cell, then merge the repeated column header values of the upper header rows
+    for (r, header_row) in header_rows.iter().enumerate() {
+        for (c, cell) in header_row.iter().enumerate() {
+            worksheet.write_string_with_format(r as u32, c as u16, cell, &header_format)?;
+        }
+    }
+    for r in 0..n_header_rows - 1 {
+        for (first, last) in merged_runs(&header_rows, r, n_row_headers) {
+            worksheet.merge_range(r as u32, first as u16, r as u32, last as u16, &header_rows[r][first], &header_format)?;
+        }
+    }
+
+    // Write the data rows. The row keys are always written as text so that identifiers such as 007 are preserved.
+    let styles = cell_styles(crosstab, &write_config.highlights)?;
+    for (i, data_row) in crosstab.data_rows().iter().enumerate() {
+        let r = (n_header_rows + i) as u32;
+        for (c, cell) in data_row.iter().enumerate() {
+            if cell.is_empty() {
+                continue;
+            }
+            let style = styles.get(i).and_then(|row| row[c].as_ref());
+            match (cell.parse::<f64>(), style) {
+                (Ok(number), Some(style)) if c >= n_row_headers => {
+                    worksheet.write_number_with_format(r, c as u16, number, &highlight_format(style))?
+                }
+                (Ok(number), None) if c >= n_row_headers => worksheet.write_number(r, c as u16, number)?,
+                _ => worksheet.write_string(r, c as u16, cell)?,
+            };
+        }
+    }
+
+    worksheet.set_freeze_panes(n_header_rows as u32, n_row_headers as u16)?;
+    Ok(())
+}
+
+// The characters that Excel does not allow in worksheet names, and the longest name that it allows
+const SHEET_NAME_FORBIDDEN: [char; 7] = ['[', ']', ':', '*', '?', '/', '\\'];
+const SHEET_NAME_MAX_LEN: usize = 31;
+
+/// The names of the worksheets of the given names that Excel allows: the forbidden characters are replaced by
+/// underscores, the names are shortened to 31 characters, an empty name is replaced by `(blank)`, and names that are
+/// the same (regardless of capitalization) as an earlier one are numbered, as in `Zinc (2)`.
+pub fn sheet_names<S: AsRef<str>>(names: &[S]) -> Vec<String> {
+    let mut used: Vec<String> = Vec::new();
+    names
+        .iter()
+        .map(|name| {
+            let cleaned: String = name.as_ref().chars().map(|c| if SHEET_NAME_FORBIDDEN.contains(&c) { '_' } else { c }).collect();
+            let cleaned = cleaned.trim_matches('\'');
+            let base = match cleaned.is_empty() {
+                true => "(blank)".to_string(),
+                false => cleaned.chars().take(SHEET_NAME_MAX_LEN).collect(),
+            };
+            let mut sheet_name = base.clone();
+            let mut n = 1;
+            while used.contains(&sheet_name.to_lowercase()) {
+                n += 1;
+                let suffix = format!(" ({})", n);
+                sheet_name = format!("{}{}", base.chars().take(SHEET_NAME_MAX_LEN - suffix.len()).collect::<String>(), suffix);
+            }
+            used.push(sheet_name.to_lowercase());
+            sheet_name
+        })
+        .collect()
+}
+
+impl<W: Write> ExcelWriter<W> {
+    /// Write several crosstabs in a single workbook, each on its own worksheet named by its name, in order, such as
+    /// the crosstabs of the groups of the input data. The names are made into worksheet names with [`sheet_names`].
+    pub fn write_sheets(&mut self, sheets: &[(&str, &Crosstab)]) -> Result<()> {
+        let mut workbook = Workbook::new();
+        let names: Vec<&str> = sheets.iter().map(|(name, _)| *name).collect();
+        for (name, (_, crosstab)) in sheet_names(&names).iter().zip(sheets) {
+            let worksheet = workbook.add_worksheet();
+            worksheet.set_name(name)?;
+            write_worksheet(worksheet, crosstab, &self.write_config)?;
+        }
+        self.writer.write_all(&workbook.save_to_buffer()?)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> CrosstabWriter for ExcelWriter<W> {
+    fn write(&mut self, crosstab: &Crosstab) -> Result<()> {
+        let mut workbook = Workbook::new();
+        write_worksheet(workbook.add_worksheet(), crosstab, &self.write_config)?;
+        self.writer.write_all(&workbook.save_to_buffer()?)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes the crosstab as an OpenDocument spreadsheet with a single sheet.
+///
+/// As in an Excel workbook, the header rows are bold, and in the multi-row header formats, adjacent copies of the
+/// same column header value are merged into a single cell spanning its group of columns. Cells whose values are
+/// numbers are written as numbers.
+pub struct OdsWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> OdsWriter<W> {
+    pub fn new(writer: W) -> OdsWriter<W> {
+        OdsWriter { writer }
+    }
+}
+
+impl<W: Write> CrosstabWriter for OdsWriter<W> {
+    fn write(&mut self, crosstab: &Crosstab) -> Result<()> {
+        let n_row_headers = crosstab.n_row_headers();
+        ods::write_ods(&mut self.writer, &crosstab.header_rows(), &crosstab.data_rows(), n_row_headers)
+    }
+}
+
+/// Writes the crosstab as a Parquet file, with the format 1 (single row) column names.
+#[cfg(feature = "native")]
+pub struct ParquetWriter<W: Write> {
+    writer: W,
+}
+
+#[cfg(feature = "native")]
+impl<W: Write> ParquetWriter<W> {
+    pub fn new(writer: W) -> ParquetWriter<W> {
+        ParquetWriter { writer }
+    }
+}
+
+#[cfg(feature = "native")]
+impl<W: Write> CrosstabWriter for ParquetWriter<W> {
+    fn write(&mut self, crosstab: &Crosstab) -> Result<()> {
+        let mut df = crosstab.to_dataframe()?;
+        polars::prelude::ParquetWriter::new(&mut self.writer).finish(&mut df)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes the crosstab as an Arrow IPC file, with the format 1 (single row) column names, so that it can be
+/// memory-mapped by other programs without being parsed. Generated columns whose values are all numbers are
+/// written as numbers.
+#[cfg(feature = "native")]
+pub struct ArrowWriter<W: Write> {
+    writer: W,
+}
+
+#[cfg(feature = "native")]
+impl<W: Write> ArrowWriter<W> {
+    pub fn new(writer: W) -> ArrowWriter<W> {
+        ArrowWriter { writer }
+    }
+}
+
+#[cfg(feature = "native")]
+impl<W: Write> CrosstabWriter for ArrowWriter<W> {
+    fn write(&mut self, crosstab: &Crosstab) -> Result<()> {
+        let mut df = crosstab.to_dataframe()?;
+        IpcWriter::new(&mut self.writer).finish(&mut df)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// Write a DataFrame as a pretty-printed JSON array of row objects. Float64 columns are written as numbers,
+// and all other columns as strings.
+fn write_json<W: Write>(writer: &mut W, df: &DataFrame) -> Result<()> {
+    let mut rows: Vec<Map<String, Value>> = vec![Map::new(); df.height()];
+    for series in df.get_columns() {
+        let name = series.name().to_string();
+        if series.dtype() == &DataType::Float64 {
+            for (row, value) in rows.iter_mut().zip(series.f64()?) {
+                row.insert(name.clone(), value.map(Value::from).unwrap_or(Value::Null));
+            }
+        } else {
+            let strings = series.cast(&DataType::String)?;
+            for (row, value) in rows.iter_mut().zip(strings.str()?) {
+                row.insert(name.clone(), value.map(Value::from).unwrap_or(Value::Null));
+            }
+        }
+    }
+    serde_json::to_writer_pretty(&mut *writer, &rows)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the crosstab as a JSON array with one object for each output row, keyed by the format 1 (single row)
+/// column names. Generated columns whose values are all numbers are written as numbers, and cells without
+/// input data are null unless there is a fill value.
+pub struct JsonWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonWriter<W> {
+    pub fn new(writer: W) -> JsonWriter<W> {
+        JsonWriter { writer }
+    }
+}
+
+impl<W: Write> CrosstabWriter for JsonWriter<W> {
+    fn write(&mut self, crosstab: &Crosstab) -> Result<()> {
+        write_json(&mut self.writer, &crosstab.to_dataframe()?)
+    }
+}
+
+/// Writes the crosstab as a GitHub-flavored Markdown table.
+///
+/// A Markdown table has a single header row, so in the multi-row header formats the column header values of each
+/// generated column are stacked in its header cell, separated by line breaks. Generated columns whose values are
+/// all numbers are right-aligned.
+pub struct MarkdownWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> MarkdownWriter<W> {
+    pub fn new(writer: W) -> MarkdownWriter<W> {
+        MarkdownWriter { writer }
+    }
+}
+
+impl<W: Write> CrosstabWriter for MarkdownWriter<W> {
+    fn write(&mut self, crosstab: &Crosstab) -> Result<()> {
+        let n_row_headers = crosstab.n_row_headers();
+        markup::write_markdown(&mut self.writer, &crosstab.header_rows(), &crosstab.data_rows(), n_row_headers)
+    }
+}
+
+/// Writes the crosstab as an HTML table with `thead` and `tbody` sections, in a standalone document or in the
+/// document of the HTML template of the write configuration.
+///
+/// In the multi-row header formats, the row header names span every header row, and adjacent copies of the same
+/// column header value are merged into a single cell spanning its group of columns. The row header cells of the
+/// data rows are header cells, and the cells of generated columns whose values are all numbers have the `number`
+/// class, for styling. The cells that match a highlighting rule of the write configuration have its style.
+pub struct HtmlWriter<W: Write> {
+    writer: W,
+    write_config: WriteConfig,
+}
+
+impl<W: Write> HtmlWriter<W> {
+    pub fn new(writer: W, write_config: &WriteConfig) -> HtmlWriter<W> {
+        HtmlWriter { writer, write_config: write_config.clone() }
+    }
+}
+
+impl<W: Write> CrosstabWriter for HtmlWriter<W> {
+    fn write(&mut self, crosstab: &Crosstab) -> Result<()> {
+        let n_row_headers = crosstab.n_row_headers();
+        let styles = cell_styles(crosstab, &self.write_config.highlights)?;
+        markup::write_html(&mut self.writer, &crosstab.header_rows(), &crosstab.data_rows(), n_row_headers, &styles, &self.write_config)
+    }
+}
+
+/// Writes the crosstab as a LaTeX tabular in the style of the booktabs package, for including in a document that
+/// uses the package.
+///
+/// In the multi-row header formats, adjacent copies of the same column header value are merged into a multicolumn
+/// cell spanning its group of columns, with a partial rule below it. Generated columns whose values are all numbers
+/// are right-aligned.
+pub struct LatexWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> LatexWriter<W> {
+    pub fn new(writer: W) -> LatexWriter<W> {
+        LatexWriter { writer }
+    }
+}
+
+impl<W: Write> CrosstabWriter for LatexWriter<W> {
+    fn write(&mut self, crosstab: &Crosstab) -> Result<()> {
+        let n_row_headers = crosstab.n_row_headers();
+        markup::write_latex(&mut self.writer, &crosstab.header_rows(), &crosstab.data_rows(), n_row_headers)
+    }
+}
+
+/// Writes the crosstab as a table with aligned columns and borders, for reading in a terminal.
+///
+/// The column header values of each generated column are stacked in its header cell, and generated columns whose
+/// values are all numbers are right-aligned. A crosstab wider than the maximum width of the write configuration (or
+/// than the terminal) is truncated to the columns that fit, followed by an ellipsis column.
+pub struct PrettyWriter<W: Write> {
+    writer: W,
+    max_width: Option<usize>,
+}
+
+impl<W: Write> PrettyWriter<W> {
+    pub fn new(writer: W, write_config: &WriteConfig) -> PrettyWriter<W> {
+        PrettyWriter { writer, max_width: write_config.max_width }
+    }
+}
+
+impl<W: Write> CrosstabWriter for PrettyWriter<W> {
+    fn write(&mut self, crosstab: &Crosstab) -> Result<()> {
+        let n_row_headers = crosstab.n_row_headers();
+        pretty::write_pretty(&mut self.writer, &crosstab.header_rows(), &crosstab.data_rows(), n_row_headers, self.max_width)
+    }
+}
+
+/// Writes the crosstab as a table of a database, with the format 1 (single row) column names.
+/// Generated columns whose values are all numbers become real columns, and all other columns are text.
+pub struct DatabaseWriter {
+    url: String,
+    table: String,
+    mode: TableMode,
+}
+
+impl DatabaseWriter {
+    pub fn new(url: &str, table: &str, mode: TableMode) -> DatabaseWriter {
+        DatabaseWriter { url: url.to_string(), table: table.to_string(), mode }
+    }
+}
+
+impl CrosstabWriter for DatabaseWriter {
+    fn write(&mut self, crosstab: &Crosstab) -> Result<()> {
+        write_database(&self.url, &self.table, self.mode, &crosstab.to_dataframe()?)
+    }
+}
+
+/// Create the writer for the given output format.
+pub fn writer_for<'a, W: Write + 'a>(format: OutputFormat, writer: W, write_config: &WriteConfig) -> Result<Box<dyn CrosstabWriter + 'a>> {
+    match format {
+        OutputFormat::Csv => Ok(Box::new(CsvWriter::new(writer, write_config))),
+        OutputFormat::Xlsx => Ok(Box::new(ExcelWriter::new(writer, write_config))),
+        OutputFormat::Ods => Ok(Box::new(OdsWriter::new(writer))),
+        #[cfg(feature = "native")]
+        OutputFormat::Parquet => Ok(Box::new(ParquetWriter::new(writer))),
+        #[cfg(feature = "native")]
+        OutputFormat::Arrow => Ok(Box::new(ArrowWriter::new(writer))),
+        #[cfg(not(feature = "native"))]
+        OutputFormat::Parquet | OutputFormat::Arrow => anyhow::bail!("xtab was built without support for {:?} output", format),
+        OutputFormat::Json => Ok(Box::new(JsonWriter::new(writer))),
+        OutputFormat::Markdown => Ok(Box::new(MarkdownWriter::new(writer))),
+        OutputFormat::Html => Ok(Box::new(HtmlWriter::new(writer, write_config))),
+        OutputFormat::Latex => Ok(Box::new(LatexWriter::new(writer))),
+        OutputFormat::Pretty => Ok(Box::new(PrettyWriter::new(writer, write_config))),
+    }
+}
+
+/// Write a DataFrame, such as a melted crosstab, in the given output format, with one header row of column names.
+pub fn write_dataframe<W: Write>(format: OutputFormat, mut writer: W, df: &DataFrame, write_config: &WriteConfig) -> Result<()> {
+    let names: Vec<String> = df.get_column_names().iter().map(|n| n.to_string()).collect();
+    let rows = || -> Result<Vec<Vec<Option<String>>>> {
+        let columns = df
+            .get_columns()
+            .iter()
+            .map(|s| Ok(s.cast(&DataType::String)?.str()?.into_iter().map(|v| v.map(|v| v.to_string())).collect()))
+            .collect::<Result<Vec<Vec<Option<String>>>>>()?;
+        Ok((0..df.height()).map(|i| columns.iter().map(|c| c[i].clone()).collect()).collect())
+    };
+    match format {
+        OutputFormat::Csv => {
+            let mut csv_writer = csv::WriterBuilder::new().delimiter(write_config.delimiter).from_writer(&mut writer);
+            csv_writer.write_record(&names)?;
+            for row in rows()? {
+                csv_writer.write_record(row.iter().map(|v| v.as_deref().unwrap_or_default()))?;
+            }
+            csv_writer.flush()?;
+        }
+        OutputFormat::Xlsx => {
+            let mut workbook = Workbook::new();
+            let worksheet = workbook.add_worksheet();
+            let header_format = Format::new().set_bold().set_border_bottom(FormatBorder::Thin);
+            for (c, name) in names.iter().enumerate() {
+                worksheet.write_string_with_format(0, c as u16, name, &header_format)?;
+            }
+            for (r, row) in rows()?.iter().enumerate() {
+                for (c, value) in row.iter().enumerate() {
+                    if let Some(value) = value {
+                        worksheet.write_string(r as u32 + 1, c as u16, value)?;
+                    }
+                }
+            }
+            worksheet.set_freeze_panes(1, 0)?;
+            writer.write_all(&workbook.save_to_buffer()?)?;
+        }
+        #[cfg(feature = "native")]
+        OutputFormat::Parquet => {
+            polars::prelude::ParquetWriter::new(&mut writer).finish(&mut df.clone())?;
+        }
+        #[cfg(feature = "native")]
+        OutputFormat::Arrow => IpcWriter::new(&mut writer).finish(&mut df.clone())?,
+        #[cfg(not(feature = "native"))]
+        OutputFormat::Parquet | OutputFormat::Arrow => anyhow::bail!("xtab was built without support for {:?} output", format),
+        OutputFormat::Json => write_json(&mut writer, df)?,
+        OutputFormat::Ods | OutputFormat::Markdown | OutputFormat::Html | OutputFormat::Latex | OutputFormat::Pretty => {
+            let rows: Vec<Vec<String>> = rows()?.into_iter().map(|row| row.into_iter().map(Option::unwrap_or_default).collect()).collect();
+            match format {
+                OutputFormat::Ods => ods::write_ods(&mut writer, &[names], &rows, 0)?,
+                OutputFormat::Markdown => markup::write_markdown(&mut writer, &[names], &rows, 0)?,
+                OutputFormat::Html => markup::write_html(&mut writer, &[names], &rows, 0, &[], write_config)?,
+                OutputFormat::Latex => markup::write_latex(&mut writer, &[names], &rows, 0)?,
+                _ => pretty::write_pretty(&mut writer, &[names], &rows, 0, write_config.max_width)?,
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|s| s.to_string()).collect()
+    }
+
+    // Test that merged header cells never cross into a different column group
+    #[test]
+    fn test_merged_runs() {
+        let header_rows = vec![
+            row(&["", "2023", "2023", "2024", "2024", "2024"]),
+            row(&["", "Q4", "Q4", "Q1", "Q1", "Q2"]),
+            row(&["site", "result", "unit", "result", "unit", "result"]),
+        ];
+        assert_eq!(merged_runs(&header_rows, 0, 1), vec![(1, 2), (3, 5)]);
+        assert_eq!(merged_runs(&header_rows, 1, 1), vec![(1, 2), (3, 4)]);
+    }
+
+    // Test that the worksheet names are cleaned, shortened, and numbered to be unique
+    #[test]
+    fn test_sheet_names() {
+        let long = "Total Petroleum Hydrocarbons (Diesel Range)";
+        let names = sheet_names(&["Zinc", "zinc", "As/Se", "", long, long]);
+        assert_eq!(names[..4], ["Zinc", "zinc (2)", "As_Se", "(blank)"]);
+        assert_eq!(names[4], "Total Petroleum Hydrocarbons (D");
+        assert_eq!(names[5], "Total Petroleum Hydrocarbon (2)");
+    }
+
+    // Test that the output format is detected from the extension
+    #[test]
+    fn test_output_format_from_path() {
+        assert_eq!(OutputFormat::from_path("out.tsv"), Some(OutputFormat::Csv));
+        assert_eq!(OutputFormat::from_path("out.XLSX"), Some(OutputFormat::Xlsx));
+        assert_eq!(OutputFormat::from_path("out.ods"), Some(OutputFormat::Ods));
+        assert_eq!(OutputFormat::from_path("out.parquet"), Some(OutputFormat::Parquet));
+        assert_eq!(OutputFormat::from_path("out.feather"), Some(OutputFormat::Arrow));
+        assert_eq!(OutputFormat::from_path("out.json.zst"), Some(OutputFormat::Json));
+        assert_eq!(OutputFormat::from_path("out.md"), Some(OutputFormat::Markdown));
+        assert_eq!(OutputFormat::from_path("out.html"), Some(OutputFormat::Html));
+        assert_eq!(OutputFormat::from_path("out.tex"), Some(OutputFormat::Latex));
+        assert_eq!(OutputFormat::from_path("out.xyz"), None);
+    }
+}
@@ -0,0 +1,316 @@
+// Rendering of tables in markup languages, for pasting or including crosstabs in documents
+
+use super::merged_runs;
+use crate::highlight::HighlightStyle;
+use crate::WriteConfig;
+use anyhow::Result;
+use std::io::Write;
+
+// The placeholder in an HTML template that is replaced by the table
+pub const TABLE_PLACEHOLDER: &str = "{{table}}";
+
+// The HTML document that a table is written in without a template, with a style sheet so that it renders legibly
+const HTML_DOCUMENT: &str = "\
+<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>Crosstab</title>
+<style>
+table { border-collapse: collapse; font-family: sans-serif; }
+th, td { border: 1px solid #ccc; padding: 0.25em 0.5em; }
+thead th { background: #f0f0f0; text-align: center; }
+tbody th { text-align: left; font-weight: normal; }
+td.number { text-align: right; }
+</style>
+</head>
+<body>
+{{table}}
+</body>
+</html>
+";
+
+// Whether the cells of a column are all numbers, apart from empty cells, and at least one of them is
+fn is_numeric<'a>(mut cells: impl Iterator<Item = &'a str>) -> bool {
+    let mut any = false;
+    cells.all(|cell| {
+        any |= !cell.is_empty();
+        cell.is_empty() || cell.trim().parse::<f64>().is_ok()
+    }) && any
+}
+
+// Whether each column of a table is numeric. The row header columns never are, so that identifiers such as 007
+// are treated as text.
+pub(crate) fn numeric_columns(data_rows: &[Vec<String>], width: usize, n_row_headers: usize) -> Vec<bool> {
+    (0..width)
+        .map(|c| c >= n_row_headers && is_numeric(data_rows.iter().map(|r| r.get(c).map_or("", |v| v.as_str()))))
+        .collect()
+}
+
+// Write a table as a GitHub-flavored Markdown table. Markdown tables have a single header row, so the non-empty
+// cells of each column in the header rows are stacked in its header cell, separated by line breaks. Numeric columns
+// are right-aligned and the others left-aligned, and the cells are padded so that the text is aligned too.
+pub(crate) fn write_markdown<W: Write>(
+    writer: &mut W,
+    header_rows: &[Vec<String>],
+    data_rows: &[Vec<String>],
+    n_row_headers: usize,
+) -> Result<()> {
+    let escape = |cell: &str| cell.replace('|', "\\|").replace('\n', "<br>");
+    let width = header_rows.iter().chain(data_rows).map(|r| r.len()).max().unwrap_or(0);
+    let header: Vec<String> = (0..width)
+        .map(|c| {
+            let cells: Vec<String> = header_rows.iter().filter_map(|r| r.get(c)).filter(|v| !v.is_empty()).map(|v| escape(v)).collect();
+            cells.join("<br>")
+        })
+        .collect();
+    let rows: Vec<Vec<String>> = data_rows
+        .iter()
+        .map(|r| (0..width).map(|c| r.get(c).map(|v| escape(v)).unwrap_or_default()).collect())
+        .collect();
+    let numeric = numeric_columns(&rows, width, n_row_headers);
+    let widths: Vec<usize> = (0..width)
+        .map(|c| rows.iter().map(|r| &r[c]).chain([&header[c]]).map(|v| v.chars().count()).max().unwrap_or(0).max(3))
+        .collect();
+
+    let line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+    let pad = |c: usize, cell: &str| match numeric[c] {
+        true => format!("{:>1$}", cell, widths[c]),
+        false => format!("{:<1$}", cell, widths[c]),
+    };
+    writeln!(writer, "{}", line(header.iter().enumerate().map(|(c, v)| pad(c, v)).collect()))?;
+    let rules = (0..width).map(|c| match numeric[c] {
+        true => format!("{}:", "-".repeat(widths[c] - 1)),
+        false => "-".repeat(widths[c]),
+    });
+    writeln!(writer, "{}", line(rules.collect()))?;
+    for row in &rows {
+        writeln!(writer, "{}", line(row.iter().enumerate().map(|(c, v)| pad(c, v)).collect()))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Escape the characters that are special in HTML text and attribute values
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// Write a table as an HTML table in a document, either the template in the write configuration or a standalone
+// document. In the multi-row header formats, the row header names span every header row, and the repeated column
+// header values of the upper header rows are merged into cells that span their groups of columns. The row header
+// cells of the data rows are header cells, and the cells of numeric columns have the `number` class. Each cell with a
+// highlighting style has it as its `style` attribute.
+pub(crate) fn write_html<W: Write>(
+    writer: &mut W,
+    header_rows: &[Vec<String>],
+    data_rows: &[Vec<String>],
+    n_row_headers: usize,
+    styles: &[Vec<Option<HighlightStyle>>],
+    write_config: &WriteConfig,
+) -> Result<()> {
+    let width = header_rows.iter().chain(data_rows).map(|r| r.len()).max().unwrap_or(0);
+    let numeric = numeric_columns(data_rows, width, n_row_headers);
+    let n_header_rows = header_rows.len();
+    let mut table: Vec<String> = Vec::new();
+    match &write_config.html_class {
+        Some(class) => table.push(format!("<table class=\"{}\">", escape_html(class))),
+        None => table.push("<table>".to_string()),
+    }
+
+    table.push("<thead>".to_string());
+    for (r, header_row) in header_rows.iter().enumerate() {
+        let mut cells: Vec<String> = Vec::new();
+        let names = &header_rows[n_header_rows - 1];
+        if r == 0 {
+            for name in names.iter().take(n_row_headers) {
+                match n_header_rows {
+                    1 => cells.push(format!("<th>{}</th>", escape_html(name))),
+                    n => cells.push(format!("<th rowspan=\"{}\">{}</th>", n, escape_html(name))),
+                }
+            }
+        }
+        let runs = if r + 1 < n_header_rows { merged_runs(header_rows, r, n_row_headers) } else { Vec::new() };
+        let mut c = n_row_headers;
+        while c < header_row.len() {
+            match runs.iter().find(|(first, _)| *first == c) {
+                Some(&(first, last)) => {
+                    cells.push(format!("<th colspan=\"{}\">{}</th>", last - first + 1, escape_html(&header_row[c])));
+                    c = last + 1;
+                }
+                None => {
+                    cells.push(format!("<th>{}</th>", escape_html(&header_row[c])));
+                    c += 1;
+                }
+            }
+        }
+        table.push(format!("<tr>{}</tr>", cells.concat()));
+    }
+    table.push("</thead>".to_string());
+
+    table.push("<tbody>".to_string());
+    for (r, data_row) in data_rows.iter().enumerate() {
+        let cells: Vec<String> = data_row
+            .iter()
+            .enumerate()
+            .map(|(c, cell)| {
+                let style = match styles.get(r).and_then(|row| row[c].as_ref()) {
+                    Some(style) => format!(" style=\"{}\"", style.css()),
+                    None => String::new(),
+                };
+                match (c < n_row_headers, numeric[c]) {
+                    (true, _) => format!("<th scope=\"row\">{}</th>", escape_html(cell)),
+                    (false, true) => format!("<td class=\"number\"{}>{}</td>", style, escape_html(cell)),
+                    (false, false) => format!("<td{}>{}</td>", style, escape_html(cell)),
+                }
+            })
+            .collect();
+        table.push(format!("<tr>{}</tr>", cells.concat()));
+    }
+    table.push("</tbody>".to_string());
+    table.push("</table>".to_string());
+
+    let document = write_config.html_template.as_deref().unwrap_or(HTML_DOCUMENT);
+    write!(writer, "{}", document.replace(TABLE_PLACEHOLDER, &table.join("\n")))?;
+    writer.flush()?;
+    Ok(())
+}
+
+// Escape the characters that are special in LaTeX text
+fn escape_latex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Write a table as a LaTeX tabular in the style of the booktabs package, with rules above and below the table and
+// below the header rows. In the multi-row header formats, the repeated column header values of the upper header
+// rows are merged into multicolumn cells that span their groups of columns, each underlined by a partial rule.
+// Numeric columns are right-aligned and the others left-aligned.
+pub(crate) fn write_latex<W: Write>(
+    writer: &mut W,
+    header_rows: &[Vec<String>],
+    data_rows: &[Vec<String>],
+    n_row_headers: usize,
+) -> Result<()> {
+    let width = header_rows.iter().chain(data_rows).map(|r| r.len()).max().unwrap_or(0);
+    let numeric = numeric_columns(data_rows, width, n_row_headers);
+    let alignment: String = numeric.iter().map(|n| if *n { 'r' } else { 'l' }).collect();
+    let line = |cells: Vec<String>| format!("{} \\\\", cells.join(" & "));
+
+    writeln!(writer, "\\begin{{tabular}}{{{}}}", alignment)?;
+    writeln!(writer, "\\toprule")?;
+    let n_header_rows = header_rows.len();
+    for (r, header_row) in header_rows.iter().enumerate() {
+        let runs = if r + 1 < n_header_rows { merged_runs(header_rows, r, n_row_headers) } else { Vec::new() };
+        let mut cells: Vec<String> = header_row.iter().take(n_row_headers).map(|v| escape_latex(v)).collect();
+        let mut c = n_row_headers;
+        while c < header_row.len() {
+            match runs.iter().find(|(first, _)| *first == c) {
+                Some(&(first, last)) => {
+                    cells.push(format!("\\multicolumn{{{}}}{{c}}{{{}}}", last - first + 1, escape_latex(&header_row[c])));
+                    c = last + 1;
+                }
+                None => {
+                    cells.push(escape_latex(&header_row[c]));
+                    c += 1;
+                }
+            }
+        }
+        writeln!(writer, "{}", line(cells))?;
+        if !runs.is_empty() {
+            let rules: Vec<String> = runs.iter().map(|(first, last)| format!("\\cmidrule(lr){{{}-{}}}", first + 1, last + 1)).collect();
+            writeln!(writer, "{}", rules.join(" "))?;
+        }
+    }
+    writeln!(writer, "\\midrule")?;
+    for data_row in data_rows {
+        writeln!(writer, "{}", line(data_row.iter().map(|v| escape_latex(v)).collect()))?;
+    }
+    writeln!(writer, "\\bottomrule")?;
+    writeln!(writer, "\\end{{tabular}}")?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(cells: &[&[&str]]) -> Vec<Vec<String>> {
+        cells.iter().map(|r| r.iter().map(|s| s.to_string()).collect()).collect()
+    }
+
+    // Test that the header rows are stacked, the numeric columns right-aligned, and pipes escaped
+    #[test]
+    fn test_write_markdown() {
+        let header_rows = rows(&[&["", "2024-01-01", "2024-01-01"], &["site", "result", "unit"]]);
+        let data_rows = rows(&[&["007", "1.5", "mg|L"], &["B02", "", "mg/L"]]);
+        let mut markdown: Vec<u8> = Vec::new();
+        write_markdown(&mut markdown, &header_rows, &data_rows, 1).unwrap();
+        let expected = "\
+| site | 2024-01-01<br>result | 2024-01-01<br>unit |
+| ---- | -------------------: | ------------------ |
+| 007  |                  1.5 | mg\\|L              |
+| B02  |                      | mg/L               |
+";
+        assert_eq!(String::from_utf8(markdown).unwrap(), expected);
+    }
+
+    // Test that the header rows are merged with row and column spans, and that the template is filled in
+    #[test]
+    fn test_write_html() {
+        let header_rows = rows(&[&["", "2024", "2024"], &["site", "result", "unit"]]);
+        let data_rows = rows(&[&["A&B", "1.5", "<1"]]);
+        let write_config = WriteConfig {
+            html_class: Some("qa".to_string()),
+            html_template: Some("<div>{{table}}</div>".to_string()),
+            ..WriteConfig::default()
+        };
+        let mut html: Vec<u8> = Vec::new();
+        let styles = vec![vec![None, Some(HighlightStyle { fill: Some(0xFFC7CE), font: None, bold: true }), None]];
+        write_html(&mut html, &header_rows, &data_rows, 1, &styles, &write_config).unwrap();
+        let expected = "\
+<div><table class=\"qa\">
+<thead>
+<tr><th rowspan=\"2\">site</th><th colspan=\"2\">2024</th></tr>
+<tr><th>result</th><th>unit</th></tr>
+</thead>
+<tbody>
+<tr><th scope=\"row\">A&amp;B</th><td class=\"number\" style=\"background-color: #FFC7CE; font-weight: bold\">1.5</td><td>&lt;1</td></tr>
+</tbody>
+</table></div>";
+        assert_eq!(String::from_utf8(html).unwrap(), expected);
+    }
+
+    // Test that the upper header rows are merged into multicolumn cells and that special characters are escaped
+    #[test]
+    fn test_write_latex() {
+        let header_rows = rows(&[&["", "2024", "2024", "2025"], &["site_id", "result", "unit", "result"]]);
+        let data_rows = rows(&[&["A01", "1.5", "50%", ""]]);
+        let mut latex: Vec<u8> = Vec::new();
+        write_latex(&mut latex, &header_rows, &data_rows, 1).unwrap();
+        let expected = r"\begin{tabular}{lrll}
+\toprule
+ & \multicolumn{2}{c}{2024} & 2025 \\
+\cmidrule(lr){2-3}
+site\_id & result & unit & result \\
+\midrule
+A01 & 1.5 & 50\% &  \\
+\bottomrule
+\end{tabular}
+";
+        assert_eq!(String::from_utf8(latex).unwrap(), expected);
+    }
+}
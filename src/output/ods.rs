@@ -0,0 +1,133 @@
+// Writing of OpenDocument spreadsheets, which are zip archives of XML documents
+
+use super::merged_runs;
+use anyhow::Result;
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const MIMETYPE: &str = "application/vnd.oasis.opendocument.spreadsheet";
+
+const MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.2">
+<manifest:file-entry manifest:full-path="/" manifest:version="1.2" manifest:media-type="application/vnd.oasis.opendocument.spreadsheet"/>
+<manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>
+</manifest:manifest>
+"#;
+
+// The start of the content document, with the bold, centered, underlined style of the header cells
+const CONTENT_START: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0" office:version="1.2">
+<office:automatic-styles>
+<style:style style:name="header" style:family="table-cell"><style:table-cell-properties fo:border-bottom="0.5pt solid #000000"/><style:paragraph-properties fo:text-align="center"/><style:text-properties fo:font-weight="bold"/></style:style>
+</office:automatic-styles>
+<office:body>
+<office:spreadsheet>
+"#;
+
+const CONTENT_END: &str = "</office:spreadsheet>\n</office:body>\n</office:document-content>\n";
+
+// Escape the characters that are special in XML text and attribute values
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// A text cell, with the given extra attributes
+fn text_cell(text: &str, attributes: &str) -> String {
+    format!("<table:table-cell{} office:value-type=\"string\"><text:p>{}</text:p></table:table-cell>", attributes, escape_xml(text))
+}
+
+// Write a table as an OpenDocument spreadsheet with a single sheet. The header rows are bold, and in the multi-row
+// header formats the repeated column header values of the upper header rows are merged into cells that span their
+// groups of columns. The cells of the data rows after the row header columns whose values are numbers are written
+// as numbers, and all other cells as text.
+pub(crate) fn write_ods<W: Write>(writer: &mut W, header_rows: &[Vec<String>], data_rows: &[Vec<String>], n_row_headers: usize) -> Result<()> {
+    let width = header_rows.iter().chain(data_rows).map(|r| r.len()).max().unwrap_or(0);
+    let mut content = String::from(CONTENT_START);
+    content.push_str("<table:table table:name=\"Sheet1\">\n");
+    content.push_str(&format!("<table:table-column table:number-columns-repeated=\"{}\"/>\n", width.max(1)));
+
+    content.push_str("<table:table-header-rows>\n");
+    let n_header_rows = header_rows.len();
+    for (r, header_row) in header_rows.iter().enumerate() {
+        let runs = if r + 1 < n_header_rows { merged_runs(header_rows, r, n_row_headers) } else { Vec::new() };
+        content.push_str("<table:table-row>");
+        let mut c = 0;
+        while c < header_row.len() {
+            match runs.iter().find(|(first, _)| *first == c) {
+                Some(&(first, last)) => {
+                    let span = format!(" table:style-name=\"header\" table:number-columns-spanned=\"{}\"", last - first + 1);
+                    content.push_str(&text_cell(&header_row[c], &span));
+                    content.push_str(&"<table:covered-table-cell/>".repeat(last - first));
+                    c = last + 1;
+                }
+                None => {
+                    content.push_str(&text_cell(&header_row[c], " table:style-name=\"header\""));
+                    c += 1;
+                }
+            }
+        }
+        content.push_str("</table:table-row>\n");
+    }
+    content.push_str("</table:table-header-rows>\n");
+
+    // The row keys are always written as text so that identifiers such as 007 are preserved
+    for data_row in data_rows {
+        content.push_str("<table:table-row>");
+        for (c, cell) in data_row.iter().enumerate() {
+            match cell.parse::<f64>() {
+                _ if cell.is_empty() => content.push_str("<table:table-cell/>"),
+                Ok(number) if c >= n_row_headers && number.is_finite() => content.push_str(&format!(
+                    "<table:table-cell office:value-type=\"float\" office:value=\"{}\"><text:p>{}</text:p></table:table-cell>",
+                    number,
+                    escape_xml(cell)
+                )),
+                _ => content.push_str(&text_cell(cell, "")),
+            }
+        }
+        content.push_str("</table:table-row>\n");
+    }
+    content.push_str("</table:table>\n");
+    content.push_str(CONTENT_END);
+
+    // The mimetype must be the first file of the archive, and must not be compressed
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("mimetype", SimpleFileOptions::default().compression_method(CompressionMethod::Stored))?;
+    zip.write_all(MIMETYPE.as_bytes())?;
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file("META-INF/manifest.xml", deflated)?;
+    zip.write_all(MANIFEST.as_bytes())?;
+    zip.start_file("content.xml", deflated)?;
+    zip.write_all(content.as_bytes())?;
+    writer.write_all(&zip.finish()?.into_inner())?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calamine::{Data, Ods, Reader};
+
+    fn rows(cells: &[&[&str]]) -> Vec<Vec<String>> {
+        cells.iter().map(|r| r.iter().map(|s| s.to_string()).collect()).collect()
+    }
+
+    // Test that the spreadsheet can be read, with merged header cells and numeric values
+    #[test]
+    fn test_write_ods() {
+        let header_rows = rows(&[&["", "2024", "2024"], &["site", "result", "unit"]]);
+        let data_rows = rows(&[&["007", "1.5", "<1 mg/L"], &["B02", "", "mg/L"]]);
+        let mut ods: Vec<u8> = Vec::new();
+        write_ods(&mut ods, &header_rows, &data_rows, 1).unwrap();
+
+        let mut workbook: Ods<_> = Ods::new(Cursor::new(ods)).unwrap();
+        let range = workbook.worksheet_range("Sheet1").unwrap();
+        assert_eq!(range.get_value((0, 1)), Some(&Data::String("2024".to_string())));
+        assert_eq!(range.get_value((1, 2)), Some(&Data::String("unit".to_string())));
+        assert_eq!(range.get_value((2, 0)), Some(&Data::String("007".to_string())));
+        assert_eq!(range.get_value((2, 1)), Some(&Data::Float(1.5)));
+        assert_eq!(range.get_value((2, 2)), Some(&Data::String("<1 mg/L".to_string())));
+        assert_eq!(range.get_value((3, 1)), Some(&Data::Empty));
+    }
+}
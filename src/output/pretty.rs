@@ -0,0 +1,119 @@
+// Rendering of tables for reading in a terminal, with aligned columns and borders
+
+use super::markup::numeric_columns;
+use anyhow::Result;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{CellAlignment, ContentArrangement, Table};
+use std::io::Write;
+
+// The width of the border and padding that each column adds to a table, and of the border at its left edge
+const COLUMN_MARGIN: usize = 3;
+const EDGE: usize = 1;
+
+// The header of the column that stands for the columns left out of a table that is too wide
+const ELLIPSIS: &str = "…";
+
+// Write a table with aligned columns for reading in a terminal. The non-empty cells of each column in the header
+// rows are stacked in its header cell, and numeric columns are right-aligned. A table wider than the maximum width,
+// or than the terminal if there is no maximum, ends with the columns that fit (but always includes the row header
+// columns), followed by an ellipsis column and a note of the number of columns left out.
+pub(crate) fn write_pretty<W: Write>(
+    writer: &mut W,
+    header_rows: &[Vec<String>],
+    data_rows: &[Vec<String>],
+    n_row_headers: usize,
+    max_width: Option<usize>,
+) -> Result<()> {
+    let width = header_rows.iter().chain(data_rows).map(|r| r.len()).max().unwrap_or(0);
+    let header: Vec<String> = (0..width)
+        .map(|c| {
+            let cells: Vec<&str> = header_rows.iter().filter_map(|r| r.get(c)).map(|v| v.as_str()).filter(|v| !v.is_empty()).collect();
+            cells.join("\n")
+        })
+        .collect();
+    let cell = |row: &Vec<String>, c: usize| row.get(c).cloned().unwrap_or_default();
+    let numeric = numeric_columns(data_rows, width, n_row_headers);
+
+    // Keep the columns that fit in the maximum width, leaving room for the ellipsis column if any are left out
+    let mut table = Table::new();
+    let max_width = max_width.or_else(|| table.width().map(usize::from));
+    let text_width = |text: &str| text.lines().map(|l| l.chars().count()).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..width)
+        .map(|c| data_rows.iter().map(|r| text_width(&cell(r, c))).chain([text_width(&header[c])]).max().unwrap_or(0))
+        .collect();
+    let mut n_shown = width;
+    if let Some(max_width) = max_width {
+        let total: usize = EDGE + widths.iter().map(|w| w + COLUMN_MARGIN).sum::<usize>();
+        if total > max_width {
+            let room = max_width.saturating_sub(EDGE + ELLIPSIS.chars().count() + COLUMN_MARGIN);
+            let mut used = 0;
+            n_shown = widths
+                .iter()
+                .enumerate()
+                .take_while(|&(c, w)| {
+                    used += w + COLUMN_MARGIN;
+                    c < n_row_headers || used <= room
+                })
+                .count();
+        }
+    }
+    let truncated = n_shown < width;
+
+    table.load_preset(UTF8_FULL_CONDENSED).set_content_arrangement(ContentArrangement::Disabled);
+    let mut header_cells: Vec<String> = header[..n_shown].to_vec();
+    if truncated {
+        header_cells.push(ELLIPSIS.to_string());
+    }
+    table.set_header(header_cells);
+    for row in data_rows {
+        let mut cells: Vec<String> = (0..n_shown).map(|c| cell(row, c)).collect();
+        if truncated {
+            cells.push(ELLIPSIS.to_string());
+        }
+        table.add_row(cells);
+    }
+    for (c, _) in numeric.iter().enumerate().take(n_shown).filter(|(_, n)| **n) {
+        if let Some(column) = table.column_mut(c) {
+            column.set_cell_alignment(CellAlignment::Right);
+        }
+    }
+    writeln!(writer, "{}", table)?;
+    if truncated {
+        writeln!(writer, "({} of {} columns not shown)", width - n_shown, width)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(cells: &[&[&str]]) -> Vec<Vec<String>> {
+        cells.iter().map(|r| r.iter().map(|s| s.to_string()).collect()).collect()
+    }
+
+    // Test that the columns are aligned, and that the columns that do not fit are left out
+    #[test]
+    fn test_write_pretty() {
+        let header_rows = rows(&[&["site", "Pb_result", "Zn_result", "Cu_result"]]);
+        let data_rows = rows(&[&["A01", "1.5", "12.25", "0.5"], &["B02", "", "3", "1"]]);
+        let mut pretty: Vec<u8> = Vec::new();
+        write_pretty(&mut pretty, &header_rows, &data_rows, 1, Some(80)).unwrap();
+        let expected = "\
+┌──────┬───────────┬───────────┬───────────┐
+│ site ┆ Pb_result ┆ Zn_result ┆ Cu_result │
+╞══════╪═══════════╪═══════════╪═══════════╡
+│ A01  ┆       1.5 ┆     12.25 ┆       0.5 │
+│ B02  ┆           ┆         3 ┆         1 │
+└──────┴───────────┴───────────┴───────────┘
+";
+        assert_eq!(String::from_utf8(pretty).unwrap(), expected);
+
+        let mut pretty: Vec<u8> = Vec::new();
+        write_pretty(&mut pretty, &header_rows, &data_rows, 1, Some(32)).unwrap();
+        let pretty = String::from_utf8(pretty).unwrap();
+        assert!(pretty.starts_with("┌──────┬───────────┬───┐\n│ site ┆ Pb_result ┆ … │\n"), "{}", pretty);
+        assert!(pretty.ends_with("(2 of 4 columns not shown)\n"));
+    }
+}
@@ -0,0 +1,164 @@
+//! Companion columns: qualifier or flag columns (such as a `qual` column of U and J flags) that are paired with a
+//! value column, so that each cell of the value carries the companion value of its input row into the output.
+
+use crate::aggregate::Aggregation;
+use std::str::FromStr;
+
+/// How the companion value of a cell is written.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PairStyle {
+    /// In a generated column of its own, after the column of its value.
+    #[default]
+    Adjacent,
+    /// After the value in the same cell, separated by a space, as in `0.5 U`.
+    Suffix,
+}
+
+impl FromStr for PairStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "adjacent" => Ok(PairStyle::Adjacent),
+            "suffix" => Ok(PairStyle::Suffix),
+            _ => Err(format!("Unknown pair style '{}'. Must be one of adjacent, suffix", s)),
+        }
+    }
+}
+
+/// What to do when the input rows of a cell have different companion values and its aggregation does not pick one
+/// of the rows, as `sum` and `mean` do not.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PairConflict {
+    /// Join the distinct companion values, in input order, as the `concat` aggregation does.
+    #[default]
+    Join,
+    /// Leave the companion value of the cell empty.
+    Blank,
+    /// Fail to build the crosstab.
+    Error,
+}
+
+impl FromStr for PairConflict {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "join" => Ok(PairConflict::Join),
+            "blank" => Ok(PairConflict::Blank),
+            "error" => Ok(PairConflict::Error),
+            _ => Err(format!("Unknown pair conflict handling '{}'. Must be one of join, blank, error", s)),
+        }
+    }
+}
+
+// The separator placed between the distinct companion values of a cell that are joined.
+const JOIN_SEP: &str = "; ";
+
+/// Parse the `--pair` specifications, each a `value:companion` pair of column names such as `result:qual`.
+pub fn parse_pairs(specs: &[String]) -> Result<Vec<(String, String)>, String> {
+    specs
+        .iter()
+        .map(|spec| match spec.rsplit_once(':') {
+            Some((value, companion)) if !value.trim().is_empty() && !companion.trim().is_empty() => {
+                Ok((value.trim().to_string(), companion.trim().to_string()))
+            }
+            _ => Err(format!("The pair '{}' must be a value:companion pair of columns, e.g. result:qual", spec)),
+        })
+        .collect()
+}
+
+// The position of the input row whose value the aggregation picks, or `None` if it combines the values. `min` and
+// `max` pick the first row with the smallest or largest number.
+fn picked_row(aggregation: Aggregation, values: &[Option<String>]) -> Option<usize> {
+    let numbers = values.iter().enumerate().filter_map(|(i, v)| Some((i, v.as_deref()?.trim().parse::<f64>().ok()?)));
+    let extreme = |smallest: bool| {
+        numbers
+            .fold(None, |picked: Option<(usize, f64)>, (i, x)| match picked {
+                Some((_, p)) if (smallest && p <= x) || (!smallest && p >= x) => picked,
+                _ => Some((i, x)),
+            })
+            .map(|(i, _)| i)
+    };
+    match aggregation {
+        Aggregation::First => Some(0).filter(|_| !values.is_empty()),
+        Aggregation::Last => values.len().checked_sub(1),
+        Aggregation::Min => extreme(true),
+        Aggregation::Max => extreme(false),
+        _ => None,
+    }
+}
+
+/// The companion value of a cell, from the values of its value column and its companion column, in input order.
+/// If the aggregation of the value picks one of the input rows (`first`, `last`, `min`, or `max`), it is the
+/// companion value of that row. Otherwise the distinct non-missing companion values of the rows with values are combined, and if there is
+/// more than one, the conflict handling decides.
+pub fn companion(
+    aggregation: Aggregation,
+    values: &[Option<String>],
+    companions: &[Option<String>],
+    conflict: PairConflict,
+) -> Result<Option<String>, String> {
+    if let Some(i) = picked_row(aggregation, values) {
+        return Ok(companions.get(i).cloned().flatten());
+    }
+    let mut distinct: Vec<&str> = Vec::new();
+    for (_, companion) in values.iter().zip(companions).filter(|(v, _)| v.is_some()) {
+        let Some(companion) = companion else { continue };
+        if !distinct.contains(&companion.as_str()) {
+            distinct.push(companion);
+        }
+    }
+    match (distinct.len(), conflict) {
+        (0, _) => Ok(None),
+        (1, _) | (_, PairConflict::Join) => Ok(Some(distinct.join(JOIN_SEP))),
+        (_, PairConflict::Blank) => Ok(None),
+        (_, PairConflict::Error) => Err(format!(
+            "The values of a cell that are combined with {:?} have different companion values: '{}'",
+            aggregation,
+            distinct.join("', '")
+        )),
+    }
+}
+
+/// A cell value with its companion value appended, as in `0.5 U`. A missing value stays missing.
+pub fn with_suffix(value: Option<String>, companion: Option<&str>) -> Option<String> {
+    match (value, companion) {
+        (Some(value), Some(companion)) if !companion.is_empty() => Some(format!("{} {}", value, companion)),
+        (value, _) => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(values: &[Option<&str>]) -> Vec<Option<String>> {
+        values.iter().map(|v| v.map(|s| s.to_string())).collect()
+    }
+
+    // Test that the companion of the picked row is kept, and that differing companions of combined values are
+    // handled as configured
+    #[test]
+    fn test_companion() {
+        assert_eq!(parse_pairs(&["result : qual".to_string()]).unwrap(), vec![("result".to_string(), "qual".to_string())]);
+        assert!(parse_pairs(&["result".to_string()]).is_err());
+
+        let results = values(&[Some("0.5"), Some("2.5"), None, Some("2.5"), Some("0.1")]);
+        let quals = values(&[Some("U"), Some("J"), Some("R"), None, Some("U")]);
+        let join = PairConflict::Join;
+        assert_eq!(companion(Aggregation::First, &results, &quals, join).unwrap(), Some("U".to_string()));
+        assert_eq!(companion(Aggregation::Last, &results, &quals, join).unwrap(), Some("U".to_string()));
+        assert_eq!(companion(Aggregation::Max, &results, &quals, join).unwrap(), Some("J".to_string()));
+        assert_eq!(companion(Aggregation::Min, &results, &quals, join).unwrap(), Some("U".to_string()));
+        assert_eq!(companion(Aggregation::Sum, &results, &quals, join).unwrap(), Some("U; J".to_string()));
+        assert_eq!(companion(Aggregation::Sum, &results, &quals, PairConflict::Blank).unwrap(), None);
+        assert!(companion(Aggregation::Mean, &results, &quals, PairConflict::Error).is_err());
+        let same = values(&[Some("U"), None, Some("U"), Some("U"), Some("U")]);
+        assert_eq!(companion(Aggregation::Mean, &results, &same, PairConflict::Error).unwrap(), Some("U".to_string()));
+
+        assert_eq!(with_suffix(Some("0.5".to_string()), Some("U")), Some("0.5 U".to_string()));
+        assert_eq!(with_suffix(Some("0.5".to_string()), None), Some("0.5".to_string()));
+        assert_eq!(with_suffix(None, Some("U")), None);
+    }
+}
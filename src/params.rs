@@ -0,0 +1,918 @@
+//! The parameters of a crosstab by the names of the command-line arguments, which the command line parses its
+//! arguments into, so that a crosstab described to any of the bindings of xtab in other languages and environments
+//! is built as the command line builds it, with the same defaults.
+//!
+//! The parameters can be read from the settings of a table, such as a JSON object, in the form of a job file (see
+//! [`job`](crate::job)): `{"row": ["site"], "col": "date", "value": "result", "aggregate": "mean"}`. With
+//! [`Settings`], such an object describes the crosstab, the transformations of its input data, and its input and
+//! output text, and [`crosstab_json`] builds the crosstab of delimited text that it describes.
+
+use crate::aggregate::{expand_stats, parse_aggregations, parse_value_spec, Aggregation, ValueSpec, CONCAT_SEP};
+use crate::bins::{bin_lazy, parse_bins, Bin};
+use crate::dates::{parse_date_specs, parse_dates};
+use crate::filter::filter_lazy;
+use crate::format::{parse_value_formats, ValueFormat};
+use crate::io::{read_csv_from, read_input, InputFormat};
+use crate::join::join_lazy;
+use crate::output::{writer_for, OutputFormat};
+use crate::pair::parse_pairs;
+use crate::recode::{parse_recodes, read_mapping, recode_lazy};
+use crate::schema::{cast_columns, parse_dtypes, read_schema_file};
+use crate::sort::{parse_sort, SortKey};
+use crate::sql::{derive_lazy, parse_derivations, sql_lazy};
+use crate::{delimiter_for_path, parse_delimiter, Crosstab, HeaderNames, OnDuplicate, ReadConfig, WriteConfig, XtabConfig, XtabError, COUNT_LABEL};
+use anyhow::{anyhow, Context, Result};
+use polars::prelude::{DataFrame, DataType, Expr, IntoLazy, LazyFrame};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The description of a crosstab, each part of which has the name, the meaning, and the default of a command-line
+/// argument of the pivot subcommand.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "cli", derive(clap::Args))]
+#[serde(default)]
+pub struct CrosstabParams {
+    /// The row header columns.
+    #[cfg_attr(feature = "cli", arg(short, long, required = true, value_delimiter = ',', help = "A comma-separated list of one or more column names to use as row headers in the crosstab. Unique values of these columns will appear at the beginning of every output line."))]
+    #[serde(deserialize_with = "list")]
+    pub row: Vec<String>,
+    /// The column header columns.
+    #[cfg_attr(feature = "cli", arg(short, long, required = true, value_delimiter = ',', help="A comma-separated list of one or more column names to use as column headers in the crosstab. Each unique combination of the values of these columns generates a group of output columns, with one column for each -v column. With several -c columns, the groups are nested in the order of the columns, so the values of the first column vary slowest, and only the combinations that appear in the input are generated unless --col-product is given. Formats 3 and 4 write one header row for each -c column."))]
+    #[serde(deserialize_with = "list")]
+    pub col: Vec<String>,
+    /// The value columns, each of which can be given with its own aggregation and label, as in `result:mean:avg_result`.
+    #[cfg_attr(feature = "cli", arg(short, long, required_unless_present = "count", value_delimiter = ',', help="One or more column names with values to be used to fill the cells of the cross-table.  If n columns names are specified, then there will be n columns in the output table for each of the column headers corresponding to values of the -c argument.  The column names specified with the -v argument will be appended to the output column headers created from values of the -c argument.  There should be only one value of the -v column(s) for each combination of the -r and -c columns; if there is more than one, a warning will be printed and only the first value will appear in the output.  (That is, values are not combined in any way when there are multiple values for each output cell, unless an aggregation is chosen with the -a argument.)  A name with a * or ? wildcard is a glob pattern (e.g. 'conc_*'), and a name that starts with re: is a regular expression (e.g. 're:^result_\\d+$'); each selects every matching input column other than the -r and -c columns, in input order, with the aggregation of the pattern.  A value given as column:aggregation or column:aggregation:label (e.g. result:mean:avg_result,result:count:n) is combined with its own aggregation, overriding the -a argument, and is named with the label in the generated column names, so that one column can fill several values."))]
+    #[serde(deserialize_with = "list")]
+    pub value: Vec<String>,
+    /// The format of the column headers, between 1 and 4.
+    #[cfg_attr(feature = "cli", arg(short, long, default_value = "1", help="Controls the format of the column headers. The four possible values are: 1) One row of column headers, with elements joined by underscores to facilitate parsing by other programs; 2) Two rows of column headers.  The first row contains values of the columns specified by the -c argument, and the second row contains the column names specified by the -v argument; 3) One header row for each of the values of the columns specified by the -c argument, plus one row with the column names specified by the -v argument; 4) Like 3, but the values of the columns specified by the -c argument are labeled with (preceded by) the column names."))]
+    #[serde(deserialize_with = "number")]
+    pub format: u8,
+    /// The aggregation of every value column, or column=aggregation pairs.
+    #[cfg_attr(feature = "cli", arg(short, long, value_delimiter = ',', help="How to combine the values when more than one input row maps to the same output cell. One of sum, mean, min, max, count, first, last, concat, sd, min_nd, max_nd, nunique, mode, any, or all. The nunique aggregation is the number of distinct values and mode is the most frequent value (the first in input order of equally frequent values); any and all combine boolean values (true/false, 1/0, or yes/no). The min_nd and max_nd aggregations understand non-detects, values below their detection limit written like <0.5 (or flagged by the --nd-column qualifier): max_nd is the largest detected value, or the non-detect with the largest limit if every value is a non-detect, and min_nd is the smallest value, counting each non-detect at its limit. A single name applies to every value column; use column=aggregation pairs (e.g. result=mean,unit=first) to choose an aggregation for each value column. The aggregations of --plugin libraries are named in the same way. If this is not specified, only the first value is used and a warning is printed."))]
+    #[serde(deserialize_with = "list")]
+    pub aggregate: Vec<String>,
+    /// The plugins to load before the crosstab is built.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "PATH", help="A plugin to load before the crosstab is built: a shared library (.so, .dylib, or .dll) with the xtab_plugin_aggregations, xtab_plugin_aggregate, and xtab_plugin_free functions of the xtab plugin interface, whose aggregations can then be named like the built-in ones, with -a or in a -v column:aggregation. Give the argument more than once to load several plugins."))]
+    #[serde(deserialize_with = "values")]
+    pub plugin: Vec<PathBuf>,
+    /// Whether to append the totals columns.
+    #[cfg_attr(feature = "cli", arg(long, help="Append a group of totals columns, one for each of the -v columns, with the aggregation (chosen with the -a argument) of all of the values of each output row."))]
+    pub row_totals: bool,
+    /// Whether to append the totals row.
+    #[cfg_attr(feature = "cli", arg(long, help="Append a totals row with the aggregation (chosen with the -a argument) of all of the values of each output column."))]
+    pub col_totals: bool,
+    /// Whether to add the subtotal rows.
+    #[cfg_attr(feature = "cli", arg(long, help="Add a subtotal row, with the aggregation (chosen with the -a argument) of all of the values of the group, after each group of output rows that share their leading row header values. With n -r columns, there are subtotals at each of the first n-1 levels, labeled like 'A01 — subtotal'."))]
+    pub subtotals: bool,
+    /// The total that each cell is a percentage of: row, col, or total.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = ["row", "col", "total"], help="Express each cell value as a percentage of the sum of the values of its output row (row), of its output column (col), or of the whole crosstab (total), instead of the value itself. The values must be numbers, such as the counts of a -a count crosstab."))]
+    #[serde(deserialize_with = "optional_text")]
+    pub percent: Option<String>,
+    /// Whether each cell is the number of input rows, instead of the values of value columns.
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with_all = ["value", "aggregate"], help="Instead of filling the cells with the values of -v columns, fill each cell with the number of input rows with its row and column header values, producing a contingency table of frequencies. The generated columns are named like 2024-01-01_count."))]
+    pub count: bool,
+    /// The value of the cells without input data.
+    #[cfg_attr(feature = "cli", arg(long, help="The value of the cells of row and column header combinations that have no input data, e.g. 0 or NA. If this is not specified, these cells are empty (or null in Parquet and JSON output)."))]
+    #[serde(deserialize_with = "optional_text")]
+    pub fill: Option<String>,
+    /// The order of the output rows, as column:order pairs.
+    #[cfg_attr(feature = "cli", arg(long, value_delimiter = ',', help="The order of the output rows, as a comma-separated list of column:order pairs (e.g. site:asc,date:desc) of row header columns in order of precedence. The order is one of asc, desc, natural, or natural-desc; the natural orders compare runs of digits as numbers, so that S2 comes before S10. Row header columns that are not listed are sorted in ascending order after the others."))]
+    #[serde(deserialize_with = "list")]
+    pub sort_rows: Vec<String>,
+    /// The order of the generated columns.
+    #[cfg_attr(feature = "cli", arg(long, default_value = "asc", value_parser = ["asc", "desc", "natural", "natural-desc", "appearance"], help="The order of the generated columns, by the values of the -c columns: asc or desc, natural or natural-desc (comparing runs of digits as numbers), or appearance for the order in which the values first appear in the input."))]
+    #[serde(deserialize_with = "text")]
+    pub sort_cols: String,
+    /// The column header values that come first, or the name of a file that lists them.
+    #[cfg_attr(feature = "cli", arg(long, value_delimiter = ',', help="A comma-separated list of -c column values (e.g. Jan,Feb,Mar) that come first in the generated columns, in the listed order, or the name of a file that lists them one per line. Values that are not listed follow them, in the --sort-cols order."))]
+    #[serde(deserialize_with = "list")]
+    pub col_order: Vec<String>,
+    /// The separator in the generated column names.
+    #[cfg_attr(feature = "cli", arg(long, default_value = "_", help="The separator placed between the values of the -c columns in the generated column names, and before the -v column name in format 1."))]
+    #[serde(deserialize_with = "text")]
+    pub header_sep: String,
+    /// The template of the format 1 generated column names.
+    #[cfg_attr(feature = "cli", arg(long, help="A template for the format 1 generated column names, e.g. {value}.{var}, in which {value} is replaced by the values of the -c columns joined by the --header-sep separator, {var} by the -v column name, and {name} by the value of the -c column name."))]
+    #[serde(deserialize_with = "optional_text")]
+    pub header_template: Option<String>,
+    /// What to do with input rows that have a missing row or column header value: drop, keep, or error.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = ["drop", "keep", "error"], default_value = "keep", help="What to do with input rows that have a missing value in a -r or -c column: leave them out of the crosstab, keep them with the --null-label value in place of each missing value, or fail."))]
+    #[serde(deserialize_with = "text")]
+    pub null_keys: String,
+    /// The value that stands for a missing row or column header value.
+    #[cfg_attr(feature = "cli", arg(long, default_value = "", help="The value that stands for a missing -r or -c column value when --null-keys is keep, e.g. <NULL>. If this is not specified, missing values are empty."))]
+    #[serde(deserialize_with = "text")]
+    pub null_label: String,
+    /// The strftime format of the values of date row and column header columns.
+    #[cfg_attr(feature = "cli", arg(long, help="The strftime format in which the values of the date -r and -c columns are written, e.g. %Y-%m or %b %Y. The values are still sorted chronologically, and dates that are written alike share an output row or column."))]
+    #[serde(deserialize_with = "optional_text")]
+    pub date_format: Option<String>,
+    /// The format of the numeric values, or column=format pairs.
+    #[cfg_attr(feature = "cli", arg(long, help="The format of the numeric cell values: %.Nf for N decimal places, %,.Nf to also separate the thousands with commas, or %.Ne for scientific notation, e.g. %.3f. A column=format pair (e.g. result=%.3f) formats a single -v column, overriding a bare format. The argument can be repeated."))]
+    #[serde(deserialize_with = "values")]
+    pub value_format: Vec<String>,
+    /// The absolute value from which numeric values are written in scientific notation.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = clap::value_parser!(f64), help="Write the numeric cell values whose absolute value is at least this (e.g. 1e6), or less than its reciprocal, in scientific notation."))]
+    #[serde(deserialize_with = "optional_number")]
+    pub sci_threshold: Option<f64>,
+    /// Whether the columns are given by their positions in the input, counting from 1.
+    #[cfg_attr(feature = "cli", arg(long, help="Give the -r, -c, and -v columns (and the --sort-rows columns) by their positions in the input, counting from 1, instead of their names, e.g. -r 1,2 -c 3 -v 5, for input files with repeated or unwieldy column names. The positions are resolved to the columns after the input is read, so they count the columns of the --sql query result, if there is one."))]
+    pub by_index: bool,
+    /// The columns whose values are written once in each output row.
+    #[cfg_attr(feature = "cli", arg(long, value_delimiter = ',', help="A comma-separated list of columns (e.g. latitude,longitude) whose values are written once in each output row, after the -r columns, without being part of the row keys. Each must have a single value for each combination of -r column values; different values are an error."))]
+    #[serde(deserialize_with = "list")]
+    pub carry: Vec<String>,
+    /// Whether to generate the columns of every combination of the column header values.
+    #[cfg_attr(feature = "cli", arg(long, help="Generate a group of output columns for every combination of the values of the -c columns (e.g. every year × quarter × analyte), rather than only for the combinations that appear in the input. The cells of combinations without input data contain the --fill value."))]
+    pub col_product: bool,
+    /// The largest number of generated columns allowed, or 0 for no limit.
+    #[cfg_attr(feature = "cli", arg(long, default_value = "10000", help="The largest number of generated output columns allowed. A crosstab whose -c columns would generate more columns fails with the number of distinct combinations of their values, before the cells are built. Use 0 for no limit."))]
+    #[serde(deserialize_with = "number")]
+    pub max_columns: usize,
+    /// The number of column keys with the most input rows to keep.
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with = "col_product", help="Keep only the N column keys (values of the -c columns) with the most input rows, and aggregate the input rows with every other column key into a single group of columns named by --other-label, after the others."))]
+    #[serde(deserialize_with = "optional_number")]
+    pub top_cols: Option<usize>,
+    /// The name of the group of columns of the column keys that are not kept.
+    #[cfg_attr(feature = "cli", arg(long, default_value = "Other", help="The name of the group of columns with the column keys that are not among the --top-cols."))]
+    #[serde(deserialize_with = "text")]
+    pub other_label: String,
+    /// Whether to leave out the generated columns without values.
+    #[cfg_attr(feature = "cli", arg(long, help="Leave out the generated columns of each combination of -c values that has no values at all, such as the combinations without input data that --col-product generates."))]
+    pub drop_empty_cols: bool,
+    /// Whether to leave out the output rows without values.
+    #[cfg_attr(feature = "cli", arg(long, help="Leave out the output rows that have no values at all, such as the rows whose input rows all have missing values."))]
+    pub drop_empty_rows: bool,
+    /// Whether to write the crosstab transposed.
+    #[cfg_attr(feature = "cli", arg(long, help="Write the crosstab transposed, with the row keys across the top and the generated columns down the side, e.g. when there are a few sites but hundreds of dates."))]
+    pub transpose: bool,
+    /// The value:companion pairs of the value columns and their companion columns.
+    #[cfg_attr(feature = "cli", arg(long, help="Pair a -v column with a companion column, such as the qualifier (U or J) of a result, as a value:companion pair, e.g. result:qual, so that each cell of the value also has the companion value of its input row. With an aggregation that picks one of the input rows of a cell (first, last, min, or max), the companion value is that of the picked row. The argument can be repeated."))]
+    #[serde(deserialize_with = "values")]
+    pub pair: Vec<String>,
+    /// How the companion values are written: adjacent or suffix.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = ["adjacent", "suffix"], default_value = "adjacent", help="How the --pair companion values are written: adjacent for a generated column of their own after each column of their value, named by the companion column, or suffix for after the value in the same cell, e.g. 0.5 U."))]
+    #[serde(deserialize_with = "text")]
+    pub pair_style: String,
+    /// What to do with different companion values of the input rows of a cell: join, blank, or error.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = ["join", "blank", "error"], default_value = "join", help="What to do when the input rows of a cell have different --pair companion values and the aggregation combines their values (e.g. sum or mean): join the distinct companion values with semicolons, leave the companion value empty, or fail."))]
+    #[serde(deserialize_with = "text")]
+    pub pair_conflict: String,
+    /// The qualifier column that flags the values of non-detects.
+    #[cfg_attr(feature = "cli", arg(long, help="A qualifier column that flags the -v values of non-detects, e.g. qual, whose values are their detection limits. The flagged values are written as non-detects, e.g. <0.5, as are values that already start with <."))]
+    #[serde(deserialize_with = "optional_text")]
+    pub nd_column: Option<String>,
+    /// The qualifiers that flag a non-detect.
+    #[cfg_attr(feature = "cli", arg(long, default_value = "U", value_delimiter = ',', help="A comma-separated list of the --nd-column qualifiers that flag a non-detect, e.g. U,UJ,ND, matched regardless of capitalization."))]
+    #[serde(deserialize_with = "list")]
+    pub nd_flags: Vec<String>,
+    /// The number that stands for each non-detect in the values that are combined: zero, half, or dl.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = ["zero", "half", "dl"], help="The number that stands for each non-detect (e.g. <0.5) in the values combined with the sum, mean, min, and max aggregations: zero, half of the detection limit, or the detection limit (dl). If this is not specified, these aggregations fail on non-detects."))]
+    #[serde(deserialize_with = "optional_text")]
+    pub nd_substitute: Option<String>,
+    /// The template of the statistics of the values of each cell.
+    #[cfg_attr(feature = "cli", arg(long, num_args = 0..=1, default_missing_value = crate::aggregate::DEFAULT_STATS_TEMPLATE, conflicts_with_all = ["aggregate", "count", "stats_columns"], help="Write the statistics of the values of each cell in a template, for report-ready summary tables, instead of a single aggregated value. The template's {mean}, {sd} (the sample standard deviation), {n}, {min}, {max}, and {sum} placeholders are replaced by the statistics of the cell, and the standard deviation of a single value is written as NA. If the template is not given, it is '{mean} ({sd}), n={n}'. The statistics other than {n} are written in the --value-format of their -v column."))]
+    #[serde(deserialize_with = "optional_text")]
+    pub stats: Option<String>,
+    /// The template that annotates the aggregated value of each cell with other aggregations.
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with_all = ["stats", "stats_columns", "percent", "rank", "cumulative", "delta"], help="Write each cell in a template that annotates its aggregated value with other aggregations of the same values, e.g. '{value} ({count})' for the mean of each cell with its number of values. {value} is replaced by the aggregated value (of -a), and each other placeholder by the aggregation that it names, such as {count}, {min}, {max}, {sd}, or {nunique}. A missing aggregation is written as NA. The numbers other than counts are written in the --value-format of their -v column."))]
+    #[serde(deserialize_with = "optional_text")]
+    pub cell_template: Option<String>,
+    /// The statistics of each value column that are written in generated columns of their own.
+    #[cfg_attr(feature = "cli", arg(long, value_delimiter = ',', conflicts_with_all = ["aggregate", "count"], help="Write each of these statistics of the values of each -v column in a generated column of its own, e.g. mean,sd,n, instead of a single aggregated value. Each is an aggregation (of those of the -a argument, such as mean, sd, min, or max) or n for the number of values, and is named by the statistic, e.g. 2024-01_mean, or by the -v column and the statistic, e.g. 2024-01_result_mean, if there is more than one -v column. The statistics other than n are written in the --value-format of their -v column."))]
+    #[serde(deserialize_with = "list")]
+    pub stats_columns: Vec<String>,
+    /// Whether to test the independence of the rows and columns of the counts.
+    #[cfg_attr(feature = "cli", arg(long, requires = "count", conflicts_with_all = ["percent", "cumulative", "delta"], help="Test the independence of the rows and columns of the --count contingency table, and print the chi-square statistic, its degrees of freedom and p-value, and Cramér's V (the strength of the association, between 0 and 1) to standard error after writing the crosstab. The totals and subtotals are not counted."))]
+    pub chisq: bool,
+    /// What each cell value is ranked among: row or col.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = ["row", "col"], conflicts_with_all = ["percent", "stats", "row_totals", "col_totals", "subtotals"], help="Replace each cell value with its rank among the values of its output row (row) or of its generated column (col), separately for each -v column, e.g. to compare the stations on each date. The smallest value has rank 1 unless --rank-desc is given, and equal values share the smallest of their ranks, as in 1, 2, 2, 4."))]
+    #[serde(deserialize_with = "optional_text")]
+    pub rank: Option<String>,
+    /// Whether the largest value has rank 1.
+    #[cfg_attr(feature = "cli", arg(long, requires = "rank", help="Give the largest value rank 1 with --rank, rather than the smallest."))]
+    pub rank_desc: bool,
+    /// Whether each cell value is the running total of its output row.
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with_all = ["stats", "rank"], help="Replace each cell value with the running total of the values of its output row, in the order of the generated columns, e.g. to turn a monthly crosstab into a year-to-date one. The cells without input data after the first value of a row get the running total. With --percent, the percentages are accumulated."))]
+    pub cumulative: bool,
+    /// The change of each cell value from the previous generated column: abs or pct.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = ["abs", "pct"], conflicts_with_all = ["stats", "rank"], help="Replace each cell value with its change from the value of the previous generated column of its output row, for period-over-period reports: the difference (abs) or the percentage change (pct). The cells of the first generated column, and those whose previous cell has no value (or a value of 0, for pct), are empty."))]
+    #[serde(deserialize_with = "optional_text")]
+    pub delta: Option<String>,
+    /// The column of the weight of each input row.
+    #[cfg_attr(feature = "cli", arg(long, help="A column of the weight of each input row, e.g. a sample volume or a survey weight, by which the sum, mean, and count aggregations weight the values: the weighted sum, the weighted mean (the weighted sum divided by the sum of the weights), and the sum of the weights. With --count, each cell is the sum of the weights of its input rows. The values of input rows without a weight are left out of these aggregations."))]
+    #[serde(deserialize_with = "optional_text")]
+    pub weight: Option<String>,
+    /// The separator between the values that are combined with the concat aggregation.
+    #[cfg_attr(feature = "cli", arg(long, default_value = CONCAT_SEP, help="The separator between the values of a cell that are combined with the concat aggregation, e.g. \" | \" for comments that contain semicolons."))]
+    #[serde(deserialize_with = "text")]
+    pub concat_sep: String,
+    /// Whether the concat aggregation keeps only the first of the values that are the same.
+    #[cfg_attr(feature = "cli", arg(long, help="Keep only the first of the values of a cell that are the same when they are combined with the concat aggregation."))]
+    pub concat_unique: bool,
+    /// Whether the concat aggregation sorts the values.
+    #[cfg_attr(feature = "cli", arg(long, help="Sort the values of a cell that are combined with the concat aggregation, instead of keeping them in input order."))]
+    pub concat_sort: bool,
+}
+
+impl Default for CrosstabParams {
+    fn default() -> Self {
+        CrosstabParams {
+            row: Vec::new(),
+            col: Vec::new(),
+            value: Vec::new(),
+            format: 1,
+            aggregate: Vec::new(),
+            plugin: Vec::new(),
+            row_totals: false,
+            col_totals: false,
+            subtotals: false,
+            percent: None,
+            count: false,
+            fill: None,
+            sort_rows: Vec::new(),
+            sort_cols: "asc".to_string(),
+            col_order: Vec::new(),
+            header_sep: "_".to_string(),
+            header_template: None,
+            null_keys: "keep".to_string(),
+            null_label: String::new(),
+            date_format: None,
+            value_format: Vec::new(),
+            sci_threshold: None,
+            by_index: false,
+            carry: Vec::new(),
+            col_product: false,
+            max_columns: 10000,
+            top_cols: None,
+            other_label: "Other".to_string(),
+            drop_empty_cols: false,
+            drop_empty_rows: false,
+            transpose: false,
+            pair: Vec::new(),
+            pair_style: "adjacent".to_string(),
+            pair_conflict: "join".to_string(),
+            nd_column: None,
+            nd_flags: vec!["U".to_string()],
+            nd_substitute: None,
+            stats: None,
+            cell_template: None,
+            stats_columns: Vec::new(),
+            chisq: false,
+            rank: None,
+            rank_desc: false,
+            cumulative: false,
+            delta: None,
+            weight: None,
+            concat_sep: CONCAT_SEP.to_string(),
+            concat_unique: false,
+            concat_sort: false,
+        }
+    }
+}
+
+impl CrosstabParams {
+    /// Whether the values of a cell are combined, by an aggregation of any value column or by counting them. Multiple
+    /// values for a cell are only reported when they are not.
+    pub fn aggregated(&self) -> bool {
+        !self.aggregate.is_empty()
+            || self.count
+            || self.stats.is_some()
+            || !self.stats_columns.is_empty()
+            || self.value.iter().any(|v| parse_value_spec(v).aggregation.is_some())
+    }
+
+    /// The files that the parameters name, which are read when the crosstab is configured: the plugins, and the file
+    /// of the column order if it names one.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let col_order = match self.col_order.as_slice() {
+            [path] if Path::new(path).is_file() => Some(PathBuf::from(path)),
+            _ => None,
+        };
+        self.plugin.iter().cloned().chain(col_order).collect()
+    }
+
+    /// The configuration of the crosstab, which fails if any of the parameters are invalid. The aggregations of the
+    /// plugins are registered first, so that they can be named like the built-in aggregations.
+    pub fn config(&self) -> Result<XtabConfig> {
+        // Check the format, and the columns that the command line requires
+        if !(1..=4).contains(&self.format) {
+            return Err(XtabError::Argument("The format argument must be an integer between 1 and 4".to_string()).into());
+        }
+        if self.row.is_empty() || self.col.is_empty() {
+            return Err(XtabError::Argument("Give the row and column header columns".to_string()).into());
+        }
+        if self.value.is_empty() != self.count {
+            return Err(XtabError::Argument("Give either the value columns or count".to_string()).into());
+        }
+        if self.count && !self.aggregate.is_empty() {
+            return Err(XtabError::Argument("A count crosstab has no aggregation".to_string()).into());
+        }
+        self.load_plugins()?;
+        // Determine the aggregation of each value column. The aggregation of a value specification overrides the
+        // others. Each statistic of a value column whose statistics are written in columns of their own is a value of
+        // its own.
+        let given: Vec<ValueSpec> = self.value.iter().map(|v| parse_value_spec(v)).collect();
+        let (sources, specs): (Vec<usize>, Vec<ValueSpec>) = expand_stats(&given, &self.stats_columns).map_err(XtabError::Argument)?.into_iter().unzip();
+        let cell_values: Vec<String> = specs.iter().map(|spec| spec.column.clone()).collect();
+        let mut aggregations: Vec<Aggregation> = parse_aggregations(&self.aggregate, &cell_values).map_err(XtabError::Argument)?;
+        for (aggregation, spec) in aggregations.iter_mut().zip(&specs) {
+            *aggregation = spec.aggregation.unwrap_or(*aggregation);
+        }
+        // Determine the format of each value column. A crosstab without value columns formats its counts, and the
+        // counts of the statistics written in columns of their own are not formatted
+        let value_columns: Vec<String> = match self.count {
+            true => vec![COUNT_LABEL.to_string()],
+            false => given.iter().map(|spec| spec.column.clone()).collect(),
+        };
+        let formats: Vec<Option<ValueFormat>> = parse_value_formats(&self.value_format, &value_columns).map_err(XtabError::Argument)?;
+        let mut value_formats: Vec<Option<ValueFormat>> = match self.count {
+            true => formats,
+            false => sources
+                .iter()
+                .zip(&specs)
+                .map(|(&i, spec)| formats[i].filter(|_| self.stats_columns.is_empty() || spec.aggregation != Some(Aggregation::Count)))
+                .collect(),
+        };
+        if let Some(threshold) = self.sci_threshold {
+            for format in value_formats.iter_mut() {
+                *format = Some(ValueFormat { scientific_threshold: Some(threshold), ..format.unwrap_or_default() });
+            }
+        }
+        // Determine the order of the output rows
+        let row_sort: Vec<SortKey> = parse_sort(&self.sort_rows).map_err(XtabError::Argument)?;
+        // Read the explicit order of the column header values from a file if the parameter names one
+        let col_order: Vec<String> = match self.col_order.as_slice() {
+            [path] if Path::new(path).is_file() => std::fs::read_to_string(path)
+                .with_context(|| format!("The column order file cannot be read: {}", path))?
+                .lines()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty())
+                .collect(),
+            _ => self.col_order.clone(),
+        };
+
+        Ok(XtabConfig {
+            row_headers: self.row.clone(),
+            col_headers: self.col.clone(),
+            cell_values,
+            format: self.format,
+            aggregations,
+            row_totals: self.row_totals,
+            col_totals: self.col_totals,
+            subtotals: self.subtotals,
+            percent: parsed(self.percent.as_deref())?,
+            fill: self.fill.clone(),
+            row_sort,
+            col_sort: self.sort_cols.parse().map_err(XtabError::Argument)?,
+            col_order,
+            header_names: HeaderNames { separator: self.header_sep.clone(), template: self.header_template.clone() },
+            null_keys: self.null_keys.parse().map_err(XtabError::Argument)?,
+            null_label: self.null_label.clone(),
+            value_formats,
+            date_format: self.date_format.clone(),
+            by_index: self.by_index,
+            value_labels: specs.into_iter().map(|spec| spec.label).collect(),
+            carry: self.carry.clone(),
+            col_product: self.col_product,
+            max_columns: Some(self.max_columns).filter(|max| *max > 0),
+            top_cols: self.top_cols,
+            other_label: self.other_label.clone(),
+            drop_empty_cols: self.drop_empty_cols,
+            drop_empty_rows: self.drop_empty_rows,
+            transpose: self.transpose,
+            pairs: parse_pairs(&self.pair).map_err(XtabError::Argument)?,
+            pair_style: self.pair_style.parse().map_err(XtabError::Argument)?,
+            pair_conflict: self.pair_conflict.parse().map_err(XtabError::Argument)?,
+            nd_column: self.nd_column.clone(),
+            nd_flags: self.nd_flags.clone(),
+            nd_substitute: parsed(self.nd_substitute.as_deref())?,
+            stats_template: self.stats.clone(),
+            cell_template: self.cell_template.clone(),
+            rank: parsed(self.rank.as_deref())?,
+            rank_descending: self.rank_desc,
+            cumulative: self.cumulative,
+            delta: parsed(self.delta.as_deref())?,
+            weight: self.weight.clone(),
+            concat_sep: self.concat_sep.clone(),
+            concat_unique: self.concat_unique,
+            concat_sort: self.concat_sort,
+        })
+    }
+
+    // Register the aggregations of the plugins
+    #[cfg(feature = "native")]
+    fn load_plugins(&self) -> Result<()> {
+        for path in &self.plugin {
+            let names = crate::plugin::load_plugin(path)?;
+            log::debug!("Loaded the aggregations {} from the plugin {}", names.join(", "), path.display());
+        }
+        Ok(())
+    }
+
+    // Plugins are shared libraries, which can only be loaded with the native features
+    #[cfg(not(feature = "native"))]
+    fn load_plugins(&self) -> Result<()> {
+        match self.plugin.first() {
+            Some(path) => Err(XtabError::Argument(format!("Plugins cannot be loaded in this build: {}", path.display())).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+// An optional parameter parsed into its value
+fn parsed<T: FromStr<Err = String>>(value: Option<&str>) -> Result<Option<T>> {
+    Ok(value.map(str::parse).transpose().map_err(XtabError::Argument)?)
+}
+
+/// The transformations of the input data before it is crosstabbed, each of which has the name, the meaning, and the
+/// default of a command-line argument. They are applied in the order of their fields.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "cli", derive(clap::Args))]
+#[serde(default)]
+pub struct DataParams {
+    /// The column=format pairs of the date columns to parse.
+    #[cfg_attr(feature = "cli", arg(long, help="Parse a column as dates in the given strftime format, as a column=format pair (e.g. date=%m/%d/%y, or date=%m/%d/%y %H:%M for dates with a time of day), so that its values sort chronologically. The column is read as text before it is parsed. The argument can be repeated."))]
+    #[serde(deserialize_with = "values")]
+    pub parse_dates: Vec<String>,
+    /// The column=type pairs of the types of input columns.
+    #[cfg_attr(feature = "cli", arg(long, value_delimiter = ',', help="The types of input columns, as a comma-separated list of column=type pairs (e.g. result=f64,site=str,count=i64), instead of the types inferred from delimited text, so that identifiers with leading zeros can be read as text. The type is one of str, i64, i32, f64, f32, bool, date, or datetime. Columns of other input formats are converted to the type."))]
+    #[serde(deserialize_with = "list")]
+    pub dtypes: Vec<String>,
+    /// A JSON file with the types of input columns.
+    #[cfg_attr(feature = "cli", arg(long, help="A JSON file with the types of input columns, as an object of column names and types, e.g. {\"result\": \"f64\", \"site\": \"str\"}. Types given with --dtypes override those in the file."))]
+    pub schema: Option<PathBuf>,
+    /// The SQL query over the input data, the table named input.
+    #[cfg_attr(feature = "cli", arg(long, help="A SQL query over the input data, which is the table named input, whose result is used instead of the input data itself, e.g. \"SELECT site, date, analyte, result FROM input WHERE qc_flag IS NULL\". The query can select, rename, and derive columns. It is run before any --filter expression, which then applies to the columns of the result."))]
+    #[serde(deserialize_with = "optional_text")]
+    pub sql: Option<String>,
+    /// The name=expression pairs of the derived columns.
+    #[cfg_attr(feature = "cli", arg(long, help="Derive a column from a SQL expression over the input columns, as a name=expression pair, e.g. \"load=flow * concentration\". A derived column can be a -r, -c, or -v column, and can be used by the --filter expression and by later --derive expressions. It is derived after the --sql query is run, from the columns of its result. The argument can be repeated."))]
+    #[serde(deserialize_with = "values")]
+    pub derive: Vec<String>,
+    /// A lookup table file to join to the input data.
+    #[cfg_attr(feature = "cli", arg(long, requires = "join_on", help="A lookup table file (in any of the input formats) to join to the input data, such as a table of site names and regions, so that its columns can be used as -r, -c, or --carry columns. Its rows are matched to the input rows by the --join-on columns, and input rows without a match get missing values. It is joined after any --derive columns are derived, and before the --filter expression is applied."))]
+    pub join: Option<PathBuf>,
+    /// The columns that match the rows of the lookup table to the input rows.
+    #[cfg_attr(feature = "cli", arg(long, requires = "join", value_delimiter = ',', help="A comma-separated list of the columns, in both the input data and the --join lookup table, that match the lookup rows to the input rows. The lookup table must have a single row for each combination of their values."))]
+    #[serde(deserialize_with = "list")]
+    pub join_on: Vec<String>,
+    /// The columns of the lookup table to add, or all of them.
+    #[cfg_attr(feature = "cli", arg(long, requires = "join", value_delimiter = ',', help="A comma-separated list of the columns of the --join lookup table to add to the input data. If this is not specified, every lookup column other than the --join-on columns is added."))]
+    #[serde(deserialize_with = "list")]
+    pub join_cols: Vec<String>,
+    /// The column=file pairs of the recoded columns and their mapping files.
+    #[cfg_attr(feature = "cli", arg(long, help="Replace the codes in a column with their labels from a mapping file, as a column=file pair, e.g. analyte=analytes.csv, so that the crosstab shows the labels. The mapping file is delimited text (with a column names line) whose first column has the codes and whose second column has their labels. Values that are not codes in the file are kept as they are. The column is recoded after the --join lookup table is joined, and before the --filter expression is applied. The argument can be repeated."))]
+    #[serde(deserialize_with = "values")]
+    pub recode: Vec<String>,
+    /// The expression that selects the input rows to use.
+    #[cfg_attr(feature = "cli", arg(long, help="An expression that selects the input rows to use, e.g. \"depth > 10 && site != 'QC'\". Columns are compared with numbers, quoted strings, true, false, or null using ==, !=, <, <=, >, and >=, and comparisons are combined with && (and), || (or), ! (not), and parentheses. Column names that contain spaces or punctuation are written in backquotes."))]
+    #[serde(deserialize_with = "optional_text")]
+    pub filter: Option<String>,
+    /// The column=bin pairs of the binned columns.
+    #[cfg_attr(feature = "cli", arg(long, help="Replace the values of a column with the bins that they fall in, as a column=bin pair: a bin width for numbers, e.g. depth=10, which writes each number as the lower bound of its bin, or month, quarter, or year for dates, e.g. date=month. The values are binned after the --filter expression is applied. The argument can be repeated."))]
+    #[serde(deserialize_with = "values")]
+    pub bin: Vec<String>,
+}
+
+impl DataParams {
+    /// The files that the parameters name: the schema file, the lookup table, and the mapping files.
+    pub fn files(&self) -> Result<Vec<PathBuf>> {
+        let recodes = parse_recodes(&self.recode).map_err(XtabError::Argument)?;
+        Ok(self.schema.iter().chain(&self.join).cloned().chain(recodes.into_iter().map(|(_, path)| path)).collect())
+    }
+
+    /// Resolve the transformations, reading the files that they name, which fails if any of the parameters are
+    /// invalid.
+    pub fn transforms(&self) -> Result<Transforms> {
+        // Determine the column types from the schema file and the parameter, which overrides it. The date columns are
+        // read as text, so that type inference cannot change their values before they are parsed
+        let mut dtypes: Vec<(String, DataType)> = match &self.schema {
+            Some(path) => read_schema_file(path)?,
+            None => Vec::new(),
+        };
+        let date_columns = parse_date_specs(&self.parse_dates).map_err(XtabError::Argument)?;
+        let overrides = parse_dtypes(&self.dtypes).map_err(XtabError::Argument)?;
+        let text = date_columns.iter().map(|(name, _)| (name.clone(), DataType::String));
+        for (name, dtype) in overrides.into_iter().chain(text) {
+            dtypes.retain(|(n, _)| n != &name);
+            dtypes.push((name, dtype));
+        }
+        // Read the lookup table, in the format of its extension
+        let lookup: Option<DataFrame> = match &self.join {
+            Some(path) => {
+                let config = ReadConfig { delimiter: delimiter_for_path(path), ..ReadConfig::default() };
+                Some(read_input(InputFormat::from_path(path), path, &config).with_context(|| format!("The lookup table cannot be read: {}", path.display()))?)
+            }
+            None => None,
+        };
+        // Read the mapping files of the recoded columns
+        let recodes = parse_recodes(&self.recode)
+            .map_err(XtabError::Argument)?
+            .into_iter()
+            .map(|(column, path)| Ok((column, read_mapping(&path, delimiter_for_path(&path))?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Transforms {
+            dtypes,
+            date_columns,
+            sql: self.sql.clone(),
+            derivations: parse_derivations(&self.derive).map_err(XtabError::Argument)?,
+            lookup: lookup.map(|lookup| (lookup, self.join_on.clone(), self.join_cols.clone())),
+            recodes,
+            filter: self.filter.clone(),
+            bins: parse_bins(&self.bin).map_err(XtabError::Argument)?,
+        })
+    }
+}
+
+/// The transformations of the input data, resolved from [`DataParams`].
+#[derive(Clone, Debug, Default)]
+pub struct Transforms {
+    /// The types of the input columns, which the input is read with and converted to.
+    pub dtypes: Vec<(String, DataType)>,
+    /// The date columns to parse, with their formats.
+    pub date_columns: Vec<(String, String)>,
+    /// The SQL query over the input data.
+    pub sql: Option<String>,
+    /// The derived columns, with their expressions.
+    pub derivations: Vec<(String, Expr)>,
+    /// The lookup table to join to the input data, with the columns to join it on and the columns to add.
+    pub lookup: Option<(DataFrame, Vec<String>, Vec<String>)>,
+    /// The recoded columns, with the codes and labels of their mapping files.
+    pub recodes: Vec<(String, Vec<(String, String)>)>,
+    /// The expression that selects the input rows to use.
+    pub filter: Option<String>,
+    /// The binned columns, with their bins.
+    pub bins: Vec<(String, Bin)>,
+}
+
+impl Transforms {
+    /// Transform the input data: convert the columns to their types and parse the date columns, run the SQL query,
+    /// derive the columns from their expressions, join the lookup table, recode the recoded columns, keep only the
+    /// rows selected by the filter expression, and bin the binned columns.
+    pub fn apply(&self, lf: LazyFrame) -> Result<LazyFrame> {
+        let lf = parse_dates(cast_columns(lf, &self.dtypes), &self.date_columns);
+        let lf = match &self.sql {
+            Some(query) => sql_lazy(lf, query)?,
+            None => lf,
+        };
+        let lf = derive_lazy(lf, &self.derivations);
+        let lf = match &self.lookup {
+            Some((lookup, on, columns)) => join_lazy(lf, lookup.clone(), on, columns)?,
+            None => lf,
+        };
+        let lf = recode_lazy(lf, &self.recodes);
+        let lf = match &self.filter {
+            Some(expression) => filter_lazy(lf, expression)?,
+            None => lf,
+        };
+        Ok(bin_lazy(lf, &self.bins)?)
+    }
+}
+
+/// The settings of the input and output text of a crosstab in memory, and of multiple values for a cell, each of which
+/// has the name, the meaning, and the default of a command-line argument.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IoParams {
+    /// The character that separates the fields of the input text, or a comma.
+    #[serde(deserialize_with = "optional_text")]
+    pub delimiter: Option<String>,
+    /// The character that separates the fields of the output text, or a comma.
+    #[serde(deserialize_with = "optional_text")]
+    pub output_delimiter: Option<String>,
+    /// The format of the output, or csv.
+    #[serde(deserialize_with = "optional_text")]
+    pub output_format: Option<String>,
+    /// What to do when more than one value maps to a cell and no aggregation was requested: first, warn, or error.
+    #[serde(deserialize_with = "text")]
+    pub on_duplicate: String,
+    /// Whether to fail when more than one value maps to a cell, as with an `on_duplicate` of error.
+    pub strict: bool,
+}
+
+impl Default for IoParams {
+    fn default() -> Self {
+        IoParams { delimiter: None, output_delimiter: None, output_format: None, on_duplicate: "warn".to_string(), strict: false }
+    }
+}
+
+impl IoParams {
+    /// What to do with multiple values for a cell.
+    pub fn on_duplicate(&self) -> Result<OnDuplicate> {
+        match self.strict {
+            true => Ok(OnDuplicate::Error),
+            false => Ok(self.on_duplicate.parse().map_err(XtabError::Argument)?),
+        }
+    }
+
+    /// The format of the output.
+    pub fn output_format(&self) -> Result<OutputFormat> {
+        Ok(parsed(self.output_format.as_deref())?.unwrap_or(OutputFormat::Csv))
+    }
+
+    /// The options of the input, with its delimiter and the column types of the transformations.
+    pub fn read_config(&self, transforms: &Transforms) -> Result<ReadConfig> {
+        let delimiter = self.delimiter.as_deref().map(parse_delimiter).transpose().map_err(XtabError::Argument)?;
+        Ok(ReadConfig { delimiter: delimiter.unwrap_or(b','), dtypes: transforms.dtypes.clone(), ..ReadConfig::default() })
+    }
+
+    /// The options of the output, with its delimiter.
+    pub fn write_config(&self) -> Result<WriteConfig> {
+        let delimiter = self.output_delimiter.as_deref().map(parse_delimiter).transpose().map_err(XtabError::Argument)?;
+        Ok(WriteConfig { delimiter: delimiter.unwrap_or(b','), ..WriteConfig::default() })
+    }
+}
+
+/// The settings of a crosstab of data in memory, as the bindings receive them: the description of the crosstab, the
+/// transformations of its input data, and its input and output, by the names of the command-line arguments.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Settings {
+    /// The description of the crosstab.
+    pub crosstab: CrosstabParams,
+    /// The transformations of the input data.
+    pub data: DataParams,
+    /// The input and output.
+    pub io: IoParams,
+}
+
+// The settings of a job file that are not settings of a crosstab
+const JOB_KEYS: [&str; 2] = ["name", "jobs"];
+
+impl Settings {
+    /// The settings of the text of a JSON object, as in a job file.
+    pub fn from_json(config_json: &str) -> Result<Settings> {
+        let json: Value = serde_json::from_str(config_json).map_err(|e| XtabError::Argument(format!("The configuration is not valid JSON: {}", e)))?;
+        match json {
+            Value::Object(table) => Settings::from_table(table),
+            _ => Err(XtabError::Argument("The configuration must be a JSON object of settings".to_string()).into()),
+        }
+    }
+
+    /// The settings of a table, with the names of the arguments (with hyphens or underscores). A single value of a
+    /// list is split on commas, as on the command line, and a missing (null) value is left out. A setting that is not
+    /// the name of an argument is an error.
+    pub fn from_table(table: serde_json::Map<String, Value>) -> Result<Settings> {
+        let table: serde_json::Map<String, Value> = table
+            .into_iter()
+            .filter(|(key, value)| !value.is_null() && !JOB_KEYS.contains(&key.as_str()))
+            .map(|(key, value)| (key.replace('-', "_"), value))
+            .collect();
+        let known: Vec<String> = [names(CrosstabParams::default()), names(DataParams::default()), names(IoParams::default())].concat();
+        if let Some(key) = table.keys().find(|key| !known.contains(key)) {
+            return Err(XtabError::Argument(format!("There is no crosstab setting '{}'", key)).into());
+        }
+        let invalid = |e: serde_json::Error| XtabError::Argument(format!("A setting has an invalid value: {}", e));
+        let value = Value::Object(table);
+        Ok(Settings {
+            crosstab: CrosstabParams::deserialize(&value).map_err(invalid)?,
+            data: DataParams::deserialize(&value).map_err(invalid)?,
+            io: IoParams::deserialize(&value).map_err(invalid)?,
+        })
+    }
+
+    /// Build the crosstab of the input data, after its transformations, as the command line builds it. Multiple values
+    /// for a cell that are not aggregated fail the crosstab or are described by the warning that the command line
+    /// would print, which is returned with the crosstab, by the `on_duplicate` setting.
+    pub fn crosstab(&self, df: DataFrame, transforms: &Transforms) -> Result<(Crosstab, Option<String>)> {
+        let on_duplicate = self.io.on_duplicate()?;
+        let config = self.crosstab.config()?;
+        let df = transforms.apply(df.lazy())?.collect()?;
+        let crosstab = Crosstab::new(&df, &config)?;
+        let warning = match self.crosstab.aggregated() {
+            true => None,
+            false => check_duplicates(&crosstab, on_duplicate, None)?,
+        };
+        Ok((crosstab, warning))
+    }
+
+    // Check that the settings of a crosstab of data received from elsewhere name no files or plugins to read on this
+    // host
+    fn check_in_memory(&self) -> Result<()> {
+        match self.crosstab.files().into_iter().chain(self.data.files()?).next() {
+            Some(path) => Err(XtabError::Argument(format!("The settings of a crosstab in memory cannot name a file or plugin: {}", path.display())).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+// The names of the settings of a group of parameters
+fn names<T: Serialize>(params: T) -> Vec<String> {
+    match serde_json::to_value(params) {
+        Ok(Value::Object(table)) => table.into_iter().map(|(key, _)| key).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Describe each row/column key combination of the crosstab with multiple values, one per line.
+pub fn duplicate_lines(crosstab: &Crosstab) -> Vec<String> {
+    let config = crosstab.config();
+    crosstab
+        .duplicates()
+        .iter()
+        .map(|(row_key, col_key)| format!("  rows {:?} = {:?}, columns {:?} = {:?}", config.row_headers, row_key, config.col_headers, col_key))
+        .collect()
+}
+
+/// The failure for multiple values for some output cells, listing each offending row/column key combination.
+pub fn duplicates_error(crosstab: &Crosstab) -> anyhow::Error {
+    let listing = anyhow!("Choose an aggregation with the -a argument:\n{}", duplicate_lines(crosstab).join("\n"));
+    listing.context(XtabError::Duplicates(crosstab.duplicates().len()))
+}
+
+/// Check a crosstab whose values are not aggregated for multiple values for any output cell, as the command line
+/// does: fail with [`duplicates_error`], or return the warning that lists each offending row/column key combination,
+/// by `on_duplicate`. The group of the input rows of the crosstab, if it is one of several, is named in both.
+pub fn check_duplicates(crosstab: &Crosstab, on_duplicate: OnDuplicate, group: Option<&str>) -> Result<Option<String>> {
+    if crosstab.duplicates().is_empty() {
+        return Ok(None);
+    }
+    match (on_duplicate, group) {
+        (OnDuplicate::First, _) => Ok(None),
+        (OnDuplicate::Warn, _) => Ok(Some(format!(
+            "Multiple values were found for {} row/column combination(s){}; only the first value of each was used:\n{}",
+            crosstab.duplicates().len(),
+            group.map(|group| format!(" in the group '{}'", group)).unwrap_or_default(),
+            duplicate_lines(crosstab).join("\n")
+        ))),
+        (OnDuplicate::Error, Some(group)) => Err(duplicates_error(crosstab).context(format!("In the group '{}'", group))),
+        (OnDuplicate::Error, None) => Err(duplicates_error(crosstab)),
+    }
+}
+
+/// Crosstab delimited text as described by the text of a JSON object of [`Settings`], and return the output. The
+/// settings are the parameters of the crosstab and of the transformations of its input data, the `delimiter`,
+/// `output_delimiter`, and `output_format` (csv by default) of the input and output, and the `on_duplicate` (or
+/// `strict`) handling of multiple values for a cell, whose warning is logged, as in a job file. The settings cannot
+/// name files or plugins.
+pub fn crosstab_json(input: &[u8], config_json: &str) -> Result<Vec<u8>> {
+    let settings = Settings::from_json(config_json)?;
+    settings.check_in_memory()?;
+    let transforms = settings.data.transforms()?;
+    let output_format = settings.io.output_format()?;
+    let write_config = settings.io.write_config()?;
+
+    let df = read_csv_from(input, &settings.io.read_config(&transforms)?)?;
+    let (crosstab, warning) = settings.crosstab(df, &transforms)?;
+    if let Some(warning) = warning {
+        log::warn!("{}", warning);
+    }
+    let mut output: Vec<u8> = Vec::new();
+    writer_for(output_format, &mut output, &write_config)?.write(&crosstab)?;
+    Ok(output)
+}
+
+/// Crosstab an Arrow IPC file (such as record batches received from a data pipeline) as described by the text of a
+/// JSON object of the [`Settings`] of the crosstab and of the transformations of its input data, and return the
+/// crosstab as an Arrow IPC file, as written by [`ArrowWriter`](crate::output::ArrowWriter). The settings of the input
+/// and output text do not apply.
+#[cfg(feature = "native")]
+pub fn crosstab_ipc(input: &[u8], config_json: &str) -> Result<Vec<u8>> {
+    let settings = Settings::from_json(config_json)?;
+    settings.check_in_memory()?;
+    if settings.io.delimiter.is_some() || settings.io.output_delimiter.is_some() || settings.io.output_format.is_some() {
+        return Err(XtabError::Argument("An Arrow IPC crosstab has no delimiter or output format".to_string()).into());
+    }
+    let transforms = settings.data.transforms()?;
+    let df = crate::io::read_ipc_from(input)?;
+    let (crosstab, warning) = settings.crosstab(df, &transforms)?;
+    if let Some(warning) = warning {
+        log::warn!("{}", warning);
+    }
+    let mut output: Vec<u8> = Vec::new();
+    writer_for(OutputFormat::Arrow, &mut output, &WriteConfig::default())?.write(&crosstab)?;
+    Ok(output)
+}
+
+// The text of a scalar setting, as it is written on the command line
+fn scalar(value: Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        other => Err(format!("expected a string or a number, found {}", other)),
+    }
+}
+
+// A setting of text, given as a string or a number
+fn text<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    scalar(Value::deserialize(deserializer)?).map_err(D::Error::custom)
+}
+
+// An optional setting of text
+fn optional_text<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::Null => Ok(None),
+        value => scalar(value).map(Some).map_err(D::Error::custom),
+    }
+}
+
+// A numeric setting, given as a number or as its text
+fn number<'de, D: Deserializer<'de>, T: FromStr>(deserializer: D) -> Result<T, D::Error> {
+    let text = text(deserializer)?;
+    text.parse().map_err(|_| D::Error::custom(format!("expected a number, found {}", text)))
+}
+
+// An optional numeric setting
+fn optional_number<'de, D: Deserializer<'de>, T: FromStr>(deserializer: D) -> Result<Option<T>, D::Error> {
+    match optional_text(deserializer)? {
+        Some(text) => text.parse().map(Some).map_err(|_| D::Error::custom(format!("expected a number, found {}", text))),
+        None => Ok(None),
+    }
+}
+
+// A setting of values that can be repeated on the command line, given as a list or as a single value
+fn values<'de, D: Deserializer<'de>, T: From<String>>(deserializer: D) -> Result<Vec<T>, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::Null => Ok(Vec::new()),
+        Value::Array(values) => values.into_iter().map(|v| scalar(v).map(T::from).map_err(D::Error::custom)).collect(),
+        value => Ok(vec![T::from(scalar(value).map_err(D::Error::custom)?)]),
+    }
+}
+
+// A setting of a comma-separated list of values, given as a list or as the text of the list, whose values are split on
+// commas as on the command line
+fn list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let values: Vec<String> = values(deserializer)?;
+    Ok(values.iter().flat_map(|v| v.split(',')).map(str::to_string).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that the settings of a JSON object are read as the parameters, and resolved as on the command line
+    #[test]
+    fn test_from_table() {
+        let json = serde_json::json!({"row": "site,date", "col": ["analyte"], "value": ["result:max", "unit"], "aggregate": "first", "format": 2, "row-totals": true, "fill": 0, "max_columns": "5", "bin": "depth=10"});
+        let settings = Settings::from_table(json.as_object().unwrap().clone()).unwrap();
+        assert_eq!(settings.crosstab.row, vec!["site", "date"]);
+        assert_eq!(settings.data.bin, vec!["depth=10"]);
+        let config = settings.crosstab.config().unwrap();
+        assert_eq!(config.format, 2);
+        assert_eq!(config.aggregations, vec![Aggregation::Max, Aggregation::First]);
+        assert!(config.row_totals);
+        assert_eq!(config.fill.as_deref(), Some("0"));
+        assert_eq!(config.max_columns, Some(5));
+
+        // The defaults are those of the command line
+        let json = serde_json::json!({"row": "site", "col": "date", "value": "result"});
+        let config = Settings::from_table(json.as_object().unwrap().clone()).unwrap().crosstab.config().unwrap();
+        assert_eq!(config.max_columns, Some(10000));
+        assert_eq!(config.nd_flags, vec!["U"]);
+
+        for json in [
+            serde_json::json!({"row": "site", "col": "date", "values": "result"}),
+            serde_json::json!({"row": "site", "col": "date", "value": "result", "format": "two"}),
+            serde_json::json!({"row": "site", "col": "date", "value": {"column": "result"}}),
+        ] {
+            assert!(Settings::from_table(json.as_object().unwrap().clone()).is_err());
+        }
+        let params = CrosstabParams { row: vec!["site".to_string()], col: vec!["date".to_string()], ..CrosstabParams::default() };
+        assert!(params.config().is_err());
+        let params = CrosstabParams { value: vec!["result".to_string()], sort_cols: "sideways".to_string(), ..params };
+        assert!(params.config().is_err());
+    }
+
+    // Test that a JSON object describes the crosstab, the transformations of its input, and the delimiters of its
+    // input and output, and that multiple values for a cell are handled as on the command line
+    #[test]
+    fn test_crosstab_json() {
+        let csv = b"site;date;result\nA01;d1;1.5\nA01;d2;2\nB02;d1;3\n";
+        let config = r#"{"row": "site", "col": ["date"], "value": "result", "delimiter": ";", "output_delimiter": "tab"}"#;
+        let output = String::from_utf8(crosstab_json(csv, config).unwrap()).unwrap();
+        assert_eq!(output, "site\td1_result\td2_result\nA01\t1.5\t2.0\nB02\t3.0\t\n");
+        let config = r#"{"row": "site", "col": "date", "value": "result", "delimiter": ";", "filter": "result > 1.5", "sql": "SELECT site, date, result * 2 AS result FROM input"}"#;
+        let output = String::from_utf8(crosstab_json(csv, config).unwrap()).unwrap();
+        assert_eq!(output, "site,d1_result,d2_result\nA01,3.0,4.0\nB02,6.0,\n");
+        assert!(crosstab_json(csv, r#"{"row": "site", "col": "date", "value": "result"}"#).is_err());
+        assert!(crosstab_json(csv, "[]").is_err());
+
+        let csv = b"site,date,result\nA01,d1,1\nA01,d1,2\n";
+        assert!(crosstab_json(csv, r#"{"row": "site", "col": "date", "value": "result"}"#).is_ok());
+        let error = crosstab_json(csv, r#"{"row": "site", "col": "date", "value": "result", "on_duplicate": "error"}"#).unwrap_err();
+        assert!(matches!(error.downcast_ref::<XtabError>(), Some(XtabError::Duplicates(1))));
+        assert!(crosstab_json(csv, r#"{"row": "site", "col": "date", "value": "result", "strict": true, "aggregate": "sum"}"#).is_ok());
+        assert!(crosstab_json(csv, r#"{"row": "site", "col": "date", "value": "result", "join": "sites.csv", "join_on": "site"}"#).is_err());
+    }
+
+    // Test that an Arrow IPC file is crosstabbed into another, with numeric generated columns
+    #[cfg(feature = "native")]
+    #[test]
+    fn test_crosstab_ipc() {
+        use polars::prelude::{IpcWriter, SerWriter};
+        let csv = b"site,date,result\nA01,d1,1.5\nA01,d2,2\nB02,d1,3\n";
+        let mut df = read_csv_from(csv.as_slice(), &ReadConfig::default()).unwrap();
+        let mut input: Vec<u8> = Vec::new();
+        IpcWriter::new(&mut input).finish(&mut df).unwrap();
+        let output = crosstab_ipc(&input, r#"{"row": "site", "col": "date", "value": "result"}"#).unwrap();
+        let crosstab = crate::io::read_ipc_from(output.as_slice()).unwrap();
+        assert_eq!(crosstab.get_column_names(), vec!["site", "d1_result", "d2_result"]);
+        assert_eq!(crosstab.column("d1_result").unwrap().f64().unwrap().get(1), Some(3.0));
+        assert!(crosstab_ipc(&input, r#"{"row": "site", "col": "date", "value": "result", "delimiter": ";"}"#).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+//! Aggregations from outside of xtab, for domain-specific ways of combining the values of a cell (such as the geometric
+//! mean of results with a substitution for non-detects) without changing xtab itself.
+//!
+//! A program that embeds xtab registers an implementation of [`CellAggregator`] under a name with
+//! [`register_aggregator`], after which the name is an [`Aggregation`] like the built-in ones, as in `-a geomean` or
+//! `result:geomean`. The command line loads them from plugins (see [`load_plugin`]), shared libraries with this C
+//! interface:
+//!
+//! ```c
+//! /* The names of the aggregations of the plugin, separated by commas. */
+//! const char *xtab_plugin_aggregations(void);
+//!
+//! /* Combine the n values of a cell (in input order, with NULL for a missing value) with the named aggregation. On
+//!  * success, return 0 and set *result to the combined value, or to NULL if it is missing; on failure, return any
+//!  * other number and set *result to the error message. */
+//! int xtab_plugin_aggregate(const char *name, const char *const *values, size_t n, char **result);
+//!
+//! /* Release a result of xtab_plugin_aggregate. */
+//! void xtab_plugin_free(char *result);
+//! ```
+
+use crate::aggregate::Aggregation;
+use std::sync::{Arc, RwLock};
+
+/// A way of combining the values of a cell when more than one input row maps to it. It is called from the threads
+/// that build the crosstab, so it must be shareable between threads.
+pub trait CellAggregator: Send + Sync {
+    /// Combine the values of one cell, in input order, with `None` for a missing value. The result is the value of
+    /// the cell, or `None` to leave it missing, and an error is the message of a value that cannot be combined.
+    fn aggregate(&self, values: &[Option<String>]) -> Result<Option<String>, String>;
+}
+
+// The registered aggregators, by name, in the order of their registration. An Aggregation::Custom is a position here.
+static AGGREGATORS: RwLock<Vec<(String, Arc<dyn CellAggregator>)>> = RwLock::new(Vec::new());
+
+/// Register an aggregator under a name, for every crosstab built afterwards. A name that is already registered is
+/// given the new aggregator, and the name of a built-in aggregation is refused.
+pub fn register_aggregator<A: CellAggregator + 'static>(name: &str, aggregator: A) -> Result<Aggregation, String> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() || name.contains([',', '=', ':']) {
+        return Err(format!("The aggregation name '{}' must not be empty or contain a comma, equals sign, or colon", name));
+    }
+    if Aggregation::builtin(&name).is_some() {
+        return Err(format!("The aggregation '{}' is built in and cannot be replaced", name));
+    }
+    let mut aggregators = AGGREGATORS.write().unwrap_or_else(|e| e.into_inner());
+    let aggregator: Arc<dyn CellAggregator> = Arc::new(aggregator);
+    match aggregators.iter().position(|(n, _)| *n == name) {
+        Some(i) => {
+            aggregators[i].1 = aggregator;
+            Ok(Aggregation::Custom(i))
+        }
+        None => {
+            aggregators.push((name, aggregator));
+            Ok(Aggregation::Custom(aggregators.len() - 1))
+        }
+    }
+}
+
+/// The aggregation registered under the name, if there is one.
+pub fn registered(name: &str) -> Option<Aggregation> {
+    let name = name.trim().to_lowercase();
+    let aggregators = AGGREGATORS.read().unwrap_or_else(|e| e.into_inner());
+    aggregators.iter().position(|(n, _)| *n == name).map(Aggregation::Custom)
+}
+
+/// The names of the registered aggregations, in the order of their registration.
+pub fn registered_names() -> Vec<String> {
+    let aggregators = AGGREGATORS.read().unwrap_or_else(|e| e.into_inner());
+    aggregators.iter().map(|(name, _)| name.clone()).collect()
+}
+
+// The name and aggregator at a position of the registry
+pub(crate) fn aggregator(i: usize) -> Option<(String, Arc<dyn CellAggregator>)> {
+    let aggregators = AGGREGATORS.read().unwrap_or_else(|e| e.into_inner());
+    aggregators.get(i).cloned()
+}
+
+#[cfg(feature = "native")]
+pub use native::load_plugin;
+
+#[cfg(feature = "native")]
+mod native {
+    use super::{register_aggregator, CellAggregator};
+    use crate::XtabError;
+    use anyhow::{Context, Result};
+    use libloading::Library;
+    use std::ffi::{c_char, c_int, CStr, CString};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    type Aggregations = unsafe extern "C" fn() -> *const c_char;
+    type Aggregate = unsafe extern "C" fn(*const c_char, *const *const c_char, usize, *mut *mut c_char) -> c_int;
+    type Free = unsafe extern "C" fn(*mut c_char);
+
+    // An aggregation of a plugin, which keeps the library loaded for as long as it is registered
+    struct PluginAggregator {
+        name: CString,
+        aggregate: Aggregate,
+        free: Free,
+        _library: Arc<Library>,
+    }
+
+    impl CellAggregator for PluginAggregator {
+        fn aggregate(&self, values: &[Option<String>]) -> Result<Option<String>, String> {
+            let values = values
+                .iter()
+                .map(|v| v.as_deref().map(CString::new).transpose())
+                .collect::<Result<Vec<Option<CString>>, _>>()
+                .map_err(|_| "A value with a NUL character cannot be aggregated by a plugin".to_string())?;
+            let pointers: Vec<*const c_char> = values.iter().map(|v| v.as_ref().map_or(std::ptr::null(), |v| v.as_ptr())).collect();
+            let mut result: *mut c_char = std::ptr::null_mut();
+            // SAFETY: the plugin reads the values and writes a result that it allocated, which is copied and released
+            let (status, text) = unsafe {
+                let status = (self.aggregate)(self.name.as_ptr(), pointers.as_ptr(), pointers.len(), &mut result);
+                let text = (!result.is_null()).then(|| CStr::from_ptr(result).to_string_lossy().to_string());
+                if !result.is_null() {
+                    (self.free)(result);
+                }
+                (status, text)
+            };
+            match status {
+                0 => Ok(text),
+                _ => Err(text.unwrap_or_else(|| format!("The plugin aggregation '{}' failed", self.name.to_string_lossy()))),
+            }
+        }
+    }
+
+    /// Load a plugin, a shared library with the C interface of the [`plugin`](crate::plugin) module, and register each
+    /// of its aggregations. Returns the names of the aggregations.
+    pub fn load_plugin<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+        let path = path.as_ref();
+        let invalid = |message: String| XtabError::Argument(format!("{}: {}", message, path.display()));
+        // SAFETY: loading a library runs its initialization code, which the user trusts by naming the plugin
+        let library = unsafe { Library::new(path) }.with_context(|| format!("The plugin cannot be loaded: {}", path.display()))?;
+        let (names, aggregate, free) = unsafe {
+            let symbols = (
+                library.get::<Aggregations>(b"xtab_plugin_aggregations\0"),
+                library.get::<Aggregate>(b"xtab_plugin_aggregate\0"),
+                library.get::<Free>(b"xtab_plugin_free\0"),
+            );
+            let (Ok(names), Ok(aggregate), Ok(free)) = symbols else {
+                return Err(invalid("The plugin does not have the xtab_plugin_aggregations, xtab_plugin_aggregate, and xtab_plugin_free functions".to_string()).into());
+            };
+            let names = names();
+            if names.is_null() {
+                return Err(invalid("The plugin has no aggregations".to_string()).into());
+            }
+            (CStr::from_ptr(names).to_string_lossy().to_string(), *aggregate, *free)
+        };
+        let library = Arc::new(library);
+        let names: Vec<String> = names.split(',').map(|n| n.trim().to_string()).filter(|n| !n.is_empty()).collect();
+        for name in &names {
+            let aggregator = PluginAggregator {
+                name: CString::new(name.as_str()).map_err(|_| invalid(format!("The aggregation name '{}' is invalid", name)))?,
+                aggregate,
+                free,
+                _library: Arc::clone(&library),
+            };
+            register_aggregator(name, aggregator).map_err(|e| invalid(format!("{} in the plugin", e)))?;
+        }
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregate::{aggregate, round_significant};
+
+    // The geometric mean of the values, with each non-detect at half of its detection limit
+    struct GeoMean;
+
+    impl CellAggregator for GeoMean {
+        fn aggregate(&self, values: &[Option<String>]) -> Result<Option<String>, String> {
+            let logs = values
+                .iter()
+                .flatten()
+                .map(|v| {
+                    let x = match v.strip_prefix('<') {
+                        Some(limit) => limit.parse::<f64>().map(|limit| limit / 2.0),
+                        None => v.parse::<f64>(),
+                    };
+                    x.map(f64::ln).map_err(|_| format!("'{}' is not a number", v))
+                })
+                .collect::<Result<Vec<f64>, String>>()?;
+            Ok((!logs.is_empty()).then(|| round_significant((logs.iter().sum::<f64>() / logs.len() as f64).exp()).to_string()))
+        }
+    }
+
+    // Test that a registered aggregator is an aggregation by its name, and that built-in names are refused
+    #[test]
+    fn test_register_aggregator() {
+        let geomean = register_aggregator("GeoMean", GeoMean).unwrap();
+        assert_eq!("geomean".parse::<Aggregation>(), Ok(geomean));
+        assert_eq!(registered("geomean"), Some(geomean));
+        assert!(registered_names().contains(&"geomean".to_string()));
+        let values = vec![Some("2".to_string()), Some("<16".to_string()), None];
+        assert_eq!(aggregate(geomean, &values).unwrap(), Some("4".to_string()));
+        assert!(aggregate(geomean, &[Some("x".to_string())]).is_err());
+        assert!(register_aggregator("mean", GeoMean).is_err());
+        assert!(register_aggregator("geo=mean", GeoMean).is_err());
+    }
+}
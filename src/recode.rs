@@ -0,0 +1,83 @@
+//! Recoding: replacing the raw codes of a column, such as analyte codes, with their labels from a mapping file, so
+//! that the row headers, column headers, or cells of the crosstab show the labels.
+
+use anyhow::{bail, Context, Result};
+use polars::prelude::*;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Parse the `--recode` specifications, each a `column=file` pair such as `analyte=analytes.csv`.
+pub fn parse_recodes(specs: &[String]) -> Result<Vec<(String, PathBuf)>, String> {
+    specs
+        .iter()
+        .map(|spec| match spec.split_once('=') {
+            Some((column, file)) if !column.trim().is_empty() && !file.trim().is_empty() => Ok((column.trim().to_string(), PathBuf::from(file.trim()))),
+            _ => Err(format!("The recoding '{}' must be a column=file pair, e.g. analyte=analytes.csv", spec)),
+        })
+        .collect()
+}
+
+/// Read a mapping file of delimited text, whose first line has column names and whose first two columns are the
+/// codes and their labels. The codes are read as text, so that codes such as 007 are kept, and a code that is mapped
+/// more than once is an error.
+pub fn read_mapping<P: AsRef<Path>>(path: P, delimiter: u8) -> Result<Vec<(String, String)>> {
+    let path = path.as_ref();
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .from_path(path)
+        .with_context(|| format!("The mapping file cannot be read: {}", path.display()))?;
+    if reader.headers()?.len() < 2 {
+        bail!("The mapping file must have a column of codes and a column of labels: {}", path.display());
+    }
+    let mut mapping: Vec<(String, String)> = Vec::new();
+    let mut codes: HashSet<String> = HashSet::new();
+    for record in reader.records() {
+        let record = record?;
+        let (code, label) = (record.get(0).unwrap_or_default(), record.get(1).unwrap_or_default());
+        if !codes.insert(code.to_string()) {
+            bail!("The code '{}' is mapped more than once in the mapping file {}", code, path.display());
+        }
+        mapping.push((code.to_string(), label.to_string()));
+    }
+    Ok(mapping)
+}
+
+/// Plan the replacement of the values of each of the columns that are codes in its mapping with their labels.
+/// The columns become text, and values that are not codes in the mapping are kept as they are.
+pub fn recode_lazy(lf: LazyFrame, recodes: &[(String, Vec<(String, String)>)]) -> LazyFrame {
+    if recodes.is_empty() {
+        return lf;
+    }
+    let exprs: Vec<Expr> = recodes
+        .iter()
+        .map(|(name, mapping)| {
+            let codes = Series::new("codes", mapping.iter().map(|(code, _)| code.as_str()).collect::<Vec<_>>());
+            let labels = Series::new("labels", mapping.iter().map(|(_, label)| label.as_str()).collect::<Vec<_>>());
+            col(name).cast(DataType::String).replace(lit(codes), lit(labels), None, None).alias(name)
+        })
+        .collect();
+    lf.with_columns(exprs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that the codes of a column are replaced by their labels, and that other values are kept
+    #[test]
+    fn test_recode() {
+        let path = std::env::temp_dir().join(format!("xtab_{}_recode.csv", std::process::id()));
+        std::fs::write(&path, "code,name\n007,Lead\n029,Copper\n").unwrap();
+        let recodes = parse_recodes(&[format!("analyte = {}", path.display())]).unwrap();
+        assert_eq!(recodes, vec![("analyte".to_string(), path.clone())]);
+        assert!(parse_recodes(&["analyte".to_string()]).is_err());
+
+        let mapping = read_mapping(&path, b',').unwrap();
+        let df = df!("analyte" => &["029", "007", "999"], "result" => &[1.5, 2.5, 3.5]).unwrap();
+        let recoded = recode_lazy(df.lazy(), &[("analyte".to_string(), mapping)]).collect().unwrap();
+        assert_eq!(recoded.column("analyte").unwrap(), &Series::new("analyte", &["Copper", "Lead", "999"]));
+
+        std::fs::write(&path, "code,name\n007,Lead\n007,Copper\n").unwrap();
+        assert!(read_mapping(&path, b',').is_err());
+    }
+}
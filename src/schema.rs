@@ -0,0 +1,91 @@
+//! Explicit types of input columns, such as `result=f64,site=str`, so that they are not left to type inference.
+
+use anyhow::{anyhow, Context, Result};
+use polars::prelude::*;
+use std::path::Path;
+
+/// Parse the name of a column type: `str`, `i64`, `i32`, `f64`, `f32`, `bool`, `date`, or `datetime`, or one of
+/// their longer names such as `string` or `float64`.
+pub fn parse_dtype(s: &str) -> Result<DataType, String> {
+    match s.trim().to_lowercase().as_str() {
+        "str" | "string" | "utf8" | "text" => Ok(DataType::String),
+        "i64" | "int" | "int64" | "integer" => Ok(DataType::Int64),
+        "i32" | "int32" => Ok(DataType::Int32),
+        "f64" | "float" | "float64" | "double" => Ok(DataType::Float64),
+        "f32" | "float32" => Ok(DataType::Float32),
+        "bool" | "boolean" => Ok(DataType::Boolean),
+        "date" => Ok(DataType::Date),
+        "datetime" => Ok(DataType::Datetime(TimeUnit::Microseconds, None)),
+        _ => Err(format!("Unknown column type '{}'. Must be one of str, i64, i32, f64, f32, bool, date, datetime", s)),
+    }
+}
+
+/// Parse the `--dtypes` specifications, each a `column=type` pair such as `result=f64`.
+pub fn parse_dtypes(specs: &[String]) -> Result<Vec<(String, DataType)>, String> {
+    specs
+        .iter()
+        .map(|spec| match spec.split_once('=') {
+            Some((column, dtype)) if !column.trim().is_empty() => Ok((column.trim().to_string(), parse_dtype(dtype)?)),
+            _ => Err(format!("The column type '{}' must be a column=type pair, e.g. result=f64", spec)),
+        })
+        .collect()
+}
+
+/// Read the column types from a JSON file with an object of column names and type names, such as
+/// `{"result": "f64", "site": "str"}`.
+pub fn read_schema_file<P: AsRef<Path>>(path: P) -> Result<Vec<(String, DataType)>> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path).with_context(|| format!("The schema file cannot be read: {}", path.display()))?;
+    let value: serde_json::Value =
+        serde_json::from_str(&contents).with_context(|| format!("The schema file is not valid JSON: {}", path.display()))?;
+    let object = value
+        .as_object()
+        .ok_or_else(|| anyhow!("The schema file must contain an object of column names and types: {}", path.display()))?;
+    object
+        .iter()
+        .map(|(column, dtype)| match dtype.as_str() {
+            Some(dtype) => Ok((column.clone(), parse_dtype(dtype).map_err(|e| anyhow!(e))?)),
+            None => Err(anyhow!("The type of the column '{}' in the schema file must be a string", column)),
+        })
+        .collect()
+}
+
+/// Plan the conversion of each of the columns to its type. A value that cannot be converted is an error, rather
+/// than becoming missing.
+pub fn cast_columns(lf: LazyFrame, dtypes: &[(String, DataType)]) -> LazyFrame {
+    if dtypes.is_empty() {
+        return lf;
+    }
+    lf.with_columns(dtypes.iter().map(|(name, dtype)| col(name).strict_cast(dtype.clone())).collect::<Vec<_>>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that the column types are parsed from specifications and schema files
+    #[test]
+    fn test_parse_dtypes() {
+        let specs = vec!["result=f64".to_string(), "site = str".to_string()];
+        let dtypes = parse_dtypes(&specs).unwrap();
+        assert_eq!(dtypes, vec![("result".to_string(), DataType::Float64), ("site".to_string(), DataType::String)]);
+        assert!(parse_dtypes(&["result=decimal".to_string()]).is_err());
+        assert!(parse_dtypes(&["f64".to_string()]).is_err());
+
+        let path = std::env::temp_dir().join(format!("xtab_{}_schema.json", std::process::id()));
+        std::fs::write(&path, r#"{"count": "i64", "site": "string"}"#).unwrap();
+        let dtypes = read_schema_file(&path).unwrap();
+        assert_eq!(dtypes, vec![("count".to_string(), DataType::Int64), ("site".to_string(), DataType::String)]);
+        std::fs::write(&path, r#"["count"]"#).unwrap();
+        assert!(read_schema_file(&path).is_err());
+    }
+
+    // Test that values that cannot be converted are an error
+    #[test]
+    fn test_cast_columns() {
+        let df = df!("site" => &[1, 2], "result" => &["1.5", "ND"]).unwrap();
+        let cast = cast_columns(df.clone().lazy(), &[("site".to_string(), DataType::String)]).collect().unwrap();
+        assert_eq!(cast.column("site").unwrap().dtype(), &DataType::String);
+        assert!(cast_columns(df.lazy(), &[("result".to_string(), DataType::Float64)]).collect().is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! A small HTTP server of crosstabs, so that web applications can build crosstabs with the engine without bundling it.
+//!
+//! The server answers `POST /crosstab` requests with a `multipart/form-data` body (as sent by an HTML form with a
+//! file input, or by `curl -F`) of two parts: `data`, the delimited text to crosstab, and `config`, the text of a
+//! JSON object with the settings of the crosstab and of its input and output (see
+//! [`crosstab_json`](crate::params::crosstab_json)):
+//!
+//! ```sh
+//! curl -F data=@results.csv -F 'config={"row": "site", "col": "date", "value": "result", "aggregate": "mean"}' \
+//!     http://localhost:8080/crosstab
+//! ```
+//!
+//! The response is the crosstab, in the `output_format` of the settings (csv by default). A request that cannot be
+//! read is answered with status 400, and a crosstab that fails (such as for a missing column) with status 422, each
+//! with a JSON object whose `error` is the message that the command line would print.
+
+use crate::output::OutputFormat;
+use crate::params::crosstab_json;
+use anyhow::{anyhow, Result};
+use log::info;
+use std::io::Read;
+use std::net::SocketAddr;
+use tiny_http::{Header, Request, Server};
+
+/// The path of the crosstab endpoint.
+pub const CROSSTAB_PATH: &str = "/crosstab";
+
+/// A response of the server, before it is sent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Response {
+    /// The HTTP status code.
+    pub status: u16,
+    /// The media type of the body.
+    pub content_type: &'static str,
+    /// The body.
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    // A JSON object with the error message
+    fn error(status: u16, message: &str) -> Response {
+        let body = serde_json::json!({ "error": message }).to_string().into_bytes();
+        Response { status, content_type: "application/json", body }
+    }
+}
+
+// The media type of the output format
+fn media_type(format: OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Csv => "text/csv; charset=utf-8",
+        OutputFormat::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        OutputFormat::Ods => "application/vnd.oasis.opendocument.spreadsheet",
+        OutputFormat::Parquet => "application/vnd.apache.parquet",
+        OutputFormat::Arrow => "application/vnd.apache.arrow.file",
+        OutputFormat::Json => "application/json",
+        OutputFormat::Markdown => "text/markdown; charset=utf-8",
+        OutputFormat::Html => "text/html; charset=utf-8",
+        OutputFormat::Latex => "application/x-latex",
+        OutputFormat::Pretty => "text/plain; charset=utf-8",
+    }
+}
+
+// The position of the first occurrence of the needle in the haystack
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// The named parts of a `multipart/form-data` body, given the value of its Content-Type header.
+pub fn form_parts(content_type: &str, body: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let (media_type, parameters) = content_type.split_once(';').unwrap_or((content_type, ""));
+    if !media_type.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return Err(format!("The request must be multipart/form-data, not {}", media_type.trim()));
+    }
+    let boundary = parameters
+        .split(';')
+        .filter_map(|p| p.trim().split_once('='))
+        .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim_matches('"'))
+        .ok_or("The multipart/form-data request has no boundary")?;
+    let delimiter = format!("--{}", boundary).into_bytes();
+
+    let mut parts: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut rest = match find(body, &delimiter) {
+        Some(i) => &body[i + delimiter.len()..],
+        None => return Err("The multipart/form-data request has no parts".to_string()),
+    };
+    // Each part follows a line break after the delimiter, until the line break before the next delimiter, and the
+    // last delimiter is followed by two dashes
+    while !rest.starts_with(b"--") {
+        let part = rest.strip_prefix(b"\r\n").ok_or("The multipart/form-data request is malformed")?;
+        let end = find(part, &[b"\r\n".as_slice(), &delimiter].concat()).ok_or("The multipart/form-data request is not complete")?;
+        let (headers, content) = match find(&part[..end], b"\r\n\r\n") {
+            Some(i) => (&part[..i], &part[i + 4..end]),
+            None => return Err("A part of the multipart/form-data request has no headers".to_string()),
+        };
+        let headers = String::from_utf8_lossy(headers);
+        let name = headers
+            .lines()
+            .filter(|line| line.to_lowercase().starts_with("content-disposition:"))
+            .flat_map(|line| line.split(';').skip(1))
+            .filter_map(|p| p.trim().split_once('='))
+            .find(|(name, _)| name.eq_ignore_ascii_case("name"))
+            .map(|(_, value)| value.trim_matches('"').to_string())
+            .ok_or("A part of the multipart/form-data request has no name")?;
+        parts.push((name, content.to_vec()));
+        rest = &part[end + 2 + delimiter.len()..];
+    }
+    Ok(parts)
+}
+
+/// Answer a request, given its method, path (without the query), Content-Type header, and body.
+pub fn respond(method: &str, path: &str, content_type: Option<&str>, body: &[u8]) -> Response {
+    if path != CROSSTAB_PATH {
+        return Response::error(404, &format!("There is nothing at {}; crosstabs are built at {}", path, CROSSTAB_PATH));
+    }
+    if !method.eq_ignore_ascii_case("POST") {
+        return Response::error(405, &format!("Crosstabs are built with POST requests, not {}", method));
+    }
+    let parts = match form_parts(content_type.unwrap_or("none"), body) {
+        Ok(parts) => parts,
+        Err(message) => return Response::error(400, &message),
+    };
+    let part = |name: &str| parts.iter().find(|(n, _)| n == name).map(|(_, content)| content.as_slice());
+    let (Some(data), Some(config)) = (part("data"), part("config")) else {
+        return Response::error(400, "The request must have a data part with the input and a config part with the settings");
+    };
+    let Ok(config) = std::str::from_utf8(config) else {
+        return Response::error(400, "The config part must be UTF-8 text");
+    };
+    match crosstab_json(data, config) {
+        Ok(output) => {
+            // The settings are valid, since the crosstab was built with them
+            let settings: serde_json::Value = serde_json::from_str(config).unwrap_or_default();
+            let format = ["output_format", "output-format"]
+                .iter()
+                .find_map(|key| settings.get(key).and_then(|v| v.as_str()))
+                .and_then(|f| f.parse().ok())
+                .unwrap_or(OutputFormat::Csv);
+            Response { status: 200, content_type: media_type(format), body: output }
+        }
+        Err(e) => Response::error(422, &format!("{:#}", e)),
+    }
+}
+
+// Read a request and send its response, refusing a body that is larger than the limit
+fn handle(mut request: Request, max_upload: usize) {
+    let content_type: Option<String> =
+        request.headers().iter().find(|h| h.field.equiv("Content-Type")).map(|h| h.value.as_str().to_string());
+    let path = request.url().split('?').next().unwrap_or_default().to_string();
+    let method = request.method().as_str().to_string();
+    let mut body: Vec<u8> = Vec::new();
+    let response = match request.as_reader().take(max_upload as u64 + 1).read_to_end(&mut body) {
+        Err(e) => Response::error(400, &format!("The request cannot be read: {}", e)),
+        Ok(n) if n > max_upload => Response::error(413, &format!("The request is larger than {} bytes", max_upload)),
+        Ok(_) => respond(&method, &path, content_type.as_deref(), &body),
+    };
+    info!("{} {} {} ({} bytes in, {} bytes out)", method, path, response.status, body.len(), response.body.len());
+    let header = Header::from_bytes("Content-Type", response.content_type).expect("The media types are valid headers");
+    let http_response = tiny_http::Response::from_data(response.body).with_status_code(response.status).with_header(header);
+    if let Err(e) = request.respond(http_response) {
+        info!("The response could not be sent: {}", e);
+    }
+}
+
+/// Listen for requests at the address (such as `127.0.0.1:8080`, or port 0 for any free port), calling `listening`
+/// with the address once the server is listening, and answer each request on a thread of its own until the process
+/// ends. Request bodies larger than `max_upload` bytes are refused.
+pub fn serve<F: FnOnce(SocketAddr)>(address: &str, max_upload: usize, listening: F) -> Result<()> {
+    let server = Server::http(address).map_err(|e| anyhow!("The server cannot listen at {}: {}", address, e))?;
+    let local = server.server_addr().to_ip().ok_or_else(|| anyhow!("The server is not listening at an IP address"))?;
+    listening(local);
+    for request in server.incoming_requests() {
+        std::thread::spawn(move || handle(request, max_upload));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A multipart/form-data body with the parts
+    fn form(parts: &[(&str, &str)]) -> Vec<u8> {
+        let mut body = String::new();
+        for (name, content) in parts {
+            body.push_str(&format!("--XTAB\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n", name, content));
+        }
+        body.push_str("--XTAB--\r\n");
+        body.into_bytes()
+    }
+
+    // Test that the parts of a form are read, and that the crosstab or an error is the response
+    #[test]
+    fn test_respond() {
+        let form_type = "multipart/form-data; boundary=XTAB";
+        let content_type = Some(form_type);
+        let data = "site,date,result\nA01,d1,1.5\nB02,d1,3\n";
+        let body = form(&[("config", r#"{"row": "site", "col": "date", "value": "result", "output_format": "md"}"#), ("data", data)]);
+        assert_eq!(form_parts(form_type, &body).unwrap()[1], ("data".to_string(), data.as_bytes().to_vec()));
+        let response = respond("POST", CROSSTAB_PATH, content_type, &body);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.content_type, "text/markdown; charset=utf-8");
+        assert!(String::from_utf8(response.body).unwrap().starts_with("| site | d1_result |"));
+
+        let body = form(&[("config", r#"{"row": "site", "col": "day", "value": "result"}"#), ("data", data)]);
+        let response = respond("POST", CROSSTAB_PATH, content_type, &body);
+        assert_eq!(response.status, 422);
+        assert!(String::from_utf8(response.body).unwrap().contains("day"));
+        assert_eq!(respond("POST", CROSSTAB_PATH, content_type, &form(&[("data", data)])).status, 400);
+        assert_eq!(respond("POST", CROSSTAB_PATH, Some("text/csv"), data.as_bytes()).status, 400);
+        assert_eq!(respond("GET", CROSSTAB_PATH, None, b"").status, 405);
+        assert_eq!(respond("POST", "/", content_type, &body).status, 404);
+    }
+}
@@ -0,0 +1,195 @@
+// Functions used to order the output rows by their row header values, and the generated columns by their column header values.
+
+use crate::Key;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// The order of the values of one row header column.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortOrder {
+    /// Ascending, comparing the values as text.
+    Asc,
+    /// Descending, comparing the values as text.
+    Desc,
+    /// Ascending, comparing runs of digits as numbers so that `S2` comes before `S10`.
+    Natural,
+    /// Descending, comparing runs of digits as numbers.
+    NaturalDesc,
+    /// In the order in which the values first appear in the input.
+    Appearance,
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "asc" => Ok(SortOrder::Asc),
+            "desc" => Ok(SortOrder::Desc),
+            "natural" => Ok(SortOrder::Natural),
+            "natural-desc" => Ok(SortOrder::NaturalDesc),
+            "appearance" => Ok(SortOrder::Appearance),
+            _ => Err(format!("Unknown sort order '{}'. Must be one of asc, desc, natural, natural-desc, appearance", s)),
+        }
+    }
+}
+
+/// The order of one row header column, with precedence over the columns after it in a list of sort keys.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortKey {
+    pub column: String,
+    pub order: SortOrder,
+}
+
+/// Parse the `--sort-rows` specifications: `column:order` pairs (e.g. `site:asc`), or a bare column name for
+/// ascending order.
+pub fn parse_sort(specs: &[String]) -> Result<Vec<SortKey>, String> {
+    specs
+        .iter()
+        .map(|spec| {
+            let (column, order) = match spec.rsplit_once(':') {
+                Some((column, order)) => (column, order.parse()?),
+                None => (spec.as_str(), SortOrder::Asc),
+            };
+            Ok(SortKey { column: column.trim().to_string(), order })
+        })
+        .collect()
+}
+
+// Split a value into alternating runs of digits and of other characters
+fn runs(s: &str) -> Vec<&str> {
+    let mut runs: Vec<&str> = Vec::new();
+    let mut start = 0;
+    for (i, c) in s.char_indices().skip(1) {
+        let previous = s[..i].chars().next_back().unwrap();
+        if c.is_ascii_digit() != previous.is_ascii_digit() {
+            runs.push(&s[start..i]);
+            start = i;
+        }
+    }
+    if !s.is_empty() {
+        runs.push(&s[start..]);
+    }
+    runs
+}
+
+/// Compare two values in natural order: runs of digits are compared as numbers and other text is compared as text.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    for (x, y) in runs(a).iter().zip(runs(b).iter()) {
+        let digits = |s: &str| s.starts_with(|c: char| c.is_ascii_digit());
+        let ordering = if digits(x) && digits(y) {
+            let (tx, ty) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+            tx.len().cmp(&ty.len()).then_with(|| tx.cmp(ty))
+        } else {
+            x.cmp(y)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    runs(a).len().cmp(&runs(b).len()).then_with(|| a.cmp(b))
+}
+
+/// Sort keys by the sort keys, in order of precedence, then by the remaining header columns in ascending order.
+/// The input keys, in input order, determine the appearance order. Values listed in the explicit order come before
+/// all other values of their column, in the listed order, whatever the sort order of the column.
+pub fn sort_keys(keys: &mut [Key], headers: &[String], sort: &[SortKey], input_keys: &[Key], explicit: &[String]) -> Result<(), String> {
+    sort_keys_ranked(keys, headers, sort, input_keys, explicit, &[])
+}
+
+/// Sort keys like [`sort_keys`], except that the values of each header column with ranks, such as formatted dates
+/// ranked in chronological order, are compared by rank in the ascending, descending, and natural orders.
+pub fn sort_keys_ranked(
+    keys: &mut [Key],
+    headers: &[String],
+    sort: &[SortKey],
+    input_keys: &[Key],
+    explicit: &[String],
+    ranks: &[Option<HashMap<String, usize>>],
+) -> Result<(), String> {
+    let mut columns: Vec<(usize, SortOrder)> = Vec::new();
+    for key in sort {
+        match headers.iter().position(|h| h == &key.column) {
+            Some(i) => columns.push((i, key.order)),
+            None => return Err(format!("The sorted column '{}' is not one of the header columns", key.column)),
+        }
+    }
+    columns.extend((0..headers.len()).filter(|i| !sort.iter().any(|k| k.column == headers[*i])).map(|i| (i, SortOrder::Asc)));
+
+    // The position of the first appearance of each value of each column, and of each explicitly ordered value
+    let mut appearance: Vec<HashMap<&str, usize>> = vec![HashMap::new(); headers.len()];
+    if sort.iter().any(|k| k.order == SortOrder::Appearance) {
+        for key in input_keys {
+            for (i, value) in key.iter().enumerate() {
+                let next = appearance[i].len();
+                appearance[i].entry(value).or_insert(next);
+            }
+        }
+    }
+    let listed: HashMap<&str, usize> = explicit.iter().enumerate().map(|(i, v)| (v.as_str(), i)).collect();
+
+    keys.sort_by(|a, b| {
+        columns
+            .iter()
+            .map(|&(i, order)| {
+                let rank = |k: &Key| listed.get(k[i].as_str()).copied().unwrap_or(usize::MAX);
+                let ranked = ranks.get(i).and_then(|r| r.as_ref());
+                rank(a).cmp(&rank(b)).then_with(|| match (order, ranked) {
+                    (SortOrder::Asc | SortOrder::Natural, Some(r)) => r.get(&a[i]).cmp(&r.get(&b[i])),
+                    (SortOrder::Desc | SortOrder::NaturalDesc, Some(r)) => r.get(&b[i]).cmp(&r.get(&a[i])),
+                    (SortOrder::Asc, None) => a[i].cmp(&b[i]),
+                    (SortOrder::Desc, None) => b[i].cmp(&a[i]),
+                    (SortOrder::Natural, None) => natural_cmp(&a[i], &b[i]),
+                    (SortOrder::NaturalDesc, None) => natural_cmp(&b[i], &a[i]),
+                    (SortOrder::Appearance, _) => appearance[i].get(a[i].as_str()).cmp(&appearance[i].get(b[i].as_str())),
+                })
+            })
+            .find(|o| o != &Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that runs of digits are compared as numbers
+    #[test]
+    fn test_natural_cmp() {
+        let mut ids = vec!["S10", "S2", "S1a", "S01", "T1", "S"];
+        ids.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(ids, vec!["S", "S01", "S1a", "S2", "S10", "T1"]);
+    }
+
+    // Test that the sort keys take precedence, in order, over the remaining row header columns
+    #[test]
+    fn test_sort_keys() {
+        let key = |site: &str, date: &str| vec![site.to_string(), date.to_string()];
+        let headers = vec!["site".to_string(), "date".to_string()];
+        let mut keys = vec![key("S10", "d1"), key("S2", "d1"), key("S2", "d2")];
+        let sort = parse_sort(&["date:desc".to_string(), "site:natural".to_string()]).unwrap();
+        sort_keys(&mut keys, &headers, &sort, &[], &[]).unwrap();
+        assert_eq!(keys, vec![key("S2", "d2"), key("S2", "d1"), key("S10", "d1")]);
+        assert!(sort_keys(&mut keys, &headers, &parse_sort(&["depth".to_string()]).unwrap(), &[], &[]).is_err());
+        assert!(parse_sort(&["site:up".to_string()]).is_err());
+    }
+
+    // Test the appearance order and an explicit order of values
+    #[test]
+    fn test_sort_keys_appearance_and_explicit() {
+        let key = |month: &str| vec![month.to_string()];
+        let headers = vec!["month".to_string()];
+        let input = vec![key("Mar"), key("Jan"), key("Mar"), key("Feb")];
+        let sort = vec![SortKey { column: "month".to_string(), order: SortOrder::Appearance }];
+
+        let mut keys = vec![key("Feb"), key("Jan"), key("Mar")];
+        sort_keys(&mut keys, &headers, &sort, &input, &[]).unwrap();
+        assert_eq!(keys, vec![key("Mar"), key("Jan"), key("Feb")]);
+
+        let explicit = vec!["Jan".to_string(), "Feb".to_string()];
+        sort_keys(&mut keys, &headers, &sort, &input, &explicit).unwrap();
+        assert_eq!(keys, vec![key("Jan"), key("Feb"), key("Mar")]);
+    }
+}
@@ -0,0 +1,241 @@
+//! Databases as sources of normalized input data, read with a query into a DataFrame,
+//! and as destinations for a crosstab, written as a table.
+
+// Without any of the database features, as for WebAssembly, every URI is refused and the helpers are unused
+#![cfg_attr(not(any(feature = "native", feature = "postgres", feature = "mysql")), allow(dead_code, unused_variables))]
+
+#[cfg(feature = "mysql")]
+pub mod mysql;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "native")]
+pub mod sqlite;
+
+use anyhow::{bail, Result};
+use polars::prelude::*;
+use std::str::FromStr;
+
+/// A single value of a database result set.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum SqlValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+}
+
+// Convert one result set column to a Series, using the narrowest type that holds every non-null value.
+fn values_to_series(name: &str, values: &[SqlValue]) -> Series {
+    let present = || values.iter().filter(|v| !matches!(v, SqlValue::Null));
+    if present().all(|v| matches!(v, SqlValue::Integer(_))) {
+        let values: Vec<Option<i64>> = values.iter().map(|v| match v { SqlValue::Integer(i) => Some(*i), _ => None }).collect();
+        Series::new(name, values)
+    } else if present().all(|v| matches!(v, SqlValue::Integer(_) | SqlValue::Real(_))) {
+        let values: Vec<Option<f64>> = values.iter().map(|v| match v {
+            SqlValue::Integer(i) => Some(*i as f64),
+            SqlValue::Real(f) => Some(*f),
+            _ => None,
+        }).collect();
+        Series::new(name, values)
+    } else {
+        let values: Vec<Option<String>> = values.iter().map(|v| match v {
+            SqlValue::Null => None,
+            SqlValue::Integer(i) => Some(i.to_string()),
+            SqlValue::Real(f) => Some(f.to_string()),
+            SqlValue::Text(s) => Some(s.clone()),
+        }).collect();
+        Series::new(name, values)
+    }
+}
+
+/// Convert the rows of a result set, with the given column names, to a DataFrame.
+pub(crate) fn rows_to_dataframe(names: &[String], rows: &[Vec<SqlValue>]) -> PolarsResult<DataFrame> {
+    let columns = names
+        .iter()
+        .enumerate()
+        .map(|(j, name)| {
+            let values: Vec<SqlValue> = rows.iter().map(|row| row[j].clone()).collect();
+            values_to_series(name, &values)
+        })
+        .collect();
+    DataFrame::new(columns)
+}
+
+/// What to do when writing a table to a database.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TableMode {
+    /// Create a new table. It is an error if the table already exists.
+    Create,
+    /// Drop the table if it already exists, then create it.
+    Replace,
+    /// Append the rows to the table, creating it if it does not exist.
+    Append,
+}
+
+impl FromStr for TableMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "create" => Ok(TableMode::Create),
+            "replace" => Ok(TableMode::Replace),
+            "append" => Ok(TableMode::Append),
+            _ => Err(format!("Unknown table mode '{}'. Must be one of create, replace, append", s)),
+        }
+    }
+}
+
+// How identifiers, column types, and parameters are written in each database's SQL
+pub(crate) struct Dialect {
+    pub quote: char,
+    pub real_type: &'static str,
+    pub placeholder: fn(usize) -> String,
+}
+
+impl Dialect {
+    fn quote(&self, name: &str) -> String {
+        let q = self.quote.to_string();
+        format!("{}{}{}", q, name.replace(&q, &q.repeat(2)), q)
+    }
+}
+
+/// The statements that prepare a table for the rows of a DataFrame, and the statement that inserts one row.
+/// Float64 columns become real columns and all other columns become text columns.
+pub(crate) fn table_statements(df: &DataFrame, table: &str, mode: TableMode, dialect: &Dialect) -> (Vec<String>, String) {
+    let table = dialect.quote(table);
+    let columns: Vec<String> = df
+        .get_columns()
+        .iter()
+        .map(|s| {
+            let sql_type = if s.dtype() == &DataType::Float64 { dialect.real_type } else { "TEXT" };
+            format!("{} {}", dialect.quote(s.name()), sql_type)
+        })
+        .collect();
+    let create = |if_not_exists: &str| format!("CREATE TABLE {}{} ({})", if_not_exists, table, columns.join(", "));
+    let prepare = match mode {
+        TableMode::Create => vec![create("")],
+        TableMode::Replace => vec![format!("DROP TABLE IF EXISTS {}", table), create("")],
+        TableMode::Append => vec![create("IF NOT EXISTS ")],
+    };
+    let names: Vec<String> = df.get_column_names().iter().map(|n| dialect.quote(n)).collect();
+    let placeholders: Vec<String> = (1..=names.len()).map(dialect.placeholder).collect();
+    let insert = format!("INSERT INTO {} ({}) VALUES ({})", table, names.join(", "), placeholders.join(", "));
+    (prepare, insert)
+}
+
+/// The rows of a DataFrame as values to insert into a database. Float64 columns become reals and all
+/// other columns are cast to text.
+pub(crate) fn dataframe_rows(df: &DataFrame) -> PolarsResult<Vec<Vec<SqlValue>>> {
+    let mut rows: Vec<Vec<SqlValue>> = vec![Vec::with_capacity(df.width()); df.height()];
+    for series in df.get_columns() {
+        if series.dtype() == &DataType::Float64 {
+            for (row, value) in rows.iter_mut().zip(series.f64()?) {
+                row.push(value.map(SqlValue::Real).unwrap_or(SqlValue::Null));
+            }
+        } else {
+            let strings = series.cast(&DataType::String)?;
+            for (row, value) in rows.iter_mut().zip(strings.str()?) {
+                row.push(value.map(|v| SqlValue::Text(v.to_string())).unwrap_or(SqlValue::Null));
+            }
+        }
+    }
+    Ok(rows)
+}
+
+// The lowercase scheme of a connection URI, such as postgresql in postgresql://host/db
+fn scheme(url: &str) -> String {
+    url.split_once("://").map(|(scheme, _)| scheme.to_lowercase()).unwrap_or_default()
+}
+
+/// The query that selects every row and column of a table.
+pub fn table_query(table: &str) -> String {
+    format!("SELECT * FROM \"{}\"", table.replace('"', "\"\""))
+}
+
+/// Read the result of a query into a DataFrame from the database at a connection URI. The scheme of the URI
+/// selects the database: `postgres://` or `postgresql://` for PostgreSQL, `mysql://` or `mariadb://` for MySQL
+/// and MariaDB, and `sqlite://` followed by a file path for SQLite.
+pub fn read_database(url: &str, query: &str) -> Result<DataFrame> {
+    let scheme = scheme(url);
+    match scheme.as_str() {
+        #[cfg(feature = "postgres")]
+        "postgres" | "postgresql" => postgres::read_postgres(url, query),
+        #[cfg(feature = "mysql")]
+        "mysql" => mysql::read_mysql(url, query),
+        #[cfg(feature = "mysql")]
+        "mariadb" => mysql::read_mysql(&format!("mysql{}", &url["mariadb".len()..]), query),
+        #[cfg(feature = "native")]
+        "sqlite" => sqlite::read_sqlite(&url["sqlite://".len()..], query),
+        // Only reachable when xtab is built without one of the database features
+        #[allow(unreachable_patterns)]
+        "postgres" | "postgresql" | "mysql" | "mariadb" | "sqlite" => {
+            bail!("xtab was built without support for {} databases: {}", scheme, url)
+        }
+        _ => bail!("The database URL must start with postgresql://, mysql://, mariadb://, or sqlite://: {}", url),
+    }
+}
+
+/// Write a DataFrame as a table of the database at a connection URI, with the same URI schemes as [`read_database`].
+pub fn write_database(url: &str, table: &str, mode: TableMode, df: &DataFrame) -> Result<()> {
+    let scheme = scheme(url);
+    match scheme.as_str() {
+        #[cfg(feature = "postgres")]
+        "postgres" | "postgresql" => postgres::write_postgres(url, table, mode, df),
+        #[cfg(feature = "mysql")]
+        "mysql" => mysql::write_mysql(url, table, mode, df),
+        #[cfg(feature = "mysql")]
+        "mariadb" => mysql::write_mysql(&format!("mysql{}", &url["mariadb".len()..]), table, mode, df),
+        #[cfg(feature = "native")]
+        "sqlite" => sqlite::write_sqlite(&url["sqlite://".len()..], table, mode, df),
+        // Only reachable when xtab is built without one of the database features
+        #[allow(unreachable_patterns)]
+        "postgres" | "postgresql" | "mysql" | "mariadb" | "sqlite" => {
+            bail!("xtab was built without support for {} databases: {}", scheme, url)
+        }
+        _ => bail!("The database URL must start with postgresql://, mysql://, mariadb://, or sqlite://: {}", url),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that each result set column gets the narrowest type that holds its values
+    #[test]
+    fn test_rows_to_dataframe() {
+        let names = vec!["count".to_string(), "result".to_string(), "site".to_string()];
+        let rows = vec![
+            vec![SqlValue::Integer(1), SqlValue::Integer(1), SqlValue::Text("A01".to_string())],
+            vec![SqlValue::Null, SqlValue::Real(2.5), SqlValue::Integer(2)],
+        ];
+        let df = rows_to_dataframe(&names, &rows).unwrap();
+        assert_eq!(df.column("count").unwrap().dtype(), &DataType::Int64);
+        assert_eq!(df.column("result").unwrap().dtype(), &DataType::Float64);
+        assert_eq!(df.column("site").unwrap().dtype(), &DataType::String);
+    }
+
+    // Test the statements that create a table for a DataFrame in each table mode
+    #[test]
+    fn test_table_statements() {
+        let df = df!("site" => &["A01"], "2024-01-01_result" => &[1.5]).unwrap();
+        let dialect = Dialect { quote: '"', real_type: "REAL", placeholder: |_| "?".to_string() };
+        let (prepare, insert) = table_statements(&df, "xtab", TableMode::Replace, &dialect);
+        assert_eq!(
+            prepare,
+            vec![
+                "DROP TABLE IF EXISTS \"xtab\"".to_string(),
+                "CREATE TABLE \"xtab\" (\"site\" TEXT, \"2024-01-01_result\" REAL)".to_string(),
+            ]
+        );
+        assert_eq!(insert, "INSERT INTO \"xtab\" (\"site\", \"2024-01-01_result\") VALUES (?, ?)");
+        let (prepare, _) = table_statements(&df, "xtab", TableMode::Append, &dialect);
+        assert!(prepare[0].starts_with("CREATE TABLE IF NOT EXISTS"));
+    }
+
+    // Test that a database URL with an unknown scheme is refused
+    #[test]
+    fn test_read_database_scheme() {
+        let error = read_database("oracle://host/db", "SELECT 1").unwrap_err();
+        assert!(error.to_string().starts_with("The database URL must start with"));
+    }
+}
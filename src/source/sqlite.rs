@@ -0,0 +1,101 @@
+// Reading the result of a query of a SQLite database into a DataFrame, and writing a DataFrame as a table.
+
+pub use super::table_query;
+use super::{dataframe_rows, rows_to_dataframe, table_statements, Dialect, SqlValue, TableMode};
+use anyhow::Result;
+use polars::prelude::*;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{Connection, OpenFlags};
+use std::path::Path;
+
+/// Read the result of a query of a SQLite database file into a DataFrame. The database is opened read-only.
+pub fn read_sqlite<P: AsRef<Path>>(path: P, query: &str) -> Result<DataFrame> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut statement = connection.prepare(query)?;
+    let names: Vec<String> = statement.column_names().iter().map(|s| s.to_string()).collect();
+
+    let mut rows: Vec<Vec<SqlValue>> = Vec::new();
+    let mut result = statement.query([])?;
+    while let Some(row) = result.next()? {
+        let values = (0..names.len())
+            .map(|i| {
+                Ok(match row.get_ref(i)? {
+                    ValueRef::Null => SqlValue::Null,
+                    ValueRef::Integer(i) => SqlValue::Integer(i),
+                    ValueRef::Real(f) => SqlValue::Real(f),
+                    ValueRef::Text(t) | ValueRef::Blob(t) => SqlValue::Text(String::from_utf8_lossy(t).to_string()),
+                })
+            })
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.push(values);
+    }
+    Ok(rows_to_dataframe(&names, &rows)?)
+}
+
+/// Write a DataFrame as a table of a SQLite database file, creating the file if it does not exist.
+pub fn write_sqlite<P: AsRef<Path>>(path: P, table: &str, mode: TableMode, df: &DataFrame) -> Result<()> {
+    let dialect = Dialect { quote: '"', real_type: "REAL", placeholder: |_| "?".to_string() };
+    let (prepare, insert) = table_statements(df, table, mode, &dialect);
+    let mut connection = Connection::open(path)?;
+    let transaction = connection.transaction()?;
+    for statement in &prepare {
+        transaction.execute(statement, [])?;
+    }
+    {
+        let mut statement = transaction.prepare(&insert)?;
+        for row in dataframe_rows(df)? {
+            let values = row.into_iter().map(|v| match v {
+                SqlValue::Null => Value::Null,
+                SqlValue::Integer(i) => Value::Integer(i),
+                SqlValue::Real(f) => Value::Real(f),
+                SqlValue::Text(s) => Value::Text(s),
+            });
+            statement.execute(rusqlite::params_from_iter(values))?;
+        }
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that a table and a query of a SQLite database are read into DataFrames
+    #[test]
+    fn test_read_sqlite() {
+        let path = std::env::temp_dir().join(format!("xtab_{}_read_sqlite.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let connection = Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE \"lab results\" (site TEXT, date TEXT, result REAL);
+                 INSERT INTO \"lab results\" VALUES ('A01', '2024-01-01', 1.5), ('B02', '2024-01-01', NULL);",
+            )
+            .unwrap();
+
+        let df = read_sqlite(&path, &table_query("lab results")).unwrap();
+        assert_eq!(df.shape(), (2, 3));
+        assert_eq!(df.column("result").unwrap().dtype(), &DataType::Float64);
+
+        let df = read_sqlite(&path, "SELECT site FROM \"lab results\" WHERE result IS NOT NULL").unwrap();
+        assert_eq!(df.shape(), (1, 1));
+    }
+
+    // Test that a table is created, refused when it already exists, appended to, and replaced
+    #[test]
+    fn test_write_sqlite() {
+        let path = std::env::temp_dir().join(format!("xtab_{}_write_sqlite.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let df = df!("site" => &["A01", "B02"], "2024-01-01_result" => &[Some(1.5), None]).unwrap();
+        let count = || read_sqlite(&path, "SELECT * FROM xtab").unwrap().height();
+
+        write_sqlite(&path, "xtab", TableMode::Create, &df).unwrap();
+        assert_eq!(count(), 2);
+        assert!(write_sqlite(&path, "xtab", TableMode::Create, &df).is_err());
+        write_sqlite(&path, "xtab", TableMode::Append, &df).unwrap();
+        assert_eq!(count(), 4);
+        write_sqlite(&path, "xtab", TableMode::Replace, &df).unwrap();
+        assert_eq!(read_sqlite(&path, "SELECT * FROM xtab").unwrap(), df);
+    }
+}
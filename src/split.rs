@@ -0,0 +1,42 @@
+//! Splitting of the input data into groups by the values of a column, so that each group is crosstabbed on its own,
+//! such as one crosstab for each analyte on its own worksheet.
+
+use crate::columns::describe_missing;
+use crate::XtabError;
+use anyhow::Result;
+use polars::prelude::*;
+use std::collections::BTreeMap;
+
+/// The input rows of each distinct value of the column, in ascending order of the values, found in a single pass over
+/// the column. The rows of each group keep their input order, and missing values are grouped as the null label.
+pub fn split_groups(df: &DataFrame, column: &str, null_label: &str) -> Result<Vec<(String, DataFrame)>> {
+    let Ok(series) = df.column(column) else {
+        let names: Vec<&str> = df.get_column_names();
+        return Err(XtabError::MissingColumns(vec![describe_missing(&names, "grouping", column)]).into());
+    };
+    let values = series.cast(&DataType::String)?;
+    let mut rows: BTreeMap<String, Vec<IdxSize>> = BTreeMap::new();
+    for (i, value) in values.str()?.into_iter().enumerate() {
+        rows.entry(value.unwrap_or(null_label).to_string()).or_default().push(i as IdxSize);
+    }
+    rows.into_iter()
+        .map(|(value, indices)| Ok((value, df.take(&IdxCa::from_vec("", indices))?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that the rows are grouped by the values of the column, in order, with the missing values as the null label
+    #[test]
+    fn test_split_groups() {
+        let df = df!("analyte" => &[Some("Zn"), Some("As"), None, Some("Zn")], "result" => &[1, 2, 3, 4]).unwrap();
+        let groups = split_groups(&df, "analyte", "<NULL>").unwrap();
+        let values: Vec<&str> = groups.iter().map(|(value, _)| value.as_str()).collect();
+        assert_eq!(values, vec!["<NULL>", "As", "Zn"]);
+        let results: Vec<Option<i32>> = groups[2].1.column("result").unwrap().i32().unwrap().into_iter().collect();
+        assert_eq!(results, vec![Some(1), Some(4)]);
+        assert!(split_groups(&df, "analyt", "").is_err());
+    }
+}
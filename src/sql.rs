@@ -0,0 +1,75 @@
+//! SQL queries over the input data, and columns derived from SQL expressions, run with the polars SQL engine before
+//! the crosstab is built.
+
+use anyhow::Result;
+use polars::prelude::*;
+use polars::sql::{sql_expr, SQLContext};
+
+/// The name of the table that holds the input data in a query.
+pub const INPUT_TABLE: &str = "input";
+
+/// Run a SQL query, such as `SELECT site, date, result FROM input WHERE qc_flag IS NULL`, over the DataFrame,
+/// which is the table named [`INPUT_TABLE`], and return the result.
+pub fn sql_query(df: DataFrame, query: &str) -> Result<DataFrame> {
+    Ok(sql_lazy(df.lazy(), query)?.collect()?)
+}
+
+/// Plan a SQL query over a LazyFrame, which is the table named [`INPUT_TABLE`], without running it.
+pub fn sql_lazy(lf: LazyFrame, query: &str) -> Result<LazyFrame> {
+    let mut context = SQLContext::new();
+    context.register(INPUT_TABLE, lf);
+    Ok(context.execute(query)?)
+}
+
+/// Parse the `--derive` specifications, each a `name=expression` pair such as `load=flow * concentration`, where
+/// the expression is a SQL expression over the input columns.
+pub fn parse_derivations(specs: &[String]) -> Result<Vec<(String, Expr)>, String> {
+    specs
+        .iter()
+        .map(|spec| match spec.split_once('=') {
+            Some((name, expression)) if !name.trim().is_empty() => {
+                let expr = sql_expr(expression).map_err(|e| format!("The expression of the derived column '{}' is invalid: {}", name.trim(), e))?;
+                Ok((name.trim().to_string(), expr))
+            }
+            _ => Err(format!("The derived column '{}' must be a name=expression pair, e.g. load=flow*concentration", spec)),
+        })
+        .collect()
+}
+
+/// Plan the derivation of each of the columns from its expression, in order, so that an expression can use the
+/// columns derived before it. A derived column with the name of an input column replaces it.
+pub fn derive_lazy(lf: LazyFrame, derivations: &[(String, Expr)]) -> LazyFrame {
+    derivations.iter().fold(lf, |lf, (name, expr)| lf.with_column(expr.clone().alias(name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that a query can filter, rename, and derive columns of the input
+    #[test]
+    fn test_sql_query() {
+        let df = df!(
+            "site" => &["A01", "B02", "C03"],
+            "result" => &[1.5, 3.1, 4.0],
+            "qc_flag" => &[None, Some("J"), None],
+        )
+        .unwrap();
+        let output = sql_query(df, "SELECT site AS station, result * 1000 AS result_ug FROM input WHERE qc_flag IS NULL").unwrap();
+        assert_eq!(output.get_column_names(), &["station", "result_ug"]);
+        assert_eq!(output.height(), 2);
+        assert_eq!(output.column("result_ug").unwrap().f64().unwrap().get(1), Some(4000.0));
+    }
+
+    // Test that derived columns are computed in order, and that invalid specifications are errors
+    #[test]
+    fn test_derive_lazy() {
+        let df = df!("flow" => &[2.0, 0.5], "concentration" => &[1.5, 4.0]).unwrap();
+        let specs = vec!["load = flow * concentration".to_string(), "load_kg=load / 1000".to_string()];
+        let derived = derive_lazy(df.lazy(), &parse_derivations(&specs).unwrap()).collect().unwrap();
+        assert_eq!(derived.column("load").unwrap().f64().unwrap().get(0), Some(3.0));
+        assert_eq!(derived.column("load_kg").unwrap().f64().unwrap().get(1), Some(0.002));
+        assert!(parse_derivations(&["flow * concentration".to_string()]).is_err());
+        assert!(parse_derivations(&["load=flow *".to_string()]).is_err());
+    }
+}
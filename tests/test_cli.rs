@@ -1,42 +1,1684 @@
-use clap::Parser;
+use std::fs;
 use std::path::PathBuf;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
 
+// Write the given contents to a uniquely named file in the system temporary directory and return its path
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("xtab_{}_{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+// Run the xtab binary with the given arguments
+fn run_xtab(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_xtab"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+// Crosstab the given input data with the given extra arguments and return the contents of the output file
+fn crosstab(name: &str, contents: &str, args: &[&str]) -> String {
+    crosstab_file(&format!("{}_in.csv", name), contents, args)
+}
+
+// Like `crosstab`, but with the name (and so the extension) of the input file given in full
+fn crosstab_file(name: &str, contents: &str, args: &[&str]) -> String {
+    let infile = temp_file(name, contents);
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_{}_out.csv", std::process::id(), name));
+    let mut all_args = vec!["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap()];
+    all_args.extend_from_slice(args);
+    let output = run_xtab(&all_args);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    fs::read_to_string(&outfile).unwrap()
+}
+
+const NORMALIZED: &str = "\
+site,date,result,unit
+B02,2024-01-02,3.1,mg/L
+A01,2024-01-01,1.5,mg/L
+A01,2024-01-02,2.5,mg/L
+B02,2024-01-01,4.0,mg/L
+";
+
+// Test that the crosstab writes a header row and one data row for every unique row key
+#[test]
+fn test_crosstab_format_1() {
+    let output = crosstab(
+        "format_1",
+        NORMALIZED,
+        &["-r", "site", "-c", "date", "-v", "result,unit"],
+    );
+    let expected = "\
+site,2024-01-01_result,2024-01-01_unit,2024-01-02_result,2024-01-02_unit
+A01,1.5,mg/L,2.5,mg/L
+B02,4.0,mg/L,3.1,mg/L
+";
+    assert_eq!(output, expected);
+}
 
-// Test parsing of the program argument parser using the `clap` library and the Args struct defined in src/main.rs
+// Test that missing row/column combinations produce empty cells
 #[test]
-fn test_parse_args() {
+fn test_crosstab_missing_cells() {
+    let output = crosstab(
+        "missing",
+        "site,date,result\nA01,2024-01-01,1.5\nB02,2024-01-02,3.1\n",
+        &["-r", "site", "-c", "date", "-v", "result"],
+    );
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+A01,1.5,
+B02,,3.1
+";
+    assert_eq!(output, expected);
+}
+
+// Test that format 2 writes the column header values and the value column names on separate rows
+#[test]
+fn test_crosstab_format_2() {
+    let output = crosstab(
+        "format_2",
+        NORMALIZED,
+        &["-r", "site", "-c", "date", "-v", "result,unit", "-f", "2"],
+    );
+    let expected = "\
+,2024-01-01,2024-01-01,2024-01-02,2024-01-02
+site,result,unit,result,unit
+A01,1.5,mg/L,2.5,mg/L
+B02,4.0,mg/L,3.1,mg/L
+";
+    assert_eq!(output, expected);
+}
+
+// Test that multiple values for one output cell produce a warning and keep only the first value
+#[test]
+fn test_crosstab_duplicate_cells() {
+    let infile = temp_file(
+        "duplicates_in.csv",
+        "site,date,result\nA01,2024-01-01,1.5\nA01,2024-01-01,9.9\nB02,2024-01-01,3.1\n",
+    );
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_duplicates_out.csv", std::process::id()));
+    let output = run_xtab(&[
+        "-i",
+        infile.to_str().unwrap(),
+        "-o",
+        outfile.to_str().unwrap(),
+        "-r",
+        "site",
+        "-c",
+        "date",
+        "-v",
+        "result",
+    ]);
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Warning: Multiple values were found for 1 row/column combination(s)"));
+    assert!(stderr.contains(r#"rows ["site"] = ["A01"], columns ["date"] = ["2024-01-01"]"#));
+
+    let expected = "\
+site,2024-01-01_result
+A01,1.5
+B02,3.1
+";
+    assert_eq!(fs::read_to_string(&outfile).unwrap(), expected);
+}
 
+// Test that an aggregation combines multiple values per cell, with per-column overrides
+#[test]
+fn test_crosstab_aggregate() {
+    let output = crosstab(
+        "aggregate",
+        "site,date,result,unit\nA01,2024-01-01,1.5,mg/L\nA01,2024-01-01,2.5,ug/L\nB02,2024-01-01,3.1,mg/L\n",
+        &["-r", "site", "-c", "date", "-v", "result,unit", "--aggregate", "sum,unit=concat"],
+    );
+    let expected = "\
+site,2024-01-01_result,2024-01-01_unit
+A01,4,mg/L; ug/L
+B02,3.1,mg/L
+";
+    assert_eq!(output, expected);
+}
 
-    // Define the input arguments
-    let args = vec![
-        "test",
+// Test that the program exits with an error listing every column that is missing from the input file
+#[test]
+fn test_crosstab_missing_columns() {
+    let infile = temp_file("missing_columns_in.csv", NORMALIZED);
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_missing_columns_out.csv", std::process::id()));
+    let output = run_xtab(&[
         "-i",
-        "infile.csv",
+        infile.to_str().unwrap(),
         "-o",
-        "outfile.csv",
-        "--row",
-        "1,2,3",
-        "--col",
-        "1,2,3",
-        "--value",
-        "1,2,3",
-        "--format",
-        "1",
+        outfile.to_str().unwrap(),
+        "-r",
+        "station",
+        "-c",
+        "date",
+        "-v",
+        "result,qualifier",
+    ]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Error: The row header column 'station' is not in the input file"));
+    assert!(stderr.contains("Error: The value column 'qualifier' is not in the input file"));
+    assert!(!outfile.exists());
+}
+
+// Test that the crosstab can be read from standard input and written to standard output without diagnostics
+#[test]
+fn test_crosstab_stdin_stdout() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_xtab"))
+        .args(["-i", "-", "-r", "site", "-c", "date", "-v", "result"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(NORMALIZED.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+A01,1.5,2.5
+B02,4.0,3.1
+";
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+}
+
+// Test that tab-delimited input is detected from the extension and custom delimiters can be given
+#[test]
+fn test_crosstab_delimiters() {
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+A01,1.5,2.5
+B02,4.0,3.1
+";
+    let tsv = NORMALIZED.replace(',', "\t");
+    assert_eq!(crosstab_file("delimiter_in.tsv", &tsv, &["-r", "site", "-c", "date", "-v", "result"]), expected);
+
+    let psv = NORMALIZED.replace(',', "|");
+    assert_eq!(
+        crosstab("delimiter_pipe", &psv, &["-r", "site", "-c", "date", "-v", "result", "-d", "|"]),
+        expected
+    );
+}
+
+// Test that a .tsv output file is written tab-delimited and that the output delimiter can be overridden
+#[test]
+fn test_crosstab_output_delimiter() {
+    let infile = temp_file("output_delimiter_in.csv", NORMALIZED);
+    let expected = "\
+site\t2024-01-01_result\t2024-01-02_result
+A01\t1.5\t2.5
+B02\t4.0\t3.1
+";
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_output_delimiter_out.tsv", std::process::id()));
+    let args = ["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result"];
+    assert!(run_xtab(&args).status.success());
+    assert_eq!(fs::read_to_string(&outfile).unwrap(), expected);
+
+    let output = crosstab(
+        "output_delimiter_pipe",
+        NORMALIZED,
+        &["-r", "site", "-c", "date", "-v", "result", "--output-delimiter", "|"],
+    );
+    assert_eq!(output, expected.replace('\t', "|"));
+
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_output_delimiter_out.xyz", std::process::id()));
+    let args = ["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result"];
+    assert!(!run_xtab(&args).status.success());
+}
+
+// Test that an Excel workbook is read from the named worksheet
+#[test]
+fn test_crosstab_excel_input() {
+    let infile = std::env::temp_dir().join(format!("xtab_{}_excel_in.xlsx", std::process::id()));
+    let mut workbook = rust_xlsxwriter::Workbook::new();
+    workbook.add_worksheet().set_name("Notes").unwrap().write(0, 0, "Not the data").unwrap();
+    let worksheet = workbook.add_worksheet().set_name("Results").unwrap();
+    for (i, line) in NORMALIZED.lines().enumerate() {
+        for (j, field) in line.split(',').enumerate() {
+            match field.parse::<f64>() {
+                Ok(number) => worksheet.write(i as u32, j as u16, number).unwrap(),
+                Err(_) => worksheet.write(i as u32, j as u16, field).unwrap(),
+            };
+        }
+    }
+    workbook.save(&infile).unwrap();
+
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_excel_out.csv", std::process::id()));
+    let output = run_xtab(&[
+        "-i",
+        infile.to_str().unwrap(),
+        "-o",
+        outfile.to_str().unwrap(),
+        "--sheet",
+        "Results",
+        "-r",
+        "site",
+        "-c",
+        "date",
+        "-v",
+        "result",
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+A01,1.5,2.5
+B02,4.0,3.1
+";
+    assert_eq!(fs::read_to_string(&outfile).unwrap(), expected);
+}
+
+// Test that an Excel workbook is written with merged multi-row headers and numeric cells, and can be read back
+#[test]
+fn test_crosstab_excel_output() {
+    use calamine::{open_workbook, Data, Reader, Xlsx};
+
+    let infile = temp_file("excel_output_in.csv", NORMALIZED);
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_excel_output_out.xlsx", std::process::id()));
+    let output = run_xtab(&[
+        "-i",
+        infile.to_str().unwrap(),
+        "-o",
+        outfile.to_str().unwrap(),
+        "-r",
+        "site",
+        "-c",
+        "date",
+        "-v",
+        "result,unit",
+        "-f",
+        "2",
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let mut workbook: Xlsx<_> = open_workbook(&outfile).unwrap();
+    let range = workbook.worksheet_range_at(0).unwrap().unwrap();
+    assert_eq!(range.get_value((0, 1)), Some(&Data::String("2024-01-01".to_string())));
+    assert_eq!(range.get_value((1, 0)), Some(&Data::String("site".to_string())));
+    assert_eq!(range.get_value((2, 1)), Some(&Data::Float(1.5)));
+    assert_eq!(range.get_value((3, 4)), Some(&Data::String("mg/L".to_string())));
+    let merged = workbook.merge_cells_by_sheet_id(0).unwrap();
+    assert_eq!(merged.len(), 2);
+}
+
+// Test that a crosstab can be written as Parquet and that Parquet input can be crosstabbed
+#[test]
+fn test_crosstab_parquet() {
+    let infile = temp_file("parquet_in.csv", NORMALIZED);
+    let parquet = std::env::temp_dir().join(format!("xtab_{}_parquet_out.parquet", std::process::id()));
+    let output = run_xtab(&[
+        "-i",
+        infile.to_str().unwrap(),
+        "-o",
+        parquet.to_str().unwrap(),
+        "-r",
+        "date",
+        "-c",
+        "site",
+        "-v",
+        "result",
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // Crosstab the Parquet crosstab again, using its first generated column as the value column
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_parquet_out.csv", std::process::id()));
+    let output = run_xtab(&[
+        "-i",
+        parquet.to_str().unwrap(),
+        "-o",
+        outfile.to_str().unwrap(),
+        "-r",
+        "date",
+        "-c",
+        "A01_result",
+        "-v",
+        "B02_result",
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let expected = "\
+date,1.5_B02_result,2.5_B02_result
+2024-01-01,4.0,
+2024-01-02,,3.1
+";
+    assert_eq!(fs::read_to_string(&outfile).unwrap(), expected);
+}
+
+// Test that newline-delimited JSON can be crosstabbed from standard input
+#[test]
+fn test_crosstab_ndjson_input() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_xtab"))
+        .args(["--input-format", "ndjson", "-r", "site", "-c", "date", "-v", "result"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let ndjson = "\
+{\"site\":\"B02\",\"date\":\"2024-01-02\",\"result\":3.1}
+{\"site\":\"A01\",\"date\":\"2024-01-01\",\"result\":1.5}
+{\"site\":\"A01\",\"date\":\"2024-01-02\",\"result\":2.5}
+";
+    child.stdin.take().unwrap().write_all(ndjson.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+A01,1.5,2.5
+B02,,3.1
+";
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+}
+
+// Test that JSON output is an array of objects keyed by the format 1 column names
+#[test]
+fn test_crosstab_json_output() {
+    let output = crosstab(
+        "json_output",
+        "site,date,result,unit\nA01,2024-01-01,1.5,mg/L\nB02,2024-01-02,3.1,mg/L\n",
+        &["-r", "site", "-c", "date", "-v", "result", "--output-format", "json"],
+    );
+    let expected = r#"[
+  {
+    "site": "A01",
+    "2024-01-01_result": 1.5,
+    "2024-01-02_result": null
+  },
+  {
+    "site": "B02",
+    "2024-01-01_result": null,
+    "2024-01-02_result": 3.1
+  }
+]
+"#;
+    assert_eq!(output, expected);
+}
+
+// Test that a query of the database at a --db-url is crosstabbed like an input file
+#[test]
+fn test_crosstab_db_url() {
+    let database = std::env::temp_dir().join(format!("xtab_{}_db_url.sqlite", std::process::id()));
+    let _ = fs::remove_file(&database);
+    let connection = rusqlite::Connection::open(&database).unwrap();
+    connection
+        .execute_batch(
+            "CREATE TABLE lab_results (site TEXT, date TEXT, result REAL);
+             INSERT INTO lab_results VALUES ('A01', '2024-01-01', 1.5), ('B02', '2024-01-02', 3.1), ('B02', '2024-01-01', NULL);",
+        )
+        .unwrap();
+    let url = format!("sqlite://{}", database.display());
+    let output = run_xtab(&[
+        "--db-url", &url, "--query", "SELECT * FROM lab_results WHERE result IS NOT NULL",
+        "-r", "site", "-c", "date", "-v", "result",
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+A01,1.5,
+B02,,3.1
+";
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+}
+
+// Test that the crosstab is written as a database table, and that an existing table is only replaced when asked
+#[test]
+fn test_crosstab_db_out() {
+    let infile = temp_file("db_out_in.csv", NORMALIZED);
+    let database = std::env::temp_dir().join(format!("xtab_{}_db_out.sqlite", std::process::id()));
+    let _ = fs::remove_file(&database);
+    let url = format!("sqlite://{}", database.display());
+    let mut args = vec![
+        "-i", infile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result",
+        "--db-out", &url, "--db-table", "xtab",
     ];
+    assert!(run_xtab(&args).status.success());
+    assert!(!run_xtab(&args).status.success());
+    args.extend_from_slice(&["--if-exists", "replace"]);
+    assert!(run_xtab(&args).status.success());
+
+    let connection = rusqlite::Connection::open(&database).unwrap();
+    let total: f64 = connection
+        .query_row("SELECT SUM(\"2024-01-01_result\") FROM xtab", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(total, 5.5);
+}
+
+// Test that a gzipped input file is decompressed and a .zst output file is compressed
+#[test]
+fn test_crosstab_compression() {
+    let infile = std::env::temp_dir().join(format!("xtab_{}_compressed_in.csv.gz", std::process::id()));
+    let mut encoder = flate2::write::GzEncoder::new(fs::File::create(&infile).unwrap(), flate2::Compression::default());
+    encoder.write_all(NORMALIZED.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_compressed_out.tsv.zst", std::process::id()));
+
+    let output = run_xtab(&[
+        "-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(),
+        "-r", "site", "-c", "date", "-v", "result",
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let contents = zstd::decode_all(fs::File::open(&outfile).unwrap()).unwrap();
+    let expected = "\
+site\t2024-01-01_result\t2024-01-02_result
+A01\t1.5\t2.5
+B02\t4.0\t3.1
+";
+    assert_eq!(String::from_utf8_lossy(&contents), expected);
+}
 
-    // Parse the input arguments
-    let parsed_args = Args::parse_from(args);
-
-    // Define the expected output
-    let expected_args = Args {
-        infile: PathBuf::from("infile.csv"),
-        outfile: PathBuf::from("outfile.csv"),
-        row: vec!["1".to_string(), "2".to_string(), "3".to_string()],
-        col: vec!["1".to_string(), "2".to_string(), "3".to_string()],
-        value: vec!["1".to_string(), "2".to_string(), "3".to_string()],
-        format: 1,
+// Test that the totals row and columns are appended after the crosstab
+#[test]
+fn test_crosstab_totals() {
+    let output = crosstab(
+        "totals",
+        NORMALIZED,
+        &["-r", "site", "-c", "date", "-v", "result", "-a", "max", "--row-totals", "--col-totals"],
+    );
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result,Total_result
+A01,1.5,2.5,2.5
+B02,4,3.1,4
+Total,4,3.1,4
+";
+    assert_eq!(output, expected);
+}
+
+// Test that --count fills the cells with the number of input rows, without a value column
+#[test]
+fn test_crosstab_count() {
+    let infile = temp_file("count_in.csv", "site,species\nA01,trout\nA01,trout\nA01,bass\nB02,bass\n");
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-r", "site", "-c", "species", "--count"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.stderr.is_empty());
+    let expected = "\
+site,bass_count,trout_count
+A01,1,2
+B02,1,
+";
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+}
+
+// Test that the result of a --sql query of the input is crosstabbed
+#[test]
+fn test_crosstab_sql() {
+    let output = crosstab(
+        "sql",
+        "site,date,result,qc_flag\nA01,2024-01-01,1.5,\nA01,2024-01-02,2.5,R\nB02,2024-01-01,4.0,\n",
+        &["-r", "station", "-c", "date", "-v", "result", "--sql", "SELECT site AS station, date, result FROM input WHERE qc_flag IS NULL"],
+    );
+    assert_eq!(output, "station,2024-01-01_result\nA01,1.5\nB02,4.0\n");
+}
+
+// Test that --sort-rows orders the output rows, with natural order for alphanumeric identifiers
+#[test]
+fn test_crosstab_sort_rows() {
+    let output = crosstab(
+        "sort_rows",
+        "site,date,result\nS10,2024-01-01,1\nS2,2024-01-01,2\nS2,2024-01-02,3\n",
+        &["-r", "site,date", "-c", "date", "-v", "result", "--sort-rows", "site:natural,date:desc"],
+    );
+    let expected = "\
+site,date,2024-01-01_result,2024-01-02_result
+S2,2024-01-02,,3
+S2,2024-01-01,2,
+S10,2024-01-01,1,
+";
+    assert_eq!(output, expected);
+}
+
+// Test that the melt subcommand with a split pattern turns a format 1 crosstab back into normalized data
+#[test]
+fn test_melt() {
+    let infile = temp_file("melt_in.csv", "\
+site,2024-01-01_result,2024-01-01_unit,2024-01-02_result,2024-01-02_unit
+A01,1.5,mg/L,2.5,mg/L
+B02,,,3.1,mg/L
+");
+    let output = run_xtab(&["melt", "-i", infile.to_str().unwrap(), "-r", "site", "--split", "^(?P<date>.+)_(?P<var>[^_]+)$"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let expected = "\
+site,date,result,unit
+A01,2024-01-01,1.5,mg/L
+A01,2024-01-02,2.5,mg/L
+B02,2024-01-02,3.1,mg/L
+";
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+}
+
+// Test that the validate subcommand reports the size of the crosstab, and fails when cells have multiple values
+#[test]
+fn test_validate() {
+    let infile = temp_file("validate_in.csv", NORMALIZED);
+    let output = run_xtab(&["validate", "-i", infile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Output rows: 2"));
+    assert!(stdout.contains("Output columns: 3 (2 generated)"));
+
+    let output = run_xtab(&["validate", "-i", infile.to_str().unwrap(), "-r", "site", "-c", "unit", "-v", "result"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Multiple values"));
+}
+
+// Test that the inspect subcommand lists the distinct and missing values of every column
+#[test]
+fn test_inspect() {
+    let infile = temp_file("inspect_in.csv", "site,date,result\nA01,2024-01-01,1.5\nA01,2024-01-02,\n");
+    let output = run_xtab(&["inspect", "-i", infile.to_str().unwrap(), "-n", "0"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let expected = "\
+Rows: 2
+Columns: 3
+  column  dtype     distinct     nulls
+  site    str              1         0
+  date    str              2         0
+  result  f64              1         1
+";
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+}
+
+// Test that --dry-run describes the crosstab without writing the output file
+#[test]
+fn test_dry_run() {
+    let infile = temp_file("dry_run_in.csv", NORMALIZED);
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_dry_run_out.csv", std::process::id()));
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "unit", "-v", "result", "--dry-run"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let expected = "\
+Input rows: 4
+Output rows: 2
+Output columns: 2 (1 generated)
+Header rows:
+  [\"site\", \"mg/L_result\"]
+Cells with multiple values: 2 (4 input rows, at most 2 in one cell)
+";
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+    assert!(!outfile.exists());
+}
+
+// Test that a --config job file declares the arguments, and that the command line overrides it
+#[test]
+fn test_config() {
+    let infile = temp_file("config_in.csv", NORMALIZED);
+    let job = format!("infile = {:?}\nrow = [\"site\"]\ncol = \"date\"\nvalue = [\"result\", \"unit\"]\n", infile.to_str().unwrap());
+    let config = temp_file("config_job.toml", &job);
+    let output = run_xtab(&["--config", config.to_str().unwrap(), "-v", "result"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "site,2024-01-01_result,2024-01-02_result\nA01,1.5,2.5\nB02,4.0,3.1\n");
+
+    let config = temp_file("config_bad.toml", "rows = [\"site\"]\n");
+    let output = run_xtab(&["--config", config.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown setting 'rows'"));
+}
+
+// Test that the jobs of a batch job file are each written from the shared input
+#[test]
+fn test_config_batch() {
+    let infile = temp_file("batch_in.csv", NORMALIZED);
+    let outfile = |name: &str| std::env::temp_dir().join(format!("xtab_{}_batch_{}.csv", std::process::id(), name));
+    let job = format!(
+        "infile = {:?}\nrow = [\"site\"]\ncol = \"date\"\n\n[[jobs]]\nname = \"results\"\nvalue = \"result\"\noutfile = {:?}\n\n[[jobs]]\nname = \"counts\"\ncount = true\noutfile = {:?}\n",
+        infile.to_str().unwrap(),
+        outfile("results").to_str().unwrap(),
+        outfile("counts").to_str().unwrap(),
+    );
+    let config = temp_file("batch_job.toml", &job);
+    let output = run_xtab(&["--config", config.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read_to_string(outfile("results")).unwrap(), "site,2024-01-01_result,2024-01-02_result\nA01,1.5,2.5\nB02,4.0,3.1\n");
+    assert_eq!(fs::read_to_string(outfile("counts")).unwrap(), "site,2024-01-01_count,2024-01-02_count\nA01,1,1\nB02,1,1\n");
+}
+
+// Test that the invalid arguments of a job of a batch, and the unknown settings of a job file, are argument errors
+// that are summarized in the JSON report
+#[test]
+fn test_config_report_json() {
+    let infile = temp_file("batch_report_in.csv", NORMALIZED);
+    let job = format!(
+        "infile = {:?}\nrow = [\"site\"]\ncol = \"date\"\n\n[[jobs]]\nname = \"results\"\nvalue = \"result\"\n\n[[jobs]]\nname = \"counts\"\ncount = true\non_duplicate = \"maybe\"\n",
+        infile.to_str().unwrap(),
+    );
+    for (name, contents) in [("batch_report.toml", job.as_str()), ("batch_report_bad.toml", "rows = [\"site\"]\n")] {
+        let config = temp_file(name, contents);
+        let output = run_xtab(&["--config", config.to_str().unwrap(), "--report", "json"]);
+        assert_eq!(output.status.code(), Some(2));
+        let report: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+        assert_eq!(report["status"], "error");
+        assert_eq!(report["exit_code"], 2);
+    }
+}
+
+// Test that a streamed input is crosstabbed like one read into memory, with the filter applied
+#[test]
+fn test_streaming() {
+    let output = crosstab(
+        "streaming",
+        "site,date,result,unit,lab\nA01,2024-01-01,1.5,mg/L,X\nA01,2024-01-02,2.5,mg/L,Y\nB02,2024-01-01,4.0,mg/L,X\n",
+        &["-r", "site", "-c", "date", "-v", "result", "--streaming", "--filter", "lab == 'X'"],
+    );
+    assert_eq!(output, "site,2024-01-01_result\nA01,1.5\nB02,4.0\n");
+}
+
+// Test that a streamed input aggregated into its cells as it is read is crosstabbed like one read into memory, and
+// that all of its input rows are reported
+#[test]
+fn test_streaming_aggregation() {
+    let contents = "site,date,result\nA01,2024-01-01,1.5\nA01,2024-01-01,2.5\nB02,2024-01-01,\nB02,2024-01-02,3.1\nB02,2024-01-02,0.9\n";
+    for args in [&["-v", "result", "-a", "sum", "--col-totals"][..], &["-v", "result", "-a", "mean"], &["-v", "result", "-a", "count"], &["--count"]] {
+        let args = [&["-r", "site", "-c", "date"], args].concat();
+        assert_eq!(crosstab("streaming_aggregation", contents, &[&args[..], &["--streaming"]].concat()), crosstab("streaming_aggregation", contents, &args));
+    }
+    let infile = temp_file("streaming_aggregation_in.csv", contents);
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-r", "site", "-c", "date", "--count", "--streaming", "--dry-run", "-V"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Aggregating the input rows into the cells"));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Input rows: 5\n"));
+}
+
+// Test that a dry run describes a streamed crosstab aggregated into its cells like one read into memory, with the same
+// cells with multiple values, and that the cells are not aggregated for a duplicates report
+#[test]
+fn test_streaming_aggregation_dry_run() {
+    let contents = "site,date,result\nA01,2024-01-01,1.5\nA01,2024-01-01,2.5\nB02,2024-01-01,\nB02,2024-01-02,3.1\nB02,2024-01-02,0.9\nB02,2024-01-03,1.0\n";
+    let infile = temp_file("streaming_aggregation_dry_run_in.csv", contents);
+    let args = ["-i", infile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "-a", "sum", "--dry-run"];
+    let read = run_xtab(&args);
+    let streamed = run_xtab(&[&args[..], &["--streaming"]].concat());
+    assert_eq!(String::from_utf8_lossy(&streamed.stdout), String::from_utf8_lossy(&read.stdout));
+    assert!(String::from_utf8_lossy(&streamed.stdout).contains("Cells with multiple values: 2 (4 input rows, at most 2 in one cell)"));
+
+    let report = std::env::temp_dir().join(format!("xtab_{}_streaming_aggregation_duplicates.csv", std::process::id()));
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_streaming_aggregation_dry_run_out.csv", std::process::id()));
+    let args = ["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "-a", "sum"];
+    let output = run_xtab(&[&args[..], &["--streaming", "--report", "json"]].concat());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("\"duplicate_cells\":2"));
+    let output = run_xtab(&[&args[..], &["--streaming", "--report", "json", "--duplicates-report", report.to_str().unwrap()]].concat());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("\"duplicate_cells\":2"));
+    assert_eq!(fs::read_to_string(&report).unwrap().lines().count(), 5);
+}
+
+// Test that the crosstab is the same when it is built with a single thread
+#[test]
+fn test_threads() {
+    let output = crosstab("threads", NORMALIZED, &["-r", "site", "-c", "date", "-v", "result", "--threads", "1"]);
+    assert_eq!(output, "site,2024-01-01_result,2024-01-02_result\nA01,1.5,2.5\nB02,4.0,3.1\n");
+}
+
+// Test that --timings reports the elapsed time of each stage on standard error
+#[test]
+fn test_timings() {
+    let infile = temp_file("timings_in.csv", NORMALIZED);
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "--timings"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for stage in ["Timings:", "  read: ", "  pivot: ", "  write: ", "  total: "] {
+        assert!(stderr.contains(stage), "{}", stderr);
+    }
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "site,2024-01-01_result,2024-01-02_result\nA01,1.5,2.5\nB02,4.0,3.1\n");
+}
+
+// Test that -q silences warnings, and that -V logs the stages of the run, without writing to standard output
+#[test]
+fn test_verbosity() {
+    let infile = temp_file("verbosity_in.csv", NORMALIZED);
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_verbosity_out.csv", std::process::id()));
+    let args = |flag: &str| run_xtab(&["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "unit", "-v", "result", flag]);
+
+    let output = args("-q");
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    let output = args("-V");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Read 4 input rows"));
+    assert!(stderr.contains("Warning: Multiple values"));
+    assert!(!stderr.contains("After processing arguments"));
+}
+
+// Test that each kind of failure exits with its own exit code
+#[test]
+fn test_exit_codes() {
+    let infile = temp_file("exit_codes_in.csv", NORMALIZED);
+    let infile = infile.to_str().unwrap();
+    let code = |args: &[&str]| run_xtab(args).status.code();
+    assert_eq!(code(&["-i", infile, "-r", "site", "-c", "date", "-v", "result", "-f", "7"]), Some(2));
+    assert_eq!(code(&["-i", infile, "-r", "site", "-c", "month", "-v", "result"]), Some(3));
+    assert_eq!(code(&["-i", "missing.csv", "-r", "site", "-c", "date", "-v", "result"]), Some(4));
+    assert_eq!(code(&["validate", "-i", infile, "-r", "site", "-c", "unit", "-v", "result"]), Some(5));
+}
+
+// Test that --strict and --on-duplicate error fail on multiple values for a cell without writing the output,
+// and that --on-duplicate first uses the first value without a warning
+#[test]
+fn test_on_duplicate() {
+    let infile = temp_file("on_duplicate_in.csv", NORMALIZED);
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_on_duplicate_out.csv", std::process::id()));
+    let args = |flags: &[&str]| {
+        let _ = fs::remove_file(&outfile);
+        let mut args = vec!["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "unit", "-v", "result"];
+        args.extend(flags);
+        run_xtab(&args)
     };
 
-    // Compare the parsed arguments to the expected output
-    assert_eq!(parsed_args, expected_args);
+    for flags in [&["--strict"][..], &["--on-duplicate", "error"][..]] {
+        let output = args(flags);
+        assert_eq!(output.status.code(), Some(5));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Error: Multiple values were found for 2 row/column combination(s)"));
+        assert!(!outfile.exists());
+    }
+
+    let output = args(&["--on-duplicate", "first"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    assert!(outfile.exists());
+}
+
+// Test that --duplicates-report lists every input row of the cells with multiple values, even with --strict
+#[test]
+fn test_duplicates_report() {
+    let report = std::env::temp_dir().join(format!("xtab_{}_duplicates_report.csv", std::process::id()));
+    let infile = temp_file("duplicates_report_in.csv", NORMALIZED);
+    let output = run_xtab(&[
+        "-i",
+        infile.to_str().unwrap(),
+        "-r",
+        "site",
+        "-c",
+        "unit",
+        "-v",
+        "result",
+        "--strict",
+        "--duplicates-report",
+        report.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(5));
+    let expected = "\
+input_row,site,unit,result
+2,A01,mg/L,1.5
+3,A01,mg/L,2.5
+1,B02,mg/L,3.1
+4,B02,mg/L,4.0
+";
+    assert_eq!(fs::read_to_string(&report).unwrap(), expected);
+}
+
+// Test that --value-format formats the numeric cell values, with a per-column override
+#[test]
+fn test_value_format() {
+    let output = crosstab(
+        "value_format",
+        "site,date,result,depth\nA01,2024-01-01,1.5,1200\nA01,2024-01-02,2.25,35000\n",
+        &["-r", "site", "-c", "date", "-v", "result,depth", "--value-format", "%,.0f", "--value-format", "result=%.3f"],
+    );
+    let expected = "\
+site,2024-01-01_result,2024-01-01_depth,2024-01-02_result,2024-01-02_depth
+A01,1.500,\"1,200\",2.250,\"35,000\"
+";
+    assert_eq!(output, expected);
+}
+
+// Test that --parse-dates and --date-format write date column headers consistently and in chronological order
+#[test]
+fn test_dates() {
+    let contents = "site,date,result\nA01,3/1/24 0:00,1.5\nA01,12/1/23 0:00,2.5\nA01,11/1/23 0:00,3.5\n";
+    let expected = "\
+site,2023-11_result,2023-12_result,2024-03_result
+A01,3.5,2.5,1.5
+";
+    for streaming in [&[][..], &["--streaming"][..]] {
+        let mut args = vec!["-r", "site", "-c", "date", "-v", "result", "--parse-dates", "date=%m/%d/%y %H:%M", "--date-format", "%Y-%m"];
+        args.extend(streaming);
+        assert_eq!(crosstab("dates", contents, &args), expected);
+    }
+}
+
+// Test that --dtypes keeps the leading zeros of identifiers that would otherwise be read as integers
+#[test]
+fn test_dtypes() {
+    let contents = "site,date,result\n007,2024-01-01,1.5\n010,2024-01-01,2.5\n";
+    let expected = "\
+site,2024-01-01_result
+007,1.5
+010,2.5
+";
+    assert_eq!(crosstab("dtypes", contents, &["-r", "site", "-c", "date", "-v", "result", "--dtypes", "site=str,result=f64"]), expected);
+    assert!(crosstab("dtypes", contents, &["-r", "site", "-c", "date", "-v", "result"]).contains("\n7,1.5\n"));
+}
+
+// Test that lines with an extra field are skipped or reported by line number, and that comment lines are ignored
+#[test]
+fn test_on_bad_lines() {
+    let contents = "# exported 2024-01-03\nsite,date,result\nA01,2024-01-01,1.5\nA01,2024-01-02,2.5,extra\nB02,2024-01-01,4.0\n";
+    let expected = "\
+site,2024-01-01_result
+A01,1.5
+B02,4.0
+";
+    let args = ["-r", "site", "-c", "date", "-v", "result", "--comment", "#"];
+    assert_eq!(crosstab("bad_lines_skip", contents, &[&args[..], &["--on-bad-lines", "skip"]].concat()), expected);
+
+    let infile = temp_file("bad_lines_in.csv", contents);
+    let output = run_xtab(&[&["-i", infile.to_str().unwrap()], &args[..], &["--on-bad-lines", "warn"]].concat());
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Line 4 has 4 fields, but the header line has 3"));
+
+    let output = run_xtab(&[&["-i", infile.to_str().unwrap()], &args[..]].concat());
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Line 4 has 4 fields, but the header line has 3"));
+}
+
+// Test that windows-1252 input is converted to UTF-8, rather than failing or producing mojibake
+#[test]
+fn test_encoding() {
+    let infile = std::env::temp_dir().join(format!("xtab_{}_encoding_in.csv", std::process::id()));
+    fs::write(&infile, b"site,unit,result\nA01,\xb5g/L,1.5\nB02,\xb5g/L,2.5\n").unwrap();
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_encoding_out.csv", std::process::id()));
+    let args = ["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "unit", "-v", "result"];
+    let output = run_xtab(&[&args[..], &["--encoding", "windows-1252"]].concat());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read_to_string(&outfile).unwrap(), "site,µg/L_result\nA01,1.5\nB02,2.5\n");
+    assert_eq!(run_xtab(&[&args[..], &["--encoding", "ebcdic"]].concat()).status.code(), Some(2));
+}
+
+// Test that instrument metadata lines before the column names are skipped with --skip-rows or --header-row
+#[test]
+fn test_skip_rows() {
+    let contents = format!("Instrument: ICP-MS 7900\nRun: 2024-01-03, operator \"JW\"\n\n{}", NORMALIZED);
+    let expected = crosstab("skip_rows_plain", NORMALIZED, &["-r", "site", "-c", "date", "-v", "result"]);
+    assert_eq!(crosstab("skip_rows", &contents, &["-r", "site", "-c", "date", "-v", "result", "--skip-rows", "3"]), expected);
+    assert_eq!(crosstab("header_row", &contents, &["-r", "site", "-c", "date", "-v", "result", "--header-row", "4"]), expected);
+    let args = ["-r", "site", "-c", "date", "-v", "result", "--header-row", "4", "--on-bad-lines", "skip"];
+    assert_eq!(crosstab("header_row_clean", &contents, &args), expected);
+}
+
+// Test that --by-index selects columns by their positions, including those with repeated names
+#[test]
+fn test_by_index() {
+    let contents = "site,date,result,result\nA01,2024-01-01,1.5,ND\nB02,2024-01-01,4.0,2.5\n";
+    let expected = "\
+site,2024-01-01_result
+A01,1.5
+B02,4.0
+";
+    assert_eq!(crosstab("by_index", contents, &["-r", "1", "-c", "2", "-v", "3", "--by-index"]), expected);
+    let output = crosstab("by_index_repeated", contents, &["-r", "1", "-c", "2", "-v", "4", "--by-index", "--streaming"]);
+    assert!(output.ends_with("\nA01,ND\nB02,2.5\n"), "{}", output);
+}
+
+// Test that column names match apart from capitalization and whitespace, and that misspelled names get suggestions
+#[test]
+fn test_column_name_matching() {
+    let expected = crosstab("names_exact", NORMALIZED, &["-r", "site", "-c", "date", "-v", "result"]);
+    assert_eq!(crosstab("names_case", NORMALIZED, &["-r", "Site", "-c", " DATE", "-v", "Result"]), expected);
+
+    let infile = temp_file("names_in.csv", NORMALIZED);
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-r", "site", "-c", "dates", "-v", "reslt"]);
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Error: The column header column 'dates' is not in the input file; did you mean `date`?"), "{}", stderr);
+    assert!(stderr.contains("Error: The value column 'reslt' is not in the input file; did you mean `result`?"), "{}", stderr);
+
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "--filter", "reslt > 1"]);
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Error: The filter column 'reslt' is not in the input file; did you mean `result`?"), "{}", stderr);
+}
+
+// Test that glob patterns and regular expressions select many value columns at once
+#[test]
+fn test_value_patterns() {
+    let contents = "site,date,conc_Pb,conc_Zn,result_1,note\nA01,2024-01-01,1.5,20,3,ok\nB02,2024-01-01,2.5,30,4,ok\n";
+    let expected = "\
+site,2024-01-01_conc_Pb,2024-01-01_conc_Zn
+A01,1.5,20
+B02,2.5,30
+";
+    assert_eq!(crosstab("glob", contents, &["-r", "site", "-c", "date", "-v", "conc_*"]), expected);
+    assert_eq!(crosstab("regex", contents, &["-r", "site", "-c", "date", "-v", "re:^conc_(Pb|Zn)$"]), expected);
+
+    let infile = temp_file("patterns_in.csv", contents);
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "dissolved_*"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("The value column pattern 'dissolved_*' does not match any input columns"));
+}
+
+// Test that one value column can fill several values, each with its own aggregation and label
+#[test]
+fn test_value_specs() {
+    let expected = "\
+site,mg/L_avg_result,mg/L_n
+A01,2,2
+B02,3.55,2
+";
+    let args = ["-r", "site", "-c", "unit", "-v", "result:mean:avg_result,result:count:n"];
+    assert_eq!(crosstab("value_specs", NORMALIZED, &args), expected);
+
+    let infile = temp_file("value_specs_in.csv", NORMALIZED);
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-r", "site", "-c", "unit", "-v", "result:mean,result:max"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("The value 'result' is named more than once"));
+}
+
+// Test that a derived column can be pivoted as a value column
+#[test]
+fn test_derive() {
+    let contents = "site,date,flow,concentration\nA01,2024-01-01,2.0,1.5\nB02,2024-01-01,0.5,4.0\n";
+    let expected = "\
+site,2024-01-01_load
+A01,3.0
+B02,2.0
+";
+    let args = ["-r", "site", "-c", "date", "-v", "load", "--derive", "load=flow * concentration"];
+    assert_eq!(crosstab("derive", contents, &args), expected);
+    assert_eq!(crosstab("derive_streaming", contents, &[&args[..], &["--streaming"]].concat()), expected);
+}
+
+// Test that carried columns appear once per output row after the row headers
+#[test]
+fn test_carry() {
+    let contents = "site,lat,date,result\nA01,45.1,2024-01-01,1.5\nA01,45.1,2024-01-02,2.5\nB02,46.2,2024-01-01,4.0\n";
+    let expected = "\
+site,lat,2024-01-01_result,2024-01-02_result
+A01,45.1,1.5,2.5
+B02,46.2,4.0,
+";
+    assert_eq!(crosstab("carry", contents, &["-r", "site", "-c", "date", "-v", "result", "--carry", "lat"]), expected);
+}
+
+// Test that --col-product generates every combination of the values of several -c columns
+#[test]
+fn test_col_product() {
+    let contents = "site,year,analyte,result\nA01,2023,Pb,1.5\nA01,2024,Zn,2.5\nB02,2024,Pb,4.0\n";
+    let expected = "\
+,2023,2023,2024,2024
+,Pb,Zn,Pb,Zn
+site,result,result,result,result
+A01,1.5,ND,ND,2.5
+B02,ND,ND,4.0,ND
+";
+    let args = ["-r", "site", "-c", "year,analyte", "-v", "result", "-f", "3", "--col-product", "--fill", "ND"];
+    assert_eq!(crosstab("col_product", contents, &args), expected);
+}
+
+// Test that a crosstab with more generated columns than --max-columns fails before it is built
+#[test]
+fn test_max_columns() {
+    let infile = temp_file("max_columns_in.csv", NORMALIZED);
+    let args = ["-i", infile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result,unit"];
+    let output = run_xtab(&[&args[..], &["--max-columns", "3"]].concat());
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("would generate 4 output columns (2 distinct combinations"), "{}", stderr);
+    assert!(run_xtab(&[&args[..], &["--max-columns", "0"]].concat()).status.success());
+}
+
+// Test that the column keys outside the most frequent are aggregated into an "Other" column
+#[test]
+fn test_top_cols() {
+    let contents = "site,analyte\nA01,Zn\nA01,As\nA01,Pb\nA01,Zn\nB02,Pb\nB02,Cu\n";
+    let expected = "\
+site,Pb_count,Zn_count,Rest_count
+A01,1,2,1
+B02,1,0,1
+";
+    let args = ["-r", "site", "-c", "analyte", "--count", "--top-cols", "2", "--other-label", "Rest", "--fill", "0"];
+    assert_eq!(crosstab("top_cols", contents, &args), expected);
+}
+
+// Test that numeric and date columns are binned before they are pivoted
+#[test]
+fn test_bin() {
+    let contents = "depth,date,result\n2,2024-01-05,1.0\n7,2024-01-20,3.0\n12,2024-01-09,4.0\n3,2024-02-14,2.0\n";
+    let expected = "\
+depth,2024-01_result,2024-02_result
+0,2,2
+10,4,
+";
+    let args = ["-r", "depth", "-c", "date", "-v", "result", "-a", "mean", "--bin", "depth=10", "--bin", "date=month"];
+    assert_eq!(crosstab("bin", contents, &args), expected);
+}
+
+// Test that a crosstab written to a .md file is a Markdown table with the header rows stacked in one
+#[test]
+fn test_markdown_output() {
+    let expected = "\
+| site | 2024-01-01<br>result | 2024-01-01<br>unit | 2024-01-02<br>result | 2024-01-02<br>unit |
+| ---- | -------------------: | ------------------ | -------------------: | ------------------ |
+| A01  |                  1.5 | mg/L               |                  2.5 | mg/L               |
+| B02  |                  4.0 | mg/L               |                  3.1 | mg/L               |
+";
+    let output = crosstab_file("markdown.csv", NORMALIZED, &["-r", "site", "-c", "date", "-v", "result,unit", "-f", "2", "--output-format", "md"]);
+    assert_eq!(output, expected);
+}
+
+// Test that HTML output is written in the template, with the repeated column header values merged
+#[test]
+fn test_html_output() {
+    let template = temp_file("html_template.html", "<section>\n{{table}}\n</section>\n");
+    let args = ["-r", "site", "-c", "date", "-v", "result,unit", "-f", "2", "--output-format", "html", "--html-class", "qa"];
+    let output = crosstab("html", NORMALIZED, &[&args[..], &["--html-template", template.to_str().unwrap()]].concat());
+    assert!(output.starts_with("<section>\n<table class=\"qa\">\n<thead>\n"), "{}", output);
+    assert!(output.contains("<tr><th rowspan=\"2\">site</th><th colspan=\"2\">2024-01-01</th><th colspan=\"2\">2024-01-02</th></tr>"));
+    assert!(output.contains("<tr><th scope=\"row\">A01</th><td class=\"number\">1.5</td><td>mg/L</td>"));
+    assert!(output.ends_with("</table>\n</section>\n"));
+
+    let standalone = crosstab("html_standalone", NORMALIZED, &args);
+    assert!(standalone.starts_with("<!DOCTYPE html>"));
+}
+
+// Test that a crosstab written to a .tex file is a booktabs tabular with multicolumn column headers
+#[test]
+fn test_latex_output() {
+    let expected = r"\begin{tabular}{lrlrl}
+\toprule
+ & \multicolumn{2}{c}{2024-01-01} & \multicolumn{2}{c}{2024-01-02} \\
+\cmidrule(lr){2-3} \cmidrule(lr){4-5}
+site & result & unit & result & unit \\
+\midrule
+A01 & 1.5 & mg/L & 2.5 & mg/L \\
+B02 & 4.0 & mg/L & 3.1 & mg/L \\
+\bottomrule
+\end{tabular}
+";
+    let output = crosstab_file("latex.csv", NORMALIZED, &["-r", "site", "-c", "date", "-v", "result,unit", "-f", "2", "--output-format", "latex"]);
+    assert_eq!(output, expected);
+}
+
+// Test that --print writes an aligned table to standard output, leaving out the columns that do not fit
+#[test]
+fn test_print() {
+    let infile = temp_file("print_in.csv", NORMALIZED);
+    let args = ["-i", infile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "--print"];
+    let output = run_xtab(&args);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let expected = "\
+┌──────┬───────────────────┬───────────────────┐
+│ site ┆ 2024-01-01_result ┆ 2024-01-02_result │
+╞══════╪═══════════════════╪═══════════════════╡
+│ A01  ┆               1.5 ┆               2.5 │
+│ B02  ┆               4.0 ┆               3.1 │
+└──────┴───────────────────┴───────────────────┘
+";
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+
+    let output = run_xtab(&[&args[..], &["--max-width", "40"]].concat());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("│ A01  ┆               1.5 ┆ … │"), "{}", stdout);
+    assert!(stdout.ends_with("(1 of 3 columns not shown)\n"));
+}
+
+// Test that a crosstab written to a .arrow file is an Arrow IPC file with numeric generated columns
+#[test]
+fn test_arrow_output() {
+    use polars::prelude::{DataType, IpcReader, SerReader};
+    let infile = temp_file("arrow_in.csv", NORMALIZED);
+    let arrow = std::env::temp_dir().join(format!("xtab_{}_arrow_out.arrow", std::process::id()));
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-o", arrow.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let df = IpcReader::new(fs::File::open(&arrow).unwrap()).finish().unwrap();
+    assert_eq!(df.get_column_names(), vec!["site", "2024-01-01_result", "2024-01-02_result"]);
+    assert_eq!(df.column("2024-01-02_result").unwrap().dtype(), &DataType::Float64);
+    assert_eq!(df.column("2024-01-02_result").unwrap().f64().unwrap().get(1), Some(3.1));
+}
+
+// Test that a crosstab written to a .ods file is an OpenDocument spreadsheet that can be read back as input
+#[test]
+fn test_ods_output() {
+    use calamine::{open_workbook, Data, Ods, Reader};
+    let infile = temp_file("ods_output_in.csv", NORMALIZED);
+    let ods = std::env::temp_dir().join(format!("xtab_{}_ods_output_out.ods", std::process::id()));
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-o", ods.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let mut workbook: Ods<_> = open_workbook(&ods).unwrap();
+    let range = workbook.worksheet_range_at(0).unwrap().unwrap();
+    assert_eq!(range.get_value((0, 2)), Some(&Data::String("2024-01-02_result".to_string())));
+    assert_eq!(range.get_value((2, 1)), Some(&Data::Float(4.0)));
+}
+
+// Test that --output-format ods writes an OpenDocument spreadsheet to standard output
+#[test]
+fn test_ods_output_format() {
+    use calamine::{Data, Ods, Reader};
+    let infile = temp_file("ods_output_format_in.csv", NORMALIZED);
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "--output-format", "ods", "-r", "site", "-c", "date", "-v", "result"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let mut workbook: Ods<_> = Ods::new(std::io::Cursor::new(output.stdout)).unwrap();
+    let range = workbook.worksheet_range_at(0).unwrap().unwrap();
+    assert_eq!(range.get_value((0, 1)), Some(&Data::String("2024-01-01_result".to_string())));
+    assert_eq!(range.get_value((2, 1)), Some(&Data::Float(4.0)));
+}
+
+// Test that --append merges a crosstab into the existing output file, adding its new columns and rows
+#[test]
+fn test_append() {
+    let outfile = temp_file("append_out.csv", "site,2024-01-01_result\nA01,1.5\nB02,4.0\n");
+    let infile = temp_file("append_in.csv", "site,date,result\nB02,2024-01-02,3.1\nC03,2024-01-01,0.5\n");
+    let args = ["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "--append"];
+    let output = run_xtab(&args);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+A01,1.5,
+B02,4.0,3.1
+C03,0.5,
+";
+    assert_eq!(fs::read_to_string(&outfile).unwrap(), expected);
+
+    let output = run_xtab(&[&args[..], &["--merge-on", "station"]].concat());
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("The merge column 'station' is not in the existing table"));
+    assert_eq!(run_xtab(&[&args[..], &["-f", "2"]].concat()).status.code(), Some(2));
+    assert_eq!(fs::read_to_string(&outfile).unwrap(), expected);
+}
+
+// Test that the generated columns and output rows without values are left out
+#[test]
+fn test_drop_empty() {
+    let contents = "site,date,result\nA01,2024-01-01,1.5\nA01,2024-01-02,\nB02,2024-01-02,\nC03,2024-01-01,4.0\n";
+    let expected = "\
+site,2024-01-01_result
+A01,1.5
+C03,4.0
+";
+    let args = ["-r", "site", "-c", "date", "-v", "result", "--drop-empty-cols", "--drop-empty-rows"];
+    assert_eq!(crosstab("drop_empty", contents, &args), expected);
+}
+
+// Test that --transpose writes the row keys across the top and the generated columns down the side
+#[test]
+fn test_transpose() {
+    let expected = "\
+,site,A01,B02
+2024-01-01,result,1.5,4.0
+2024-01-01,unit,mg/L,mg/L
+2024-01-02,result,2.5,3.1
+2024-01-02,unit,mg/L,mg/L
+";
+    let args = ["-r", "site", "-c", "date", "-v", "result,unit", "-f", "2", "--transpose"];
+    assert_eq!(crosstab("transpose", NORMALIZED, &args), expected);
+}
+
+// Test that the metadata file records the sizes of the input and output and the hash of the output
+#[test]
+fn test_metadata() {
+    let infile = temp_file("metadata_in.csv", NORMALIZED);
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_metadata_out.csv", std::process::id()));
+    let metadata = std::env::temp_dir().join(format!("xtab_{}_metadata.json", std::process::id()));
+    let args = ["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "--metadata", metadata.to_str().unwrap()];
+    let output = run_xtab(&args);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&metadata).unwrap()).unwrap();
+    assert_eq!(json["input_rows"], 4);
+    assert_eq!(json["output_rows"], 2);
+    assert_eq!(json["output_columns"], 3);
+    assert_eq!(json["duplicate_cells"], 0);
+    assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(json["arguments"][2], "-i");
+
+    // The hash is of the output file
+    let sha256 = json["sha256"].as_str().unwrap().to_string();
+    fs::write(&outfile, "").unwrap();
+    run_xtab(&args);
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&metadata).unwrap()).unwrap();
+    assert_eq!(json["sha256"], sha256.as_str());
+    assert_eq!(sha256.len(), 64);
+}
+
+// Test that --report json prints a single JSON object with the outcome of the run instead of the warnings and errors
+#[test]
+fn test_report_json() {
+    let infile = temp_file("report_in.csv", &format!("{}A01,2024-01-01,9.9,mg/L\n", NORMALIZED));
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_report_out.csv", std::process::id()));
+    let args = ["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "date", "--report", "json"];
+    let output = run_xtab(&[&args[..], &["-v", "result"]].concat());
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(report["status"], "ok");
+    assert_eq!(report["exit_code"], 0);
+    assert!(report["warnings"][0].as_str().unwrap().starts_with("Multiple values were found for 1 row/column combination(s)"));
+    assert_eq!(report["jobs"][0]["input_rows"], 5);
+    assert_eq!(report["jobs"][0]["output_columns"], 3);
+    assert_eq!(report["jobs"][0]["duplicate_cells"], 1);
+    assert_eq!(report["jobs"][0]["dropped_rows"], 0);
+    assert!(report["timings"]["read"].is_number());
+
+    let output = run_xtab(&[&args[..], &["-v", "depth"]].concat());
+    assert_eq!(output.status.code(), Some(3));
+    let report: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(report["status"], "error");
+    assert_eq!(report["exit_code"], 3);
+    assert!(report["error"].as_str().unwrap().contains("depth"));
+}
+
+// Test that a completion script is printed for each shell
+#[test]
+fn test_completions() {
+    for shell in ["bash", "zsh", "fish", "powershell"] {
+        let output = run_xtab(&["completions", shell]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert!(String::from_utf8_lossy(&output.stdout).contains("row-totals"), "{}", shell);
+    }
+    assert_eq!(run_xtab(&["completions", "tcsh"]).status.code(), Some(2));
+}
+
+// Test that the columns that are not given are prompted for, and that the equivalent command line is printed
+#[test]
+fn test_interactive() {
+    let infile = temp_file("interactive_in.csv", NORMALIZED);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_xtab"))
+        .args(["-i", infile.to_str().unwrap(), "-r", "site", "--interactive"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"depth\n2\n3\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+A01,1.5,2.5
+B02,4.0,3.1
+";
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("    2  date (str)"), "{}", stderr);
+    assert!(stderr.contains("There is no column 'depth'"));
+    assert!(stderr.contains(&format!("  xtab pivot -i {} -r site -c date -v result\n", infile.display())), "{}", stderr);
+}
+
+// Test that the output is written again when the watched input file is saved
+#[test]
+fn test_watch() {
+    let infile = temp_file("watch_in.csv", NORMALIZED);
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_watch_out.csv", std::process::id()));
+    let _ = fs::remove_file(&outfile);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_xtab"))
+        .args(["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "--watch"])
+        .spawn()
+        .unwrap();
+    // Wait for the output file to have the given contents
+    let wait_for = |expected: &str| {
+        for _ in 0..100 {
+            if fs::read_to_string(&outfile).is_ok_and(|contents| contents == expected) {
+                return true;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        false
+    };
+    let written = wait_for("site,2024-01-01_result,2024-01-02_result\nA01,1.5,2.5\nB02,4.0,3.1\n");
+    fs::write(&infile, "site,date,result\nC03,2024-02-01,7.5\n").unwrap();
+    let rewritten = wait_for("site,2024-02-01_result\nC03,7.5\n");
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(written && rewritten);
+    assert_eq!(run_xtab(&["-r", "site", "-c", "date", "-v", "result", "--watch"]).status.code(), Some(2));
+}
+
+// Test that the files that match a glob pattern are stacked into one crosstab, or each crosstabbed into its own file
+#[test]
+fn test_glob_input() {
+    let dir = std::env::temp_dir().join(format!("xtab_{}_glob", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("2024-01.csv"), "site,date,result\nA01,2024-01-01,1.5\nB02,2024-01-01,4\n").unwrap();
+    fs::write(dir.join("2024-02.csv"), "site,date,result\nA01,2024-02-01,2.5\n").unwrap();
+    let pattern = dir.join("2024-*.csv");
+    let outfile = dir.join("combined.csv");
+    let args = ["-i", pattern.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result"];
+    let output = run_xtab(&args);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let expected = "\
+site,2024-01-01_result,2024-02-01_result
+A01,1.5,2.5
+B02,4.0,
+";
+    assert_eq!(fs::read_to_string(&outfile).unwrap(), expected);
+
+    let output = run_xtab(&["-i", pattern.to_str().unwrap(), "-r", "source", "-c", "site", "-v", "result", "--source-column", "source"]);
+    let expected = format!("source,A01_result,B02_result\n{},1.5,4.0\n{},2.5,\n", dir.join("2024-01.csv").display(), dir.join("2024-02.csv").display());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+
+    let template = dir.join("{stem}_xtab.csv");
+    let output = run_xtab(&["-i", pattern.to_str().unwrap(), "-o", template.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read_to_string(dir.join("2024-02_xtab.csv")).unwrap(), "site,2024-02-01_result\nA01,2.5\n");
+    assert!(dir.join("2024-01_xtab.csv").exists());
+
+    let missing = dir.join("2023-*.csv");
+    assert_eq!(run_xtab(&["-i", missing.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result"]).status.code(), Some(4));
+}
+
+// Test that repeated input files are stacked, and that input files with different columns are reported
+#[test]
+fn test_multiple_inputs() {
+    let first = temp_file("multiple_a.csv", "site,date,result\nA01,2024-01-01,1.5\n");
+    let second = temp_file("multiple_b.csv", "date,site,result\n2024-01-02,A01,2\n");
+    let third = temp_file("multiple_c.csv", "site,date,value\nB02,2024-01-01,4.0\n");
+    let args = ["-r", "site", "-c", "date", "-v", "result", "-i", first.to_str().unwrap()];
+    let output = run_xtab(&[&args[..], &["-i", second.to_str().unwrap()]].concat());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "site,2024-01-01_result,2024-01-02_result\nA01,1.5,2.0\n");
+
+    let output = run_xtab(&[&args[..], &["-i", third.to_str().unwrap()]].concat());
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("  {} lacks the column(s) 'result'\n", third.display())), "{}", stderr);
+    assert!(stderr.contains(&format!("  {} has the extra column(s) 'value'\n", third.display())));
+    assert_eq!(run_xtab(&[&args[..], &["-i", "-"]].concat()).status.code(), Some(2));
+}
+
+// Test that the columns of a lookup table can be used as row headers
+#[test]
+fn test_join() {
+    let lookup = temp_file("join_sites.csv", "site,site_name,region,notes\nA01,Mill Creek,North,\nB02,Cedar Run,South,dry\n");
+    let args = ["-r", "region,site_name", "-c", "date", "-v", "result", "--join", lookup.to_str().unwrap(), "--join-on", "site", "--join-cols", "site_name,region"];
+    let expected = "\
+region,site_name,2024-01-01_result,2024-01-02_result
+North,Mill Creek,1.5,2.5
+South,Cedar Run,4.0,3.1
+";
+    assert_eq!(crosstab("join", NORMALIZED, &args), expected);
+}
+
+// Test that the codes of a column are replaced by the labels of the mapping file
+#[test]
+fn test_recode() {
+    let mapping = temp_file("recode_sites.csv", "code,label\nA01,Mill Creek\nB02,Cedar Run\n");
+    let args = ["-r", "site", "-c", "date", "-v", "result", "--recode", &format!("site={}", mapping.display())];
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+Cedar Run,4.0,3.1
+Mill Creek,1.5,2.5
+";
+    assert_eq!(crosstab("recode", NORMALIZED, &args), expected);
+}
+
+// Test that the qualifier of each result is written after it, in its own column or as a suffix
+#[test]
+fn test_pair() {
+    let input = "\
+site,date,result,qual
+A01,2024-01-01,0.5,U
+A01,2024-01-02,2.5,J
+B02,2024-01-01,1.5,
+";
+    let expected = "\
+site,2024-01-01_result,2024-01-01_qual,2024-01-02_result,2024-01-02_qual
+A01,0.5,U,2.5,J
+B02,1.5,,,
+";
+    assert_eq!(crosstab("pair", input, &["-r", "site", "-c", "date", "-v", "result", "--pair", "result:qual"]), expected);
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+A01,0.5 U,2.5 J
+B02,1.5,
+";
+    let args = ["-r", "site", "-c", "date", "-v", "result", "--pair", "result:qual", "--pair-style", "suffix"];
+    assert_eq!(crosstab("pair_suffix", input, &args), expected);
+}
+
+// Test that the results flagged as non-detects are aggregated with max_nd, or substituted with half of their limits
+#[test]
+fn test_nondetects() {
+    let input = "\
+site,date,result,qual
+A01,2024-01-01,0.5,U
+A01,2024-01-01,0.25,
+B02,2024-01-01,<0.2,
+B02,2024-01-01,0.4,U
+";
+    let args = ["-r", "site", "-c", "date", "-v", "result", "-a", "max_nd", "--nd-column", "qual"];
+    assert_eq!(crosstab("nondetects", input, &args), "site,2024-01-01_result\nA01,0.25\nB02,<0.4\n");
+    let args = ["-r", "site", "-c", "date", "-v", "result", "-a", "sum", "--nd-column", "qual", "--nd-substitute", "half"];
+    assert_eq!(crosstab("nondetects_half", input, &args), "site,2024-01-01_result\nA01,0.5\nB02,0.3\n");
+}
+
+// Test that the statistics of each cell are written in a template, or in adjacent columns
+#[test]
+fn test_stats() {
+    let input = "\
+site,date,result
+A01,2024-01-01,1.5
+A01,2024-01-01,2.5
+A01,2024-01-02,3
+";
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+A01,\"2.00 (0.71), n=2\",\"3.00 (NA), n=1\"
+";
+    let args = ["-r", "site", "-c", "date", "-v", "result", "--stats", "--value-format", "%.2f"];
+    assert_eq!(crosstab("stats", input, &args), expected);
+    let expected = "\
+site,2024-01-01_mean,2024-01-01_n,2024-01-02_mean,2024-01-02_n
+A01,2.0,2,3.0,1
+";
+    let args = ["-r", "site", "-c", "date", "-v", "result", "--stats-columns", "mean,n", "--value-format", "%.1f"];
+    assert_eq!(crosstab("stats_columns", input, &args), expected);
+}
+
+// Test that the chi-square statistic of the counts is printed, and recorded in the JSON report
+#[test]
+fn test_chisq() {
+    let infile = temp_file("chisq_in.csv", "site,answer\nA,yes\nA,yes\nA,yes\nA,no\nB,yes\nB,no\nB,no\nB,no\n");
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_chisq_out.csv", std::process::id()));
+    let args = ["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "answer", "--count", "--chisq"];
+    let output = run_xtab(&args);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("chi-square: 2.0000\n  degrees of freedom: 1\n  p-value: 0.1573\n  Cramér's V: 0.5000"), "{}", stderr);
+
+    let output = run_xtab(&[&args[..], &["--report", "json"]].concat());
+    let report: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(report["jobs"][0]["chisq"]["df"], 1);
+    assert!((report["jobs"][0]["chisq"]["cramers_v"].as_f64().unwrap() - 0.5).abs() < 1e-12);
+
+    // The test is of the counts themselves, not of their running totals or changes
+    let output = run_xtab(&[&args[..], &["--cumulative"]].concat());
+    assert_eq!(output.status.code(), Some(2));
+    let output = run_xtab(&[&args[..], &["--delta", "abs"]].concat());
+    assert_eq!(output.status.code(), Some(2));
+}
+
+// Test that each result is replaced by its rank on its date, the largest first
+#[test]
+fn test_rank() {
+    let args = ["-r", "site", "-c", "date", "-v", "result", "--rank", "col", "--rank-desc"];
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+A01,2,2
+B02,1,1
+";
+    assert_eq!(crosstab("rank", NORMALIZED, &args), expected);
+}
+
+// Test that each cell has the running total of its row
+#[test]
+fn test_cumulative() {
+    let args = ["-r", "site", "-c", "date", "-v", "result", "--cumulative"];
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+A01,1.5,4
+B02,4,7.1
+";
+    assert_eq!(crosstab("cumulative", NORMALIZED, &args), expected);
+}
+
+// Test that each cell after the first column has the percentage change from the previous column
+#[test]
+fn test_delta() {
+    let args = ["-r", "site", "-c", "date", "-v", "result", "--delta", "pct"];
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+A01,,66.6666666666667
+B02,,-22.5
+";
+    assert_eq!(crosstab("delta", NORMALIZED, &args), expected);
+}
+
+// Test that the mean of each cell is weighted by the volume of each sample
+#[test]
+fn test_weight() {
+    let input = "site,date,result,volume\nA01,2024-01-01,1,2\nA01,2024-01-01,4,1\nB02,2024-01-01,2.5,0.5\n";
+    let args = ["-r", "site", "-c", "date", "-v", "result", "-a", "mean", "--weight", "volume"];
+    assert_eq!(crosstab("weight", input, &args), "site,2024-01-01_result\nA01,2\nB02,2.5\n");
+}
+
+// Test that the qualifiers of each cell are combined by their distinct count and their most frequent value
+#[test]
+fn test_aggregate_mode() {
+    let input = "site,date,qual\nA01,2024-01-01,J\nA01,2024-01-01,U\nA01,2024-01-01,U\nB02,2024-01-01,J\n";
+    let args = ["-r", "site", "-c", "date", "-v", "qual", "-a", "mode"];
+    assert_eq!(crosstab("mode", input, &args), "site,2024-01-01_qual\nA01,U\nB02,J\n");
+    let args = ["-r", "site", "-c", "date", "-v", "qual", "-a", "nunique"];
+    assert_eq!(crosstab("nunique", input, &args), "site,2024-01-01_qual\nA01,2\nB02,1\n");
+}
+
+// Test that the comments of each cell are joined with the separator, without repeats, in sorted order
+#[test]
+fn test_concat_sep() {
+    let input = "site,date,comment\nA01,2024-01-01,turbid\nA01,2024-01-01,algae\nA01,2024-01-01,turbid\nB02,2024-01-01,clear\n";
+    let args = ["-r", "site", "-c", "date", "-v", "comment", "-a", "concat", "--concat-sep", " | ", "--concat-unique", "--concat-sort"];
+    assert_eq!(crosstab("concat_sep", input, &args), "site,2024-01-01_comment\nA01,algae | turbid\nB02,clear\n");
+}
+
+// Test that each cell writes the mean of its values with their count
+#[test]
+fn test_cell_template() {
+    let args = ["-r", "site", "-c", "date", "-v", "result", "-a", "mean", "--cell-template", "{value} (n={count})", "--value-format", "%.1f"];
+    let input = format!("{}A01,2024-01-01,2.5,mg/L\n", NORMALIZED);
+    let expected = "\
+site,2024-01-01_result,2024-01-02_result
+A01,2.0 (n=2),2.5 (n=1)
+B02,4.0 (n=1),3.1 (n=1)
+";
+    assert_eq!(crosstab("cell_template", &input, &args), expected);
+}
+
+// Test that the exceedances are highlighted in HTML output, and that other output cannot be highlighted
+#[test]
+fn test_highlight() {
+    let args = ["-r", "site", "-c", "date", "-v", "result", "--output-format", "html", "--highlight", "result>3:red+bold"];
+    let output = crosstab("highlight", NORMALIZED, &args);
+    assert!(output.contains("<td class=\"number\" style=\"background-color: #FF0000; font-weight: bold\">4.0</td>"));
+    assert!(output.contains("<td class=\"number\">1.5</td>"));
+
+    let infile = temp_file("highlight_csv_in.csv", NORMALIZED);
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "--highlight", "result>3:red"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+// Test that a crosstab of each group is written on its own worksheet, and that other output cannot have worksheets
+#[test]
+fn test_sheet_by() {
+    use calamine::{open_workbook, Data, Reader, Xlsx};
+
+    let input = "\
+site,date,analyte,result
+A01,2024-01-01,Zn,1.5
+A01,2024-01-01,As,0.2
+B02,2024-01-02,Zn,4.0
+";
+    let infile = temp_file("sheet_by_in.csv", input);
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_sheet_by_out.xlsx", std::process::id()));
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "--sheet-by", "analyte"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let mut workbook: Xlsx<_> = open_workbook(&outfile).unwrap();
+    assert_eq!(workbook.sheet_names(), vec!["As", "Zn"]);
+    let range = workbook.worksheet_range("As").unwrap();
+    assert_eq!(range.get_size(), (2, 2));
+    assert_eq!(range.get_value((1, 1)), Some(&Data::Float(0.2)));
+    let range = workbook.worksheet_range("Zn").unwrap();
+    assert_eq!(range.get_value((0, 2)), Some(&Data::String("2024-01-02_result".to_string())));
+    assert_eq!(range.get_value((2, 2)), Some(&Data::Float(4.0)));
+
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "--sheet-by", "analyte"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+// Test that a crosstab of each group is written to its own file, named by the value of the group
+#[test]
+fn test_split_by() {
+    let input = "\
+site,date,analyte,result
+A01,2024-01-01,Zn,1.5
+A01,2024-01-01,As/Se,0.2
+B02,2024-01-02,Zn,4.0
+";
+    let infile = temp_file("split_by_in.csv", input);
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_split_by_{{value}}.csv", std::process::id()));
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "--split-by", "analyte"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let group_file = |value: &str| std::env::temp_dir().join(format!("xtab_{}_split_by_{}.csv", std::process::id(), value));
+    assert_eq!(fs::read_to_string(group_file("As_Se")).unwrap(), "site,2024-01-01_result\nA01,0.2\n");
+    assert_eq!(fs::read_to_string(group_file("Zn")).unwrap(), "site,2024-01-01_result,2024-01-02_result\nA01,1.5,\nB02,,4.0\n");
+
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "--split-by", "analyte"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+// Test that groups whose values make the same file name are written to numbered files instead of overwriting each other
+#[test]
+fn test_split_by_colliding_names() {
+    let infile = temp_file("split_by_colliding_in.csv", "site,date,grp,result\nA01,2024-01-01,a/b,1.5\nB02,2024-01-01,a_b,4.0\n");
+    let outfile = std::env::temp_dir().join(format!("xtab_{}_split_by_colliding_{{value}}.csv", std::process::id()));
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-o", outfile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "--split-by", "grp"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let group_file = |value: &str| std::env::temp_dir().join(format!("xtab_{}_split_by_colliding_{}.csv", std::process::id(), value));
+    assert_eq!(fs::read_to_string(group_file("a_b")).unwrap(), "site,2024-01-01_result\nA01,1.5\n");
+    assert_eq!(fs::read_to_string(group_file("a_b (2)")).unwrap(), "site,2024-01-01_result\nB02,4.0\n");
+}
+
+// Test that an aggregation is only known once its plugin is loaded, and that a file that is not a plugin is refused
+#[test]
+fn test_plugin() {
+    let infile = temp_file("plugin_in.csv", NORMALIZED);
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "-a", "geomean"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown aggregation 'geomean'"));
+
+    let output = run_xtab(&["-i", infile.to_str().unwrap(), "-r", "site", "-c", "date", "-v", "result", "--plugin", infile.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("The plugin cannot be loaded"));
+}
+
+// Test that the server answers a request with the crosstab of its data, as described by its settings
+#[test]
+fn test_serve() {
+    use std::io::{BufRead, BufReader, Read};
+    let mut server = Command::new(env!("CARGO_BIN_EXE_xtab")).args(["serve", "--port", "0"]).stderr(Stdio::piped()).spawn().unwrap();
+    let mut banner = String::new();
+    BufReader::new(server.stderr.take().unwrap()).read_line(&mut banner).unwrap();
+    let address = banner.trim().trim_start_matches("Serving crosstabs at http://").trim_end_matches("/crosstab").to_string();
+
+    let config = r#"{"row": "site", "col": "date", "value": "result"}"#;
+    let body = format!(
+        "--XTAB\r\nContent-Disposition: form-data; name=\"config\"\r\n\r\n{}\r\n--XTAB\r\nContent-Disposition: form-data; name=\"data\"; filename=\"results.csv\"\r\nContent-Type: text/csv\r\n\r\n{}\r\n--XTAB--\r\n",
+        config, NORMALIZED
+    );
+    let mut stream = std::net::TcpStream::connect(&address).unwrap();
+    write!(
+        stream,
+        "POST /crosstab HTTP/1.1\r\nHost: {}\r\nContent-Type: multipart/form-data; boundary=XTAB\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        address,
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    server.kill().unwrap();
+    server.wait().unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.ends_with("site,2024-01-01_result,2024-01-02_result\nA01,1.5,2.5\nB02,4.0,3.1\n"), "{}", response);
 }
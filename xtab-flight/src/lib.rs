@@ -0,0 +1,200 @@
+//! An Arrow Flight service of the xtab crosstab engine, so that data pipelines can crosstab record batches in memory,
+//! without writing them to files for the command line.
+//!
+//! A client calls `DoExchange` with a stream of record batches whose flight descriptor is a command: the text of a
+//! JSON object with the parameters of the crosstab, as in a job file, such as
+//! `{"row": "site", "col": "date", "value": "result", "aggregate": "mean"}`. The service answers with the record
+//! batches of the crosstab, with the format 1 (single row) column names, and generated columns whose values are all
+//! numbers as numbers.
+//!
+//! ```python
+//! import pyarrow.flight as flight
+//!
+//! client = flight.connect("grpc://localhost:50051")
+//! descriptor = flight.FlightDescriptor.for_command('{"row": "site", "col": "date", "value": "result"}')
+//! writer, reader = client.do_exchange(descriptor)
+//! writer.begin(table.schema)
+//! writer.write_table(table)
+//! writer.done_writing()
+//! crosstab = reader.read_all()
+//! ```
+//!
+//! A crosstab that cannot be built is answered with status `INVALID_ARGUMENT` (such as for a missing column), or
+//! `INTERNAL`, with the message that the command line would print.
+
+use arrow_array::RecordBatch;
+use arrow_flight::decode::FlightRecordBatchStream;
+use arrow_flight::encode::FlightDataEncoderBuilder;
+use arrow_flight::error::FlightError;
+use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+use arrow_flight::{
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightInfo, HandshakeRequest, HandshakeResponse, PollInfo, PutResult,
+    SchemaResult, Ticket,
+};
+use arrow_ipc::reader::FileReader;
+use arrow_ipc::writer::FileWriter;
+use futures::stream::{self, BoxStream};
+use futures::{StreamExt, TryStreamExt};
+use std::io::Cursor;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+use xtab::params::crosstab_ipc;
+use xtab::XtabError;
+
+/// The Flight service of crosstabs, which only answers `DoExchange`.
+#[derive(Clone, Debug, Default)]
+pub struct CrosstabService;
+
+// The status of a failure, by its kind: an error in the request is an invalid argument, and anything else, such as
+// data that cannot be read, an internal error
+fn status(error: anyhow::Error) -> Status {
+    let message = format!("{:#}", error);
+    match error.downcast_ref::<XtabError>() {
+        Some(XtabError::Argument(_) | XtabError::MissingColumns(_) | XtabError::Duplicates(_)) => Status::invalid_argument(message),
+        _ => Status::internal(message),
+    }
+}
+
+// The status of record batches that cannot be read or written
+fn arrow_status<E: std::fmt::Display>(error: E) -> Status {
+    Status::internal(format!("The record batches cannot be exchanged: {}", error))
+}
+
+/// Crosstab record batches as described by the text of a JSON object of the parameters of the crosstab, and return
+/// the record batches of the crosstab.
+pub fn crosstab_batches(schema: arrow_schema::SchemaRef, batches: &[RecordBatch], config_json: &str) -> Result<Vec<RecordBatch>, Status> {
+    let mut input: Vec<u8> = Vec::new();
+    let mut writer = FileWriter::try_new(&mut input, &schema).map_err(arrow_status)?;
+    for batch in batches {
+        writer.write(batch).map_err(arrow_status)?;
+    }
+    writer.finish().map_err(arrow_status)?;
+    drop(writer);
+    let output = crosstab_ipc(&input, config_json).map_err(status)?;
+    let reader = FileReader::try_new(Cursor::new(output), None).map_err(arrow_status)?;
+    reader.collect::<Result<Vec<RecordBatch>, _>>().map_err(arrow_status)
+}
+
+#[tonic::async_trait]
+impl FlightService for CrosstabService {
+    type HandshakeStream = BoxStream<'static, Result<HandshakeResponse, Status>>;
+    type ListFlightsStream = BoxStream<'static, Result<FlightInfo, Status>>;
+    type DoGetStream = BoxStream<'static, Result<FlightData, Status>>;
+    type DoPutStream = BoxStream<'static, Result<PutResult, Status>>;
+    type DoActionStream = BoxStream<'static, Result<arrow_flight::Result, Status>>;
+    type ListActionsStream = BoxStream<'static, Result<ActionType, Status>>;
+    type DoExchangeStream = BoxStream<'static, Result<FlightData, Status>>;
+
+    async fn do_exchange(&self, request: Request<Streaming<FlightData>>) -> Result<Response<Self::DoExchangeStream>, Status> {
+        let mut data = request.into_inner();
+        // The first message describes the crosstab, and may also have the schema of the input
+        let first = data.message().await?.ok_or_else(|| Status::invalid_argument("The exchange has no data"))?;
+        let config_json = match &first.flight_descriptor {
+            Some(FlightDescriptor { cmd, .. }) if !cmd.is_empty() => String::from_utf8(cmd.to_vec())
+                .map_err(|_| Status::invalid_argument("The command of the flight descriptor must be UTF-8 text"))?,
+            _ => return Err(Status::invalid_argument("The flight descriptor must be a command with the JSON parameters of the crosstab")),
+        };
+        let data = stream::once(async { Ok(first) }).chain(data).map_err(FlightError::from);
+        let mut batches = FlightRecordBatchStream::new_from_flight_data(data);
+        let mut input: Vec<RecordBatch> = Vec::new();
+        while let Some(batch) = batches.try_next().await.map_err(arrow_status)? {
+            input.push(batch);
+        }
+        let schema = batches.schema().cloned().ok_or_else(|| Status::invalid_argument("The exchange has no schema"))?;
+
+        let output = tokio::task::spawn_blocking(move || crosstab_batches(schema, &input, &config_json)).await.map_err(arrow_status)??;
+        let schema = output.first().map(RecordBatch::schema).ok_or_else(|| Status::internal("The crosstab has no schema"))?;
+        let encoder = FlightDataEncoderBuilder::new().with_schema(schema).build(stream::iter(output.into_iter().map(Ok)));
+        Ok(Response::new(encoder.map_err(arrow_status).boxed()))
+    }
+
+    async fn handshake(&self, _request: Request<Streaming<HandshakeRequest>>) -> Result<Response<Self::HandshakeStream>, Status> {
+        Err(Status::unimplemented("Crosstabs are built with DoExchange"))
+    }
+
+    async fn list_flights(&self, _request: Request<Criteria>) -> Result<Response<Self::ListFlightsStream>, Status> {
+        Err(Status::unimplemented("Crosstabs are built with DoExchange"))
+    }
+
+    async fn get_flight_info(&self, _request: Request<FlightDescriptor>) -> Result<Response<FlightInfo>, Status> {
+        Err(Status::unimplemented("Crosstabs are built with DoExchange"))
+    }
+
+    async fn poll_flight_info(&self, _request: Request<FlightDescriptor>) -> Result<Response<PollInfo>, Status> {
+        Err(Status::unimplemented("Crosstabs are built with DoExchange"))
+    }
+
+    async fn get_schema(&self, _request: Request<FlightDescriptor>) -> Result<Response<SchemaResult>, Status> {
+        Err(Status::unimplemented("Crosstabs are built with DoExchange"))
+    }
+
+    async fn do_get(&self, _request: Request<Ticket>) -> Result<Response<Self::DoGetStream>, Status> {
+        Err(Status::unimplemented("Crosstabs are built with DoExchange"))
+    }
+
+    async fn do_put(&self, _request: Request<Streaming<FlightData>>) -> Result<Response<Self::DoPutStream>, Status> {
+        Err(Status::unimplemented("Crosstabs are built with DoExchange"))
+    }
+
+    async fn do_action(&self, _request: Request<Action>) -> Result<Response<Self::DoActionStream>, Status> {
+        Err(Status::unimplemented("Crosstabs are built with DoExchange"))
+    }
+
+    async fn list_actions(&self, _request: Request<Empty>) -> Result<Response<Self::ListActionsStream>, Status> {
+        Err(Status::unimplemented("Crosstabs are built with DoExchange"))
+    }
+}
+
+/// Serve crosstabs to the connections of the listener until the process ends.
+pub async fn serve(listener: TcpListener) -> Result<(), tonic::transport::Error> {
+    Server::builder()
+        .add_service(FlightServiceServer::new(CrosstabService))
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{ArrayRef, Float64Array, StringArray};
+    use arrow_flight::FlightClient;
+    use std::sync::Arc;
+    use tonic::transport::Channel;
+
+    // Test that record batches are exchanged for the record batches of the crosstab, or for the status of an error
+    #[tokio::test]
+    async fn test_do_exchange() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener));
+        let channel = Channel::from_shared(format!("http://{}", address)).unwrap().connect().await.unwrap();
+        let mut client = FlightClient::new(channel);
+
+        let columns: Vec<(&str, ArrayRef)> = vec![
+            ("site", Arc::new(StringArray::from(vec!["A01", "A01", "B02"]))),
+            ("date", Arc::new(StringArray::from(vec!["d1", "d2", "d1"]))),
+            ("result", Arc::new(Float64Array::from(vec![1.5, 2.0, 3.0]))),
+        ];
+        let batch = RecordBatch::try_from_iter(columns).unwrap();
+        let exchange = |config: &str| {
+            let descriptor = FlightDescriptor::new_cmd(config.to_string());
+            FlightDataEncoderBuilder::new().with_flight_descriptor(Some(descriptor)).build(stream::iter(vec![Ok(batch.clone())]))
+        };
+
+        let output = client.do_exchange(exchange(r#"{"row": "site", "col": "date", "value": "result"}"#)).await.unwrap();
+        let crosstab: Vec<RecordBatch> = output.try_collect().await.unwrap();
+        let schema = crosstab[0].schema();
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, vec!["site", "d1_result", "d2_result"]);
+        let d1 = crosstab[0].column(1).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(d1.values().to_vec(), vec![1.5, 3.0]);
+
+        let Err(FlightError::Tonic(error)) = client.do_exchange(exchange(r#"{"row": "site", "col": "day", "value": "result"}"#)).await else {
+            panic!("A missing column is not an error");
+        };
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+        assert!(error.message().contains("day"));
+    }
+}
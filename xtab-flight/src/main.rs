@@ -0,0 +1,26 @@
+use clap::Parser;
+use tokio::net::TcpListener;
+use xtab_flight::serve;
+
+/// Serve crosstabs of record batches with Arrow Flight: DoExchange a stream of record batches, with a flight
+/// descriptor whose command is the JSON object of the parameters of the crosstab, for the record batches of the
+/// crosstab.
+#[derive(Parser, Debug)]
+#[command(name = "xtab-flight", version)]
+struct Args {
+    /// The address to listen at.
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+    /// The port to listen at, or 0 for any free port.
+    #[arg(short, long, default_value_t = 50051)]
+    port: u16,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let listener = TcpListener::bind((args.host.as_str(), args.port)).await?;
+    eprintln!("Serving crosstabs with Arrow Flight at grpc://{}", listener.local_addr()?);
+    serve(listener).await?;
+    Ok(())
+}
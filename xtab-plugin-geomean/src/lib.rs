@@ -0,0 +1,94 @@
+//! An example of an xtab plugin, with the geometric mean of environmental results, which are often log-normally
+//! distributed: `geomean` counts each non-detect (such as `<0.5`) at half of its detection limit, and `geomean_nd0`
+//! leaves the non-detects out.
+//!
+//! ```sh
+//! xtab -i results.csv -r site -c analyte -v result -a geomean --plugin target/release/libxtab_plugin_geomean.so
+//! ```
+
+use std::ffi::{c_char, c_int, CStr, CString};
+
+// The aggregations of the plugin, separated by commas
+const AGGREGATIONS: &CStr = c"geomean,geomean_nd0";
+
+// The geometric mean of the values, with each non-detect at the fraction of its detection limit, or without the
+// non-detects if there is no fraction
+fn geomean(values: &[Option<&str>], nd_fraction: Option<f64>) -> Result<Option<String>, String> {
+    let mut logs: Vec<f64> = Vec::new();
+    for value in values.iter().flatten() {
+        let x = match (value.trim().strip_prefix('<'), nd_fraction) {
+            (Some(_), None) => continue,
+            (Some(limit), Some(fraction)) => limit.trim().parse::<f64>().map(|limit| limit * fraction),
+            (None, _) => value.trim().parse::<f64>(),
+        };
+        match x {
+            Ok(x) if x > 0.0 => logs.push(x.ln()),
+            _ => return Err(format!("The value '{}' is not a positive number or non-detect", value)),
+        }
+    }
+    if logs.is_empty() {
+        return Ok(None);
+    }
+    let mean = (logs.iter().sum::<f64>() / logs.len() as f64).exp();
+    // Round to 15 significant digits, as xtab does, so that the error of the arithmetic is not written
+    Ok(Some(format!("{:.14e}", mean).parse::<f64>().unwrap_or(mean).to_string()))
+}
+
+/// The names of the aggregations of the plugin.
+#[no_mangle]
+pub extern "C" fn xtab_plugin_aggregations() -> *const c_char {
+    AGGREGATIONS.as_ptr()
+}
+
+/// Combine the values of a cell with the named aggregation.
+///
+/// # Safety
+///
+/// `name` must be a NUL-terminated string, `values` must point to `n` pointers that are null or NUL-terminated
+/// strings, and `result` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn xtab_plugin_aggregate(name: *const c_char, values: *const *const c_char, n: usize, result: *mut *mut c_char) -> c_int {
+    let name = CStr::from_ptr(name).to_string_lossy();
+    let values: Vec<Option<&str>> = (0..n)
+        .map(|i| *values.add(i))
+        .map(|v| (!v.is_null()).then(|| CStr::from_ptr(v).to_str().unwrap_or_default()))
+        .collect();
+    let combined = match name.as_ref() {
+        "geomean" => geomean(&values, Some(0.5)),
+        "geomean_nd0" => geomean(&values, None),
+        _ => Err(format!("The plugin has no aggregation '{}'", name)),
+    };
+    let (status, text) = match combined {
+        Ok(value) => (0, value),
+        Err(message) => (1, Some(message)),
+    };
+    *result = text.and_then(|t| CString::new(t).ok()).map_or(std::ptr::null_mut(), CString::into_raw);
+    status
+}
+
+/// Release a result of [`xtab_plugin_aggregate`].
+///
+/// # Safety
+///
+/// `result` must be a result of `xtab_plugin_aggregate` that was not released before.
+#[no_mangle]
+pub unsafe extern "C" fn xtab_plugin_free(result: *mut c_char) {
+    if !result.is_null() {
+        drop(CString::from_raw(result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that non-detects are counted at half of their limits, or left out
+    #[test]
+    fn test_geomean() {
+        let values = [Some("2"), Some("<16"), None];
+        assert_eq!(geomean(&values, Some(0.5)), Ok(Some("4".to_string())));
+        assert_eq!(geomean(&values, None), Ok(Some("2".to_string())));
+        assert_eq!(geomean(&[Some("<1")], None), Ok(None));
+        assert!(geomean(&[Some("-1")], Some(0.5)).is_err());
+    }
+}
@@ -0,0 +1,26 @@
+//! WebAssembly bindings of the xtab crosstab engine, so that a static web page can crosstab the CSV files that its
+//! users upload, in the browser, with the same logic as the command line.
+//!
+//! ```js
+//! import init, { crosstab } from "./pkg/xtab_wasm.js";
+//!
+//! await init();
+//! const csv = new Uint8Array(await file.arrayBuffer());
+//! const output = crosstab(csv, JSON.stringify({ row: "site", col: "date", value: "result", aggregate: "mean" }));
+//! ```
+//!
+//! The configuration is a JSON object with the settings of a job file: the names of the command-line arguments, with
+//! underscores or dashes, and lists (or comma-separated strings) for the arguments that take comma-separated lists.
+//! The `delimiter`, `output_delimiter`, and `output_format` settings describe the input and output (see
+//! [`crosstab_json`]). The library is built without its native features, so there is no file, database, or Parquet
+//! input or output.
+
+use wasm_bindgen::prelude::*;
+use xtab::params::crosstab_json;
+
+/// Crosstab the bytes of a CSV file, as described by the configuration (the text of a JSON object), and return the
+/// bytes of the output. An error is thrown with the message that the command line would print.
+#[wasm_bindgen]
+pub fn crosstab(csv: &[u8], config_json: &str) -> Result<Vec<u8>, JsError> {
+    crosstab_json(csv, config_json).map_err(|e| JsError::new(&format!("{:#}", e)))
+}